@@ -99,6 +99,10 @@ fn define_error(mut out: impl Write, name: &str, error_info: &str) {
         .iter()
         .map(|v| format!("{}::{} => {}::{},\n", v.module, v.name, kind, v.name))
         .collect::<String>();
+    let from_kind = variants
+        .iter()
+        .map(|v| format!("{}::{} => Self({}::{}),\n", kind, v.name, v.module, v.name))
+        .collect::<String>();
     let enum_variants = variants
         .iter()
         .map(|v| {
@@ -129,6 +133,21 @@ impl {name} {{
             _ => {kind}::OTHER,
         }}
     }}
+    /// Create an error with the `HRESULT` that corresponds to the specified
+    /// kind. This is mostly useful for tests and mock implementations.
+    ///
+    /// # Panics
+    ///
+    /// If the kind is `OTHER` since it doesn't correspond to a single `HRESULT`.
+    pub fn from_kind(kind: {kind}) -> Self {{
+        match kind {{
+            {from_kind}
+            {kind}::OTHER => panic!(
+                "can't create a {{}} from the OTHER kind",
+                stringify!({name})
+            ),
+        }}
+    }}
 }}
 impl fmt::Display for {name} {{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {{
@@ -172,6 +191,7 @@ pub enum {kind} {{
         name = name,
         kind = kind,
         to_kind = to_kind,
+        from_kind = from_kind,
         enum_variants = enum_variants,
     )
     .unwrap();
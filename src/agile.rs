@@ -0,0 +1,267 @@
+//! Share COM interfaces between threads and apartments.
+//!
+//! The wrappers in this crate hold COM interface pointers that are only
+//! valid in the apartment that they were created in, so they can't be sent
+//! to other threads. An [`AgileHandle`] marshals an interface once and can
+//! then be sent to any thread that has initialized COM, where it is resolved
+//! into a new wrapper for the same object.
+//!
+//! Most programs are better served by keeping VSS objects on a single thread,
+//! see [`crate::worker`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::{agile::AgileHandle, vsbackup::BackupComponents};
+//!
+//! volume_shadow_copy::initialize_com()?;
+//! let backup_comp = BackupComponents::new()?;
+//! let handle = AgileHandle::new(&backup_comp)?;
+//! std::thread::spawn(move || -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//!     volume_shadow_copy::initialize_com()?;
+//!     let backup_comp: BackupComponents = handle.resolve()?;
+//!     backup_comp.initialize_for_backup(None)?;
+//!     Ok(())
+//! })
+//! .join()
+//! .unwrap()
+//! .map_err(|e| e as Box<dyn std::error::Error>)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # References
+//!
+//! [RoGetAgileReference function (combaseapi.h) - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/api/combaseapi/nf-combaseapi-rogetagilereference)
+//!
+//! [IGlobalInterfaceTable (objidl.h) - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/api/objidl/nn-objidl-iglobalinterfacetable)
+
+use std::{
+    error::Error as StdError,
+    fmt,
+    marker::PhantomData,
+    mem,
+    ptr::{null_mut, NonNull},
+};
+
+use once_cell::sync::OnceCell;
+use winapi::{
+    ctypes::c_void,
+    shared::{guiddef::REFIID, minwindef::DWORD, wtypesbase::CLSCTX_INPROC_SERVER},
+    um::{
+        cguid::CLSID_StdGlobalInterfaceTable,
+        combaseapi::{AgileReferenceOptions, CoCreateInstance, AGILEREFERENCE_DEFAULT},
+        libloaderapi::{GetProcAddress, LoadLibraryW},
+        objidlbase::{IAgileReference, IGlobalInterfaceTable},
+        unknwnbase::IUnknown,
+        winnt::HRESULT,
+    },
+    Interface as RawInterface,
+};
+
+use crate::{
+    check_com, trace_com_call, unsafe_impl_as_IUnknown, Interface, QueryInterface, SafeCOMComponent,
+};
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(IGlobalInterfaceTable);
+
+/// Error returned by [`AgileHandle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgileHandleError {
+    /// `RoGetAgileReference` isn't available since it was added in Windows
+    /// 8.1. [`AgileHandle::new`] uses the global interface table instead.
+    NotSupported,
+    /// The interface couldn't be registered for use from other apartments.
+    Marshal(HRESULT),
+    /// The interface couldn't be resolved in the current apartment, for
+    /// example because COM hasn't been initialized on the current thread.
+    Resolve(HRESULT),
+}
+impl fmt::Display for AgileHandleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSupported => write!(f, "RoGetAgileReference is not supported on this system"),
+            Self::Marshal(hr) => write!(f, "failed to marshal interface (HRESULT: {:#X})", hr),
+            Self::Resolve(hr) => write!(f, "failed to resolve interface (HRESULT: {:#X})", hr),
+        }
+    }
+}
+impl StdError for AgileHandleError {}
+
+type RoGetAgileReferenceFn = unsafe extern "system" fn(
+    AgileReferenceOptions,
+    REFIID,
+    *mut IUnknown,
+    *mut *mut IAgileReference,
+) -> HRESULT;
+
+/// Look up `RoGetAgileReference` at runtime so that programs still start on
+/// Windows versions that don't have it.
+fn ro_get_agile_reference() -> Option<RoGetAgileReferenceFn> {
+    static FUNCTION: OnceCell<Option<usize>> = OnceCell::new();
+    let address = FUNCTION.get_or_init(|| {
+        let library: Vec<u16> = "ole32.dll\0".encode_utf16().collect();
+        unsafe {
+            let module = LoadLibraryW(library.as_ptr());
+            if module.is_null() {
+                return None;
+            }
+            let function = GetProcAddress(module, b"RoGetAgileReference\0".as_ptr().cast());
+            if function.is_null() {
+                None
+            } else {
+                Some(function as usize)
+            }
+        }
+    });
+    // Safety: the address was returned by `GetProcAddress` for a function
+    // with this signature.
+    address.map(|address| unsafe { mem::transmute::<usize, RoGetAgileReferenceFn>(address) })
+}
+
+/// Get the process wide global interface table.
+fn global_interface_table() -> Result<SafeCOMComponent<IGlobalInterfaceTable>, HRESULT> {
+    unsafe {
+        let mut table = null_mut::<IGlobalInterfaceTable>();
+        check_com(CoCreateInstance(
+            &CLSID_StdGlobalInterfaceTable,
+            null_mut(),
+            CLSCTX_INPROC_SERVER,
+            &IGlobalInterfaceTable::uuidof(),
+            &mut table as *mut *mut IGlobalInterfaceTable as *mut _,
+        ))?;
+        Ok(SafeCOMComponent::new(table))
+    }
+}
+
+enum Reference {
+    /// An agile reference that is released when the handle is dropped.
+    Agile(NonNull<IAgileReference>),
+    /// A cookie for the global interface table that is revoked when the
+    /// handle is dropped.
+    Global(DWORD),
+}
+
+/// A handle to a COM object that can be sent to other threads and resolved
+/// there into the interface wrapper `T`.
+///
+/// Both agile references and the global interface table keep the object
+/// alive until the handle is dropped.
+pub struct AgileHandle<T> {
+    reference: Reference,
+    interface: PhantomData<fn() -> T>,
+}
+// Safety: agile references and global interface table cookies can be used
+// from any apartment.
+unsafe impl<T> Send for AgileHandle<T> {}
+unsafe impl<T> Sync for AgileHandle<T> {}
+impl<T> AgileHandle<T>
+where
+    T: Interface + QueryInterface,
+{
+    /// Create a handle using `RoGetAgileReference` if it is available and
+    /// the global interface table otherwise.
+    pub fn new(value: &T) -> Result<Self, AgileHandleError> {
+        match Self::agile_reference(value) {
+            Err(AgileHandleError::NotSupported) => Self::global_interface_table(value),
+            result => result,
+        }
+    }
+    /// Create a handle with `RoGetAgileReference`. Requires Windows 8.1 or
+    /// later.
+    #[doc(alias = "RoGetAgileReference")]
+    pub fn agile_reference(value: &T) -> Result<Self, AgileHandleError> {
+        trace_com_call!("RoGetAgileReference");
+        let function = ro_get_agile_reference().ok_or(AgileHandleError::NotSupported)?;
+        let interface = value
+            .query_interface::<T::Inner>()
+            .expect("an interface can always be queried for itself");
+        let mut reference = null_mut::<IAgileReference>();
+        check_com(unsafe {
+            function(
+                AGILEREFERENCE_DEFAULT,
+                &T::Inner::uuidof(),
+                &*interface as *const T::Inner as *mut IUnknown,
+                &mut reference,
+            )
+        })
+        .map_err(AgileHandleError::Marshal)?;
+        Ok(Self {
+            reference: Reference::Agile(
+                NonNull::new(reference).expect("agile reference was null even though S_OK"),
+            ),
+            interface: PhantomData,
+        })
+    }
+    /// Create a handle by registering the interface in the global interface
+    /// table.
+    #[doc(alias = "RegisterInterfaceInGlobal")]
+    pub fn global_interface_table(value: &T) -> Result<Self, AgileHandleError> {
+        trace_com_call!("RegisterInterfaceInGlobal");
+        let table = global_interface_table().map_err(AgileHandleError::Marshal)?;
+        let interface = value
+            .query_interface::<T::Inner>()
+            .expect("an interface can always be queried for itself");
+        let mut cookie: DWORD = 0;
+        check_com(unsafe {
+            table.RegisterInterfaceInGlobal(
+                &*interface as *const T::Inner as *mut IUnknown,
+                &T::Inner::uuidof(),
+                &mut cookie,
+            )
+        })
+        .map_err(AgileHandleError::Marshal)?;
+        Ok(Self {
+            reference: Reference::Global(cookie),
+            interface: PhantomData,
+        })
+    }
+    /// Get the interface for use in the current thread's apartment. COM must
+    /// be initialized on the current thread.
+    #[doc(alias = "Resolve")]
+    #[doc(alias = "GetInterfaceFromGlobal")]
+    pub fn resolve(&self) -> Result<T, AgileHandleError> {
+        trace_com_call!("Resolve");
+        let mut interface = null_mut::<c_void>();
+        let iid = T::Inner::uuidof();
+        match &self.reference {
+            Reference::Agile(reference) => {
+                check_com(unsafe { reference.as_ref().Resolve(&iid, &mut interface) })
+            }
+            Reference::Global(cookie) => {
+                let table = global_interface_table().map_err(AgileHandleError::Resolve)?;
+                check_com(unsafe { table.GetInterfaceFromGlobal(*cookie, &iid, &mut interface) })
+            }
+        }
+        .map_err(AgileHandleError::Resolve)?;
+        // Safety: the interface was queried with the id of `T::Inner` and we
+        // own the reference that was returned.
+        let interface = unsafe { SafeCOMComponent::new(interface as *mut T::Inner) };
+        Ok(T::from_safe_com_component(interface))
+    }
+}
+impl<T> fmt::Debug for AgileHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.reference {
+            Reference::Agile(_) => "AgileReference",
+            Reference::Global(_) => "GlobalInterfaceTable",
+        };
+        f.debug_struct("AgileHandle").field("kind", &kind).finish()
+    }
+}
+impl<T> Drop for AgileHandle<T> {
+    fn drop(&mut self) {
+        match self.reference {
+            Reference::Agile(reference) => unsafe {
+                reference.as_ref().Release();
+            },
+            Reference::Global(cookie) => {
+                // Fails if COM isn't initialized on this thread, in which
+                // case the registration is leaked.
+                if let Ok(table) = global_interface_table() {
+                    unsafe { table.RevokeInterfaceFromGlobal(cookie) };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! Utilities for exposing COM objects that are implemented in Rust.
+//!
+//! This is not required when developing a VSS "requester". It is used to host
+//! the objects created by the provider framework in [`crate::vsprov`] so that
+//! VSS can create them:
+//!
+//! 1. Register the class with [`register_local_server`] (usually done once by
+//!    an installer).
+//! 2. When the server process starts, create a [`ClassFactory`] and make it
+//!    available to COM using [`ClassFactory::register`].
+//! 3. Keep the process alive until the returned [`RegisteredClassObject`] is
+//!    no longer needed.
+//!
+//! # References
+//!
+//! [COM Clients and Servers - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/com/com-clients-and-servers)
+
+use std::{
+    ffi::OsStr,
+    io,
+    marker::PhantomData,
+    os::windows::ffi::OsStrExt,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr,
+    sync::atomic::{self, AtomicU32, Ordering},
+};
+
+use winapi::{
+    ctypes::c_void,
+    shared::{
+        guiddef::{IsEqualGUID, CLSID, IID, REFIID},
+        minwindef::{BOOL, DWORD, FALSE, HKEY, ULONG},
+        winerror::{
+            CLASS_E_NOAGGREGATION, ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, E_NOINTERFACE, E_POINTER,
+            E_UNEXPECTED, S_OK,
+        },
+        wtypesbase::CLSCTX_LOCAL_SERVER,
+    },
+    um::{
+        combaseapi::{
+            CoAddRefServerProcess, CoRegisterClassObject, CoReleaseServerProcess,
+            CoRevokeClassObject, StringFromGUID2, REGCLS_MULTIPLEUSE,
+        },
+        unknwnbase::{IClassFactory, IClassFactoryVtbl, IUnknown, IUnknownVtbl},
+        winnt::{HRESULT, KEY_WRITE, REG_OPTION_NON_VOLATILE, REG_SZ},
+        winreg::{
+            RegCloseKey, RegCreateKeyExW, RegDeleteTreeW, RegSetValueExW, HKEY_LOCAL_MACHINE,
+        },
+    },
+    Interface,
+};
+
+use super::{
+    check_com, errors::*, impl_query_interface, sealed, trace_com_call, unsafe_impl_as_IUnknown,
+    SafeCOMComponent,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+// COM object helpers
+////////////////////////////////////////////////////////////////////////////////
+
+/// Run a callback that was invoked by COM and make sure that no panic unwinds
+/// into foreign code.
+pub(crate) fn catch_panic(f: impl FnOnce() -> HRESULT) -> HRESULT {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(E_UNEXPECTED)
+}
+
+/// Convert a `Result` returned from a Rust implementation into a `HRESULT`.
+pub(crate) fn into_hresult(result: Result<(), HRESULT>) -> HRESULT {
+    match result {
+        Ok(()) => S_OK,
+        Err(hr) => hr,
+    }
+}
+
+/// Implements `QueryInterface` for a COM object implemented in Rust where all
+/// supported interfaces share the same interface pointer.
+///
+/// # Safety
+///
+/// The `this` pointer must be a live COM object and the other pointers must be
+/// valid if they aren't null.
+pub(crate) unsafe fn query_interface(
+    this: *mut IUnknown,
+    riid: REFIID,
+    object: *mut *mut c_void,
+    supported: &[IID],
+) -> HRESULT {
+    if object.is_null() || riid.is_null() {
+        return E_POINTER;
+    }
+    if IsEqualGUID(&*riid, &IUnknown::uuidof())
+        || supported.iter().any(|iid| IsEqualGUID(&*riid, iid))
+    {
+        (*this).AddRef();
+        *object = this as *mut c_void;
+        S_OK
+    } else {
+        *object = ptr::null_mut();
+        E_NOINTERFACE
+    }
+}
+
+/// Increment the reference count of a COM object implemented in Rust.
+pub(crate) fn add_ref(ref_count: &AtomicU32) -> ULONG {
+    ref_count.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Decrement the reference count of a COM object implemented in Rust. Returns
+/// the new count, if it is zero then the object should be freed.
+pub(crate) fn release(ref_count: &AtomicU32) -> ULONG {
+    let count = ref_count.fetch_sub(1, Ordering::Release) - 1;
+    if count == 0 {
+        // Synchronize with all previous releases before the object is freed:
+        atomic::fence(Ordering::Acquire);
+    }
+    count
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IClassFactory
+////////////////////////////////////////////////////////////////////////////////
+
+/// A COM object implemented in Rust that can be created by a
+/// [`ClassFactory`], for example a
+/// [`SoftwareProviderObject`](crate::vsprov::SoftwareProviderObject).
+///
+/// This trait is sealed and can't be implemented.
+pub trait ServerObject: sealed::ServerObjectInternal {}
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(IClassFactory);
+
+/// A COM class factory that creates new objects using a Rust closure.
+#[doc(alias = "IClassFactory")]
+#[derive(Debug, Clone)]
+pub struct ClassFactory(SafeCOMComponent<IClassFactory>);
+impl_query_interface!(ClassFactory => IClassFactory);
+
+impl ClassFactory {
+    /// Create a class factory that calls `create` every time COM needs a new
+    /// object. An error returned by the closure is forwarded to the client.
+    pub fn new<O, F>(create: F) -> Self
+    where
+        O: ServerObject,
+        F: Fn() -> Result<O, HRESULT> + Send + Sync + 'static,
+    {
+        let object = Box::new(ClassFactoryObject::<O, F> {
+            factory: IClassFactory {
+                lpVtbl: &ClassFactoryObject::<O, F>::VTBL,
+            },
+            ref_count: AtomicU32::new(1),
+            create,
+            marker: PhantomData,
+        });
+        let comp = Box::into_raw(object) as *mut IClassFactory;
+        // Safety: the object was just created so its reference count is `1`.
+        Self(unsafe { SafeCOMComponent::new(comp) })
+    }
+    /// Get the raw interface pointer without changing its reference count.
+    pub fn as_raw(&self) -> *mut IClassFactory {
+        &*self.0 as *const _ as *mut _
+    }
+    /// Release ownership of the COM object without decrementing its reference
+    /// count, for example to return it from `DllGetClassObject`.
+    pub fn into_raw(self) -> *mut IClassFactory {
+        SafeCOMComponent::into_raw(self.0)
+    }
+    /// Make the class factory available to other processes. The class will be
+    /// available until the returned value is dropped.
+    ///
+    /// COM must be initialized on the current thread.
+    #[doc(alias = "CoRegisterClassObject")]
+    pub fn register(
+        &self,
+        class_id: &CLSID,
+    ) -> Result<RegisteredClassObject, CoRegisterClassObjectError> {
+        trace_com_call!("CoRegisterClassObject");
+        let mut cookie: DWORD = 0;
+        check_com(unsafe {
+            CoRegisterClassObject(
+                class_id,
+                self.as_raw() as *mut IUnknown,
+                CLSCTX_LOCAL_SERVER,
+                REGCLS_MULTIPLEUSE,
+                &mut cookie,
+            )
+        })?;
+        Ok(RegisteredClassObject { cookie })
+    }
+}
+
+/// A class factory that was registered using [`ClassFactory::register`]. The
+/// registration is revoked when this value is dropped.
+#[derive(Debug)]
+pub struct RegisteredClassObject {
+    cookie: DWORD,
+}
+impl RegisteredClassObject {
+    /// Revoke the registration and return any error that occurred.
+    #[doc(alias = "CoRevokeClassObject")]
+    pub fn revoke(self) -> Result<(), CoRevokeClassObjectError> {
+        trace_com_call!("CoRevokeClassObject");
+        let cookie = self.cookie;
+        std::mem::forget(self);
+        check_com(unsafe { CoRevokeClassObject(cookie) })?;
+        Ok(())
+    }
+}
+impl Drop for RegisteredClassObject {
+    fn drop(&mut self) {
+        unsafe { CoRevokeClassObject(self.cookie) };
+    }
+}
+
+/// The memory layout of a [`ClassFactory`].
+#[repr(C)]
+struct ClassFactoryObject<O, F> {
+    /// Must be the first field so that a pointer to the object is also a valid
+    /// interface pointer.
+    factory: IClassFactory,
+    ref_count: AtomicU32,
+    create: F,
+    /// The factory creates values of type `O` but doesn't store them.
+    marker: PhantomData<fn() -> O>,
+}
+impl<O, F> ClassFactoryObject<O, F>
+where
+    O: ServerObject,
+    F: Fn() -> Result<O, HRESULT> + Send + Sync + 'static,
+{
+    const VTBL: IClassFactoryVtbl = IClassFactoryVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::query_interface,
+            AddRef: Self::add_ref,
+            Release: Self::release,
+        },
+        CreateInstance: Self::create_instance,
+        LockServer: Self::lock_server,
+    };
+
+    /// # Safety
+    ///
+    /// The pointer must point to a live `ClassFactoryObject<O, F>`.
+    unsafe fn from_this<'a, T>(this: *mut T) -> &'a Self {
+        &*(this as *const Self)
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        query_interface(this, riid, object, &[IClassFactory::uuidof()])
+    }
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+        add_ref(&Self::from_this(this).ref_count)
+    }
+    unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+        let count = release(&Self::from_this(this).ref_count);
+        if count == 0 {
+            drop(Box::from_raw(this as *mut Self));
+        }
+        count
+    }
+
+    unsafe extern "system" fn create_instance(
+        this: *mut IClassFactory,
+        outer: *mut IUnknown,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        if object.is_null() {
+            return E_POINTER;
+        }
+        *object = ptr::null_mut();
+        if !outer.is_null() {
+            return CLASS_E_NOAGGREGATION;
+        }
+        catch_panic(|| match (Self::from_this(this).create)() {
+            Ok(instance) => {
+                let instance = sealed::ServerObjectInternal::into_unknown(instance);
+                let hr = (*instance).QueryInterface(riid, object);
+                (*instance).Release();
+                hr
+            }
+            Err(hr) => hr,
+        })
+    }
+    unsafe extern "system" fn lock_server(_this: *mut IClassFactory, lock: BOOL) -> HRESULT {
+        if lock != FALSE {
+            CoAddRefServerProcess();
+        } else {
+            CoReleaseServerProcess();
+        }
+        S_OK
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Registration
+////////////////////////////////////////////////////////////////////////////////
+
+/// Format a class id as a string in the registry format, for example
+/// `{E579AB5F-1CC4-44B4-BED9-DE0991FF0623}`.
+fn class_id_key(class_id: &CLSID) -> Vec<u16> {
+    let mut buffer = [0u16; 39];
+    let len = unsafe { StringFromGUID2(class_id, buffer.as_mut_ptr(), buffer.len() as i32) };
+    assert_ne!(len, 0, "buffer for StringFromGUID2 was too small");
+    // The returned length includes the nul terminator:
+    let mut key: Vec<u16> = OsStr::new("SOFTWARE\\Classes\\CLSID\\")
+        .encode_wide()
+        .collect();
+    key.extend_from_slice(&buffer[..len as usize - 1]);
+    key
+}
+
+fn to_wide_nul(value: &OsStr) -> Vec<u16> {
+    value.encode_wide().chain(Some(0)).collect()
+}
+
+fn check_registry(status: i32) -> io::Result<()> {
+    if status == ERROR_SUCCESS as i32 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(status))
+    }
+}
+
+/// Create a registry key below `HKEY_LOCAL_MACHINE` and set its default value.
+fn write_registry_key(key: &[u16], value: &OsStr) -> io::Result<()> {
+    let key: Vec<u16> = key.iter().copied().chain(Some(0)).collect();
+    let value = to_wide_nul(value);
+    let mut handle: HKEY = ptr::null_mut();
+    check_registry(unsafe {
+        RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            key.as_ptr(),
+            0,
+            ptr::null_mut(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_WRITE,
+            ptr::null_mut(),
+            &mut handle,
+            ptr::null_mut(),
+        )
+    })?;
+    let result = check_registry(unsafe {
+        RegSetValueExW(
+            handle,
+            ptr::null(),
+            0,
+            REG_SZ,
+            value.as_ptr() as *const u8,
+            (value.len() * std::mem::size_of::<u16>()) as DWORD,
+        )
+    });
+    unsafe { RegCloseKey(handle) };
+    result
+}
+
+/// Register a COM class that is implemented by an executable, i.e. an
+/// out-of-process server that calls [`ClassFactory::register`] at startup.
+/// This writes to `HKEY_LOCAL_MACHINE` and so requires administrator
+/// privileges.
+///
+/// The executable will be started without any arguments.
+pub fn register_local_server(
+    class_id: &CLSID,
+    description: &str,
+    executable: &Path,
+) -> io::Result<()> {
+    let key = class_id_key(class_id);
+    write_registry_key(&key, OsStr::new(description))?;
+
+    let mut server_key = key;
+    server_key.extend(OsStr::new("\\LocalServer32").encode_wide());
+    let mut command = std::ffi::OsString::from("\"");
+    command.push(executable);
+    command.push("\"");
+    write_registry_key(&server_key, &command)
+}
+
+/// Remove a COM class registration that was created by
+/// [`register_local_server`]. Succeeds if the class wasn't registered.
+pub fn unregister_class(class_id: &CLSID) -> io::Result<()> {
+    let key: Vec<u16> = class_id_key(class_id).into_iter().chain(Some(0)).collect();
+    let status = unsafe { RegDeleteTreeW(HKEY_LOCAL_MACHINE, key.as_ptr()) };
+    if status == ERROR_FILE_NOT_FOUND as i32 {
+        return Ok(());
+    }
+    check_registry(status)
+}
@@ -0,0 +1,337 @@
+//! Read the events that VSS logs when something goes wrong.
+//!
+//! VSS error codes rarely explain why an operation failed. The details, such
+//! as which writer timed out or why the shadow copy storage area was full,
+//! are written to the Application and System event logs by the `VSS` service
+//! and the `volsnap` driver. [`recent_vss_events`] collects them so that they
+//! can be shown together with the error.
+//!
+//! [`SnapshotSet::finish_with_report`](crate::session::SnapshotSet::finish_with_report)
+//! adds these events to the [`JobReport`](crate::session::JobReport) of a job
+//! that didn't succeed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::{Duration, SystemTime};
+//!
+//! let since = SystemTime::now() - Duration::from_secs(10 * 60);
+//! for event in volume_shadow_copy::diagnostics::recent_vss_events(since)? {
+//!     println!(
+//!         "{} {} {:?}: {}",
+//!         event.source,
+//!         event.event_id,
+//!         event.level,
+//!         event.message.as_deref().unwrap_or("")
+//!     );
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # References
+//!
+//! [Event and Error Handling Under VSS - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/vss/event-and-error-handling-under-vss)
+//!
+//! [Querying for Events - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/wes/querying-for-event-source-messages)
+
+use std::{
+    io,
+    ptr::{self, null_mut},
+    time::{Duration, SystemTime},
+};
+
+use widestring::{U16CStr, U16CString};
+use winapi::{
+    shared::{
+        minwindef::{DWORD, FALSE, FILETIME},
+        winerror::{ERROR_INSUFFICIENT_BUFFER, ERROR_NO_MORE_ITEMS},
+    },
+    um::{
+        winbase::INFINITE,
+        winevt::{
+            EvtClose, EvtCreateRenderContext, EvtFormatMessage, EvtFormatMessageEvent, EvtNext,
+            EvtOpenPublisherMetadata, EvtQuery, EvtQueryChannelPath, EvtRender,
+            EvtRenderContextSystem, EvtRenderEventValues, EvtSystemChannel, EvtSystemEventID,
+            EvtSystemLevel, EvtSystemProviderName, EvtSystemTimeCreated, EvtVarTypeByte,
+            EvtVarTypeFileTime, EvtVarTypeString, EvtVarTypeUInt16, EVT_HANDLE, EVT_VARIANT,
+        },
+    },
+};
+
+use crate::session::filetime_to_system_time;
+
+/// The event logs and the event sources in them that [`recent_vss_events`]
+/// reads.
+const VSS_EVENT_SOURCES: [(&str, &str); 2] = [("Application", "VSS"), ("System", "volsnap")];
+
+/// How severe an event is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventLevel {
+    /// A critical error.
+    Critical,
+    /// An error.
+    Error,
+    /// A warning.
+    Warning,
+    /// Any other level.
+    Other(u8),
+}
+impl From<u8> for EventLevel {
+    fn from(level: u8) -> Self {
+        match level {
+            1 => Self::Critical,
+            2 => Self::Error,
+            3 => Self::Warning,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// An event that was logged by VSS, see [`recent_vss_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VssEvent {
+    /// The event log that contains the event, for example `Application`.
+    pub log: String,
+    /// The source that logged the event, for example `VSS`.
+    pub source: String,
+    /// Identifies the kind of event within its source.
+    pub event_id: u16,
+    /// How severe the event is.
+    pub level: EventLevel,
+    /// When the event was logged.
+    pub time: SystemTime,
+    /// The message of the event, `None` if it couldn't be formatted.
+    pub message: Option<String>,
+}
+
+/// Closes an event log handle when dropped.
+struct EvtHandle(EVT_HANDLE);
+impl EvtHandle {
+    fn new(handle: EVT_HANDLE) -> io::Result<Self> {
+        if handle.is_null() {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Self(handle))
+        }
+    }
+}
+impl Drop for EvtHandle {
+    fn drop(&mut self) {
+        unsafe { EvtClose(self.0) };
+    }
+}
+
+/// Build an XPath query that selects the warnings and errors that `source`
+/// logged during the last `age`.
+fn event_query(source: &str, age: Duration) -> String {
+    // Round up so that an event logged at `since` is included:
+    let millis = age.as_nanos().div_ceil(1_000_000);
+    format!(
+        "*[System[Provider[@Name='{}'] and (Level=1 or Level=2 or Level=3) \
+        and TimeCreated[timediff(@SystemTime) <= {}]]]",
+        source, millis
+    )
+}
+
+/// Read the warnings and errors that VSS logged since `since`, oldest first.
+///
+/// This includes events from the `VSS` source in the Application log and the
+/// `volsnap` source in the System log. Reading the System log might require
+/// administrator privileges, a log that can't be opened is skipped. An error
+/// is only returned if none of the logs could be opened.
+#[doc(alias = "EvtQuery")]
+pub fn recent_vss_events(since: SystemTime) -> io::Result<Vec<VssEvent>> {
+    let age = SystemTime::now().duration_since(since).unwrap_or_default();
+    let context =
+        EvtHandle::new(unsafe { EvtCreateRenderContext(0, null_mut(), EvtRenderContextSystem) })?;
+    let mut events = Vec::new();
+    let mut query_error = None;
+    let mut queried_any = false;
+    for (log, source) in VSS_EVENT_SOURCES.iter() {
+        let path = U16CString::from_str(log).expect("log names don't contain nul characters");
+        let query = U16CString::from_str(event_query(source, age))
+            .expect("queries don't contain nul characters");
+        let results = match EvtHandle::new(unsafe {
+            EvtQuery(
+                null_mut(),
+                path.as_ptr(),
+                query.as_ptr(),
+                EvtQueryChannelPath,
+            )
+        }) {
+            Ok(results) => results,
+            Err(e) => {
+                query_error.get_or_insert(e);
+                continue;
+            }
+        };
+        queried_any = true;
+        loop {
+            let mut handles = [null_mut(); 16];
+            let mut returned: DWORD = 0;
+            let ok = unsafe {
+                EvtNext(
+                    results.0,
+                    handles.len() as DWORD,
+                    handles.as_mut_ptr(),
+                    INFINITE,
+                    0,
+                    &mut returned,
+                )
+            };
+            if ok == FALSE {
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() == Some(ERROR_NO_MORE_ITEMS as i32) {
+                    break;
+                }
+                return Err(error);
+            }
+            // Take ownership of every handle before anything can fail:
+            let handles = handles[..returned as usize]
+                .iter()
+                .map(|&handle| EvtHandle(handle))
+                .collect::<Vec<_>>();
+            for event in &handles {
+                events.push(render_event(&context, event)?);
+            }
+        }
+    }
+    match query_error {
+        Some(e) if !queried_any => return Err(e),
+        _ => {}
+    }
+    events.sort_by_key(|event| event.time);
+    Ok(events)
+}
+
+/// Read the system properties of an event and format its message.
+fn render_event(context: &EvtHandle, event: &EvtHandle) -> io::Result<VssEvent> {
+    let mut used: DWORD = 0;
+    let mut count: DWORD = 0;
+    // The first call fails since the buffer is too small but it returns the
+    // needed size:
+    unsafe {
+        EvtRender(
+            context.0,
+            event.0,
+            EvtRenderEventValues,
+            0,
+            null_mut(),
+            &mut used,
+            &mut count,
+        )
+    };
+    // Use `u64` so that the buffer is correctly aligned:
+    let mut buffer = vec![0_u64; (used as usize).div_ceil(8)];
+    if unsafe {
+        EvtRender(
+            context.0,
+            event.0,
+            EvtRenderEventValues,
+            used,
+            buffer.as_mut_ptr().cast(),
+            &mut used,
+            &mut count,
+        )
+    } == FALSE
+    {
+        return Err(io::Error::last_os_error());
+    }
+    let values = unsafe {
+        std::slice::from_raw_parts(buffer.as_ptr() as *const EVT_VARIANT, count as usize)
+    };
+    let value = |id: DWORD, kind: DWORD| values.get(id as usize).filter(|v| v.Type == kind);
+    let string = |id: DWORD| {
+        value(id, EvtVarTypeString)
+            .map(|v| unsafe { U16CStr::from_ptr_str(*v.u.StringVal()) }.to_string_lossy())
+            .unwrap_or_default()
+    };
+
+    let source = string(EvtSystemProviderName);
+    let time = value(EvtSystemTimeCreated, EvtVarTypeFileTime)
+        .map(|v| {
+            let ticks = unsafe { *v.u.FileTimeVal() };
+            filetime_to_system_time(FILETIME {
+                dwLowDateTime: ticks as DWORD,
+                dwHighDateTime: (ticks >> 32) as DWORD,
+            })
+        })
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    Ok(VssEvent {
+        log: string(EvtSystemChannel),
+        message: format_message(&source, event),
+        source,
+        event_id: value(EvtSystemEventID, EvtVarTypeUInt16)
+            .map(|v| unsafe { *v.u.UInt16Val() })
+            .unwrap_or_default(),
+        level: value(EvtSystemLevel, EvtVarTypeByte)
+            .map(|v| unsafe { *v.u.ByteVal() })
+            .unwrap_or_default()
+            .into(),
+        time,
+    })
+}
+
+/// Format the message of an event using the message resources of its
+/// source.
+fn format_message(source: &str, event: &EvtHandle) -> Option<String> {
+    let source = U16CString::from_str(source).ok()?;
+    let publisher = EvtHandle::new(unsafe {
+        EvtOpenPublisherMetadata(null_mut(), source.as_ptr(), ptr::null(), 0, 0)
+    })
+    .ok()?;
+    let mut used: DWORD = 0;
+    let ok = unsafe {
+        EvtFormatMessage(
+            publisher.0,
+            event.0,
+            0,
+            0,
+            null_mut(),
+            EvtFormatMessageEvent,
+            0,
+            null_mut(),
+            &mut used,
+        )
+    };
+    if ok == FALSE
+        && io::Error::last_os_error().raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER as i32)
+    {
+        return None;
+    }
+    let mut buffer = vec![0_u16; used as usize];
+    if unsafe {
+        EvtFormatMessage(
+            publisher.0,
+            event.0,
+            0,
+            0,
+            null_mut(),
+            EvtFormatMessageEvent,
+            used,
+            buffer.as_mut_ptr(),
+            &mut used,
+        )
+    } == FALSE
+    {
+        return None;
+    }
+    let message = U16CStr::from_slice_with_nul(&buffer).ok()?;
+    Some(message.to_string_lossy().trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_query_rounds_age_up() {
+        assert_eq!(
+            event_query("VSS", Duration::from_micros(1500)),
+            "*[System[Provider[@Name='VSS'] and (Level=1 or Level=2 or Level=3) \
+            and TimeCreated[timediff(@SystemTime) <= 2]]]"
+        );
+    }
+}
@@ -25,6 +25,55 @@
 //! [causing issues for that other process]:
 //!     https://stackoverflow.com/questions/10473442/why-cant-i-open-a-file-for-reading-if-theoretically-i-should-be-allowed
 //!
+//! # Features
+//!
+//! - `serde`: implement `Serialize` and `Deserialize` for the
+//!   [`JobReport`](session::JobReport) produced by the [`session`] module and
+//!   for the [`SelectionConfig`](session::SelectionConfig) that it reads.
+//! - `futures`: await [`VssAsync`](vss::VssAsync) operations through
+//!   [`VssAsyncFuture`](vss::VssAsyncFuture) with any executor and observe
+//!   their status as a `Stream`.
+//! - `tokio`: use a backup components object from asynchronous code with the
+//!   `tokio` module.
+//! - `tracing`: emit a `tracing` span for every wrapped COM method and an
+//!   event with the `HRESULT` whenever one of them fails.
+//! - `service`: run a writer from [`vswriter::framework`] as a Windows service
+//!   using the `vswriter::service` module. Only available on 64-bit x86.
+//!
+//! # Thread safety
+//!
+//! Types that own COM interfaces, such as
+//! [`BackupComponents`](vsbackup::BackupComponents), are neither `Send` nor
+//! `Sync` since COM interface pointers belong to the apartment of the thread
+//! that obtained them. To use VSS from several threads either keep the
+//! objects on one thread with a [`VssWorker`](worker::VssWorker) or move
+//! them between apartments with an [`AgileHandle`](agile::AgileHandle). In
+//! debug builds, using an interface from a thread whose apartment differs
+//! from the one it was obtained in panics.
+//!
+//! ```compile_fail
+//! use volume_shadow_copy::vsbackup::BackupComponents;
+//!
+//! fn assert_send<T: Send>() {}
+//! assert_send::<BackupComponents>();
+//! ```
+//!
+//! The handles and error types that are meant to cross threads are `Send` and
+//! `Sync`:
+//!
+//! ```
+//! use volume_shadow_copy::{
+//!     agile::AgileHandle, session::SessionError, vsbackup::BackupComponents,
+//!     vss::CancellationToken, worker::VssWorkerHandle,
+//! };
+//!
+//! fn assert_send_sync<T: Send + Sync>() {}
+//! assert_send_sync::<AgileHandle<BackupComponents>>();
+//! assert_send_sync::<VssWorkerHandle<BackupComponents>>();
+//! assert_send_sync::<CancellationToken>();
+//! assert_send_sync::<SessionError>();
+//! ```
+//!
 //! # References
 //!
 //! This command-line tool could maybe be used to manage volume shadow copies:
@@ -73,8 +122,9 @@
 //! The above license might not apply to content copied from other sources. This
 //! includes some documentation comments in the code as well as error info
 //! inside the `errors.md` file used to generate [error types](errors). The
-//! example code in `examples/make-backup-snapshot.rs` was inspired by code from
-//! the [backup program `restic`] and so might be affected by its license.
+//! [`session`] module and the example code in `examples/make-backup-snapshot.rs`
+//! were inspired by code from the [backup program `restic`] and so might be
+//! affected by its license.
 //!
 //! [backup program `restic`]:
 //!     https://github.com/restic/restic/blob/db8a95899114ef5131818462d057cac202189b3a/internal/fs/vss_windows.go#L763-L777
@@ -91,12 +141,22 @@
     )
 ))]
 
+pub mod agile;
+pub mod com_server;
+pub mod diagnostics;
+pub mod metrics;
+pub mod paths;
+pub mod session;
+#[cfg(feature = "tokio")]
+pub mod tokio;
 pub mod vsadmin;
 pub mod vsbackup;
 pub mod vsmgmt;
 pub mod vsprov;
 pub mod vss;
 pub mod vswriter;
+pub mod worker;
+pub mod writers;
 
 mod safe_com_component;
 use safe_com_component::{
@@ -104,18 +164,52 @@ use safe_com_component::{
 };
 
 use std::{
+    convert::TryFrom,
     error::Error as StdError,
-    fmt,
+    fmt, io,
+    marker::PhantomData,
     mem::{transmute, ManuallyDrop},
     ops::Deref,
-    ptr::NonNull,
+    ptr::{self, NonNull},
+    slice,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
 };
 
-use widestring::U16CStr;
+use widestring::{U16CStr, U16CString};
 use winapi::{
     ctypes::c_void,
-    shared::{winerror::S_OK, wtypes::BSTR, wtypesbase::OLECHAR},
-    um::{combaseapi::CoTaskMemFree, oleauto::SysFreeString, vss::VSS_PWSZ, winnt::HRESULT},
+    shared::{
+        minwindef::{DWORD, FALSE},
+        winerror::{
+            CO_E_SERVER_EXEC_FAILURE, CO_E_SERVER_STOPPING, ERROR_NOT_SUPPORTED,
+            ERROR_SERVICE_ALREADY_RUNNING, ERROR_SERVICE_DISABLED, ERROR_SERVICE_NOT_ACTIVE,
+            E_UNEXPECTED, HRESULT_FROM_WIN32, RPC_E_CHANGED_MODE, RPC_E_DISCONNECTED,
+            RPC_S_CALL_FAILED, RPC_S_SERVER_UNAVAILABLE, S_FALSE, S_OK,
+        },
+        wtypes::BSTR,
+        wtypesbase::OLECHAR,
+    },
+    um::{
+        combaseapi::{CoTaskMemAlloc, CoTaskMemFree},
+        oleauto::{SysAllocStringLen, SysFreeString, SysStringLen},
+        vss::VSS_PWSZ,
+        vsserror::VSS_E_UNEXPECTED,
+        winbase::VerifyVersionInfoW,
+        winnt::{
+            VerSetConditionMask, HRESULT, OSVERSIONINFOEXW, SERVICE_DISABLED, VER_EQUAL,
+            VER_GREATER_EQUAL, VER_MAJORVERSION, VER_MINORVERSION, VER_NT_WORKSTATION,
+            VER_PRODUCT_TYPE,
+        },
+        winsvc::{
+            CloseServiceHandle, OpenSCManagerW, OpenServiceW, QueryServiceConfigW,
+            QueryServiceStatus, StartServiceW, QUERY_SERVICE_CONFIGW, SC_HANDLE,
+            SC_MANAGER_CONNECT, SERVICE_CONTINUE_PENDING, SERVICE_PAUSED, SERVICE_PAUSE_PENDING,
+            SERVICE_QUERY_CONFIG, SERVICE_QUERY_STATUS, SERVICE_RUNNING, SERVICE_START,
+            SERVICE_START_PENDING, SERVICE_STATUS, SERVICE_STOPPED, SERVICE_STOP_PENDING,
+        },
+    },
 };
 use winstr::{BStr, BString};
 
@@ -155,6 +249,30 @@ impl VssU16CString {
         }
     }
 }
+impl VssU16CString {
+    /// Copy a string into memory allocated with the
+    /// `winapi::um::combaseapi::CoTaskMemAlloc` function.
+    ///
+    /// # Panics
+    ///
+    /// If the memory allocation failed.
+    pub fn from_u16_cstr(string: &U16CStr) -> Self {
+        let len = string.len() + 1;
+        unsafe {
+            let ptr = CoTaskMemAlloc(len * std::mem::size_of::<u16>()) as VSS_PWSZ;
+            if ptr.is_null() {
+                panic!("failed to allocate memory for a string with CoTaskMemAlloc");
+            }
+            std::ptr::copy_nonoverlapping(string.as_ptr(), ptr, len);
+            Self(ptr)
+        }
+    }
+    /// Release ownership of the wrapped string. The returned pointer should be
+    /// freed using the `winapi::um::combaseapi::CoTaskMemFree` function.
+    pub fn into_raw(self) -> VSS_PWSZ {
+        ManuallyDrop::new(self).0
+    }
+}
 impl Deref for VssU16CString {
     type Target = U16CStr;
     fn deref(&self) -> &Self::Target {
@@ -243,6 +361,38 @@ pub unsafe fn take_ownership_of_bstr(bstr: BSTR) -> Result<Option<BString>, Take
     Ok(Some(transmute::<NonNull<OLECHAR>, BString>(bstr)))
 }
 
+/// Get the UTF-16 code units of a `BSTR` (without the terminating nul).
+pub(crate) fn bstr_as_u16_slice(bstr: &BStr) -> &[u16] {
+    let ptr = bstr.as_bstr();
+    // Safety: a `BStr` always points to a valid `BSTR` whose length prefix
+    // specifies the number of bytes in the string.
+    unsafe { slice::from_raw_parts(ptr, SysStringLen(ptr) as usize) }
+}
+
+/// Copy a `BSTR` into a nul terminated string, stopping at the first nul
+/// character.
+pub(crate) fn bstr_to_u16_cstring(bstr: &BStr) -> U16CString {
+    let wide = bstr_as_u16_slice(bstr);
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    U16CString::new(&wide[..len]).expect("the string was truncated at the first nul character")
+}
+
+/// Allocate a `BSTR` that contains a copy of some UTF-16 code units.
+///
+/// # Panics
+///
+/// If the system is out of memory.
+pub(crate) fn u16_slice_to_bstring(text: &[u16]) -> BString {
+    let len = u32::try_from(text.len()).expect("string is too long for a BSTR");
+    // Safety: the pointer is valid for `len` code units and the returned
+    // `BSTR` was allocated with `SysAllocStringLen` so it can be freed with
+    // `SysFreeString`.
+    unsafe { take_ownership_of_bstr(SysAllocStringLen(text.as_ptr(), len)) }
+        .ok()
+        .flatten()
+        .expect("failed to allocate BSTR")
+}
+
 pub mod errors {
     //! Errors that enumerate expected error conditions for different methods.
     use std::{error::Error as StdError, fmt};
@@ -258,32 +408,1092 @@ pub mod errors {
 
 /// Convert a `HRESULT` into a `Result`. If the value is `S_OK` then returns `Ok(())`,
 /// otherwise returns `Err(code)` where code is the provided `HRESULT`.
+///
+/// With the `tracing` feature an event is emitted for every error, inside the
+/// span of the method that made the call. The code is also passed on to the
+/// [`metrics`] sink and hook.
+#[cfg_attr(feature = "tracing", track_caller)]
 fn check_com(hr: HRESULT) -> Result<(), HRESULT> {
+    metrics::record_hresult(hr);
     if hr == S_OK {
         Ok(())
     } else {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            hresult = %format_args!("{:#X}", hr),
+            location = %std::panic::Location::caller(),
+            "COM call failed"
+        );
         Err(hr)
     }
 }
 
+/// The concurrency model that COM is initialized with on a thread.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Apartment {
+    /// A single-threaded apartment, `COINIT_APARTMENTTHREADED`. Required by
+    /// some UI frameworks. COM objects created in it can only be used from
+    /// the thread that created them.
+    SingleThreaded,
+    /// The multithreaded apartment, `COINIT_MULTITHREADED`. This is what VSS
+    /// is usually used from.
+    #[default]
+    MultiThreaded,
+}
+impl Apartment {
+    fn coinit(self) -> DWORD {
+        match self {
+            Self::SingleThreaded => winapi::um::objbase::COINIT_APARTMENTTHREADED,
+            Self::MultiThreaded => winapi::um::objbase::COINIT_MULTITHREADED,
+        }
+    }
+}
+
+/// Error returned by [`initialize_com_with`].
+#[derive(Debug, Clone, Copy)]
+pub enum InitializeComError {
+    /// COM was already initialized on this thread with a different
+    /// concurrency model (`RPC_E_CHANGED_MODE`). COM stays initialized the
+    /// way it was, so either use the existing apartment or do the work on
+    /// another thread.
+    ChangedMode {
+        /// The apartment that was requested.
+        requested: Apartment,
+    },
+    /// Any other error.
+    Other(errors::CoInitializeExError),
+}
+impl fmt::Display for InitializeComError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ChangedMode { requested } => write!(
+                f,
+                "COM is already initialized on this thread with a different concurrency model \
+                    than the requested {:?} apartment",
+                requested
+            ),
+            Self::Other(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+impl StdError for InitializeComError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::ChangedMode { .. } => None,
+            Self::Other(e) => Some(e),
+        }
+    }
+}
+
+/// Call `CoInitializeEx`, treating `S_FALSE` (already initialized with the
+/// same concurrency model) as success.
+fn co_initialize(apartment: Apartment) -> Result<(), HRESULT> {
+    let hr =
+        unsafe { winapi::um::combaseapi::CoInitializeEx(std::ptr::null_mut(), apartment.coinit()) };
+    if hr == S_FALSE {
+        Ok(())
+    } else {
+        check_com(hr)
+    }
+}
+
 /// Initializes the COM library for use by the calling thread.
 ///
 /// Note: this uses the default `COINIT_MULTITHREADED` flag to initialize the
-/// COM library.
+/// COM library. Succeeds if COM was already initialized that way on the
+/// current thread. Use [`initialize_com_with`] to choose the apartment.
 ///
 /// See the wrapped methods docs at:
 /// [CoInitializeEx function (combaseapi.h) - Win32 apps | Microsoft Docs](https://docs.microsoft.com/windows/win32/api/combaseapi/nf-combaseapi-coinitializeex?redirectedfrom=MSDN)
 #[doc(alias = "CoInitializeEx")]
 pub fn initialize_com() -> Result<(), errors::CoInitializeExError> {
+    co_initialize(Apartment::MultiThreaded)?;
+    Ok(())
+}
+
+/// Keeps COM initialized on the current thread and uninitializes it when
+/// dropped.
+///
+/// Returned by [`initialize_com_scoped`]. The high-level workflows in the
+/// [`session`] module take a reference to a guard as proof that COM is
+/// initialized. The guard can't be sent to another thread since COM is
+/// initialized per thread.
+#[must_use = "COM is uninitialized when the guard is dropped"]
+#[doc(alias = "CoUninitialize")]
+#[derive(Debug)]
+pub struct ComGuard {
+    apartment: Apartment,
+    not_send: PhantomData<*const ()>,
+}
+impl ComGuard {
+    /// The apartment that COM was initialized with.
+    pub fn apartment(&self) -> Apartment {
+        self.apartment
+    }
+}
+impl Drop for ComGuard {
+    fn drop(&mut self) {
+        unsafe { winapi::um::combaseapi::CoUninitialize() };
+    }
+}
+
+/// Initializes the COM library for use by the calling thread like
+/// [`initialize_com`] and returns a guard that uninitializes it again.
+///
+/// This also succeeds if COM was already initialized as a multithreaded
+/// apartment on the current thread, since every successful call to
+/// `CoInitializeEx` must be paired with `CoUninitialize` either way. Drop
+/// every COM object that was created on the thread before the guard.
+#[doc(alias = "CoInitializeEx")]
+pub fn initialize_com_scoped() -> Result<ComGuard, errors::CoInitializeExError> {
+    co_initialize(Apartment::MultiThreaded)?;
+    Ok(ComGuard {
+        apartment: Apartment::MultiThreaded,
+        not_send: PhantomData,
+    })
+}
+
+/// Initializes the COM library for the calling thread with the chosen
+/// apartment and returns a guard that uninitializes it again, see
+/// [`initialize_com_scoped`].
+#[doc(alias = "CoInitializeEx")]
+pub fn initialize_com_with(apartment: Apartment) -> Result<ComGuard, InitializeComError> {
+    co_initialize(apartment).map_err(|hr| {
+        if hr == RPC_E_CHANGED_MODE {
+            InitializeComError::ChangedMode {
+                requested: apartment,
+            }
+        } else {
+            InitializeComError::Other(hr.into())
+        }
+    })?;
+    Ok(ComGuard {
+        apartment,
+        not_send: PhantomData,
+    })
+}
+
+/// How much of each call between COM processes is authenticated, the
+/// `RPC_C_AUTHN_LEVEL_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthenticationLevel {
+    /// Let COM negotiate the level.
+    Default,
+    /// No authentication.
+    None,
+    /// Authenticate only when the client first connects.
+    Connect,
+    /// Authenticate at the start of each call.
+    Call,
+    /// Authenticate that all data comes from the expected client.
+    Packet,
+    /// Also verify that no data was modified.
+    PacketIntegrity,
+    /// Also encrypt the arguments of each call.
+    PacketPrivacy,
+}
+impl AuthenticationLevel {
+    fn as_raw(self) -> DWORD {
+        use winapi::shared::rpcdce::*;
+        match self {
+            Self::Default => RPC_C_AUTHN_LEVEL_DEFAULT,
+            Self::None => RPC_C_AUTHN_LEVEL_NONE,
+            Self::Connect => RPC_C_AUTHN_LEVEL_CONNECT,
+            Self::Call => RPC_C_AUTHN_LEVEL_CALL,
+            Self::Packet => RPC_C_AUTHN_LEVEL_PKT,
+            Self::PacketIntegrity => RPC_C_AUTHN_LEVEL_PKT_INTEGRITY,
+            Self::PacketPrivacy => RPC_C_AUTHN_LEVEL_PKT_PRIVACY,
+        }
+    }
+}
+
+/// What servers that this process calls are allowed to do with its identity,
+/// the `RPC_C_IMP_LEVEL_*` constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImpersonationLevel {
+    /// Let COM choose the level.
+    Default,
+    /// The server can't see who the client is.
+    Anonymous,
+    /// The server can check the client's identity but not act as it.
+    Identify,
+    /// The server can act as the client on the local machine.
+    Impersonate,
+    /// The server can act as the client on other machines too.
+    Delegate,
+}
+impl ImpersonationLevel {
+    fn as_raw(self) -> DWORD {
+        use winapi::shared::rpcdce::*;
+        match self {
+            Self::Default => RPC_C_IMP_LEVEL_DEFAULT,
+            Self::Anonymous => RPC_C_IMP_LEVEL_ANONYMOUS,
+            Self::Identify => RPC_C_IMP_LEVEL_IDENTIFY,
+            Self::Impersonate => RPC_C_IMP_LEVEL_IMPERSONATE,
+            Self::Delegate => RPC_C_IMP_LEVEL_DELEGATE,
+        }
+    }
+}
+
+/// Options for [`initialize_com_security`].
+///
+/// The default options are the ones recommended for VSS requesters and used
+/// by the `vshadow` sample: packet privacy, identify level impersonation and
+/// dynamic cloaking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComSecurityOptions {
+    /// The default authentication level for calls made by this process.
+    pub authentication_level: AuthenticationLevel,
+    /// The default impersonation level for calls made by this process.
+    pub impersonation_level: ImpersonationLevel,
+    /// Use the identity of the current thread instead of the process when
+    /// calling writers, `EOAC_DYNAMIC_CLOAKING`. Matters when the requester
+    /// impersonates another user.
+    pub dynamic_cloaking: bool,
+}
+impl Default for ComSecurityOptions {
+    fn default() -> Self {
+        Self {
+            authentication_level: AuthenticationLevel::PacketPrivacy,
+            impersonation_level: ImpersonationLevel::Identify,
+            dynamic_cloaking: true,
+        }
+    }
+}
+
+/// Set the process wide COM security settings with `CoInitializeSecurity`.
+///
+/// Writers run in other processes and call back into the requester, so if
+/// the security settings don't allow that they fail with `E_ACCESSDENIED`
+/// or the backup times out waiting for them. Call this once, after
+/// [`initialize_com`] and before any VSS object is created. COM picks
+/// defaults on its own as soon as an interface is marshaled, after which
+/// this fails with `RPC_E_TOO_LATE`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use volume_shadow_copy::{initialize_com_scoped, initialize_com_security, ComSecurityOptions};
+///
+/// let _com = initialize_com_scoped()?;
+/// initialize_com_security(ComSecurityOptions::default())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// See the wrapped methods docs at:
+/// [CoInitializeSecurity function (combaseapi.h) - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/api/combaseapi/nf-combaseapi-coinitializesecurity)
+///
+/// [Security Considerations for Requesters - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/vss/security-considerations-for-requestors)
+#[doc(alias = "CoInitializeSecurity")]
+pub fn initialize_com_security(
+    options: ComSecurityOptions,
+) -> Result<(), errors::CoInitializeSecurityError> {
+    trace_com_call!("CoInitializeSecurity");
+    let capabilities = if options.dynamic_cloaking {
+        winapi::um::objidlbase::EOAC_DYNAMIC_CLOAKING
+    } else {
+        winapi::um::objidlbase::EOAC_NONE
+    };
     check_com(unsafe {
-        winapi::um::combaseapi::CoInitializeEx(
+        winapi::um::combaseapi::CoInitializeSecurity(
+            std::ptr::null_mut(),
+            -1,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            options.authentication_level.as_raw(),
+            options.impersonation_level.as_raw(),
+            std::ptr::null_mut(),
+            capabilities,
             std::ptr::null_mut(),
-            winapi::um::objbase::COINIT_MULTITHREADED,
         )
     })?;
     Ok(())
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// Requester identity
+////////////////////////////////////////////////////////////////////////////////
+
+/// The name and version of the application that requested a backup or
+/// restore. Useful to figure out which tool created a shadow copy when
+/// multiple backup programs are used on the same machine.
+///
+/// Use the [`requester_identity!`] macro to create an identity from the
+/// calling crate's `Cargo.toml` metadata.
+///
+/// The identity is formatted as `name/version` and can be parsed back from
+/// that format, so it can be written to logs or saved alongside a backup.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequesterIdentity {
+    name: String,
+    version: String,
+}
+impl RequesterIdentity {
+    /// Create a new identity.
+    ///
+    /// # Panics
+    ///
+    /// If the name contains a `/` character or if either string is empty.
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        let name = name.into();
+        let version = version.into();
+        assert!(
+            !name.is_empty() && !name.contains('/'),
+            "invalid requester name: {:?}",
+            name
+        );
+        assert!(!version.is_empty(), "requester version can't be empty");
+        Self { name, version }
+    }
+    /// The name of the requesting application.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The version of the requesting application.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+}
+impl fmt::Display for RequesterIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.name, self.version)
+    }
+}
+impl FromStr for RequesterIdentity {
+    type Err = ParseRequesterIdentityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((name, version)) if !name.is_empty() && !version.is_empty() => Ok(Self {
+                name: name.to_owned(),
+                version: version.to_owned(),
+            }),
+            _ => Err(ParseRequesterIdentityError(())),
+        }
+    }
+}
+
+/// Error returned when a [`RequesterIdentity`] couldn't be parsed from a
+/// string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRequesterIdentityError(());
+impl fmt::Display for ParseRequesterIdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "requester identity should have the format \"name/version\""
+        )
+    }
+}
+impl StdError for ParseRequesterIdentityError {}
+
+/// Create a [`RequesterIdentity`] from the name and version of the crate that
+/// invokes the macro, as specified in its `Cargo.toml` file.
+///
+/// # Examples
+///
+/// ```
+/// let identity = volume_shadow_copy::requester_identity!();
+/// println!("Backup requested by {}", identity);
+/// ```
+#[macro_export]
+macro_rules! requester_identity {
+    () => {
+        $crate::RequesterIdentity::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    };
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Operating system
+////////////////////////////////////////////////////////////////////////////////
+
+/// Check if the current operating system is a Windows Server edition. Some
+/// VSS features, such as LUN resynchronization, are only supported there.
+pub(crate) fn is_server_os() -> bool {
+    let mut info = OSVERSIONINFOEXW {
+        dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOEXW>() as DWORD,
+        wProductType: VER_NT_WORKSTATION,
+        ..Default::default()
+    };
+    unsafe {
+        let mask = VerSetConditionMask(0, VER_PRODUCT_TYPE, VER_EQUAL);
+        // Fails if the product type isn't a workstation:
+        VerifyVersionInfoW(&mut info, VER_PRODUCT_TYPE, mask) == FALSE
+    }
+}
+
+/// Check if the Windows version is at least `major.minor`. Versions newer
+/// than Windows 8 are reported as 6.2 to programs without a compatibility
+/// manifest, so only use this for older versions.
+fn is_windows_version_or_greater(major: DWORD, minor: DWORD) -> bool {
+    let mut info = OSVERSIONINFOEXW {
+        dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOEXW>() as DWORD,
+        dwMajorVersion: major,
+        dwMinorVersion: minor,
+        ..Default::default()
+    };
+    unsafe {
+        let mask = VerSetConditionMask(
+            VerSetConditionMask(0, VER_MAJORVERSION, VER_GREATER_EQUAL),
+            VER_MINORVERSION,
+            VER_GREATER_EQUAL,
+        );
+        VerifyVersionInfoW(&mut info, VER_MAJORVERSION | VER_MINORVERSION, mask) != FALSE
+    }
+}
+
+/// VSS features that depend on the edition and version of Windows, see
+/// [`capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The current process can use VSS at all, see [`check_vss_supported`].
+    pub supported: bool,
+    /// The operating system is a Windows Server edition.
+    pub server: bool,
+    /// Transportable shadow copies can be imported with
+    /// [`IBackupComponents::import_snapshots`](vsbackup::IBackupComponents::import_snapshots).
+    /// This also requires a hardware provider.
+    pub transportable_snapshots: bool,
+    /// Volumes can be reverted with
+    /// [`IBackupComponents::revert_to_snapshot`](vsbackup::IBackupComponents::revert_to_snapshot),
+    /// Windows Server 2008 or later.
+    pub revert_to_snapshot: bool,
+    /// LUNs can be resynchronized with
+    /// [`IBackupComponentsEx3::recover_set`](vsbackup::IBackupComponentsEx3::recover_set),
+    /// Windows Server 2012 or later.
+    pub lun_resync: bool,
+}
+
+/// Check which VSS features are supported by the running edition of Windows,
+/// so that tools can disable options that would fail anyway.
+///
+/// This only looks at the operating system. A feature can still fail, for
+/// example because no provider supports it.
+///
+/// # Examples
+///
+/// ```no_run
+/// let capabilities = volume_shadow_copy::capabilities();
+/// if !capabilities.revert_to_snapshot {
+///     println!("Reverting volumes is only supported on Windows Server");
+/// }
+/// ```
+pub fn capabilities() -> Capabilities {
+    let server = is_server_os();
+    Capabilities {
+        supported: !is_wow64(),
+        server,
+        transportable_snapshots: server,
+        revert_to_snapshot: server && is_windows_version_or_greater(6, 0),
+        lun_resync: server && is_windows_version_or_greater(6, 2),
+    }
+}
+
+/// Check if the current process is a 32-bit process running on 64-bit Windows
+/// (WOW64). Returns `false` if that couldn't be determined.
+#[doc(alias = "IsWow64Process")]
+pub fn is_wow64() -> bool {
+    if cfg!(target_pointer_width = "64") {
+        return false;
+    }
+    let mut wow64 = FALSE;
+    unsafe {
+        winapi::um::wow64apiset::IsWow64Process(
+            winapi::um::processthreadsapi::GetCurrentProcess(),
+            &mut wow64,
+        ) != FALSE
+            && wow64 != FALSE
+    }
+}
+
+/// VSS can't be used from a 32-bit process on 64-bit Windows, see
+/// [`check_vss_supported`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wow64Unsupported;
+impl fmt::Display for Wow64Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the Volume Shadow Copy service can't be used by a 32-bit program \
+            on 64-bit Windows, build the program for 64-bit targets instead"
+        )
+    }
+}
+impl StdError for Wow64Unsupported {}
+
+/// Check that the current process can use VSS at all.
+///
+/// 32-bit programs running on 64-bit Windows (WOW64) aren't supported by VSS
+/// and `CreateVssBackupComponents` fails for them with an error code that
+/// doesn't explain why. [`BackupComponents::new`](vsbackup::BackupComponents::new)
+/// and [`Admin::new`](vsadmin::Admin::new) call this before connecting to
+/// the service and return [`ConnectError::Wow64`].
+pub fn check_vss_supported() -> Result<(), Wow64Unsupported> {
+    if is_wow64() {
+        Err(Wow64Unsupported)
+    } else {
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Elevation
+////////////////////////////////////////////////////////////////////////////////
+
+/// Error returned by [`require_elevation`].
+#[derive(Debug)]
+pub enum ElevationError {
+    /// The process isn't running elevated. Restart it with "Run as
+    /// administrator".
+    NotElevated,
+    /// The process is elevated but its access token doesn't have the
+    /// `SeBackupPrivilege`, for example because the user isn't a member of
+    /// the Administrators or Backup Operators group.
+    MissingBackupPrivilege,
+    /// The access token of the process couldn't be queried.
+    Query(io::Error),
+}
+impl fmt::Display for ElevationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotElevated => write!(
+                f,
+                "the process must run as an administrator to use the Volume Shadow Copy service"
+            ),
+            Self::MissingBackupPrivilege => write!(
+                f,
+                "the process doesn't have the backup privilege (SeBackupPrivilege) \
+                    that is required to use the Volume Shadow Copy service"
+            ),
+            Self::Query(e) => write!(f, "failed to query the process access token: {}", e),
+        }
+    }
+}
+impl StdError for ElevationError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Query(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Check whether the current process is elevated and can use VSS. Returns
+/// `false` if that couldn't be determined.
+///
+/// See [`require_elevation`] for details.
+pub fn is_elevated() -> bool {
+    require_elevation().is_ok()
+}
+
+/// Check that the current process is elevated and that its access token has
+/// the `SeBackupPrivilege`, which is required to create shadow copies.
+///
+/// Without these, most VSS calls fail with `E_ACCESSDENIED`, often after the
+/// backup has already been started, so checking up front gives a clearer
+/// error message. The privilege doesn't need to be enabled, VSS enables it
+/// when it is needed.
+///
+/// # Examples
+///
+/// ```no_run
+/// if let Err(e) = volume_shadow_copy::require_elevation() {
+///     eprintln!("{}", e);
+///     std::process::exit(1);
+/// }
+/// ```
+#[doc(alias = "TokenElevation")]
+#[doc(alias = "SeBackupPrivilege")]
+pub fn require_elevation() -> Result<(), ElevationError> {
+    use winapi::um::{
+        handleapi::CloseHandle,
+        processthreadsapi::{GetCurrentProcess, OpenProcessToken},
+        securitybaseapi::GetTokenInformation,
+        winbase::LookupPrivilegeValueW,
+        winnt::{
+            TokenElevation, TokenPrivileges, HANDLE, LUID, LUID_AND_ATTRIBUTES, SE_BACKUP_NAME,
+            TOKEN_ELEVATION, TOKEN_PRIVILEGES, TOKEN_QUERY,
+        },
+    };
+
+    let last_error = || ElevationError::Query(io::Error::last_os_error());
+    let privilege_name = U16CString::from_str(SE_BACKUP_NAME).expect("no nul characters");
+    let mut backup_privilege = LUID::default();
+    let found = unsafe {
+        LookupPrivilegeValueW(ptr::null(), privilege_name.as_ptr(), &mut backup_privilege)
+    };
+    if found == FALSE {
+        return Err(last_error());
+    }
+
+    let mut token: HANDLE = ptr::null_mut();
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == FALSE {
+        return Err(last_error());
+    }
+    let result = (|| unsafe {
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size: DWORD = 0;
+        if GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut TOKEN_ELEVATION as *mut c_void,
+            std::mem::size_of::<TOKEN_ELEVATION>() as DWORD,
+            &mut size,
+        ) == FALSE
+        {
+            return Err(last_error());
+        }
+        if elevation.TokenIsElevated == 0 {
+            return Err(ElevationError::NotElevated);
+        }
+
+        // The first call fails since the buffer is too small but it returns
+        // the needed size:
+        GetTokenInformation(token, TokenPrivileges, ptr::null_mut(), 0, &mut size);
+        // Use `u64` so that the buffer is correctly aligned:
+        let mut buffer = vec![0_u64; (size as usize).div_ceil(8)];
+        if GetTokenInformation(
+            token,
+            TokenPrivileges,
+            buffer.as_mut_ptr() as *mut c_void,
+            size,
+            &mut size,
+        ) == FALSE
+        {
+            return Err(last_error());
+        }
+        let privileges = buffer.as_ptr() as *const TOKEN_PRIVILEGES;
+        let privileges: &[LUID_AND_ATTRIBUTES] = slice::from_raw_parts(
+            (*privileges).Privileges.as_ptr(),
+            (*privileges).PrivilegeCount as usize,
+        );
+        let has_privilege = privileges.iter().any(|privilege| {
+            privilege.Luid.LowPart == backup_privilege.LowPart
+                && privilege.Luid.HighPart == backup_privilege.HighPart
+        });
+        if has_privilege {
+            Ok(())
+        } else {
+            Err(ElevationError::MissingBackupPrivilege)
+        }
+    })();
+    unsafe { CloseHandle(token) };
+    result
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Service availability
+////////////////////////////////////////////////////////////////////////////////
+
+/// The names of the services that must be able to start for VSS to work: the
+/// Volume Shadow Copy service and the Microsoft Software Shadow Copy Provider.
+const VSS_SERVICES: [&str; 2] = ["VSS", "swprv"];
+
+/// Closes a handle to the service control manager or to a service when
+/// dropped.
+struct ServiceHandle(SC_HANDLE);
+impl ServiceHandle {
+    /// Open a service after connecting to the service control manager.
+    fn open(name: &str, access: DWORD) -> io::Result<Self> {
+        let name = U16CString::from_str(name).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "service name contains a nul character",
+            )
+        })?;
+        unsafe {
+            let manager = OpenSCManagerW(ptr::null(), ptr::null(), SC_MANAGER_CONNECT);
+            if manager.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            // The service handle stays valid after the manager is closed.
+            let manager = Self(manager);
+            let service = OpenServiceW(manager.0, name.as_ptr(), access);
+            if service.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(service))
+        }
+    }
+    fn state(&self) -> io::Result<SERVICE_STATUS> {
+        let mut status = SERVICE_STATUS::default();
+        if unsafe { QueryServiceStatus(self.0, &mut status) } == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(status)
+    }
+    fn is_disabled(&self) -> io::Result<bool> {
+        // The documented maximum size of the config is 8 KiB, use `u64` so
+        // that the buffer is correctly aligned:
+        let mut buffer = [0_u64; 1024];
+        let mut needed: DWORD = 0;
+        let config = buffer.as_mut_ptr() as *mut QUERY_SERVICE_CONFIGW;
+        unsafe {
+            if QueryServiceConfigW(
+                self.0,
+                config,
+                std::mem::size_of_val(&buffer) as DWORD,
+                &mut needed,
+            ) == FALSE
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((*config).dwStartType == SERVICE_DISABLED)
+        }
+    }
+}
+impl Drop for ServiceHandle {
+    fn drop(&mut self) {
+        unsafe { CloseServiceHandle(self.0) };
+    }
+}
+
+/// Check if a service has been disabled. Returns `false` if that couldn't be
+/// determined.
+fn is_service_disabled(name: &str) -> bool {
+    matches!(
+        ServiceHandle::open(name, SERVICE_QUERY_CONFIG).and_then(|service| service.is_disabled()),
+        Ok(true)
+    )
+}
+
+/// The current state of a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceState {
+    /// The service isn't running. `VSS` and `swprv` are started on demand
+    /// and stop on their own after a while, so this is their usual state.
+    Stopped,
+    /// The service is starting.
+    StartPending,
+    /// The service is stopping.
+    StopPending,
+    /// The service is running.
+    Running,
+    /// The service is about to continue after being paused.
+    ContinuePending,
+    /// The service is pausing.
+    PausePending,
+    /// The service is paused.
+    Paused,
+    /// A state that isn't documented.
+    Unknown(DWORD),
+}
+impl From<DWORD> for ServiceState {
+    fn from(state: DWORD) -> Self {
+        match state {
+            SERVICE_STOPPED => Self::Stopped,
+            SERVICE_START_PENDING => Self::StartPending,
+            SERVICE_STOP_PENDING => Self::StopPending,
+            SERVICE_RUNNING => Self::Running,
+            SERVICE_CONTINUE_PENDING => Self::ContinuePending,
+            SERVICE_PAUSE_PENDING => Self::PausePending,
+            SERVICE_PAUSED => Self::Paused,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// The status of one of the services that VSS needs, see
+/// [`vss_service_statuses`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceStatus {
+    /// The name of the service, for example `VSS`.
+    pub name: String,
+    /// Whether the service is running.
+    pub state: ServiceState,
+    /// The service is disabled and can't be started until its start type
+    /// is changed.
+    pub disabled: bool,
+}
+impl ServiceStatus {
+    /// Query the status of a service.
+    #[doc(alias = "QueryServiceStatus")]
+    pub fn query(name: &str) -> Result<Self, ServiceError> {
+        let query_error = |error| ServiceError::Query {
+            service: name.to_owned(),
+            error,
+        };
+        let service = ServiceHandle::open(name, SERVICE_QUERY_STATUS | SERVICE_QUERY_CONFIG)
+            .map_err(query_error)?;
+        Ok(Self {
+            name: name.to_owned(),
+            state: service.state().map_err(query_error)?.dwCurrentState.into(),
+            disabled: service.is_disabled().map_err(query_error)?,
+        })
+    }
+}
+
+/// Error returned by [`ServiceStatus::query`] and [`start_vss_services`].
+#[derive(Debug)]
+pub enum ServiceError {
+    /// The service couldn't be opened or queried, for example because it
+    /// doesn't exist or the process isn't allowed to start it.
+    Query {
+        /// The name of the service.
+        service: String,
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// The service is disabled.
+    Disabled {
+        /// The name of the service.
+        service: String,
+    },
+    /// The service failed to start.
+    Start {
+        /// The name of the service.
+        service: String,
+        /// The error returned by `StartService` or the exit code of the
+        /// service.
+        error: io::Error,
+    },
+    /// The service didn't finish starting before the timeout.
+    Timeout {
+        /// The name of the service.
+        service: String,
+        /// The state of the service when the timeout elapsed.
+        state: ServiceState,
+    },
+}
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query { service, error } => {
+                write!(f, "failed to query the \"{}\" service: {}", service, error)
+            }
+            Self::Disabled { service } => write!(f, "the \"{}\" service is disabled", service),
+            Self::Start { service, error } => {
+                write!(f, "failed to start the \"{}\" service: {}", service, error)
+            }
+            Self::Timeout { service, state } => write!(
+                f,
+                "timed out while waiting for the \"{}\" service to start (state: {:?})",
+                service, state
+            ),
+        }
+    }
+}
+impl StdError for ServiceError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Query { error, .. } | Self::Start { error, .. } => Some(error),
+            Self::Disabled { .. } | Self::Timeout { .. } => None,
+        }
+    }
+}
+
+/// Query the status of the `VSS` and `swprv` services, which must be able to
+/// start for VSS to work.
+pub fn vss_service_statuses() -> Result<Vec<ServiceStatus>, ServiceError> {
+    VSS_SERVICES
+        .iter()
+        .map(|name| ServiceStatus::query(name))
+        .collect()
+}
+
+/// Start a service unless it is already running and wait up to `timeout`
+/// for it to finish starting.
+fn start_service(name: &str, timeout: Duration) -> Result<(), ServiceError> {
+    let query_error = |error| ServiceError::Query {
+        service: name.to_owned(),
+        error,
+    };
+    let service = ServiceHandle::open(
+        name,
+        SERVICE_START | SERVICE_QUERY_STATUS | SERVICE_QUERY_CONFIG,
+    )
+    .map_err(query_error)?;
+    if service.is_disabled().map_err(query_error)? {
+        return Err(ServiceError::Disabled {
+            service: name.to_owned(),
+        });
+    }
+    if unsafe { StartServiceW(service.0, 0, ptr::null_mut()) } == FALSE {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(ERROR_SERVICE_ALREADY_RUNNING as i32) {
+            return Err(ServiceError::Start {
+                service: name.to_owned(),
+                error,
+            });
+        }
+    }
+    let deadline = Instant::now() + timeout;
+    loop {
+        let status = service.state().map_err(query_error)?;
+        match status.dwCurrentState.into() {
+            ServiceState::Running => return Ok(()),
+            ServiceState::Stopped => {
+                return Err(ServiceError::Start {
+                    service: name.to_owned(),
+                    error: io::Error::from_raw_os_error(status.dwWin32ExitCode as i32),
+                })
+            }
+            state if Instant::now() >= deadline => {
+                return Err(ServiceError::Timeout {
+                    service: name.to_owned(),
+                    state,
+                })
+            }
+            _ => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+/// Start the `VSS` and `swprv` services and wait for them to run. Requires
+/// administrator privileges.
+///
+/// Both services are started on demand by VSS, so this is mostly useful to
+/// diagnose a [`ServiceUnavailable`] error: a disabled service is reported
+/// as [`ServiceError::Disabled`] instead of a generic error code. The
+/// services stop again on their own once they have been idle for a while.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// for status in volume_shadow_copy::vss_service_statuses()? {
+///     println!("{}: {:?}", status.name, status.state);
+/// }
+/// volume_shadow_copy::start_vss_services(Duration::from_secs(30))?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[doc(alias = "StartService")]
+pub fn start_vss_services(timeout: Duration) -> Result<(), ServiceError> {
+    let deadline = Instant::now() + timeout;
+    for name in VSS_SERVICES.iter() {
+        start_service(name, deadline.saturating_duration_since(Instant::now()))?;
+    }
+    Ok(())
+}
+
+/// The VSS service couldn't be reached, most likely because the `VSS` service
+/// or the `swprv` service is disabled or failed to start.
+///
+/// [`vss_service_statuses`] shows which of them is disabled and
+/// [`start_vss_services`] reports why they don't start.
+#[derive(Debug, Clone, Copy)]
+pub struct ServiceUnavailable {
+    hresult: HRESULT,
+}
+impl ServiceUnavailable {
+    /// Check if an error code that was returned when connecting to VSS was
+    /// caused by the service being unavailable. For generic errors such as
+    /// `VSS_E_UNEXPECTED` this queries the service control manager to see if
+    /// one of the services has been disabled.
+    pub fn check(hresult: HRESULT) -> Option<Self> {
+        let service_error = [
+            ERROR_SERVICE_DISABLED,
+            ERROR_SERVICE_NOT_ACTIVE,
+            RPC_S_SERVER_UNAVAILABLE,
+            RPC_S_CALL_FAILED,
+        ]
+        .iter()
+        .any(|&code| hresult == HRESULT_FROM_WIN32(code));
+        let unavailable = match hresult {
+            CO_E_SERVER_EXEC_FAILURE | CO_E_SERVER_STOPPING | RPC_E_DISCONNECTED => true,
+            VSS_E_UNEXPECTED | E_UNEXPECTED => {
+                VSS_SERVICES.iter().any(|name| is_service_disabled(name))
+            }
+            _ => service_error,
+        };
+        if unavailable {
+            Some(Self { hresult })
+        } else {
+            None
+        }
+    }
+    /// The error code that was returned when connecting to VSS.
+    pub fn hresult(self) -> HRESULT {
+        self.hresult
+    }
+}
+impl fmt::Display for ServiceUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the Volume Shadow Copy service is unavailable (HRESULT: {:#X}), \
+            make sure that the \"VSS\" and \"swprv\" services aren't disabled",
+            self.hresult
+        )
+    }
+}
+impl StdError for ServiceUnavailable {}
+
+/// Error returned when connecting to the VSS service, for example by
+/// [`BackupComponents::new`](vsbackup::BackupComponents::new).
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectError<E> {
+    /// The service couldn't be reached.
+    ServiceUnavailable(ServiceUnavailable),
+    /// The current process is a 32-bit process running on 64-bit Windows.
+    Wow64(Wow64Unsupported),
+    /// Any other error.
+    Other(E),
+}
+impl<E> ConnectError<E>
+where
+    E: Into<HRESULT>,
+{
+    /// The underlying error code. For [`ConnectError::Wow64`] this is
+    /// `ERROR_NOT_SUPPORTED` since no call was made.
+    pub fn hresult(self) -> HRESULT {
+        match self {
+            Self::ServiceUnavailable(e) => e.hresult(),
+            Self::Wow64(_) => HRESULT_FROM_WIN32(ERROR_NOT_SUPPORTED),
+            Self::Other(e) => e.into(),
+        }
+    }
+}
+impl<E> ConnectError<E>
+where
+    E: From<HRESULT>,
+{
+    /// Create an error for a failed connection attempt, using
+    /// [`ServiceUnavailable::check`] to find out if the service is
+    /// unavailable. Unlike the `From` conversion this might query the service
+    /// control manager.
+    pub(crate) fn diagnose(hresult: HRESULT) -> Self {
+        match ServiceUnavailable::check(hresult) {
+            Some(e) => Self::ServiceUnavailable(e),
+            None => Self::Other(hresult.into()),
+        }
+    }
+}
+/// Always creates [`ConnectError::Other`], see [`ServiceUnavailable::check`]
+/// to check if the service is unavailable.
+impl<E> From<HRESULT> for ConnectError<E>
+where
+    E: From<HRESULT>,
+{
+    fn from(hresult: HRESULT) -> Self {
+        Self::Other(hresult.into())
+    }
+}
+impl<E> fmt::Display for ConnectError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ServiceUnavailable(e) => fmt::Display::fmt(e, f),
+            Self::Wow64(e) => fmt::Display::fmt(e, f),
+            Self::Other(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+impl<E> StdError for ConnectError<E>
+where
+    E: StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::ServiceUnavailable(_) | Self::Wow64(_) => None,
+            Self::Other(e) => Some(e),
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Cast interfaces
 ////////////////////////////////////////////////////////////////////////////////
@@ -301,6 +1511,17 @@ mod sealed {
         type Inner: CustomIUnknown + CorrectInterface;
         fn from_safe_com_component(com: SafeCOMComponent<Self::Inner>) -> Self;
     }
+    /// # Safety
+    ///
+    /// `Self` must be a `repr(transparent)` wrapper around `Self::Raw`.
+    pub unsafe trait TransparentWrapperInternal {
+        type Raw;
+    }
+    pub trait ServerObjectInternal {
+        /// Release ownership of the object and return its `IUnknown` interface
+        /// without changing its reference count.
+        fn into_unknown(self) -> *mut winapi::um::unknwnbase::IUnknown;
+    }
 }
 
 /// A type that represents an interface that can be queried from another interface.
@@ -362,6 +1583,92 @@ macro_rules! _impl_query_interface {
 }
 pub(crate) use _impl_query_interface as impl_query_interface;
 
+////////////////////////////////////////////////////////////////////////////////
+// Borrowed interfaces
+////////////////////////////////////////////////////////////////////////////////
+
+/// A safe wrapper type that can be used to borrow a raw interface pointer.
+///
+/// This trait is sealed and can't be implemented.
+pub trait TransparentWrapper: sealed::TransparentWrapperInternal {}
+
+/// A borrowed interface pointer, for example one that was passed as an argument
+/// to a COM callback.
+///
+/// This gives access to the safe wrapper `T` without taking ownership of the
+/// interface, so the interface's reference count is never incremented or
+/// decremented by this type.
+pub struct BorrowedComponent<'a, T: TransparentWrapper> {
+    comp: NonNull<T::Raw>,
+    marker: PhantomData<&'a T>,
+}
+impl<'a, T: TransparentWrapper> BorrowedComponent<'a, T> {
+    /// Borrow a raw interface pointer. Returns `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// - The pointer must point to a valid COM interface of the correct type.
+    /// - The caller must hold a reference to the interface for the whole
+    ///   lifetime `'a`. This is for example the case for interface pointers
+    ///   that are passed as arguments to a COM method for the duration of that
+    ///   call.
+    pub unsafe fn from_raw(comp: *mut T::Raw) -> Option<Self> {
+        Some(Self {
+            comp: NonNull::new(comp)?,
+            marker: PhantomData,
+        })
+    }
+    /// Get the borrowed raw interface pointer.
+    pub fn as_raw(&self) -> *mut T::Raw {
+        self.comp.as_ptr()
+    }
+    /// Queries the borrowed COM object for a pointer to one of its interface.
+    /// The returned interface is owned and keeps the COM object alive even
+    /// after the borrow ends.
+    #[doc(alias = "QueryInterface")]
+    pub fn query<I: Interface>(&self) -> Option<I>
+    where
+        T::Raw: CustomIUnknown,
+    {
+        let iid = <I::Inner as winapi::Interface>::uuidof();
+        let mut interface = std::ptr::null_mut();
+        // Safety: the pointer is valid for as long as this borrow exists and
+        // the queried interface is owned by us if the call succeeds.
+        let hr =
+            unsafe { CustomIUnknown::query_interface(self.comp.as_ref(), &iid, &mut interface) };
+        if hr == S_OK {
+            let comp = unsafe { SafeCOMComponent::new(interface as *mut I::Inner) };
+            Some(I::from_safe_com_component(comp))
+        } else if hr == winapi::shared::winerror::E_NOINTERFACE {
+            None
+        } else {
+            panic!("`QueryInterface` returned unexpected error code: {:#X}", hr);
+        }
+    }
+}
+impl<'a, T: TransparentWrapper> Clone for BorrowedComponent<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<'a, T: TransparentWrapper> Copy for BorrowedComponent<'a, T> {}
+impl<'a, T: TransparentWrapper> Deref for BorrowedComponent<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: `T` is a transparent wrapper around the raw interface and the
+        // pointer is valid for the lifetime `'a`.
+        unsafe { &*(self.comp.as_ptr() as *const T) }
+    }
+}
+impl<'a, T: TransparentWrapper> fmt::Debug for BorrowedComponent<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BorrowedComponent")
+            .field("com_object", &std::any::type_name::<T>())
+            .field("ptr", &self.comp)
+            .finish()
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Helper macros for COM
 ////////////////////////////////////////////////////////////////////////////////
@@ -431,6 +1738,11 @@ macro_rules! _transparent_wrapper {
                 &this.0
             }
         }
+        // Safety: this type is a transparent wrapper around the inner type.
+        unsafe impl$(<$($life),*>)? $crate::sealed::TransparentWrapperInternal for $name$(<$($life),*>)? {
+            type Raw = $inner;
+        }
+        impl$(<$($life),*>)? $crate::TransparentWrapper for $name$(<$($life),*>)? {}
     };
 }
 pub(crate) use _transparent_wrapper as transparent_wrapper;
@@ -525,6 +1837,22 @@ macro_rules! _unsafe_deref_to_ref {
 }
 pub(crate) use _unsafe_deref_to_ref as unsafe_deref_to_ref;
 
+////////////////////////////////////////////////////////////////////////////////
+// Tracing
+////////////////////////////////////////////////////////////////////////////////
+
+/// Measure the rest of the current block as a call to the wrapped COM method
+/// for the [`metrics`] sink and, with the `tracing` feature, enter a span that
+/// is named after the method.
+macro_rules! _trace_com_call {
+    ($method:literal) => {
+        let _operation = $crate::metrics::Operation::start($method);
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("com", method = $method).entered();
+    };
+}
+pub(crate) use _trace_com_call as trace_com_call;
+
 ////////////////////////////////////////////////////////////////////////////////
 // Raw bitflags
 ////////////////////////////////////////////////////////////////////////////////
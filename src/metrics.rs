@@ -0,0 +1,265 @@
+//! Report how long wrapped COM methods take.
+//!
+//! Install a [`MetricsSink`] with [`set_metrics_sink`] to be told about every
+//! call to a wrapped COM method, for example to export latencies to
+//! Prometheus or ETW. Without a sink the wrappers only check whether one has
+//! been installed.
+//!
+//! Asynchronous methods such as `DoSnapshotSet` return as soon as the
+//! operation has started. The time spent waiting for them is reported for the
+//! `Wait` and `QueryStatus` methods of [`VssAsync`](crate::vss::VssAsync),
+//! and the high-level [`session`](crate::session) module records how long
+//! each step took in [`JobDurations`](crate::session::JobDurations).
+//!
+//! To collect error codes from all modules in one place install a hook with
+//! [`set_hresult_hook`] instead.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//! use volume_shadow_copy::metrics;
+//!
+//! metrics::set_metrics_sink(|name: &'static str, duration: Duration, hresult: i32| {
+//!     if hresult < 0 {
+//!         eprintln!("{} failed after {:?} (HRESULT: {:#X})", name, duration, hresult);
+//!     }
+//! })
+//! .expect("no other sink was installed");
+//! ```
+
+use std::{
+    cell::Cell,
+    error::Error as StdError,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::OnceCell;
+use winapi::{shared::winerror::S_OK, um::winnt::HRESULT};
+
+/// Receives the duration of every call to a wrapped COM method, see
+/// [`set_metrics_sink`].
+///
+/// Implemented for closures that take the same arguments as
+/// [`on_operation_complete`](Self::on_operation_complete).
+pub trait MetricsSink: Send + Sync {
+    /// Called when a wrapped COM method returns. `name` is the name of the
+    /// COM method, for example `"AddToSnapshotSet"`, and `hresult` is the
+    /// last error code that the wrapper checked, `S_OK` if it succeeded.
+    ///
+    /// This is called on the thread that made the call, so it should return
+    /// quickly.
+    fn on_operation_complete(&self, name: &'static str, duration: Duration, hresult: HRESULT);
+}
+impl<F> MetricsSink for F
+where
+    F: Fn(&'static str, Duration, HRESULT) + Send + Sync,
+{
+    fn on_operation_complete(&self, name: &'static str, duration: Duration, hresult: HRESULT) {
+        self(name, duration, hresult)
+    }
+}
+
+/// Error returned by [`set_metrics_sink`] and [`set_hresult_hook`] if they
+/// were already called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlreadySet;
+impl fmt::Display for AlreadySet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a metrics sink or hook has already been installed")
+    }
+}
+impl StdError for AlreadySet {}
+
+/// A COM call that returned something other than `S_OK`, passed to the hook
+/// installed with [`set_hresult_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HResultEvent {
+    /// The wrapped COM method that made the call, for example
+    /// `"PrepareForBackup"`. `None` for calls that the crate makes outside of
+    /// a wrapper.
+    pub method: Option<&'static str>,
+    /// The returned code. This can also be a success code such as `S_FALSE`
+    /// that the wrapper handles.
+    pub hresult: HRESULT,
+}
+
+type HResultHook = Box<dyn Fn(HResultEvent) + Send + Sync>;
+
+static SINK: OnceCell<Box<dyn MetricsSink>> = OnceCell::new();
+static HRESULT_HOOK: OnceCell<HResultHook> = OnceCell::new();
+
+thread_local! {
+    /// The last `HRESULT` checked by a wrapper on this thread.
+    static LAST_HRESULT: Cell<HRESULT> = const { Cell::new(S_OK) };
+    /// The wrapped COM method that is running on this thread.
+    static CURRENT_METHOD: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Install the sink that is told about every call to a wrapped COM method.
+///
+/// Like a global logger the sink can only be installed once and is never
+/// dropped.
+pub fn set_metrics_sink(sink: impl MetricsSink + 'static) -> Result<(), AlreadySet> {
+    SINK.set(Box::new(sink)).map_err(|_| AlreadySet)
+}
+
+/// Install a hook that is called whenever a COM call made by this crate
+/// returns something other than `S_OK`, for centralized error telemetry.
+///
+/// The hook is called on the thread that made the call before the wrapper
+/// returns, so it should return quickly and must not call into this crate.
+/// Like [`set_metrics_sink`] it can only be installed once.
+///
+/// # Examples
+///
+/// ```
+/// volume_shadow_copy::metrics::set_hresult_hook(|event| {
+///     eprintln!(
+///         "{} returned {:#X}",
+///         event.method.unwrap_or("COM call"),
+///         event.hresult
+///     );
+/// })
+/// .expect("no other hook was installed");
+/// ```
+pub fn set_hresult_hook(
+    hook: impl Fn(HResultEvent) + Send + Sync + 'static,
+) -> Result<(), AlreadySet> {
+    HRESULT_HOOK.set(Box::new(hook)).map_err(|_| AlreadySet)
+}
+
+/// Remember the result of a COM call for the [`Operation`] that made it and
+/// pass it to the hook.
+pub(crate) fn record_hresult(hr: HRESULT) {
+    if SINK.get().is_some() {
+        LAST_HRESULT.with(|last| last.set(hr));
+    }
+    if hr != S_OK {
+        if let Some(hook) = HRESULT_HOOK.get() {
+            hook(HResultEvent {
+                method: CURRENT_METHOD.with(Cell::get),
+                hresult: hr,
+            });
+        }
+    }
+}
+
+/// Measures a call to a wrapped COM method and reports it to the sink when
+/// dropped. Created by the `trace_com_call` macro.
+pub(crate) struct Operation {
+    name: &'static str,
+    /// `None` if no sink is installed.
+    started: Option<Instant>,
+    /// The method that was running before this one, restored when this one
+    /// returns. `None` if no hook is installed.
+    outer_method: Option<Option<&'static str>>,
+}
+impl Operation {
+    pub(crate) fn start(name: &'static str) -> Self {
+        let started = SINK.get().map(|_| {
+            LAST_HRESULT.with(|last| last.set(S_OK));
+            Instant::now()
+        });
+        let outer_method = HRESULT_HOOK
+            .get()
+            .map(|_| CURRENT_METHOD.with(|method| method.replace(Some(name))));
+        Self {
+            name,
+            started,
+            outer_method,
+        }
+    }
+}
+impl Drop for Operation {
+    fn drop(&mut self) {
+        if let Some(outer_method) = self.outer_method {
+            CURRENT_METHOD.with(|method| method.set(outer_method));
+        }
+        if let (Some(started), Some(sink)) = (self.started, SINK.get()) {
+            let hresult = LAST_HRESULT.with(Cell::get);
+            sink.on_operation_complete(self.name, started.elapsed(), hresult);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use winapi::shared::winerror::E_FAIL;
+
+    use super::*;
+
+    #[test]
+    fn operations_are_reported_with_their_hresult() {
+        static CALLS: Mutex<Vec<(&'static str, HRESULT)>> = Mutex::new(Vec::new());
+        set_metrics_sink(|name: &'static str, _: Duration, hresult: HRESULT| {
+            CALLS.lock().unwrap().push((name, hresult));
+        })
+        .unwrap();
+
+        {
+            let _operation = Operation::start("MetricsTestSucceeded");
+            let _ = crate::check_com(S_OK);
+        }
+        {
+            let _operation = Operation::start("MetricsTestFailed");
+            let _ = crate::check_com(E_FAIL);
+        }
+
+        let calls = CALLS.lock().unwrap();
+        let calls = calls
+            .iter()
+            .filter(|(name, _)| name.starts_with("MetricsTest"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            [
+                &("MetricsTestSucceeded", S_OK),
+                &("MetricsTestFailed", E_FAIL)
+            ]
+        );
+        assert_eq!(
+            set_metrics_sink(|_: &'static str, _: Duration, _: HRESULT| {}),
+            Err(AlreadySet)
+        );
+    }
+
+    #[test]
+    fn hook_sees_failures_with_their_method() {
+        static EVENTS: Mutex<Vec<HResultEvent>> = Mutex::new(Vec::new());
+        set_hresult_hook(|event| EVENTS.lock().unwrap().push(event)).unwrap();
+
+        {
+            let _outer = Operation::start("HookTestOuter");
+            {
+                let _inner = Operation::start("HookTestInner");
+                let _ = crate::check_com(S_OK);
+                let _ = crate::check_com(E_FAIL);
+            }
+            let _ = crate::check_com(E_FAIL);
+        }
+
+        let events = EVENTS.lock().unwrap();
+        let events = events
+            .iter()
+            .filter(|event| matches!(event.method, Some(method) if method.starts_with("HookTest")))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                HResultEvent {
+                    method: Some("HookTestInner"),
+                    hresult: E_FAIL,
+                },
+                HResultEvent {
+                    method: Some("HookTestOuter"),
+                    hresult: E_FAIL,
+                },
+            ]
+        );
+    }
+}
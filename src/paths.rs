@@ -0,0 +1,826 @@
+//! Utilities for working with file paths on volumes and shadow copies.
+//!
+//! Paths that are reported by VSS writers or that are read from a shadow copy
+//! can differ in case from paths supplied by a user. NTFS compares file names
+//! case-insensitively using a fixed uppercase table and not the rules of the
+//! current locale, so all comparisons in this module are ordinal comparisons
+//! that ignore case in the same way. Short (8.3) file names such as
+//! `PROGRA~1` can't be compared to their long form without touching the file
+//! system, use [`expand_short_names`] first if that might be an issue.
+
+use std::{
+    cmp::Ordering,
+    convert::TryInto,
+    error::Error as StdError,
+    ffi::OsString,
+    fmt, io,
+    os::windows::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use widestring::{U16CStr, U16CString};
+use winapi::{
+    shared::minwindef::TRUE,
+    um::{
+        fileapi::{GetLongPathNameW, GetVolumeNameForVolumeMountPointW, GetVolumePathNameW},
+        processenv::ExpandEnvironmentStringsW,
+        stringapiset::CompareStringOrdinal,
+        winnls::LCMapStringEx,
+    },
+};
+
+use crate::{
+    bstr_as_u16_slice,
+    errors::*,
+    vsbackup::{
+        ExamineWriterMetadataEx2, IBackupComponents, IExamineWriterMetadata,
+        IExamineWriterMetadataEx2,
+    },
+    vss::SnapshotProperties,
+    vswriter::IWMFileDescriptor,
+    QueryInterface,
+};
+
+// Return values of `CompareStringOrdinal`, these are missing from `winapi`.
+const CSTR_LESS_THAN: i32 = 1;
+const CSTR_EQUAL: i32 = 2;
+const CSTR_GREATER_THAN: i32 = 3;
+// Flag for `LCMapStringEx`, also missing from `winapi`.
+const LCMAP_UPPERCASE: u32 = 0x0000_0200;
+
+pub(crate) fn is_separator(c: u16) -> bool {
+    c == u16::from(b'\\') || c == u16::from(b'/')
+}
+
+/// Compare two UTF-16 strings ordinally while ignoring case, the same way that
+/// NTFS compares file names.
+///
+/// `\` and `/` are considered equal.
+pub fn cmp_ignore_case(a: &[u16], b: &[u16]) -> Ordering {
+    // Compare one path segment at a time so that separators compare equal to
+    // each other and sort before any other character.
+    let mut a_parts = a.split(|&c| is_separator(c));
+    let mut b_parts = b.split(|&c| is_separator(c));
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match cmp_segment_ignore_case(a, b) {
+                Ordering::Equal => {}
+                other => return other,
+            },
+        }
+    }
+}
+
+fn cmp_segment_ignore_case(a: &[u16], b: &[u16]) -> Ordering {
+    // Fast path that doesn't need to call into the OS:
+    if a == b {
+        return Ordering::Equal;
+    }
+    let result = unsafe {
+        CompareStringOrdinal(
+            a.as_ptr(),
+            a.len().try_into().expect("path segment is too long"),
+            b.as_ptr(),
+            b.len().try_into().expect("path segment is too long"),
+            TRUE,
+        )
+    };
+    match result {
+        CSTR_LESS_THAN => Ordering::Less,
+        CSTR_EQUAL => Ordering::Equal,
+        CSTR_GREATER_THAN => Ordering::Greater,
+        _ => unreachable!("CompareStringOrdinal failed for valid arguments"),
+    }
+}
+
+/// Check if two paths are equal while ignoring case. See [`cmp_ignore_case`].
+pub fn eq_ignore_case(a: &[u16], b: &[u16]) -> bool {
+    a.len() == b.len() && cmp_ignore_case(a, b) == Ordering::Equal
+}
+
+/// If `prefix` is a parent folder of `path` (or the same path) then return the
+/// rest of `path` after the prefix, without a leading separator. Case is
+/// ignored as described by [`cmp_ignore_case`].
+///
+/// The prefix must end at a path component boundary, so `C:\Data` is a prefix
+/// of `c:\data\file.txt` but not of `C:\Database`. A trailing separator in the
+/// prefix is allowed.
+pub fn strip_prefix_ignore_case<'a>(path: &'a [u16], prefix: &[u16]) -> Option<&'a [u16]> {
+    if path.len() < prefix.len() || !eq_ignore_case(&path[..prefix.len()], prefix) {
+        return None;
+    }
+    let rest = &path[prefix.len()..];
+    if prefix.is_empty() || is_separator(prefix[prefix.len() - 1]) {
+        // Already at a component boundary:
+        return Some(rest);
+    }
+    match rest.split_first() {
+        None => Some(rest),
+        Some((&first, rest)) if is_separator(first) => Some(rest),
+        Some(_) => None,
+    }
+}
+
+/// Check if `path` is inside the folder `prefix` or is the same path. See
+/// [`strip_prefix_ignore_case`].
+pub fn starts_with_ignore_case(path: &[u16], prefix: &[u16]) -> bool {
+    strip_prefix_ignore_case(path, prefix).is_some()
+}
+
+/// Convert any short (8.3) file names in a path to their long form, for
+/// example `C:\PROGRA~1` to `C:\Program Files`. This allows the path to be
+/// compared with [`eq_ignore_case`] and friends.
+///
+/// The path must exist.
+#[doc(alias = "GetLongPathNameW")]
+pub fn expand_short_names(path: &U16CStr) -> io::Result<U16CString> {
+    let mut buffer: Vec<u16> = Vec::new();
+    loop {
+        let len = unsafe {
+            GetLongPathNameW(
+                path.as_ptr(),
+                if buffer.is_empty() {
+                    ptr::null_mut()
+                } else {
+                    buffer.as_mut_ptr()
+                },
+                buffer.len().try_into().unwrap_or(u32::MAX),
+            )
+        } as usize;
+        if len == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if len < buffer.len() {
+            // Success: the returned length doesn't include the nul terminator.
+            buffer.truncate(len);
+            return Ok(U16CString::new(buffer).expect("path shouldn't contain nul characters"));
+        }
+        // Buffer too small: the returned length includes the nul terminator.
+        buffer.resize(len, 0);
+    }
+}
+
+/// Get the mount point of the volume that contains `path`, for example `C:\`
+/// for `C:\Users\name\file.txt` or `C:\Mount\Data\` for a file on a volume
+/// that is mounted at that folder. The returned path always ends with a
+/// backslash.
+///
+/// The path doesn't need to exist.
+#[doc(alias = "GetVolumePathNameW")]
+pub fn volume_path_name(path: &U16CStr) -> io::Result<U16CString> {
+    // The mount point is never longer than the path plus a trailing backslash.
+    let mut buffer = vec![0_u16; path.len() + 2];
+    let ok = unsafe {
+        GetVolumePathNameW(
+            path.as_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len().try_into().unwrap_or(u32::MAX),
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(U16CString::from_vec_with_nul(buffer).expect("the volume path is nul terminated"))
+}
+
+/// Get the unique `\\?\Volume{GUID}\` name of the volume that is mounted at
+/// `mount_point`, which must end with a backslash.
+#[doc(alias = "GetVolumeNameForVolumeMountPointW")]
+fn volume_name_for_mount_point(mount_point: &U16CStr) -> io::Result<U16CString> {
+    // Volume GUID paths are always 49 characters long including the nul
+    // terminator.
+    let mut buffer = vec![0_u16; 50];
+    let ok = unsafe {
+        GetVolumeNameForVolumeMountPointW(
+            mount_point.as_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len() as u32,
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(U16CString::from_vec_with_nul(buffer).expect("the volume name is nul terminated"))
+}
+
+/// Remove the `\\?\` prefix from a path that starts with a drive letter, so
+/// `\\?\C:\data` becomes `C:\data`. Other paths are returned unchanged.
+fn strip_verbatim_disk_prefix(path: &[u16]) -> &[u16] {
+    const VERBATIM: &[u16] = &[b'\\' as u16, b'\\' as u16, b'?' as u16, b'\\' as u16];
+    match path.strip_prefix(VERBATIM) {
+        Some(rest @ [drive, colon, ..])
+            if *drive < 0x80
+                && (*drive as u8).is_ascii_alphabetic()
+                && *colon == u16::from(b':') =>
+        {
+            rest
+        }
+        _ => path,
+    }
+}
+
+/// Check if a path doesn't depend on the current directory. `C:data` is
+/// relative to the current directory of the `C:` drive and `\data` is
+/// relative to the current drive.
+fn is_fully_qualified(path: &[u16]) -> bool {
+    match path {
+        [first, second, ..] if is_separator(*first) && is_separator(*second) => true,
+        [_, colon, separator, ..] => *colon == u16::from(b':') && is_separator(*separator),
+        _ => false,
+    }
+}
+
+/// Get the path of a file or folder inside a shadow copy, for example
+/// `\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopy3\data\file.txt` for
+/// `C:\data\file.txt` in a shadow copy of `C:\`.
+///
+/// The path must be fully qualified and can use a `\\?\` prefix, a volume
+/// GUID path or a folder that another volume is mounted at. A trailing
+/// separator is preserved, so the root of the volume is mapped to the root of
+/// the shadow copy and not to its device. The volume that contains the path
+/// is looked up in the same way as [`volume_path_name`], so the path doesn't
+/// need to exist. Use [`SnapshotProperties::to_wide_path`] instead if the path
+/// is already relative to the root of the volume.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use volume_shadow_copy::{paths, vss::SnapshotProperties};
+/// # fn f(snapshot: &SnapshotProperties) -> Result<(), paths::MapToSnapshotError> {
+/// let path = paths::map_to_snapshot(r"C:\data\file.txt".as_ref(), snapshot)?;
+/// let contents = std::fs::read(path);
+/// # Ok(())
+/// # }
+/// ```
+pub fn map_to_snapshot(
+    original: &Path,
+    snapshot: &SnapshotProperties,
+) -> Result<PathBuf, MapToSnapshotError> {
+    let path = original.as_os_str().encode_wide().collect::<Vec<_>>();
+    let path = strip_verbatim_disk_prefix(&path);
+    if !is_fully_qualified(path) {
+        return Err(MapToSnapshotError::NotFullyQualified);
+    }
+    let c_path = U16CString::new(path).map_err(|_| MapToSnapshotError::Nul)?;
+    let mount_point = volume_path_name(&c_path).map_err(MapToSnapshotError::Volume)?;
+
+    // The original volume name is usually a volume GUID path but is the
+    // share path for shadow copies of file shares.
+    let original_volume = snapshot.original_volume_name().as_slice();
+    if !eq_ignore_case(mount_point.as_slice(), original_volume) {
+        let volume_name =
+            volume_name_for_mount_point(&mount_point).map_err(MapToSnapshotError::Volume)?;
+        if !eq_ignore_case(volume_name.as_slice(), original_volume) {
+            return Err(MapToSnapshotError::OtherVolume {
+                volume: PathBuf::from(OsString::from_wide(mount_point.as_slice())),
+            });
+        }
+    }
+
+    let relative_path =
+        strip_prefix_ignore_case(path, mount_point.as_slice()).ok_or_else(|| {
+            // Can happen if the path contains `..` components.
+            MapToSnapshotError::Volume(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the path doesn't start with the mount point of its volume",
+            ))
+        })?;
+    let mut mapped = snapshot
+        .to_wide_path(relative_path)
+        .expect("the path was checked for nul characters")
+        .into_vec();
+    if matches!(path.last(), Some(&c) if is_separator(c))
+        && !matches!(mapped.last(), Some(&c) if is_separator(c))
+    {
+        mapped.push(u16::from(b'\\'));
+    }
+    Ok(PathBuf::from(OsString::from_wide(&mapped)))
+}
+
+/// Error returned by [`map_to_snapshot`].
+#[derive(Debug)]
+pub enum MapToSnapshotError {
+    /// The path depends on the current directory, for example `data\file.txt`
+    /// or `C:file.txt`.
+    NotFullyQualified,
+    /// The path contains a nul character.
+    Nul,
+    /// Failed to find the volume that contains the path.
+    Volume(io::Error),
+    /// The path is on a different volume than the one the shadow copy was
+    /// created for.
+    OtherVolume {
+        /// The mount point of the volume that contains the path.
+        volume: PathBuf,
+    },
+}
+impl fmt::Display for MapToSnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFullyQualified => write!(f, "the path is not fully qualified"),
+            Self::Nul => write!(f, "the path contains a nul character"),
+            Self::Volume(e) => write!(f, "failed to find the volume of the path: {}", e),
+            Self::OtherVolume { volume } => write!(
+                f,
+                "the path is on the volume {} which is not in the shadow copy",
+                volume.display()
+            ),
+        }
+    }
+}
+impl StdError for MapToSnapshotError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Volume(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Expand environment variables such as `%SystemRoot%` in a path, the way
+/// that VSS does for the paths reported by writers.
+#[doc(alias = "ExpandEnvironmentStringsW")]
+fn expand_environment_strings(path: &[u16]) -> io::Result<Vec<u16>> {
+    if !path.contains(&u16::from(b'%')) {
+        return Ok(path.to_vec());
+    }
+    let path = U16CString::new(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut buffer: Vec<u16> = Vec::new();
+    loop {
+        let len = unsafe {
+            ExpandEnvironmentStringsW(
+                path.as_ptr(),
+                if buffer.is_empty() {
+                    ptr::null_mut()
+                } else {
+                    buffer.as_mut_ptr()
+                },
+                buffer.len().try_into().unwrap_or(u32::MAX),
+            )
+        } as usize;
+        if len == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if len <= buffer.len() {
+            // Success: the returned length includes the nul terminator.
+            buffer.truncate(len - 1);
+            return Ok(buffer);
+        }
+        buffer.resize(len, 0);
+    }
+}
+
+/// Check if a file name matches a file specification, ignoring case as
+/// described by [`cmp_ignore_case`].
+///
+/// The file specification can contain the wildcards `*`, which matches any
+/// number of characters, and `?`, which matches a single character. Like
+/// `FindFirstFile` a specification that ends in `.*` also matches names
+/// without an extension, so `*.*` matches every file.
+pub fn matches_file_specification(name: &[u16], file_specification: &[u16]) -> bool {
+    matches_uppercase_specification(&to_uppercase(name), &to_uppercase(file_specification))
+}
+
+/// Map `s` to upper case with the invariant locale, which is how
+/// `CompareStringOrdinal` ignores case.
+fn to_uppercase(s: &[u16]) -> Vec<u16> {
+    let mut upper = vec![0; s.len()];
+    let len = match s.len().try_into() {
+        Ok(len) if len > 0 => len,
+        // Empty strings can't be mapped and very long ones aren't file names.
+        _ => return s.to_vec(),
+    };
+    let written = unsafe {
+        LCMapStringEx(
+            // `LOCALE_NAME_INVARIANT`:
+            [0].as_ptr(),
+            LCMAP_UPPERCASE,
+            s.as_ptr(),
+            len,
+            upper.as_mut_ptr(),
+            len,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if written != len {
+        // Upper casing never changes the length of UTF-16 text, so this only
+        // happens if the call failed.
+        return s.to_vec();
+    }
+    upper
+}
+
+/// [`matches_file_specification`] for a name and specification that have
+/// already been mapped to upper case.
+fn matches_uppercase_specification(name: &[u16], file_specification: &[u16]) -> bool {
+    const STAR: u16 = b'*' as u16;
+    const QUESTION: u16 = b'?' as u16;
+    const DOT: u16 = b'.' as u16;
+
+    if let [rest @ .., DOT, STAR] = file_specification {
+        if matches_uppercase_specification(name, rest) {
+            return true;
+        }
+    }
+    // Greedy matching that backtracks to the most recent `*`.
+    let (mut n, mut s) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match file_specification.get(s) {
+            Some(&STAR) => {
+                s += 1;
+                backtrack = Some((n, s));
+            }
+            Some(&QUESTION) => {
+                n += 1;
+                s += 1;
+            }
+            Some(&c) if c == name[n] => {
+                n += 1;
+                s += 1;
+            }
+            _ => match backtrack {
+                Some((star_n, star_s)) => {
+                    n = star_n + 1;
+                    s = star_s;
+                    backtrack = Some((n, s));
+                }
+                None => return false,
+            },
+        }
+    }
+    file_specification[s..].iter().all(|&c| c == STAR)
+}
+
+/// A set of files described by a directory, a file specification and whether
+/// subdirectories are included, like the file sets that writers report
+/// through a [`WMFileDescriptor`].
+///
+/// [`WMFileDescriptor`]: crate::vswriter::WMFileDescriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSet {
+    path: Vec<u16>,
+    file_specification: Vec<u16>,
+    recursive: bool,
+}
+impl FileSet {
+    /// Create a file set. Trailing separators are removed from `path`.
+    pub fn new(path: &[u16], file_specification: &[u16], recursive: bool) -> Self {
+        let mut path = path.to_vec();
+        while matches!(path.last(), Some(&c) if is_separator(c)) {
+            path.pop();
+        }
+        Self {
+            path,
+            file_specification: file_specification.to_vec(),
+            recursive,
+        }
+    }
+    /// Read a file set from a file descriptor in a writer's metadata.
+    /// Environment variables in the path are expanded.
+    pub fn from_descriptor(descriptor: &IWMFileDescriptor) -> Result<Self, FileSetError> {
+        let path = descriptor.get_path().map_err(FileSetError::GetPath)?;
+        let path = expand_environment_strings(bstr_as_u16_slice(&path))
+            .map_err(FileSetError::ExpandPath)?;
+        let file_specification = descriptor
+            .get_file_specification()
+            .map_err(FileSetError::GetFileSpecification)?;
+        let recursive = descriptor
+            .get_recursive()
+            .map_err(FileSetError::GetRecursive)?;
+        Ok(Self::new(
+            &path,
+            bstr_as_u16_slice(&file_specification),
+            recursive,
+        ))
+    }
+    /// The directory that contains the files, without a trailing separator.
+    pub fn path(&self) -> &[u16] {
+        &self.path
+    }
+    /// The file name or wildcard pattern of the files.
+    pub fn file_specification(&self) -> &[u16] {
+        &self.file_specification
+    }
+    /// Whether files in subdirectories of [`FileSet::path`] are included.
+    pub fn recursive(&self) -> bool {
+        self.recursive
+    }
+    /// Check if the file at `path` belongs to this file set.
+    ///
+    /// `path` must be a full path in the same form as the file set's path,
+    /// for example both starting with a drive letter. Short (8.3) names
+    /// aren't expanded, see [`expand_short_names`].
+    pub fn contains(&self, path: &[u16]) -> bool {
+        let name_start = match path.iter().rposition(|&c| is_separator(c)) {
+            Some(index) => index,
+            None => return false,
+        };
+        let (parent, name) = (&path[..name_start], &path[name_start + 1..]);
+        let in_directory = if self.recursive {
+            starts_with_ignore_case(parent, &self.path)
+        } else {
+            eq_ignore_case(parent, &self.path)
+        };
+        in_directory && matches_file_specification(name, &self.file_specification)
+    }
+}
+
+/// Error returned by [`FileSet::from_descriptor`].
+#[derive(Debug)]
+pub enum FileSetError {
+    /// Failed to read the path of the file set.
+    GetPath(GetPathError),
+    /// Failed to expand environment variables in the path of the file set.
+    ExpandPath(io::Error),
+    /// Failed to read the file specification of the file set.
+    GetFileSpecification(GetFilespecError),
+    /// Failed to read whether the file set is recursive.
+    GetRecursive(GetRecursiveError),
+}
+impl fmt::Display for FileSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GetPath(e) => write!(f, "failed to get file set path: {}", e),
+            Self::ExpandPath(e) => write!(f, "failed to expand file set path: {}", e),
+            Self::GetFileSpecification(e) => {
+                write!(f, "failed to get file set specification: {}", e)
+            }
+            Self::GetRecursive(e) => write!(f, "failed to get file set recursion: {}", e),
+        }
+    }
+}
+impl StdError for FileSetError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::GetPath(e) => Some(e),
+            Self::ExpandPath(e) => Some(e),
+            Self::GetFileSpecification(e) => Some(e),
+            Self::GetRecursive(e) => Some(e),
+        }
+    }
+}
+
+/// The files that writers have excluded from the backup with
+/// `AddExcludeFiles` or from the shadow copy itself with
+/// `AddExcludeFilesFromSnapshot`.
+///
+/// A requester that builds its own list of files to copy from a shadow copy
+/// should remove the excluded files, for example temporary files or caches
+/// that the writer knows are useless after a restore. Tools that back up a
+/// whole volume image can use [`ExcludeRules::from_snapshot_exclusions`] to
+/// skip data whose content in the shadow copy is undefined.
+///
+/// # Examples
+///
+/// ```no_run
+/// use volume_shadow_copy::{paths::ExcludeRules, vsbackup::BackupComponents};
+/// use widestring::U16String;
+///
+/// # fn f(backup_comp: &BackupComponents) -> Result<(), Box<dyn std::error::Error>> {
+/// // After `gather_writer_metadata` has completed:
+/// let rules = ExcludeRules::from_backup_components(backup_comp)?;
+/// let candidates = vec![
+///     U16String::from_str("C:\\Data\\db.mdf"),
+///     U16String::from_str("C:\\Data\\db.tmp"),
+/// ];
+/// for file in rules.filter(candidates) {
+///     println!("{}", file.to_string_lossy());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeRules {
+    file_sets: Vec<FileSet>,
+}
+impl ExcludeRules {
+    /// Create rules that don't exclude any files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Collect the excluded files of every writer whose metadata was gathered
+    /// by [`IBackupComponents::gather_writer_metadata`].
+    ///
+    /// [`IBackupComponents::gather_writer_metadata`]: crate::vsbackup::IBackupComponents::gather_writer_metadata
+    pub fn from_backup_components(
+        backup_comp: &IBackupComponents,
+    ) -> Result<Self, ExcludeRulesError> {
+        let mut rules = Self::new();
+        for metadata in backup_comp
+            .writer_metadata_iter()
+            .map_err(ExcludeRulesError::GetWriterMetadataCount)?
+        {
+            let (_, metadata) = metadata.map_err(ExcludeRulesError::GetWriterMetadata)?;
+            rules.add_writer(&metadata)?;
+        }
+        Ok(rules)
+    }
+    /// Collect the file sets that every writer whose metadata was gathered by
+    /// [`IBackupComponents::gather_writer_metadata`] has excluded from the
+    /// shadow copy. Writers on systems that don't support excluding files
+    /// from shadow copies are skipped.
+    ///
+    /// [`IBackupComponents::gather_writer_metadata`]: crate::vsbackup::IBackupComponents::gather_writer_metadata
+    pub fn from_snapshot_exclusions(
+        backup_comp: &IBackupComponents,
+    ) -> Result<Self, ExcludeRulesError> {
+        let mut rules = Self::new();
+        for metadata in backup_comp
+            .writer_metadata_iter()
+            .map_err(ExcludeRulesError::GetWriterMetadataCount)?
+        {
+            let (_, metadata) = metadata.map_err(ExcludeRulesError::GetWriterMetadata)?;
+            if let Some(metadata) = metadata.query::<ExamineWriterMetadataEx2>() {
+                rules.add_writer_snapshot_exclusions(&metadata)?;
+            }
+        }
+        Ok(rules)
+    }
+    /// Add the excluded files of a single writer.
+    #[doc(alias = "GetExcludeFile")]
+    pub fn add_writer(
+        &mut self,
+        metadata: &IExamineWriterMetadata,
+    ) -> Result<(), ExcludeRulesError> {
+        let counts = metadata
+            .get_file_counts()
+            .map_err(ExcludeRulesError::GetFileCounts)?;
+        for file_index in 0..counts.excluded_files {
+            let descriptor = metadata
+                .get_exclude_file(file_index)
+                .map_err(ExcludeRulesError::GetExcludeFile)?;
+            self.add(FileSet::from_descriptor(&descriptor).map_err(ExcludeRulesError::FileSet)?);
+        }
+        Ok(())
+    }
+    /// Add the file sets that a single writer has excluded from the shadow
+    /// copy.
+    #[doc(alias = "GetExcludeFromSnapshotFile")]
+    pub fn add_writer_snapshot_exclusions(
+        &mut self,
+        metadata: &IExamineWriterMetadataEx2,
+    ) -> Result<(), ExcludeRulesError> {
+        let count = metadata
+            .get_exclude_from_snapshot_count()
+            .map_err(ExcludeRulesError::GetExcludeFromSnapshotCount)?;
+        for file_index in 0..count {
+            let descriptor = metadata
+                .get_exclude_from_snapshot_file(file_index)
+                .map_err(ExcludeRulesError::GetExcludeFromSnapshotFile)?;
+            self.add(FileSet::from_descriptor(&descriptor).map_err(ExcludeRulesError::FileSet)?);
+        }
+        Ok(())
+    }
+    /// Exclude another file set.
+    pub fn add(&mut self, file_set: FileSet) {
+        self.file_sets.push(file_set);
+    }
+    /// The excluded file sets.
+    pub fn file_sets(&self) -> &[FileSet] {
+        &self.file_sets
+    }
+    /// Check if the file at `path` is excluded. See [`FileSet::contains`] for
+    /// the requirements on `path`.
+    pub fn is_excluded(&self, path: &[u16]) -> bool {
+        self.file_sets.iter().any(|set| set.contains(path))
+    }
+    /// Remove the excluded files from a list of candidate files.
+    pub fn filter<'a, I>(&'a self, files: I) -> impl Iterator<Item = I::Item> + 'a
+    where
+        I: IntoIterator,
+        I::IntoIter: 'a,
+        I::Item: AsRef<[u16]>,
+    {
+        files
+            .into_iter()
+            .filter(move |file| !self.is_excluded(file.as_ref()))
+    }
+}
+
+/// Error returned when collecting [`ExcludeRules`] from writer metadata.
+#[derive(Debug)]
+pub enum ExcludeRulesError {
+    /// Failed to get the number of writers.
+    GetWriterMetadataCount(GetWriterMetadataCountError),
+    /// Failed to get the metadata of a writer.
+    GetWriterMetadata(GetWriterMetadataError),
+    /// Failed to get the number of excluded file sets of a writer.
+    GetFileCounts(GetFileCountsError),
+    /// Failed to get an excluded file set.
+    GetExcludeFile(GetExcludeFileError),
+    /// Failed to get the number of file sets excluded from the shadow copy.
+    GetExcludeFromSnapshotCount(GetExcludeFromSnapshotCountError),
+    /// Failed to get a file set excluded from the shadow copy.
+    GetExcludeFromSnapshotFile(GetExcludeFromSnapshotFileError),
+    /// Failed to read an excluded file set.
+    FileSet(FileSetError),
+}
+impl fmt::Display for ExcludeRulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GetWriterMetadataCount(e) => write!(f, "failed to count writers: {}", e),
+            Self::GetWriterMetadata(e) => write!(f, "failed to get writer metadata: {}", e),
+            Self::GetFileCounts(e) => write!(f, "failed to count excluded files: {}", e),
+            Self::GetExcludeFile(e) => write!(f, "failed to get excluded file: {}", e),
+            Self::GetExcludeFromSnapshotCount(e) => {
+                write!(f, "failed to count files excluded from snapshot: {}", e)
+            }
+            Self::GetExcludeFromSnapshotFile(e) => {
+                write!(f, "failed to get file excluded from snapshot: {}", e)
+            }
+            Self::FileSet(e) => write!(f, "failed to read excluded file: {}", e),
+        }
+    }
+}
+impl StdError for ExcludeRulesError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::GetWriterMetadataCount(e) => Some(e),
+            Self::GetWriterMetadata(e) => Some(e),
+            Self::GetFileCounts(e) => Some(e),
+            Self::GetExcludeFile(e) => Some(e),
+            Self::GetExcludeFromSnapshotCount(e) => Some(e),
+            Self::GetExcludeFromSnapshotFile(e) => Some(e),
+            Self::FileSet(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn file_specification_wildcards() {
+        let matches = |name: &str, spec: &str| matches_file_specification(&wide(name), &wide(spec));
+        assert!(matches("db.mdf", "db.mdf"));
+        assert!(matches("DB.MDF", "db.mdf"));
+        assert!(matches("db.mdf", "*.mdf"));
+        assert!(matches("db.mdf", "d?.*"));
+        assert!(matches("db", "*.*"));
+        assert!(matches("db", "*"));
+        assert!(matches("a.b.c", "*.c"));
+        assert!(matches("abcbc", "*bc"));
+        assert!(!matches("db.ldf", "*.mdf"));
+        assert!(!matches("db.mdf", "d?"));
+        assert!(!matches("db", "*.mdf"));
+    }
+
+    #[test]
+    fn verbatim_disk_prefix_is_removed() {
+        let strip =
+            |path: &str| String::from_utf16(strip_verbatim_disk_prefix(&wide(path))).unwrap();
+        assert_eq!(strip("\\\\?\\C:\\data"), "C:\\data");
+        assert_eq!(strip("\\\\?\\c:"), "c:");
+        assert_eq!(strip("C:\\data"), "C:\\data");
+        assert_eq!(strip("\\\\?\\Volume{0}\\"), "\\\\?\\Volume{0}\\");
+        assert_eq!(
+            strip("\\\\?\\UNC\\server\\share"),
+            "\\\\?\\UNC\\server\\share"
+        );
+    }
+
+    #[test]
+    fn fully_qualified_paths() {
+        let qualified = |path: &str| is_fully_qualified(&wide(path));
+        assert!(qualified("C:\\"));
+        assert!(qualified("C:/data/"));
+        assert!(qualified("\\\\?\\Volume{0}\\data"));
+        assert!(qualified("\\\\server\\share"));
+        assert!(!qualified("C:"));
+        assert!(!qualified("C:data"));
+        assert!(!qualified("\\data"));
+        assert!(!qualified("data\\file.txt"));
+    }
+
+    #[test]
+    fn file_set_contains() {
+        let set = FileSet::new(&wide("C:\\Data\\"), &wide("*.tmp"), false);
+        assert_eq!(set.path(), &wide("C:\\Data")[..]);
+        assert!(set.contains(&wide("c:\\data\\a.tmp")));
+        assert!(!set.contains(&wide("C:\\Data\\a.txt")));
+        assert!(!set.contains(&wide("C:\\Data\\Sub\\a.tmp")));
+        assert!(!set.contains(&wide("C:\\Database\\a.tmp")));
+
+        let set = FileSet::new(&wide("C:\\Data"), &wide("*.tmp"), true);
+        assert!(set.contains(&wide("C:\\Data\\Sub\\a.tmp")));
+
+        let mut rules = ExcludeRules::new();
+        rules.add(set);
+        let files = vec![wide("C:\\Data\\a.tmp"), wide("C:\\Data\\a.txt")];
+        let kept: Vec<_> = rules.filter(files).collect();
+        assert_eq!(kept, [wide("C:\\Data\\a.txt")]);
+    }
+}
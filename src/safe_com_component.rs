@@ -4,6 +4,7 @@ use std::{
     any::type_name,
     borrow::Borrow,
     fmt,
+    mem::ManuallyDrop,
     ops::Deref,
     ptr::{self, NonNull},
     rc::Rc,
@@ -21,6 +22,14 @@ use winapi::{
     Interface,
 };
 
+#[cfg(debug_assertions)]
+use std::thread::{self, ThreadId};
+#[cfg(debug_assertions)]
+use winapi::um::{
+    combaseapi::CoGetApartmentType,
+    objidlbase::{APTTYPE, APTTYPEQUALIFIER, APTTYPE_MTA},
+};
+
 /// # Safety
 ///
 /// Assert that the current type can be managed by the `IUnknown` interface.
@@ -86,6 +95,52 @@ macro_rules! _unsafe_impl_as_IUnknown {
 }
 pub(crate) use _unsafe_impl_as_IUnknown as unsafe_impl_as_IUnknown;
 
+/// The type of the COM apartment of the current thread or `None` if COM isn't
+/// initialized.
+#[cfg(debug_assertions)]
+fn current_apartment() -> Option<APTTYPE> {
+    let mut apartment: APTTYPE = 0;
+    let mut qualifier: APTTYPEQUALIFIER = 0;
+    let hr = unsafe { CoGetApartmentType(&mut apartment, &mut qualifier) };
+    if hr == S_OK {
+        Some(apartment)
+    } else {
+        None
+    }
+}
+
+/// The apartment that an interface was obtained in.
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone, Copy)]
+struct Owner {
+    apartment: APTTYPE,
+    thread: ThreadId,
+}
+#[cfg(debug_assertions)]
+impl Owner {
+    fn current() -> Option<Self> {
+        Some(Self {
+            apartment: current_apartment()?,
+            thread: thread::current().id(),
+        })
+    }
+    /// All threads in the multithreaded apartment share it, every other
+    /// apartment belongs to a single thread.
+    fn is_current(&self) -> bool {
+        current_apartment() == Some(self.apartment)
+            && (self.apartment == APTTYPE_MTA || thread::current().id() == self.thread)
+    }
+}
+
+/// Owns a reference to a COM interface.
+///
+/// This is neither `Send` nor `Sync`: interface pointers are only valid in
+/// the apartment that they were obtained in and the reference count below
+/// isn't atomic. In debug builds every use checks that the current thread is
+/// still in the apartment that the interface was obtained in, which catches
+/// interfaces that are used after COM was uninitialized or that were passed
+/// to another single-threaded apartment. Releasing the interface isn't
+/// checked so that dropping it never panics.
 pub struct SafeCOMComponent<T: CustomIUnknown> {
     /// The methods on the pointed to struct relies on the structs location
     /// so never try to move it (note also that most of the struct layout is
@@ -111,6 +166,9 @@ pub struct SafeCOMComponent<T: CustomIUnknown> {
     /// Uses `OnceCell` to prevent an extra allocation if the the component's
     /// reference count is never larger than 1.
     ref_count: OnceCell<Rc<()>>,
+    /// The apartment that the interface was obtained in.
+    #[cfg(debug_assertions)]
+    owner: Option<Owner>,
 }
 impl<T: CustomIUnknown> SafeCOMComponent<T> {
     /// # Safety
@@ -121,8 +179,18 @@ impl<T: CustomIUnknown> SafeCOMComponent<T> {
         Self {
             comp: NonNull::new(comp).expect("component pointer was null"),
             ref_count: OnceCell::new(),
+            #[cfg(debug_assertions)]
+            owner: Owner::current(),
         }
     }
+    /// Release ownership of the wrapped COM component without decrementing its
+    /// reference count.
+    pub fn into_raw(this: Self) -> *mut T {
+        let mut this = ManuallyDrop::new(this);
+        // Safety: the field is never used again since `this` won't be dropped.
+        unsafe { ptr::drop_in_place(&mut this.ref_count) };
+        this.comp.as_ptr()
+    }
     /// Panics if another reference count increment could cause the reference
     /// count to overflow.
     fn check_if_overflowing_reference_count(&self) {
@@ -137,6 +205,28 @@ impl<T: CustomIUnknown> SafeCOMComponent<T> {
             );
         }
     }
+    /// Panic in debug builds if the interface is used outside of the
+    /// apartment that it was obtained in.
+    #[cfg(debug_assertions)]
+    fn check_apartment(&self) {
+        // Don't turn a panic into an abort when interfaces are released while
+        // unwinding.
+        if std::thread::panicking() {
+            return;
+        }
+        if let Some(owner) = self.owner {
+            assert!(
+                owner.is_current(),
+                "a `{}` COM interface obtained in apartment type {} on thread {:?} was used in \
+                    apartment type {:?} on thread {:?}",
+                type_name::<T>(),
+                owner.apartment,
+                owner.thread,
+                current_apartment(),
+                thread::current().id(),
+            );
+        }
+    }
     fn get_rc(&self) -> &Rc<()> {
         self.ref_count.get_or_init(|| Rc::new(()))
     }
@@ -176,6 +266,8 @@ impl<T: CustomIUnknown> SafeCOMComponent<T> {
             let mut comp = SafeCOMComponent {
                 comp,
                 ref_count: OnceCell::new(),
+                #[cfg(debug_assertions)]
+                owner: self.owner,
             };
             // Increment our separate reference count (this might panic, who knows?)
             let ref_count = Rc::clone(self.get_rc());
@@ -204,12 +296,16 @@ impl<T: CustomIUnknown> Clone for SafeCOMComponent<T> {
         Self {
             comp: self.comp,
             ref_count: OnceCell::from(ref_count),
+            #[cfg(debug_assertions)]
+            owner: self.owner,
         }
     }
 }
 impl<T: CustomIUnknown> Deref for SafeCOMComponent<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
+        #[cfg(debug_assertions)]
+        self.check_apartment();
         unsafe { self.comp.as_ref() }
     }
 }
@@ -226,8 +322,10 @@ impl<T: CustomIUnknown> Borrow<T> for SafeCOMComponent<T> {
 impl<T: CustomIUnknown> Drop for SafeCOMComponent<T> {
     #[doc(alias = "Release")]
     fn drop(&mut self) {
+        // Bypass `Deref` so that the apartment isn't checked, a panic here
+        // would leak the reference anyway.
         unsafe {
-            <T as CustomIUnknown>::release(self);
+            <T as CustomIUnknown>::release(self.comp.as_ref());
         }
         // Rc is dropped after the component's reference count is decremented
         // so our reference count will never be less than the component's.
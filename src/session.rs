@@ -0,0 +1,4374 @@
+//! High-level helpers for common VSS workflows.
+//!
+//! The bindings in the other modules expose the VSS interfaces more or less
+//! directly which means that the caller is responsible for calling methods in
+//! the right order and for cleaning up when something fails. The types in
+//! this module handle that for the most common case: creating a temporary
+//! shadow copy of some volumes so that files can be read from it.
+//! [`SnapshotBuilder::create_component_backup`] performs a component-mode
+//! backup of the writer components chosen by a [`SelectionConfig`] and
+//! [`RestoreSession`] guides the caller through restoring files from such a
+//! backup.
+//!
+//! COM must be initialized on the current thread before using this module.
+//! The functions that create backup components objects take the
+//! [`ComGuard`] returned by [`crate::initialize_com_scoped`] to ensure that.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::session::SnapshotBuilder;
+//!
+//! let com = volume_shadow_copy::initialize_com_scoped()?;
+//! let snapshot_set = SnapshotBuilder::new()
+//!     .volume("C:\\")
+//!     .timeout(Some(120 * 1000))
+//!     .create(&com)?;
+//! for snapshot in snapshot_set.snapshots() {
+//!     println!(
+//!         "{} => {}",
+//!         snapshot.volume(),
+//!         snapshot.properties().snapshot_device_object().to_string_lossy()
+//!     );
+//! }
+//! snapshot_set.finish()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    error::Error as StdError,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use widestring::{U16CStr, U16CString};
+use winapi::shared::{
+    guiddef::{IsEqualGUID, GUID},
+    minwindef::FILETIME,
+};
+
+use super::{
+    bstr_as_u16_slice, bstr_to_u16_cstring,
+    diagnostics::{self, VssEvent},
+    errors::WriterFailureError,
+    paths::{self, FileSet},
+    u16_slice_to_bstring,
+    vsbackup::{
+        BackupComponents, BackupComponentsEx2, BackupOptions, BackupStateBuilder, ComponentSpec,
+        ExamineWriterMetadata, ForBackupError, PreparedBackup,
+    },
+    vss::{
+        AsyncStatus, BackupType, CancellationToken, HardwareOptions, ObjectType, RestoreType,
+        RollForward, SnapshotContext, SnapshotProperties, VolumeSnapshotAttributes, VssAsync,
+        WriterState,
+    },
+    vswriter::{
+        Component, FileRestoreStatus, IComponent, PartialFile, VssComponentType, WMFileDescriptor,
+    },
+    ComGuard, QueryInterface, RawBitFlags, VSS_ID,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+// Errors
+////////////////////////////////////////////////////////////////////////////////
+
+/// An error from one of the high-level workflows in this module.
+#[derive(Debug)]
+pub enum SessionError {
+    /// A volume name contained a nul character.
+    InvalidVolumeName(String),
+    /// No provider supports shadow copies of the volume.
+    UnsupportedVolume(String),
+    /// An asynchronous VSS operation didn't finish before the timeout and was
+    /// canceled.
+    Timeout {
+        /// The VSS method that started the operation.
+        operation: &'static str,
+        /// The timeout in milliseconds.
+        timeout: u32,
+    },
+    /// The [`CancellationToken`] passed to
+    /// [`SnapshotBuilder::cancellation_token`] was canceled while an
+    /// asynchronous VSS operation was running. The operation was canceled
+    /// and the backup aborted.
+    Canceled {
+        /// The VSS method that started the operation.
+        operation: &'static str,
+    },
+    /// One or more writers failed while the snapshot set was created and the
+    /// [`WriterFailurePolicy`] didn't allow continuing without them, or
+    /// failed to handle a restore done by a [`RestoreSession`].
+    WriterFailed(Vec<FailedWriter>),
+    /// The backup components object doesn't implement the interface that is
+    /// needed to call a VSS method, usually because the Windows version is
+    /// too old.
+    NotSupported {
+        /// The VSS method that couldn't be called.
+        operation: &'static str,
+    },
+    /// A [`RestoreSession`] method was called in a phase of the restore where
+    /// it isn't allowed.
+    OutOfOrder {
+        /// The VSS method that couldn't be called.
+        operation: &'static str,
+        /// The phase that the restore was in.
+        phase: RestorePhase,
+    },
+    /// A hook registered with [`SnapshotBuilder::hook`] failed. The backup
+    /// was aborted.
+    Hook {
+        /// The phase that the hook was registered for.
+        phase: SessionPhase,
+        /// The error returned by the hook.
+        source: Box<dyn StdError + Send + Sync + 'static>,
+    },
+    /// A partial file index passed to [`RestoreSession::set_ranges_file_path`]
+    /// isn't less than the number of partial files that the writer added to
+    /// the component.
+    InvalidPartialFile {
+        /// The index that was passed.
+        index: u32,
+        /// The number of partial files of the component.
+        count: u32,
+    },
+    /// A [`BackupManifest`] couldn't be loaded.
+    Manifest(io::Error),
+    /// A [`TransportablePackage`] contained an identifier that couldn't be
+    /// parsed.
+    InvalidPackage(String),
+    /// A VSS method failed.
+    Vss {
+        /// The VSS method that failed.
+        operation: &'static str,
+        /// The error returned by the method.
+        source: Box<dyn StdError + Send + Sync + 'static>,
+    },
+}
+impl SessionError {
+    /// Create a closure that can be used with `map_err` to wrap the error
+    /// returned by a VSS method.
+    pub(crate) fn vss<E>(operation: &'static str) -> impl FnOnce(E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        move |e| Self::Vss {
+            operation,
+            source: Box::new(e),
+        }
+    }
+}
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVolumeName(volume) => {
+                write!(f, "the volume name {:?} contains a nul character", volume)
+            }
+            Self::UnsupportedVolume(volume) => {
+                write!(f, "snapshots are not supported for the volume {:?}", volume)
+            }
+            Self::Timeout { operation, timeout } => write!(
+                f,
+                "{} didn't complete within {} milliseconds",
+                operation, timeout
+            ),
+            Self::Canceled { operation } => write!(f, "{} was canceled", operation),
+            Self::WriterFailed(writers) => {
+                write!(
+                    f,
+                    "{} writer(s) failed during snapshot creation:",
+                    writers.len()
+                )?;
+                for writer in writers {
+                    write!(f, " {:?} ({:?})", writer.name, writer.state)?;
+                }
+                Ok(())
+            }
+            Self::NotSupported { operation } => {
+                write!(f, "{} is not supported on this system", operation)
+            }
+            Self::OutOfOrder { operation, phase } => write!(
+                f,
+                "{} can't be called when the restore is in the {:?} phase",
+                operation, phase
+            ),
+            Self::Hook { phase, source } => {
+                write!(f, "the {:?} hook failed: {}", phase, source)
+            }
+            Self::InvalidPartialFile { index, count } => write!(
+                f,
+                "the partial file index {} is out of range since the component has {} partial file(s)",
+                index, count
+            ),
+            Self::Manifest(e) => write!(f, "failed to load backup manifest: {}", e),
+            Self::InvalidPackage(id) => {
+                write!(
+                    f,
+                    "the transportable package contains the invalid id {:?}",
+                    id
+                )
+            }
+            Self::Vss { operation, source } => write!(f, "{} failed: {}", operation, source),
+        }
+    }
+}
+impl StdError for SessionError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Manifest(e) => Some(e),
+            Self::Hook { source, .. } => Some(&**source),
+            Self::Vss { source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+/// Wait for an asynchronous operation and cancel it if it doesn't finish
+/// within the timeout.
+pub(crate) fn wait<E>(
+    operation: &'static str,
+    vss_async: VssAsync<E>,
+    timeout: Option<u32>,
+) -> Result<(), SessionError>
+where
+    E: From<i32> + StdError + Send + Sync + 'static,
+{
+    vss_async
+        .wait(timeout)
+        .map_err(SessionError::vss(operation))?;
+    let status = vss_async
+        .query_status()
+        .map_err(SessionError::vss(operation))?;
+    match (status, timeout) {
+        (AsyncStatus::Pending, Some(timeout)) => {
+            let _ = vss_async.cancel();
+            Err(SessionError::Timeout { operation, timeout })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// How often [`wait_cancelable`] checks its cancellation token.
+const CANCELLATION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Like [`wait`] but the operation is also canceled when `token` is.
+fn wait_cancelable<E>(
+    operation: &'static str,
+    vss_async: VssAsync<E>,
+    timeout: Option<u32>,
+    token: Option<&CancellationToken>,
+) -> Result<(), SessionError>
+where
+    E: From<i32> + StdError + Send + Sync + 'static,
+{
+    let token = match token {
+        Some(token) => token,
+        None => return wait(operation, vss_async, timeout),
+    };
+    let deadline = timeout.map(|timeout| Instant::now() + Duration::from_millis(timeout.into()));
+    loop {
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let slice = remaining.map_or(CANCELLATION_POLL_INTERVAL, |remaining| {
+            remaining.min(CANCELLATION_POLL_INTERVAL)
+        });
+        vss_async
+            .wait_timeout(Some(slice))
+            .map_err(SessionError::vss(operation))?;
+        let status = vss_async
+            .query_status()
+            .map_err(SessionError::vss(operation))?;
+        if status != AsyncStatus::Pending {
+            return Ok(());
+        }
+        if token.is_canceled() {
+            let _ = vss_async.cancel();
+            return Err(SessionError::Canceled { operation });
+        }
+        match (timeout, remaining) {
+            (Some(timeout), Some(remaining)) if remaining == Duration::ZERO => {
+                let _ = vss_async.cancel();
+                return Err(SessionError::Timeout { operation, timeout });
+            }
+            _ => {}
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Writer failures
+////////////////////////////////////////////////////////////////////////////////
+
+/// A writer that reported a failure after the shadow copies were created.
+#[derive(Clone)]
+pub struct FailedWriter {
+    /// Identifier of the writer instance.
+    pub instance_id: VSS_ID,
+    /// Identifier of the writer class.
+    pub writer_id: VSS_ID,
+    /// The name of the writer.
+    pub name: String,
+    /// The state that the writer was left in.
+    pub state: WriterState,
+    /// The error that the writer reported, if any.
+    pub failure: Option<WriterFailureError>,
+}
+impl fmt::Debug for FailedWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FailedWriter")
+            .field("name", &self.name)
+            .field("state", &self.state)
+            .field("failure", &self.failure)
+            .finish()
+    }
+}
+
+/// What [`SnapshotBuilder::create`] does when writers report failures after
+/// `DoSnapshotSet`.
+///
+/// Only used when writers are [`WriterInvolvement::Involved`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WriterFailurePolicy {
+    /// Abort the backup and return [`SessionError::WriterFailed`].
+    #[default]
+    FailFast,
+    /// Keep the snapshot set. Components of the failed writers are marked as
+    /// not backed up and the writers are listed by
+    /// [`SnapshotSet::failed_writers`]. Their data in the shadow copies should
+    /// be treated as only crash consistent.
+    ContinueWithoutWriter,
+    /// Abort the backup and create the snapshot set again once. If a writer
+    /// fails the second time as well then [`SessionError::WriterFailed`] is
+    /// returned.
+    RetryWriterOnce,
+}
+
+/// Query the status of all writers and return a report for each writer
+/// together with the ones that failed.
+fn check_writers(
+    backup_comp: &BackupComponents,
+) -> Result<(Vec<WriterReport>, Vec<FailedWriter>), SessionError> {
+    wait(
+        "GatherWriterStatus",
+        backup_comp
+            .gather_writer_status()
+            .map_err(SessionError::vss("GatherWriterStatus"))?,
+        None,
+    )?;
+    let statuses = backup_comp
+        .writer_statuses()
+        .map_err(SessionError::vss("GetWriterStatus"))?;
+    let mut reports = Vec::with_capacity(statuses.len());
+    let mut failed = Vec::new();
+    for status in statuses {
+        let has_failed = status.has_failed();
+        let name = String::from_utf16_lossy(bstr_as_u16_slice(&status.writer_name));
+        reports.push(WriterReport {
+            name: name.clone(),
+            writer_id: guid_to_string(&status.writer_id),
+            instance_id: guid_to_string(&status.instance_id),
+            state: format!("{:?}", status.state),
+            failure: status.failure.map(|e| e.to_string()),
+            succeeded: !has_failed,
+        });
+        if has_failed {
+            failed.push(FailedWriter {
+                instance_id: status.instance_id,
+                writer_id: status.writer_id,
+                name,
+                state: status.state,
+                failure: status.failure,
+            });
+        }
+    }
+    Ok((reports, failed))
+}
+
+/// Mark all components of the failed writers that were added to the Backup
+/// Components Document as not backed up.
+fn mark_not_backed_up(
+    backup_comp: &BackupComponents,
+    failed: &[FailedWriter],
+) -> Result<(), SessionError> {
+    let writers = backup_comp
+        .writer_components_iter()
+        .map_err(SessionError::vss("GetWriterComponentsCount"))?;
+    for writer in writers {
+        let writer = writer.map_err(SessionError::vss("GetWriterComponents"))?;
+        let info = writer
+            .get_writer_info()
+            .map_err(SessionError::vss("GetWriterInfo"))?;
+        let is_failed = failed.iter().any(|failed| {
+            IsEqualGUID(&failed.instance_id, &info.instance_id)
+                && IsEqualGUID(&failed.writer_id, &info.writer_id)
+        });
+        if !is_failed {
+            continue;
+        }
+        let components = writer
+            .components()
+            .map_err(SessionError::vss("GetComponentCount"))?;
+        for component in components {
+            let component = component.map_err(SessionError::vss("GetComponent"))?;
+            let component_type = component
+                .get_component_type()
+                .map_err(SessionError::vss("GetComponentType"))?;
+            let logical_path = component
+                .get_logical_path()
+                .map_err(SessionError::vss("GetLogicalPath"))?
+                .map(|path| bstr_to_u16_cstring(&path));
+            let component_name = component
+                .get_component_name()
+                .map_err(SessionError::vss("GetComponentName"))?;
+            let component_name = bstr_to_u16_cstring(&component_name);
+            backup_comp
+                .component(ComponentSpec::new(
+                    info.writer_id,
+                    component_type,
+                    logical_path.as_deref(),
+                    &component_name,
+                ))
+                .set_backup_succeeded(info.instance_id, false)
+                .map_err(SessionError::vss("SetBackupSucceeded"))?;
+        }
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Progress
+////////////////////////////////////////////////////////////////////////////////
+
+/// A step of [`SnapshotBuilder::create`] or [`SnapshotSet::finish`] that is
+/// reported to a [`Progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProgressPhase {
+    /// Creating and configuring the backup components object.
+    Initializing,
+    /// Waiting for writers to report their metadata.
+    GatheringWriterMetadata,
+    /// Adding the volumes to the snapshot set and waiting for writers to
+    /// prepare for the backup.
+    PreparingForBackup,
+    /// Waiting for the shadow copies to be created. Writers freeze their
+    /// applications during this phase.
+    CreatingSnapshots,
+    /// Checking the status of the writers after the shadow copies were
+    /// created.
+    CheckingWriters,
+    /// The shadow copies are ready to be used.
+    SnapshotsCreated,
+    /// Waiting for writers to handle the `BackupComplete` event.
+    CompletingBackup,
+    /// Deleting the shadow copies.
+    DeletingSnapshots,
+    /// The snapshot set has been finished.
+    Finished,
+}
+impl ProgressPhase {
+    /// An estimate of how far along the current operation is when this phase
+    /// starts. Creating and finishing a snapshot set are separate operations
+    /// that both go from 0 to 100.
+    pub fn percent(self) -> u8 {
+        match self {
+            Self::Initializing => 0,
+            Self::GatheringWriterMetadata => 10,
+            Self::PreparingForBackup => 30,
+            Self::CreatingSnapshots => 50,
+            Self::CheckingWriters => 80,
+            Self::SnapshotsCreated => 100,
+            Self::CompletingBackup => 0,
+            Self::DeletingSnapshots => 50,
+            Self::Finished => 100,
+        }
+    }
+    fn message(self) -> &'static str {
+        match self {
+            Self::Initializing => "Initializing backup",
+            Self::GatheringWriterMetadata => "Gathering writer metadata",
+            Self::PreparingForBackup => "Preparing writers for backup",
+            Self::CreatingSnapshots => "Creating shadow copies",
+            Self::CheckingWriters => "Checking writer status",
+            Self::SnapshotsCreated => "Shadow copies created",
+            Self::CompletingBackup => "Completing backup",
+            Self::DeletingSnapshots => "Deleting shadow copies",
+            Self::Finished => "Backup finished",
+        }
+    }
+}
+
+/// Receives progress updates from long-running operations, see
+/// [`SnapshotBuilder::progress`].
+///
+/// The VSS calls that an operation waits on don't report any progress of
+/// their own, so updates are only sent when a new phase starts. `percent` is
+/// [`ProgressPhase::percent`] and `message` is a short human-readable
+/// description of the phase.
+///
+/// This is implemented for closures that take the same arguments as
+/// [`Progress::report`].
+pub trait Progress {
+    /// Called when `phase` starts.
+    fn report(&self, phase: ProgressPhase, percent: u8, message: &str);
+}
+impl<F> Progress for F
+where
+    F: Fn(ProgressPhase, u8, &str),
+{
+    fn report(&self, phase: ProgressPhase, percent: u8, message: &str) {
+        self(phase, percent, message)
+    }
+}
+
+/// The [`Progress`] registered with [`SnapshotBuilder::progress`], if any.
+#[derive(Clone, Default)]
+struct ProgressReporter(Option<Arc<dyn Progress + Send + Sync>>);
+impl ProgressReporter {
+    fn report(&self, phase: ProgressPhase) {
+        if let Some(progress) = &self.0 {
+            progress.report(phase, phase.percent(), phase.message());
+        }
+    }
+}
+impl fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ProgressReporter")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Snapshot builder
+////////////////////////////////////////////////////////////////////////////////
+
+/// Whether VSS writers take part when a snapshot is created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WriterInvolvement {
+    /// Notify writers so that applications such as databases can flush their
+    /// data to disk and pause writes while the snapshot is created. This is
+    /// slower and can fail if a writer misbehaves but gives
+    /// [`Consistency::ApplicationConsistent`] snapshots.
+    #[default]
+    Involved,
+    /// Create the snapshot without notifying any writers, using the
+    /// [`VolumeSnapshotAttributes::NO_WRITERS`] attribute. This is faster and
+    /// doesn't depend on writers but only gives
+    /// [`Consistency::CrashConsistent`] snapshots.
+    Writerless,
+}
+
+/// How consistent the data in a shadow copy is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Consistency {
+    /// Writers prepared their data before the shadow copy was created, so
+    /// their files are in a state that the application can use directly.
+    ApplicationConsistent,
+    /// The shadow copy contains the data that was on disk at a single point in
+    /// time, like after a power failure. Files that were being written might
+    /// be incomplete and applications might need to run their own recovery
+    /// before the data can be used.
+    CrashConsistent,
+}
+impl From<WriterInvolvement> for Consistency {
+    fn from(writers: WriterInvolvement) -> Self {
+        match writers {
+            WriterInvolvement::Involved => Self::ApplicationConsistent,
+            WriterInvolvement::Writerless => Self::CrashConsistent,
+        }
+    }
+}
+
+/// A point in the life of a [`SnapshotSet`] where the hooks registered with
+/// [`SnapshotBuilder::hook`] are called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SessionPhase {
+    /// After `PrepareForBackup` and right before `DoSnapshotSet`, which is
+    /// when writers freeze their applications. The snapshot set doesn't
+    /// contain any shadow copies yet.
+    BeforeFreeze,
+    /// After the shadow copies were created and the writers' status was
+    /// checked.
+    AfterSnapshot,
+    /// When the snapshot set is finished or exported, before `BackupComplete`
+    /// is sent to the writers. Hooks are called even for
+    /// [`WriterInvolvement::Writerless`] snapshots where no event is sent.
+    BeforeBackupComplete,
+}
+
+type Hook = Arc<dyn Fn(&SnapshotSet) -> Result<(), Box<dyn StdError + Send + Sync>> + Send + Sync>;
+
+/// The hooks registered with [`SnapshotBuilder::hook`], in the order they
+/// were added.
+#[derive(Clone, Default)]
+struct Hooks(Vec<(SessionPhase, Hook)>);
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(phase, _)| phase))
+            .finish()
+    }
+}
+
+/// Configures and creates a temporary shadow copy of one or more volumes.
+#[derive(Debug, Clone)]
+pub struct SnapshotBuilder {
+    volumes: Vec<String>,
+    writers: WriterInvolvement,
+    backup_type: BackupType,
+    writer_failure_policy: WriterFailurePolicy,
+    timeout: Option<u32>,
+    cancellation: Option<CancellationToken>,
+    transportable: bool,
+    partial_file_support: bool,
+    hooks: Hooks,
+    progress: ProgressReporter,
+    previous_backup_stamps: Vec<BackupStamp>,
+}
+impl Default for SnapshotBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl SnapshotBuilder {
+    /// Create a builder without any volumes that will involve writers and
+    /// perform a [`BackupType::Copy`] backup.
+    pub fn new() -> Self {
+        Self {
+            volumes: Vec::new(),
+            writers: WriterInvolvement::Involved,
+            backup_type: BackupType::Copy,
+            writer_failure_policy: WriterFailurePolicy::FailFast,
+            timeout: None,
+            cancellation: None,
+            transportable: false,
+            partial_file_support: false,
+            hooks: Hooks::default(),
+            progress: ProgressReporter::default(),
+            previous_backup_stamps: Vec::new(),
+        }
+    }
+    /// Add a volume to the snapshot set, for example `C:\`. Forward slashes are
+    /// converted to backslashes.
+    pub fn volume(mut self, volume: &str) -> Self {
+        self.volumes.push(volume.replace('/', "\\"));
+        self
+    }
+    /// Choose whether writers take part in creating the snapshot. See
+    /// [`WriterInvolvement`] for the trade-off.
+    pub fn writers(mut self, writers: WriterInvolvement) -> Self {
+        self.writers = writers;
+        self
+    }
+    /// The type of backup that writers are told about. Ignored for
+    /// [`WriterInvolvement::Writerless`] snapshots.
+    pub fn backup_type(mut self, backup_type: BackupType) -> Self {
+        self.backup_type = backup_type;
+        self
+    }
+    /// What to do when writers fail while the snapshot set is created.
+    /// Defaults to [`WriterFailurePolicy::FailFast`].
+    pub fn writer_failure_policy(mut self, policy: WriterFailurePolicy) -> Self {
+        self.writer_failure_policy = policy;
+        self
+    }
+    /// The maximum time in milliseconds to wait for each asynchronous VSS
+    /// operation. `None` waits forever.
+    pub fn timeout(mut self, timeout: Option<u32>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Cancel the asynchronous VSS operations started while the snapshot set
+    /// is created when `token` is canceled. The backup is then aborted and
+    /// [`SessionError::Canceled`] is returned.
+    ///
+    /// This only affects [`create`](Self::create) and
+    /// [`create_component_backup`](Self::create_component_backup); finishing
+    /// a snapshot set can't be canceled.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+    /// Create transportable shadow copies using the
+    /// [`VolumeSnapshotAttributes::TRANSPORTABLE`] attribute so that they can
+    /// be moved to another machine with [`SnapshotSet::export`]. Only
+    /// hardware providers support transportable shadow copies.
+    pub fn transportable(mut self, transportable: bool) -> Self {
+        self.transportable = transportable;
+        self
+    }
+    /// Tell writers that only parts of large files need to be backed up. Such
+    /// files are listed in [`BackedUpComponent::partial_files`] by
+    /// [`SnapshotBuilder::create_component_backup`]. Disabled by default.
+    pub fn partial_file_support(mut self, partial_file_support: bool) -> Self {
+        self.partial_file_support = partial_file_support;
+        self
+    }
+    /// Perform a [`BackupType::Incremental`] backup that only includes the
+    /// changes since the backup that `previous` describes.
+    ///
+    /// The backup stamps that writers stored in `previous` are passed back to
+    /// them with `SetPreviousBackupStamp`. Backup stamps belong to
+    /// components, so this only has an effect for
+    /// [`SnapshotBuilder::create_component_backup`]. Save the manifest of the
+    /// new backup with [`SnapshotSet::manifest`] to use it as the base of the
+    /// next incremental backup.
+    pub fn incremental(mut self, previous: &BackupManifest) -> Self {
+        self.backup_type = BackupType::Incremental;
+        self.previous_backup_stamps = previous.backup_stamps.clone();
+        self
+    }
+    /// Perform a [`BackupType::Differential`] backup that includes all
+    /// changes since the full backup that `previous` describes.
+    ///
+    /// Like [`SnapshotBuilder::incremental`] this passes the backup stamps in
+    /// `previous` back to the writers, so `previous` should be the manifest of
+    /// the last full backup and not of an earlier differential backup. Writers
+    /// can report which files changed, see
+    /// [`BackedUpComponent::differenced_files`].
+    pub fn differential(mut self, previous: &BackupManifest) -> Self {
+        self.backup_type = BackupType::Differential;
+        self.previous_backup_stamps = previous.backup_stamps.clone();
+        self
+    }
+    /// Call `hook` when the snapshot set reaches `phase`, for example to
+    /// quiesce an application that doesn't have its own writer before the
+    /// shadow copies are created or to emit audit events.
+    ///
+    /// Hooks for the same phase are called in the order they were added. If
+    /// a hook returns an error then no more hooks are called, the backup is
+    /// aborted and [`SessionError::Hook`] is returned. Hooks are called again
+    /// if [`WriterFailurePolicy::RetryWriterOnce`] retries the snapshot.
+    pub fn hook<F>(mut self, phase: SessionPhase, hook: F) -> Self
+    where
+        F: Fn(&SnapshotSet) -> Result<(), Box<dyn StdError + Send + Sync>> + Send + Sync + 'static,
+    {
+        self.hooks.0.push((phase, Arc::new(hook)));
+        self
+    }
+    /// Report the progress of creating and finishing the snapshot set to
+    /// `progress`, replacing any previously registered [`Progress`].
+    pub fn progress(mut self, progress: impl Progress + Send + Sync + 'static) -> Self {
+        self.progress = ProgressReporter(Some(Arc::new(progress)));
+        self
+    }
+
+    /// Create the snapshot set.
+    ///
+    /// When writers aren't involved the writer metadata isn't gathered and no
+    /// `BackupComplete` event is sent when the snapshot set is finished.
+    /// `PrepareForBackup` is still called since VSS requires it before
+    /// `DoSnapshotSet`, but no writer receives the event.
+    ///
+    /// When writers are involved their status is checked after the shadow
+    /// copies are created and failures are handled according to the
+    /// [`WriterFailurePolicy`].
+    pub fn create(&self, _com: &ComGuard) -> Result<SnapshotSet, SessionError> {
+        match self.create_once() {
+            Err(SessionError::WriterFailed(_))
+                if self.writer_failure_policy == WriterFailurePolicy::RetryWriterOnce =>
+            {
+                self.create_once()
+            }
+            result => result,
+        }
+    }
+    /// Create a component-mode backup of the writer components that
+    /// `selection` selects.
+    ///
+    /// The selected components are added to the Backup Components Document
+    /// and every volume that contains one of their files is added to the
+    /// snapshot set, together with any volumes that were added to this
+    /// builder. After the shadow copies are created the file sets of each
+    /// component are resolved to paths inside the shadow copies, see
+    /// [`ComponentBackup::components`].
+    ///
+    /// Writers always take part in component-mode backups, so
+    /// [`SnapshotBuilder::writers`] is ignored.
+    pub fn create_component_backup(
+        &self,
+        _com: &ComGuard,
+        selection: &SelectionConfig,
+    ) -> Result<ComponentBackup, SessionError> {
+        let mut options = self.clone();
+        options.writers = WriterInvolvement::Involved;
+        match options.create_component_backup_once(selection) {
+            Err(SessionError::WriterFailed(_))
+                if self.writer_failure_policy == WriterFailurePolicy::RetryWriterOnce =>
+            {
+                options.create_component_backup_once(selection)
+            }
+            result => result,
+        }
+    }
+    fn create_component_backup_once(
+        &self,
+        selection: &SelectionConfig,
+    ) -> Result<ComponentBackup, SessionError> {
+        let started = Instant::now();
+        let mut durations = JobDurations::default();
+        let (backup_comp, mut volumes) = self.initialize(true, &mut durations)?;
+        let selected = selection.select_components(&backup_comp)?;
+        self.set_previous_backup_stamps(&backup_comp, &selected)?;
+        let component_files = component_file_sets(&backup_comp, &selected)?;
+
+        // Every file set needs a shadow copy of the volume that contains it.
+        let mut volume_names = self.volumes.clone();
+        for files in &component_files {
+            for file_set in files {
+                let path = U16CString::new(file_set.path())
+                    .map_err(SessionError::vss("GetVolumePathName"))?;
+                let volume = paths::volume_path_name(&path)
+                    .map_err(SessionError::vss("GetVolumePathName"))?;
+                // Volumes added to the builder might not end with a separator.
+                let is_known = volumes.iter().any(|known| {
+                    matches!(
+                        paths::strip_prefix_ignore_case(volume.as_slice(), known.as_slice()),
+                        Some(rest) if rest.is_empty()
+                    )
+                });
+                if !is_known {
+                    let name = volume.to_string_lossy();
+                    check_volume_supported(&backup_comp, &name, &volume)?;
+                    volume_names.push(name);
+                    volumes.push(volume);
+                }
+            }
+        }
+
+        let snapshot_set =
+            self.create_snapshot_set(started, durations, backup_comp, &volume_names, &volumes)?;
+        let differenced_files = differenced_file_sets(snapshot_set.backup_components(), &selected)?;
+        let partial_files = partial_files(snapshot_set.backup_components(), &selected)?;
+        // The longest matching volume wins since volumes can be mounted in
+        // folders of other volumes.
+        let snapshot_path = |path: &[u16]| -> Option<U16CString> {
+            let (snapshot, relative_path) = snapshot_set
+                .snapshots()
+                .iter()
+                .zip(&volumes)
+                .filter_map(|(snapshot, volume)| {
+                    paths::strip_prefix_ignore_case(path, volume.as_slice())
+                        .map(|relative_path| (snapshot, relative_path))
+                })
+                .min_by_key(|(_, relative_path)| relative_path.len())?;
+            snapshot.properties().to_wide_path(relative_path).ok()
+        };
+        let mut components = Vec::with_capacity(selected.len());
+        for (((component, files), differenced), partial) in selected
+            .into_iter()
+            .zip(component_files)
+            .zip(differenced_files)
+            .zip(partial_files)
+        {
+            let files = files
+                .into_iter()
+                .filter_map(|file_set| {
+                    Some(ComponentFiles {
+                        snapshot_path: snapshot_path(file_set.path())?,
+                        file_set,
+                    })
+                })
+                .collect();
+            let differenced_files = differenced
+                .into_iter()
+                .filter_map(|(file_set, last_modify_time)| {
+                    Some(DifferencedFiles {
+                        snapshot_path: snapshot_path(file_set.path())?,
+                        file_set,
+                        last_modify_time,
+                    })
+                })
+                .collect();
+            let partial_files = partial
+                .into_iter()
+                .filter_map(|(index, file)| {
+                    let file = StoredPartialFile::new(index, &file);
+                    let mut full_path = file.path.as_slice().to_vec();
+                    if matches!(full_path.last(), Some(&c) if !paths::is_separator(c)) {
+                        full_path.push(u16::from(b'\\'));
+                    }
+                    full_path.extend_from_slice(file.file_name.as_slice());
+                    let ranges_snapshot_path = match &file.ranges {
+                        Some(PartialFileRanges::RangesFile(path)) => snapshot_path(path.as_slice()),
+                        _ => None,
+                    };
+                    Some(ComponentPartialFile {
+                        index,
+                        snapshot_path: snapshot_path(&full_path)?,
+                        path: file.path,
+                        file_name: file.file_name,
+                        ranges: file.ranges,
+                        ranges_snapshot_path,
+                        metadata: file.metadata,
+                    })
+                })
+                .collect();
+            components.push(BackedUpComponent {
+                component,
+                files,
+                differenced_files,
+                partial_files,
+                succeeded: true,
+            });
+        }
+        Ok(ComponentBackup {
+            snapshot_set,
+            components,
+        })
+    }
+    /// Pass the backup stamps of an earlier backup to the writers of the
+    /// selected components.
+    #[doc(alias = "SetPreviousBackupStamp")]
+    fn set_previous_backup_stamps(
+        &self,
+        backup_comp: &BackupComponents,
+        selected: &[SelectedComponent],
+    ) -> Result<(), SessionError> {
+        for component in selected {
+            let writer_id = guid_to_string(&component.writer_id);
+            let logical_path = component
+                .logical_path
+                .as_ref()
+                .map(|path| path.to_string_lossy());
+            let component_name = component.component_name.to_string_lossy();
+            let stamp = self.previous_backup_stamps.iter().find(|stamp| {
+                stamp.writer_id.eq_ignore_ascii_case(&writer_id)
+                    && stamp.logical_path == logical_path
+                    && stamp.component_name == component_name
+            });
+            if let Some(stamp) = stamp {
+                let stamp = U16CString::from_str(&stamp.stamp)
+                    .map_err(SessionError::vss("SetPreviousBackupStamp"))?;
+                backup_comp
+                    .component(component.spec())
+                    .set_previous_backup_stamp(&stamp)
+                    .map_err(SessionError::vss("SetPreviousBackupStamp"))?;
+            }
+        }
+        Ok(())
+    }
+    /// Validate the configuration without creating any shadow copies.
+    ///
+    /// This performs the same steps as [`SnapshotBuilder::create`] up to
+    /// checking that the volumes are supported, including gathering writer
+    /// metadata when writers are involved, and then stops before the
+    /// snapshot set is started. The returned report lists the writers that
+    /// would take part and the components and files that they would include
+    /// in the backup.
+    pub fn dry_run(&self, _com: &ComGuard) -> Result<DryRunReport, SessionError> {
+        let (backup_comp, _) = self.initialize(false, &mut JobDurations::default())?;
+        let mut writers = Vec::new();
+        if self.writers == WriterInvolvement::Involved {
+            for metadata in backup_comp
+                .writer_metadata_iter()
+                .map_err(SessionError::vss("GetWriterMetadataCount"))?
+            {
+                let (_, metadata) = metadata.map_err(SessionError::vss("GetWriterMetadata"))?;
+                writers.push(dry_run_writer(&metadata)?);
+            }
+            backup_comp
+                .free_writer_metadata()
+                .map_err(SessionError::vss("FreeWriterMetadata"))?;
+        }
+        Ok(DryRunReport {
+            volumes: self.volumes.clone(),
+            consistency: self.writers.into(),
+            writers,
+        })
+    }
+    /// Set up a backup components object for the snapshot set and check that
+    /// every volume is supported. Returns the volume names as wide strings.
+    fn initialize(
+        &self,
+        select_components: bool,
+        durations: &mut JobDurations,
+    ) -> Result<(BackupComponents, Vec<U16CString>), SessionError> {
+        let volumes = self
+            .volumes
+            .iter()
+            .map(|volume| {
+                U16CString::from_str(volume)
+                    .map_err(|_| SessionError::InvalidVolumeName(volume.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.progress.report(ProgressPhase::Initializing);
+        let backup_comp =
+            BackupComponents::new().map_err(SessionError::vss("CreateVssBackupComponents"))?;
+        backup_comp
+            .initialize_for_backup(None)
+            .map_err(SessionError::vss("InitializeForBackup"))?;
+        let mut attributes = VolumeSnapshotAttributes::empty();
+        if self.writers == WriterInvolvement::Writerless {
+            attributes |= VolumeSnapshotAttributes::NO_WRITERS;
+        }
+        if self.transportable {
+            attributes |= VolumeSnapshotAttributes::TRANSPORTABLE;
+        }
+        backup_comp
+            .set_context(SnapshotContext::Backup, attributes.into())
+            .map_err(SessionError::vss("SetContext"))?;
+        BackupStateBuilder::new()
+            .select_components(select_components)
+            .backup_type(self.backup_type)
+            .partial_file_support(self.partial_file_support)
+            .apply(&backup_comp)
+            .map_err(SessionError::vss("SetBackupState"))?;
+        if self.writers == WriterInvolvement::Involved {
+            self.progress.report(ProgressPhase::GatheringWriterMetadata);
+            let gather_started = Instant::now();
+            self.wait(
+                "GatherWriterMetadata",
+                backup_comp
+                    .gather_writer_metadata()
+                    .map_err(SessionError::vss("GatherWriterMetadata"))?,
+            )?;
+            durations.gather_writer_metadata = gather_started.elapsed();
+        }
+        for (volume, volume_wide) in self.volumes.iter().zip(&volumes) {
+            check_volume_supported(&backup_comp, volume, volume_wide)?;
+        }
+        Ok((backup_comp, volumes))
+    }
+    /// Wait for an operation using the timeout and cancellation token of the
+    /// builder.
+    fn wait<E>(&self, operation: &'static str, vss_async: VssAsync<E>) -> Result<(), SessionError>
+    where
+        E: From<i32> + StdError + Send + Sync + 'static,
+    {
+        wait_cancelable(
+            operation,
+            vss_async,
+            self.timeout,
+            self.cancellation.as_ref(),
+        )
+    }
+    fn create_once(&self) -> Result<SnapshotSet, SessionError> {
+        let started = Instant::now();
+        let mut durations = JobDurations::default();
+        let (backup_comp, volumes) = self.initialize(false, &mut durations)?;
+        self.create_snapshot_set(started, durations, backup_comp, &self.volumes, &volumes)
+    }
+    /// Start a snapshot set that contains `volumes`, which must be supported,
+    /// and create the shadow copies. `volume_names` are the same volumes as
+    /// they are reported by [`VolumeSnapshot::volume`].
+    fn create_snapshot_set(
+        &self,
+        started: Instant,
+        mut durations: JobDurations,
+        backup_comp: BackupComponents,
+        volume_names: &[String],
+        volumes: &[U16CString],
+    ) -> Result<SnapshotSet, SessionError> {
+        let snapshot_set_id = backup_comp
+            .start_snapshot_set()
+            .map_err(SessionError::vss("StartSnapshotSet"))?;
+        // After the snapshot set has been started the backup must be aborted
+        // if anything fails, which is done when the guard or the snapshot set
+        // is dropped.
+        let backup_comp = PreparedBackup::new(backup_comp);
+        self.progress.report(ProgressPhase::PreparingForBackup);
+        let mut snapshot_ids = Vec::with_capacity(volumes.len());
+        for volume in volumes {
+            snapshot_ids.push(
+                backup_comp
+                    .add_to_snapshot_set(volume, None)
+                    .map_err(SessionError::vss("AddToSnapshotSet"))?,
+            );
+        }
+        let prepare_started = Instant::now();
+        self.wait(
+            "PrepareForBackup",
+            backup_comp
+                .prepare_for_backup()
+                .map_err(SessionError::vss("PrepareForBackup"))?,
+        )?;
+        let prepared = Instant::now();
+        durations.prepare_for_backup = prepared - prepare_started;
+
+        let mut snapshot_set = SnapshotSet {
+            backup_comp: Some(backup_comp),
+            snapshot_set_id,
+            snapshots: Vec::with_capacity(volumes.len()),
+            consistency: self.writers.into(),
+            failed_writers: Vec::new(),
+            writer_reports: Vec::new(),
+            started,
+            durations,
+            bytes_copied: 0,
+            errors: Vec::new(),
+            timeout: self.timeout,
+            hooks: self.hooks.clone(),
+            progress: self.progress.clone(),
+        };
+        snapshot_set.run_hooks(SessionPhase::BeforeFreeze)?;
+        self.progress.report(ProgressPhase::CreatingSnapshots);
+        let do_snapshot_started = Instant::now();
+        self.wait(
+            "DoSnapshotSet",
+            snapshot_set
+                .backup_components()
+                .do_snapshot_set()
+                .map_err(SessionError::vss("DoSnapshotSet"))?,
+        )?;
+        snapshot_set.durations.do_snapshot_set = do_snapshot_started.elapsed();
+        snapshot_set.durations.freeze_window = prepared.elapsed();
+        if self.writers == WriterInvolvement::Involved {
+            self.progress.report(ProgressPhase::CheckingWriters);
+            let (reports, failed) = check_writers(snapshot_set.backup_components())?;
+            snapshot_set.writer_reports = reports;
+            if !failed.is_empty() {
+                if self.writer_failure_policy != WriterFailurePolicy::ContinueWithoutWriter {
+                    return Err(SessionError::WriterFailed(failed));
+                }
+                mark_not_backed_up(snapshot_set.backup_components(), &failed)?;
+                snapshot_set.failed_writers = failed;
+            }
+        }
+        for (volume, snapshot_id) in volume_names.iter().zip(snapshot_ids) {
+            let properties = snapshot_set
+                .backup_components()
+                .get_snapshot_properties(snapshot_id)
+                .map_err(SessionError::vss("GetSnapshotProperties"))?;
+            snapshot_set.snapshots.push(VolumeSnapshot {
+                volume: volume.clone(),
+                snapshot_id,
+                properties,
+            });
+        }
+        snapshot_set.durations.snapshot_creation = started.elapsed();
+        snapshot_set.run_hooks(SessionPhase::AfterSnapshot)?;
+        self.progress.report(ProgressPhase::SnapshotsCreated);
+        Ok(snapshot_set)
+    }
+}
+
+fn check_volume_supported(
+    backup_comp: &BackupComponents,
+    volume: &str,
+    volume_wide: &U16CStr,
+) -> Result<(), SessionError> {
+    let is_supported = backup_comp
+        .is_volume_supported(None, volume_wide)
+        .map_err(SessionError::vss("IsVolumeSupported"))?;
+    if is_supported {
+        Ok(())
+    } else {
+        Err(SessionError::UnsupportedVolume(volume.to_owned()))
+    }
+}
+
+/// Describe a writer and its components for a [`DryRunReport`].
+fn dry_run_writer(metadata: &ExamineWriterMetadata) -> Result<DryRunWriter, SessionError> {
+    let identity = metadata
+        .get_identity()
+        .map_err(SessionError::vss("GetIdentity"))?;
+    let counts = metadata
+        .get_file_counts()
+        .map_err(SessionError::vss("GetFileCounts"))?;
+    let mut components = Vec::with_capacity(counts.total_components as usize);
+    for component_index in 0..counts.total_components {
+        let component = metadata
+            .get_component(component_index)
+            .map_err(SessionError::vss("GetComponent"))?;
+        let info = component
+            .get_component_info()
+            .map_err(SessionError::vss("GetComponentInfo"))?;
+        let mut files = Vec::new();
+        let mut add_file = |file: WMFileDescriptor| -> Result<(), SessionError> {
+            let path = file.get_path().map_err(SessionError::vss("GetPath"))?;
+            let filespec = file
+                .get_file_specification()
+                .map_err(SessionError::vss("GetFilespec"))?;
+            files.push(DryRunFile {
+                path: String::from_utf16_lossy(bstr_as_u16_slice(&path)),
+                file_specification: String::from_utf16_lossy(bstr_as_u16_slice(&filespec)),
+                recursive: file
+                    .get_recursive()
+                    .map_err(SessionError::vss("GetRecursive"))?,
+            });
+            Ok(())
+        };
+        for file in component
+            .files()
+            .map_err(SessionError::vss("GetComponentInfo"))?
+        {
+            add_file(file.map_err(SessionError::vss("GetFile"))?)?;
+        }
+        for file in component
+            .database_files()
+            .map_err(SessionError::vss("GetComponentInfo"))?
+        {
+            add_file(file.map_err(SessionError::vss("GetDatabaseFile"))?)?;
+        }
+        for file in component
+            .log_files()
+            .map_err(SessionError::vss("GetComponentInfo"))?
+        {
+            add_file(file.map_err(SessionError::vss("GetDatabaseLogFile"))?)?;
+        }
+        components.push(DryRunComponent {
+            logical_path: info
+                .logical_path()
+                .map(|path| String::from_utf16_lossy(bstr_as_u16_slice(path))),
+            name: String::from_utf16_lossy(bstr_as_u16_slice(info.component_name())),
+            selectable: info.selectable(),
+            files,
+        });
+    }
+    Ok(DryRunWriter {
+        name: String::from_utf16_lossy(bstr_as_u16_slice(&identity.writer_name)),
+        writer_id: guid_to_string(&identity.writer),
+        instance_id: guid_to_string(&identity.instance),
+        components,
+    })
+}
+
+/// A shadow copy of a single volume that is part of a [`SnapshotSet`].
+pub struct VolumeSnapshot {
+    volume: String,
+    snapshot_id: VSS_ID,
+    properties: SnapshotProperties,
+}
+impl VolumeSnapshot {
+    /// The volume name that was passed to [`SnapshotBuilder::volume`].
+    pub fn volume(&self) -> &str {
+        &self.volume
+    }
+    /// Identifies the shadow copy.
+    pub fn snapshot_id(&self) -> VSS_ID {
+        self.snapshot_id
+    }
+    /// The properties of the shadow copy. Use
+    /// [`SnapshotProperties::snapshot_device_object`] to get the path that
+    /// files should be read from.
+    pub fn properties(&self) -> &SnapshotProperties {
+        &self.properties
+    }
+    /// The device object of the shadow copy as a path. Join a path relative
+    /// to the root of the volume to it in order to read a file from the
+    /// shadow copy.
+    pub fn device_path(&self) -> PathBuf {
+        PathBuf::from(self.properties.snapshot_device_object().to_os_string())
+    }
+}
+
+/// A set of temporary shadow copies created by [`SnapshotBuilder::create`].
+///
+/// The shadow copies are deleted by [`SnapshotSet::finish`]. If the set is
+/// dropped without calling that method then the backup is aborted instead.
+pub struct SnapshotSet {
+    /// `None` after the backup has been completed. Aborts the backup when
+    /// dropped.
+    backup_comp: Option<PreparedBackup>,
+    snapshot_set_id: VSS_ID,
+    snapshots: Vec<VolumeSnapshot>,
+    consistency: Consistency,
+    failed_writers: Vec<FailedWriter>,
+    writer_reports: Vec<WriterReport>,
+    started: Instant,
+    /// `finish` and `total` are set by `finish_with_report`.
+    durations: JobDurations,
+    bytes_copied: u64,
+    errors: Vec<String>,
+    timeout: Option<u32>,
+    hooks: Hooks,
+    progress: ProgressReporter,
+}
+impl SnapshotSet {
+    /// The backup components object used to create the snapshot set.
+    pub fn backup_components(&self) -> &BackupComponents {
+        self.backup_comp
+            .as_deref()
+            .expect("backup components are only removed when the set is finished")
+    }
+    /// Identifies the snapshot set.
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        self.snapshot_set_id
+    }
+    /// The shadow copies in the set, in the same order as the volumes were
+    /// added to the [`SnapshotBuilder`].
+    pub fn snapshots(&self) -> &[VolumeSnapshot] {
+        &self.snapshots
+    }
+    /// Whether writers prepared their data before the shadow copies were
+    /// created. Writerless snapshots are only crash consistent.
+    pub fn consistency(&self) -> Consistency {
+        self.consistency
+    }
+    /// Writers that failed while the snapshot set was created. Only non-empty
+    /// when using [`WriterFailurePolicy::ContinueWithoutWriter`].
+    pub fn failed_writers(&self) -> &[FailedWriter] {
+        &self.failed_writers
+    }
+    /// How long the steps of creating the snapshot set took. Compare
+    /// [`JobDurations::freeze_window`] with [`WRITER_FREEZE_TIMEOUT`] to see
+    /// how close the writers came to timing out.
+    ///
+    /// [`JobDurations::finish`] and [`JobDurations::total`] are zero here,
+    /// they are only known in the report returned by
+    /// [`SnapshotSet::finish_with_report`].
+    pub fn durations(&self) -> JobDurations {
+        self.durations
+    }
+    /// Add to the number of bytes that were copied from the shadow copies,
+    /// reported by [`SnapshotSet::finish_with_report`].
+    pub fn record_bytes_copied(&mut self, bytes: u64) {
+        self.bytes_copied += bytes;
+    }
+    /// Record an error that happened while using the shadow copies, for
+    /// example a file that couldn't be copied. The error is included in the
+    /// report returned by [`SnapshotSet::finish_with_report`].
+    pub fn record_error(&mut self, error: impl fmt::Display) {
+        self.errors.push(error.to_string());
+    }
+    /// Complete the backup and delete the shadow copies.
+    pub fn finish(mut self) -> Result<(), SessionError> {
+        self.complete()
+    }
+    /// Complete the backup and delete the shadow copies like
+    /// [`SnapshotSet::finish`] and return a report describing the whole job.
+    /// Errors are recorded in the report instead of being returned. If the
+    /// job didn't succeed then the events that VSS logged while it ran are
+    /// included as well.
+    pub fn finish_with_report(mut self) -> JobReport {
+        let components = self.component_reports().unwrap_or_else(|e| {
+            self.errors.push(e.to_string());
+            Vec::new()
+        });
+        let finish_started = Instant::now();
+        if let Err(e) = self.complete() {
+            self.errors.push(e.to_string());
+        }
+        let outcome = if !self.errors.is_empty() {
+            JobOutcome::Failed
+        } else if !self.failed_writers.is_empty() {
+            JobOutcome::PartiallySucceeded
+        } else {
+            JobOutcome::Succeeded
+        };
+        let mut report = JobReport {
+            outcome,
+            snapshot_set_id: Some(guid_to_string(&self.snapshot_set_id)),
+            snapshots: self.snapshot_reports(),
+            writers: std::mem::take(&mut self.writer_reports),
+            components,
+            bytes_copied: self.bytes_copied,
+            durations: JobDurations {
+                finish: finish_started.elapsed(),
+                total: self.started.elapsed(),
+                ..self.durations
+            },
+            errors: std::mem::take(&mut self.errors),
+            events: Vec::new(),
+        };
+        if outcome != JobOutcome::Succeeded {
+            let started = SystemTime::now()
+                .checked_sub(self.started.elapsed())
+                .unwrap_or_else(SystemTime::now);
+            report.collect_vss_events(started);
+        }
+        report
+    }
+    /// Collect the outcome of every component in the Backup Components
+    /// Document.
+    fn component_reports(&self) -> Result<Vec<ComponentReport>, SessionError> {
+        let backup_comp = self.backup_components();
+        let writer_count = backup_comp
+            .get_writer_components_count()
+            .map_err(SessionError::vss("GetWriterComponentsCount"))?;
+        let mut reports = Vec::new();
+        for writer_index in 0..writer_count {
+            let writer = backup_comp
+                .get_writer_components(writer_index)
+                .map_err(SessionError::vss("GetWriterComponents"))?;
+            let info = writer
+                .get_writer_info()
+                .map_err(SessionError::vss("GetWriterInfo"))?;
+            let component_count = writer
+                .get_component_count()
+                .map_err(SessionError::vss("GetComponentCount"))?;
+            for component_index in 0..component_count {
+                let component = writer
+                    .get_component(component_index)
+                    .map_err(SessionError::vss("GetComponent"))?;
+                let logical_path = component
+                    .get_logical_path()
+                    .map_err(SessionError::vss("GetLogicalPath"))?;
+                let name = component
+                    .get_component_name()
+                    .map_err(SessionError::vss("GetComponentName"))?;
+                reports.push(ComponentReport {
+                    writer_id: guid_to_string(&info.writer_id),
+                    logical_path: logical_path
+                        .map(|path| String::from_utf16_lossy(bstr_as_u16_slice(&path))),
+                    name: String::from_utf16_lossy(bstr_as_u16_slice(&name)),
+                    backup_succeeded: component
+                        .get_backup_succeeded()
+                        .map_err(SessionError::vss("GetBackupSucceeded"))?,
+                });
+            }
+        }
+        Ok(reports)
+    }
+    /// Complete the backup and package the snapshot set so that it can be
+    /// imported on another machine, see [`SnapshotBuilder::transportable`].
+    ///
+    /// The Backup Components Document is saved after the shadow copies were
+    /// created and contains everything that is needed to import them. Unlike
+    /// [`SnapshotSet::finish`] the shadow copies aren't deleted.
+    ///
+    /// If `break_flags` is `Some` then the snapshot set is broken with
+    /// `BreakSnapshotSetEx` after the backup has been completed, for example
+    /// with [`HardwareOptions::BREAKEX_FLAG_MASK_LUNS`] to hide the shadow
+    /// copy LUNs from this machine before they are moved.
+    pub fn export(
+        mut self,
+        break_flags: Option<RawBitFlags<HardwareOptions>>,
+    ) -> Result<TransportablePackage, SessionError> {
+        let document = self
+            .backup_components()
+            .save_as_xml()
+            .map_err(SessionError::vss("SaveAsXML"))?;
+        self.run_hooks(SessionPhase::BeforeBackupComplete)?;
+        self.progress.report(ProgressPhase::CompletingBackup);
+        if self.consistency == Consistency::ApplicationConsistent {
+            wait(
+                "BackupComplete",
+                self.backup_components()
+                    .backup_complete()
+                    .map_err(SessionError::vss("BackupComplete"))?,
+                self.timeout,
+            )?;
+        }
+        // The backup was completed so it must not be aborted anymore.
+        let backup_comp = self
+            .backup_comp
+            .take()
+            .expect("backup components are only removed when the set is finished")
+            .into_backup_components();
+        if let Some(break_flags) = break_flags {
+            let backup_comp_ex2 =
+                backup_comp
+                    .query::<BackupComponentsEx2>()
+                    .ok_or(SessionError::NotSupported {
+                        operation: "BreakSnapshotSetEx",
+                    })?;
+            wait(
+                "BreakSnapshotSetEx",
+                backup_comp_ex2
+                    .break_snapshot_set_ex(self.snapshot_set_id, break_flags)
+                    .map_err(SessionError::vss("BreakSnapshotSetEx"))?,
+                self.timeout,
+            )?;
+        }
+        Ok(TransportablePackage {
+            snapshot_set_id: guid_to_string(&self.snapshot_set_id),
+            snapshots: self.snapshot_reports(),
+            backup_components_document: String::from_utf16_lossy(bstr_as_u16_slice(&document)),
+            broken: break_flags.is_some(),
+        })
+    }
+    /// Collect everything that is needed to restore from this backup later,
+    /// see [`BackupManifest`].
+    ///
+    /// Call this after marking components as backed up so that the saved
+    /// Backup Components Document records the outcome.
+    #[doc(alias = "SaveAsXML")]
+    pub fn manifest(&self) -> Result<BackupManifest, SessionError> {
+        let backup_comp = self.backup_components();
+        let document = backup_comp
+            .save_as_xml()
+            .map_err(SessionError::vss("SaveAsXML"))?;
+        let mut writer_metadata = Vec::new();
+        if self.consistency == Consistency::ApplicationConsistent {
+            for metadata in backup_comp
+                .writer_metadata_iter()
+                .map_err(SessionError::vss("GetWriterMetadataCount"))?
+            {
+                let (_, metadata) = metadata.map_err(SessionError::vss("GetWriterMetadata"))?;
+                let xml = metadata
+                    .save_as_xml()
+                    .map_err(SessionError::vss("SaveAsXML"))?;
+                writer_metadata.push(String::from_utf16_lossy(bstr_as_u16_slice(&xml)));
+            }
+        }
+        Ok(BackupManifest {
+            snapshot_set_id: guid_to_string(&self.snapshot_set_id),
+            snapshots: self.snapshot_reports(),
+            backup_components_document: String::from_utf16_lossy(bstr_as_u16_slice(&document)),
+            writer_metadata,
+            backup_stamps: self.backup_stamps()?,
+        })
+    }
+    /// Collect the backup stamps that writers stored with the components in
+    /// the Backup Components Document.
+    #[doc(alias = "GetBackupStamp")]
+    fn backup_stamps(&self) -> Result<Vec<BackupStamp>, SessionError> {
+        let mut stamps = Vec::new();
+        for writer in self
+            .backup_components()
+            .writer_components_iter()
+            .map_err(SessionError::vss("GetWriterComponentsCount"))?
+        {
+            let writer = writer.map_err(SessionError::vss("GetWriterComponents"))?;
+            let info = writer
+                .get_writer_info()
+                .map_err(SessionError::vss("GetWriterInfo"))?;
+            for component in writer
+                .components()
+                .map_err(SessionError::vss("GetComponentCount"))?
+            {
+                let component = component.map_err(SessionError::vss("GetComponent"))?;
+                let stamp = match component
+                    .get_backup_stamp()
+                    .map_err(SessionError::vss("GetBackupStamp"))?
+                {
+                    Some(stamp) => stamp,
+                    None => continue,
+                };
+                let logical_path = component
+                    .get_logical_path()
+                    .map_err(SessionError::vss("GetLogicalPath"))?;
+                let name = component
+                    .get_component_name()
+                    .map_err(SessionError::vss("GetComponentName"))?;
+                stamps.push(BackupStamp {
+                    writer_id: guid_to_string(&info.writer_id),
+                    logical_path: logical_path
+                        .map(|path| String::from_utf16_lossy(bstr_as_u16_slice(&path))),
+                    component_name: String::from_utf16_lossy(bstr_as_u16_slice(&name)),
+                    stamp: String::from_utf16_lossy(bstr_as_u16_slice(&stamp)),
+                });
+            }
+        }
+        Ok(stamps)
+    }
+    fn snapshot_reports(&self) -> Vec<SnapshotReport> {
+        self.snapshots
+            .iter()
+            .map(|snapshot| SnapshotReport {
+                volume: snapshot.volume.clone(),
+                snapshot_id: guid_to_string(&snapshot.snapshot_id),
+                device_object: snapshot
+                    .properties
+                    .snapshot_device_object()
+                    .to_string_lossy(),
+            })
+            .collect()
+    }
+    /// Call the hooks that were registered for `phase`.
+    fn run_hooks(&self, phase: SessionPhase) -> Result<(), SessionError> {
+        for (hook_phase, hook) in &self.hooks.0 {
+            if *hook_phase == phase {
+                hook(self).map_err(|source| SessionError::Hook { phase, source })?;
+            }
+        }
+        Ok(())
+    }
+    fn complete(&mut self) -> Result<(), SessionError> {
+        self.run_hooks(SessionPhase::BeforeBackupComplete)?;
+        self.progress.report(ProgressPhase::CompletingBackup);
+        let prepared = self
+            .backup_comp
+            .take()
+            .expect("backup components are only removed when the set is finished");
+        let result = match self.consistency {
+            Consistency::ApplicationConsistent => prepared
+                .backup_complete()
+                .map_err(SessionError::vss("BackupComplete"))
+                .and_then(|vss_async| wait("BackupComplete", vss_async, self.timeout)),
+            Consistency::CrashConsistent => Ok(()),
+        };
+        if let Err(e) = result {
+            let _ = prepared.abort();
+            return Err(e);
+        }
+        let backup_comp = prepared.into_backup_components();
+        self.progress.report(ProgressPhase::DeletingSnapshots);
+        let deleted =
+            backup_comp.delete_snapshots(self.snapshot_set_id, ObjectType::SnapshotSet, false);
+        if let Some(e) = deleted.error {
+            return Err(SessionError::vss("DeleteSnapshots")(e));
+        }
+        self.progress.report(ProgressPhase::Finished);
+        Ok(())
+    }
+}
+
+/// Create a shadow copy of a single volume, pass it to `f` and then clean up.
+///
+/// `options` configures how the shadow copy is created; any volumes that were
+/// already added to it are included in the snapshot set as well, but only
+/// the shadow copy of `volume` is passed to `f`.
+///
+/// When `f` returns the backup is completed and the shadow copies are deleted
+/// like [`SnapshotSet::finish`]. If `f` panics the backup is aborted and the
+/// shadow copies are deleted before the panic is resumed, so they are never
+/// left behind by this function.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs;
+/// use volume_shadow_copy::session::{with_snapshot, SnapshotBuilder};
+///
+/// let com = volume_shadow_copy::initialize_com_scoped()?;
+/// let hosts = with_snapshot(&com, "C:\\", &SnapshotBuilder::new(), |snapshot| {
+///     fs::read(snapshot.device_path().join("Windows/System32/drivers/etc/hosts"))
+/// })??;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn with_snapshot<R>(
+    com: &ComGuard,
+    volume: &str,
+    options: &SnapshotBuilder,
+    f: impl FnOnce(&VolumeSnapshot) -> R,
+) -> Result<R, SessionError> {
+    let mut snapshot_set = options.clone().volume(volume).create(com)?;
+    let snapshot = snapshot_set
+        .snapshots()
+        .last()
+        .expect("the volume was added to the snapshot set");
+    match panic::catch_unwind(AssertUnwindSafe(|| f(snapshot))) {
+        Ok(result) => {
+            snapshot_set.finish()?;
+            Ok(result)
+        }
+        Err(panic) => {
+            if let Some(prepared) = snapshot_set.backup_comp.take() {
+                let backup_comp = prepared.into_backup_components();
+                let _ = backup_comp.abort_backup();
+                let _ = backup_comp.delete_snapshots(
+                    snapshot_set.snapshot_set_id,
+                    ObjectType::SnapshotSet,
+                    true,
+                );
+            }
+            panic::resume_unwind(panic)
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Transportable snapshots
+////////////////////////////////////////////////////////////////////////////////
+
+/// A transportable snapshot set returned by [`SnapshotSet::export`].
+///
+/// Ship this together with the shadow copy LUNs to the machine that should
+/// import them with [`import_transportable`]. It holds the properties of each
+/// shadow copy and the Backup Components Document that VSS needs to import
+/// them, which can be stored as text.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransportablePackage {
+    /// Identifies the snapshot set.
+    pub snapshot_set_id: String,
+    /// The shadow copies in the set.
+    pub snapshots: Vec<SnapshotReport>,
+    /// The Backup Components Document saved after the shadow copies were
+    /// created. [`import_transportable`] passes it to `InitializeForBackup`.
+    pub backup_components_document: String,
+    /// Whether the snapshot set was broken with `BreakSnapshotSetEx` before
+    /// it was exported.
+    pub broken: bool,
+}
+
+/// Shadow copies imported by [`import_transportable`].
+///
+/// Shadow copies that were created with an auto-release context, which is
+/// the case for snapshots created by [`SnapshotBuilder`], are deleted when
+/// this is dropped.
+pub struct ImportedSnapshotSet {
+    backup_comp: BackupComponents,
+    snapshot_set_id: VSS_ID,
+    snapshots: Vec<VolumeSnapshot>,
+}
+impl ImportedSnapshotSet {
+    /// The backup components object used to import the snapshot set.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Identifies the snapshot set.
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        self.snapshot_set_id
+    }
+    /// The imported shadow copies, in the same order as in the
+    /// [`TransportablePackage`]. [`VolumeSnapshot::volume`] is the name of the
+    /// volume on the machine where the shadow copy was created.
+    pub fn snapshots(&self) -> &[VolumeSnapshot] {
+        &self.snapshots
+    }
+}
+
+/// Import a snapshot set that was exported on another machine with
+/// [`SnapshotSet::export`].
+///
+/// The shadow copy LUNs must have been made visible to this machine before
+/// this is called. The backup components object is initialized with the
+/// Backup Components Document from the package, then `ImportSnapshots` is
+/// called and the properties of every imported shadow copy are queried so
+/// that their device objects on this machine are known.
+#[doc(alias = "ImportSnapshots")]
+pub fn import_transportable(
+    _com: &ComGuard,
+    package: &TransportablePackage,
+    timeout: Option<u32>,
+) -> Result<ImportedSnapshotSet, SessionError> {
+    let parse_id =
+        |id: &str| guid_from_string(id).ok_or_else(|| SessionError::InvalidPackage(id.to_owned()));
+    let snapshot_set_id = parse_id(&package.snapshot_set_id)?;
+    let snapshot_ids = package
+        .snapshots
+        .iter()
+        .map(|snapshot| parse_id(&snapshot.snapshot_id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let document: Vec<u16> = package.backup_components_document.encode_utf16().collect();
+    let document = u16_slice_to_bstring(&document);
+    let backup_comp =
+        BackupComponents::new().map_err(SessionError::vss("CreateVssBackupComponents"))?;
+    backup_comp
+        .initialize_for_backup(Some(&document))
+        .map_err(SessionError::vss("InitializeForBackup"))?;
+    wait(
+        "ImportSnapshots",
+        backup_comp
+            .import_snapshots()
+            .map_err(SessionError::vss("ImportSnapshots"))?,
+        timeout,
+    )?;
+
+    let mut snapshots = Vec::with_capacity(snapshot_ids.len());
+    for (report, snapshot_id) in package.snapshots.iter().zip(snapshot_ids) {
+        let properties = backup_comp
+            .get_snapshot_properties(snapshot_id)
+            .map_err(SessionError::vss("GetSnapshotProperties"))?;
+        snapshots.push(VolumeSnapshot {
+            volume: report.volume.clone(),
+            snapshot_id,
+            properties,
+        });
+    }
+    Ok(ImportedSnapshotSet {
+        backup_comp,
+        snapshot_set_id,
+        snapshots,
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Backup phases
+////////////////////////////////////////////////////////////////////////////////
+
+// The types below wrap a backup components object and only expose the VSS
+// calls that are allowed in the current phase of a backup. Each phase
+// transition consumes the old type so that calls can't be made out of order.
+
+/// The first phase of a backup where the backup components object has been
+/// initialized for backup.
+///
+/// The phases of a backup are:
+///
+/// 1. [`InitializedForBackup`]
+/// 2. [`WriterMetadataGathered`]
+/// 3. [`SnapshotSetStarted`]
+/// 4. [`PreparedForBackup`]
+/// 5. [`SnapshotsCreated`]
+/// 6. [`BackupCompleted`]
+///
+/// Each phase has a `backup_components` method that can be used for calls
+/// that don't change the phase, such as examining writer metadata or adding
+/// components. Calling methods that belong to another phase through it
+/// bypasses the checks that these types provide.
+///
+/// # Examples
+///
+/// ```no_run
+/// use volume_shadow_copy::session::InitializedForBackup;
+/// use widestring::U16CString;
+///
+/// let com = volume_shadow_copy::initialize_com_scoped()?;
+/// let volume = U16CString::from_str("C:\\")?;
+/// let mut snapshot_set = InitializedForBackup::new(&com, &Default::default(), None)?
+///     .gather_writer_metadata()?
+///     .start_snapshot_set()?;
+/// let snapshot_id = snapshot_set.add_to_snapshot_set(&volume, None)?;
+/// let snapshots = snapshot_set.prepare_for_backup()?.do_snapshot_set()?;
+/// let properties = snapshots.snapshot_properties(snapshot_id)?;
+/// // Read files from `properties.snapshot_device_object()` here.
+/// snapshots.backup_complete()?.delete_snapshots()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// Calls that don't belong to the current phase don't compile:
+///
+/// ```compile_fail
+/// use volume_shadow_copy::session::InitializedForBackup;
+///
+/// let com = volume_shadow_copy::initialize_com_scoped()?;
+/// let backup = InitializedForBackup::new(&com, &Default::default(), None)?;
+/// backup.do_snapshot_set()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct InitializedForBackup {
+    backup_comp: BackupComponents,
+    timeout: Option<u32>,
+}
+impl InitializedForBackup {
+    /// Create a backup components object with
+    /// [`BackupComponents::for_backup`].
+    ///
+    /// `timeout` is the maximum time in milliseconds to wait for each
+    /// asynchronous VSS operation in the following phases. `None` waits
+    /// forever.
+    pub fn new(
+        _com: &ComGuard,
+        options: &BackupOptions,
+        timeout: Option<u32>,
+    ) -> Result<Self, SessionError> {
+        let backup_comp = BackupComponents::for_backup(options).map_err(|e| {
+            let operation = match &e {
+                ForBackupError::Create(_) => "CreateVssBackupComponents",
+                ForBackupError::InitializeForBackup(_) => "InitializeForBackup",
+                ForBackupError::SetContext(_) => "SetContext",
+                ForBackupError::SetBackupState(_) => "SetBackupState",
+            };
+            SessionError::vss(operation)(e)
+        })?;
+        Ok(Self {
+            backup_comp,
+            timeout,
+        })
+    }
+    /// The backup components object used for the backup.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Gather the metadata of the writers on the system.
+    #[doc(alias = "GatherWriterMetadata")]
+    pub fn gather_writer_metadata(self) -> Result<WriterMetadataGathered, SessionError> {
+        wait(
+            "GatherWriterMetadata",
+            self.backup_comp
+                .gather_writer_metadata()
+                .map_err(SessionError::vss("GatherWriterMetadata"))?,
+            self.timeout,
+        )?;
+        Ok(WriterMetadataGathered {
+            backup_comp: self.backup_comp,
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// The phase of a backup where writer metadata has been gathered, see
+/// [`InitializedForBackup`].
+pub struct WriterMetadataGathered {
+    backup_comp: BackupComponents,
+    timeout: Option<u32>,
+}
+impl WriterMetadataGathered {
+    /// The backup components object used for the backup.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Create a new, empty shadow copy set.
+    #[doc(alias = "StartSnapshotSet")]
+    pub fn start_snapshot_set(self) -> Result<SnapshotSetStarted, SessionError> {
+        let snapshot_set_id = self
+            .backup_comp
+            .start_snapshot_set()
+            .map_err(SessionError::vss("StartSnapshotSet"))?;
+        // The backup must be aborted if it ends after the shadow copy set has
+        // been started.
+        Ok(SnapshotSetStarted {
+            backup_comp: PreparedBackup::new(self.backup_comp),
+            snapshot_set_id,
+            snapshot_ids: Vec::new(),
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// The phase of a backup where volumes are added to the shadow copy set, see
+/// [`InitializedForBackup`].
+///
+/// The backup is aborted if this is dropped.
+pub struct SnapshotSetStarted {
+    backup_comp: PreparedBackup,
+    snapshot_set_id: VSS_ID,
+    snapshot_ids: Vec<VSS_ID>,
+    timeout: Option<u32>,
+}
+impl SnapshotSetStarted {
+    /// The backup components object used for the backup.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Identifies the shadow copy set.
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        self.snapshot_set_id
+    }
+    /// Add a volume to the shadow copy set and return the identifier of the
+    /// shadow copy that will be created for it. `None` lets VSS choose the
+    /// provider.
+    #[doc(alias = "AddToSnapshotSet")]
+    pub fn add_to_snapshot_set(
+        &mut self,
+        volume_name: &U16CStr,
+        provider_id: Option<VSS_ID>,
+    ) -> Result<VSS_ID, SessionError> {
+        let snapshot_id = self
+            .backup_comp
+            .add_to_snapshot_set(volume_name, provider_id)
+            .map_err(SessionError::vss("AddToSnapshotSet"))?;
+        self.snapshot_ids.push(snapshot_id);
+        Ok(snapshot_id)
+    }
+    /// Notify writers to prepare for the backup.
+    #[doc(alias = "PrepareForBackup")]
+    pub fn prepare_for_backup(self) -> Result<PreparedForBackup, SessionError> {
+        wait(
+            "PrepareForBackup",
+            self.backup_comp
+                .prepare_for_backup()
+                .map_err(SessionError::vss("PrepareForBackup"))?,
+            self.timeout,
+        )?;
+        Ok(PreparedForBackup {
+            backup_comp: self.backup_comp,
+            snapshot_set_id: self.snapshot_set_id,
+            snapshot_ids: self.snapshot_ids,
+            timeout: self.timeout,
+        })
+    }
+    /// Abort the backup now instead of when this is dropped so that any error
+    /// can be handled.
+    #[doc(alias = "AbortBackup")]
+    pub fn abort(self) -> Result<(), SessionError> {
+        self.backup_comp
+            .abort()
+            .map_err(SessionError::vss("AbortBackup"))
+    }
+}
+
+/// The phase of a backup where writers have prepared for the shadow copies to
+/// be created, see [`InitializedForBackup`].
+///
+/// The backup is aborted if this is dropped.
+pub struct PreparedForBackup {
+    backup_comp: PreparedBackup,
+    snapshot_set_id: VSS_ID,
+    snapshot_ids: Vec<VSS_ID>,
+    timeout: Option<u32>,
+}
+impl PreparedForBackup {
+    /// The backup components object used for the backup.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Identifies the shadow copy set.
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        self.snapshot_set_id
+    }
+    /// Create the shadow copies. The backup is aborted if this fails.
+    #[doc(alias = "DoSnapshotSet")]
+    pub fn do_snapshot_set(self) -> Result<SnapshotsCreated, SessionError> {
+        wait(
+            "DoSnapshotSet",
+            self.backup_comp
+                .do_snapshot_set()
+                .map_err(SessionError::vss("DoSnapshotSet"))?,
+            self.timeout,
+        )?;
+        Ok(SnapshotsCreated {
+            backup_comp: self.backup_comp,
+            snapshot_set_id: self.snapshot_set_id,
+            snapshot_ids: self.snapshot_ids,
+            timeout: self.timeout,
+        })
+    }
+    /// Abort the backup now instead of when this is dropped so that any error
+    /// can be handled.
+    #[doc(alias = "AbortBackup")]
+    pub fn abort(self) -> Result<(), SessionError> {
+        self.backup_comp
+            .abort()
+            .map_err(SessionError::vss("AbortBackup"))
+    }
+}
+
+/// The phase of a backup where the shadow copies exist and files can be
+/// backed up from them, see [`InitializedForBackup`].
+///
+/// The backup is aborted if this is dropped.
+pub struct SnapshotsCreated {
+    backup_comp: PreparedBackup,
+    snapshot_set_id: VSS_ID,
+    snapshot_ids: Vec<VSS_ID>,
+    timeout: Option<u32>,
+}
+impl SnapshotsCreated {
+    /// The backup components object used for the backup.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Identifies the shadow copy set.
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        self.snapshot_set_id
+    }
+    /// The shadow copies that were created, in the order that the volumes
+    /// were added.
+    pub fn snapshot_ids(&self) -> &[VSS_ID] {
+        &self.snapshot_ids
+    }
+    /// Get the properties of one of the shadow copies, for example to find
+    /// the device object that files should be read from.
+    #[doc(alias = "GetSnapshotProperties")]
+    pub fn snapshot_properties(
+        &self,
+        snapshot_id: VSS_ID,
+    ) -> Result<SnapshotProperties, SessionError> {
+        self.backup_comp
+            .get_snapshot_properties(snapshot_id)
+            .map_err(SessionError::vss("GetSnapshotProperties"))
+    }
+    /// Notify writers that the backup is complete. The backup is aborted if
+    /// this fails.
+    #[doc(alias = "BackupComplete")]
+    pub fn backup_complete(self) -> Result<BackupCompleted, SessionError> {
+        wait(
+            "BackupComplete",
+            self.backup_comp
+                .backup_complete()
+                .map_err(SessionError::vss("BackupComplete"))?,
+            self.timeout,
+        )?;
+        Ok(BackupCompleted {
+            backup_comp: self.backup_comp.into_backup_components(),
+            snapshot_set_id: self.snapshot_set_id,
+            snapshot_ids: self.snapshot_ids,
+        })
+    }
+    /// Abort the backup now instead of when this is dropped so that any error
+    /// can be handled.
+    #[doc(alias = "AbortBackup")]
+    pub fn abort(self) -> Result<(), SessionError> {
+        self.backup_comp
+            .abort()
+            .map_err(SessionError::vss("AbortBackup"))
+    }
+}
+
+/// The last phase of a backup, see [`InitializedForBackup`].
+///
+/// The shadow copies are not deleted automatically. Shadow copies in an
+/// auto-release context are deleted when the backup components object is
+/// released, others can be deleted with [`BackupCompleted::delete_snapshots`].
+pub struct BackupCompleted {
+    backup_comp: BackupComponents,
+    snapshot_set_id: VSS_ID,
+    snapshot_ids: Vec<VSS_ID>,
+}
+impl BackupCompleted {
+    /// The backup components object used for the backup, for example to save
+    /// the Backup Components Document.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Identifies the shadow copy set.
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        self.snapshot_set_id
+    }
+    /// The shadow copies that were created, in the order that the volumes
+    /// were added.
+    pub fn snapshot_ids(&self) -> &[VSS_ID] {
+        &self.snapshot_ids
+    }
+    /// Delete the shadow copy set.
+    #[doc(alias = "DeleteSnapshots")]
+    pub fn delete_snapshots(&self) -> Result<(), SessionError> {
+        let deleted =
+            self.backup_comp
+                .delete_snapshots(self.snapshot_set_id, ObjectType::SnapshotSet, false);
+        match deleted.error {
+            Some(e) => Err(SessionError::vss("DeleteSnapshots")(e)),
+            None => Ok(()),
+        }
+    }
+    /// Get the backup components object.
+    pub fn into_backup_components(self) -> BackupComponents {
+        self.backup_comp
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Component selection
+////////////////////////////////////////////////////////////////////////////////
+
+/// Whether a [`SelectionRule`] includes or excludes what it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SelectionAction {
+    /// Back up the matching writers or components.
+    Include,
+    /// Don't back up the matching writers or components.
+    Exclude,
+}
+
+/// A rule in a [`SelectionConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelectionRule {
+    /// The name of the writer, for example `"SqlServerWriter"`, or its writer
+    /// class id in the form `{XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX}`. Case is
+    /// ignored.
+    pub writer: String,
+    /// The logical path of a component followed by its name, for example
+    /// `"MSSQLSERVER\master"`. The rule also matches every component below
+    /// that path. `None` matches every component of the writer.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub component: Option<String>,
+    /// What to do with the matching writers or components.
+    pub action: SelectionAction,
+}
+impl SelectionRule {
+    fn matches_writer(&self, writer_name: &str, writer_id: &str) -> bool {
+        self.writer.eq_ignore_ascii_case(writer_name) || self.writer.eq_ignore_ascii_case(writer_id)
+    }
+}
+
+/// Decides which writers and components are included in a component-mode
+/// backup.
+///
+/// Rules are checked in order and the last rule that matches a component
+/// decides whether it is backed up, so more specific rules should come after
+/// general ones. Components that no rule matches are backed up if
+/// [`SelectionConfig::include_by_default`] is `true`.
+///
+/// With the `serde` feature the configuration can be loaded from any format
+/// that serde supports, for example TOML:
+///
+/// ```toml
+/// include_by_default = false
+///
+/// [[rules]]
+/// writer = "SqlServerWriter"
+/// action = "Include"
+///
+/// [[rules]]
+/// writer = "SqlServerWriter"
+/// component = "MSSQLSERVER\\tempdb"
+/// action = "Exclude"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SelectionConfig {
+    /// Whether writers and components that no rule matches are backed up.
+    /// Defaults to `true`.
+    pub include_by_default: bool,
+    /// The include and exclude rules.
+    pub rules: Vec<SelectionRule>,
+}
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl SelectionConfig {
+    /// Create a configuration without rules that includes everything.
+    pub fn new() -> Self {
+        Self {
+            include_by_default: true,
+            rules: Vec::new(),
+        }
+    }
+    /// Add a rule to the end of the configuration.
+    pub fn rule(mut self, writer: &str, component: Option<&str>, action: SelectionAction) -> Self {
+        self.rules.push(SelectionRule {
+            writer: writer.to_owned(),
+            component: component.map(|path| path.replace('/', "\\")),
+            action,
+        });
+        self
+    }
+    /// Check if a writer is included when its components aren't considered,
+    /// using only the rules without a [`SelectionRule::component`].
+    pub fn is_writer_selected(&self, writer_name: &str, writer_id: &VSS_ID) -> bool {
+        self.evaluate(writer_name, &guid_to_string(writer_id), None)
+    }
+    /// Check if a component is included. `component_path` is the logical path
+    /// of the component followed by its name, separated by a backslash.
+    pub fn is_component_selected(
+        &self,
+        writer_name: &str,
+        writer_id: &VSS_ID,
+        component_path: &str,
+    ) -> bool {
+        let component_path = component_path.encode_utf16().collect::<Vec<_>>();
+        self.evaluate(
+            writer_name,
+            &guid_to_string(writer_id),
+            Some(&component_path),
+        )
+    }
+    fn evaluate(&self, writer_name: &str, writer_id: &str, component_path: Option<&[u16]>) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                rule.matches_writer(writer_name, writer_id)
+                    && match (&rule.component, component_path) {
+                        (None, _) => true,
+                        (Some(_), None) => false,
+                        (Some(prefix), Some(path)) => paths::starts_with_ignore_case(
+                            path,
+                            &prefix.encode_utf16().collect::<Vec<_>>(),
+                        ),
+                    }
+            })
+            .map_or(self.include_by_default, |rule| {
+                rule.action == SelectionAction::Include
+            })
+    }
+
+    /// Add the components that this configuration selects to the Backup
+    /// Components Document. Writer metadata must have been gathered.
+    ///
+    /// Selectable components are added when they are selected, unless a
+    /// selectable ancestor in their logical path was already added since that
+    /// includes them implicitly. Components that aren't selectable and don't
+    /// have a selectable ancestor are mandatory, so they are added whenever
+    /// their writer is included or any other component of the writer was
+    /// selected. See [`ComponentInfo::selectable`] for the rules that VSS
+    /// uses.
+    ///
+    /// [`ComponentInfo::selectable`]: crate::vsbackup::ComponentInfo::selectable
+    #[doc(alias = "AddComponent")]
+    pub fn select_components(
+        &self,
+        backup_comp: &BackupComponents,
+    ) -> Result<Vec<SelectedComponent>, SessionError> {
+        let mut selected = Vec::new();
+        for metadata in backup_comp
+            .writer_metadata_iter()
+            .map_err(SessionError::vss("GetWriterMetadataCount"))?
+        {
+            let (instance_id, metadata) =
+                metadata.map_err(SessionError::vss("GetWriterMetadata"))?;
+            let identity = metadata
+                .get_identity()
+                .map_err(SessionError::vss("GetIdentity"))?;
+            let writer_name = String::from_utf16_lossy(bstr_as_u16_slice(&identity.writer_name));
+            let writer_id = guid_to_string(&identity.writer);
+            let counts = metadata
+                .get_file_counts()
+                .map_err(SessionError::vss("GetFileCounts"))?;
+
+            let mut components = Vec::with_capacity(counts.total_components as usize);
+            for component_index in 0..counts.total_components {
+                let component = metadata
+                    .get_component(component_index)
+                    .map_err(SessionError::vss("GetComponent"))?;
+                let info = component
+                    .get_component_info()
+                    .map_err(SessionError::vss("GetComponentInfo"))?;
+                components.push(SelectedComponent {
+                    instance_id,
+                    writer_id: identity.writer,
+                    writer_name: writer_name.clone(),
+                    component_type: info.component_type(),
+                    logical_path: info.logical_path().map(bstr_to_u16_cstring),
+                    component_name: bstr_to_u16_cstring(info.component_name()),
+                    selectable: info.selectable(),
+                });
+            }
+            // Ancestors have shorter paths so they are visited first.
+            components.sort_by_key(|component| component.path().len());
+
+            let mut added: Vec<SelectedComponent> = Vec::new();
+            let mut mandatory = Vec::new();
+            for component in &components {
+                let parent = component
+                    .logical_path
+                    .as_ref()
+                    .map_or(&[][..], |path| path.as_slice());
+                let has_ancestor = |candidates: &[SelectedComponent], selectable_only: bool| {
+                    candidates.iter().any(|ancestor| {
+                        (!selectable_only || ancestor.selectable)
+                            && paths::starts_with_ignore_case(parent, &ancestor.path())
+                    })
+                };
+                if !component.selectable {
+                    if !has_ancestor(&components, true) {
+                        mandatory.push(component.clone());
+                    }
+                } else if self.evaluate(&writer_name, &writer_id, Some(&component.path()))
+                    && !has_ancestor(&added, false)
+                {
+                    added.push(component.clone());
+                }
+            }
+            if !added.is_empty() || self.evaluate(&writer_name, &writer_id, None) {
+                added.extend(mandatory);
+            }
+            for component in added {
+                backup_comp
+                    .component(component.spec())
+                    .add_component(instance_id)
+                    .map_err(SessionError::vss("AddComponent"))?;
+                selected.push(component);
+            }
+        }
+        Ok(selected)
+    }
+}
+
+/// A component that was added to the Backup Components Document by
+/// [`SelectionConfig::select_components`].
+#[derive(Clone)]
+pub struct SelectedComponent {
+    /// Identifier of the writer instance.
+    pub instance_id: VSS_ID,
+    /// Identifier of the writer class.
+    pub writer_id: VSS_ID,
+    /// The name of the writer.
+    pub writer_name: String,
+    /// The type of the component.
+    pub component_type: VssComponentType,
+    /// The logical path of the component, if it has one.
+    pub logical_path: Option<U16CString>,
+    /// The name of the component.
+    pub component_name: U16CString,
+    /// Whether the component is selectable for backup. Components that aren't
+    /// selectable were added because they are mandatory.
+    pub selectable: bool,
+}
+impl SelectedComponent {
+    /// Identifies the component in calls to [`IBackupComponents`] methods.
+    ///
+    /// [`IBackupComponents`]: crate::vsbackup::IBackupComponents
+    pub fn spec(&self) -> ComponentSpec<'_> {
+        ComponentSpec::new(
+            self.writer_id,
+            self.component_type,
+            self.logical_path.as_deref(),
+            &self.component_name,
+        )
+    }
+    /// The logical path of the component followed by its name.
+    fn path(&self) -> Vec<u16> {
+        let mut path = Vec::new();
+        if let Some(logical_path) = &self.logical_path {
+            path.extend_from_slice(logical_path.as_slice());
+            path.push(u16::from(b'\\'));
+        }
+        path.extend_from_slice(self.component_name.as_slice());
+        path
+    }
+}
+impl fmt::Debug for SelectedComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SelectedComponent")
+            .field("writer_name", &self.writer_name)
+            .field("component_type", &self.component_type)
+            .field("logical_path", &self.logical_path)
+            .field("component_name", &self.component_name)
+            .field("selectable", &self.selectable)
+            .finish()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Component backup
+////////////////////////////////////////////////////////////////////////////////
+
+/// Collect the file sets of each selected component, including the files of
+/// subcomponents that are implicitly included with it.
+fn component_file_sets(
+    backup_comp: &BackupComponents,
+    selected: &[SelectedComponent],
+) -> Result<Vec<Vec<FileSet>>, SessionError> {
+    let mut file_sets = vec![Vec::new(); selected.len()];
+    let selected_paths = selected
+        .iter()
+        .map(SelectedComponent::path)
+        .collect::<Vec<_>>();
+    for metadata in backup_comp
+        .writer_metadata_iter()
+        .map_err(SessionError::vss("GetWriterMetadataCount"))?
+    {
+        let (instance_id, metadata) = metadata.map_err(SessionError::vss("GetWriterMetadata"))?;
+        let counts = metadata
+            .get_file_counts()
+            .map_err(SessionError::vss("GetFileCounts"))?;
+        for component_index in 0..counts.total_components {
+            let component = metadata
+                .get_component(component_index)
+                .map_err(SessionError::vss("GetComponent"))?;
+            let info = component
+                .get_component_info()
+                .map_err(SessionError::vss("GetComponentInfo"))?;
+            let mut path = Vec::new();
+            if let Some(logical_path) = info.logical_path() {
+                path.extend_from_slice(bstr_as_u16_slice(logical_path));
+                path.push(u16::from(b'\\'));
+            }
+            path.extend_from_slice(bstr_as_u16_slice(info.component_name()));
+
+            // The files belong to the closest selected ancestor, or to the
+            // component itself if it was selected.
+            let owner = selected
+                .iter()
+                .zip(&selected_paths)
+                .enumerate()
+                .filter(|(_, (selected, selected_path))| {
+                    IsEqualGUID(&selected.instance_id, &instance_id)
+                        && paths::starts_with_ignore_case(&path, selected_path)
+                })
+                .max_by_key(|(_, (_, selected_path))| selected_path.len())
+                .map(|(index, _)| index);
+            let owner = match owner {
+                Some(owner) => owner,
+                None => continue,
+            };
+            for file in component
+                .files()
+                .map_err(SessionError::vss("GetComponentInfo"))?
+            {
+                let file = file.map_err(SessionError::vss("GetFile"))?;
+                file_sets[owner]
+                    .push(FileSet::from_descriptor(&file).map_err(SessionError::vss("GetFile"))?);
+            }
+            for file in component
+                .database_files()
+                .map_err(SessionError::vss("GetComponentInfo"))?
+            {
+                let file = file.map_err(SessionError::vss("GetDatabaseFile"))?;
+                file_sets[owner].push(
+                    FileSet::from_descriptor(&file)
+                        .map_err(SessionError::vss("GetDatabaseFile"))?,
+                );
+            }
+            for file in component
+                .log_files()
+                .map_err(SessionError::vss("GetComponentInfo"))?
+            {
+                let file = file.map_err(SessionError::vss("GetDatabaseLogFile"))?;
+                file_sets[owner].push(
+                    FileSet::from_descriptor(&file)
+                        .map_err(SessionError::vss("GetDatabaseLogFile"))?,
+                );
+            }
+        }
+    }
+    Ok(file_sets)
+}
+
+/// Call `f` for each component in the Backup Components Document that was
+/// selected, together with its index in `selected`.
+fn for_each_selected_component<F>(
+    backup_comp: &BackupComponents,
+    selected: &[SelectedComponent],
+    mut f: F,
+) -> Result<(), SessionError>
+where
+    F: FnMut(usize, &IComponent) -> Result<(), SessionError>,
+{
+    for writer in backup_comp
+        .writer_components_iter()
+        .map_err(SessionError::vss("GetWriterComponentsCount"))?
+    {
+        let writer = writer.map_err(SessionError::vss("GetWriterComponents"))?;
+        let info = writer
+            .get_writer_info()
+            .map_err(SessionError::vss("GetWriterInfo"))?;
+        for component in writer
+            .components()
+            .map_err(SessionError::vss("GetComponentCount"))?
+        {
+            let component = component.map_err(SessionError::vss("GetComponent"))?;
+            let logical_path = component
+                .get_logical_path()
+                .map_err(SessionError::vss("GetLogicalPath"))?
+                .map(|path| bstr_to_u16_cstring(&path));
+            let name = component
+                .get_component_name()
+                .map_err(SessionError::vss("GetComponentName"))?;
+            let name = bstr_to_u16_cstring(&name);
+            let owner = selected.iter().position(|selected| {
+                IsEqualGUID(&selected.instance_id, &info.instance_id)
+                    && selected.logical_path == logical_path
+                    && selected.component_name == name
+            });
+            if let Some(owner) = owner {
+                f(owner, &component)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Collect the file sets that writers reported as changed since the previous
+/// backup for each selected component.
+fn differenced_file_sets(
+    backup_comp: &BackupComponents,
+    selected: &[SelectedComponent],
+) -> Result<Vec<Vec<(FileSet, SystemTime)>>, SessionError> {
+    let mut file_sets = vec![Vec::new(); selected.len()];
+    for_each_selected_component(backup_comp, selected, |owner, component| {
+        let count = component
+            .get_differenced_files_count()
+            .map_err(SessionError::vss("GetDifferencedFilesCount"))?;
+        for file_index in 0..count {
+            let file = component
+                .get_differenced_file(file_index)
+                .map_err(SessionError::vss("GetDifferencedFile"))?;
+            file_sets[owner].push((
+                FileSet::new(
+                    bstr_as_u16_slice(&file.path),
+                    bstr_as_u16_slice(&file.file_specification),
+                    file.recursive,
+                ),
+                filetime_to_system_time(file.last_modify_time),
+            ));
+        }
+        Ok(())
+    })?;
+    Ok(file_sets)
+}
+
+/// Collect the partial files that writers added to each selected component.
+/// Ranges are left unparsed since ranges files must be read from the shadow
+/// copy.
+#[allow(clippy::type_complexity)]
+fn partial_files(
+    backup_comp: &BackupComponents,
+    selected: &[SelectedComponent],
+) -> Result<Vec<Vec<(u32, PartialFile)>>, SessionError> {
+    let mut partial_files = Vec::new();
+    partial_files.resize_with(selected.len(), Vec::new);
+    for_each_selected_component(backup_comp, selected, |owner, component| {
+        let count = component
+            .get_partial_file_count()
+            .map_err(SessionError::vss("GetPartialFileCount"))?;
+        for file_index in 0..count {
+            let file = component
+                .get_partial_file(file_index)
+                .map_err(SessionError::vss("GetPartialFile"))?;
+            partial_files[owner].push((file_index, file));
+        }
+        Ok(())
+    })?;
+    Ok(partial_files)
+}
+
+/// Convert a `FILETIME`, which counts 100 nanosecond intervals since
+/// 1601-01-01, to a `SystemTime`.
+pub(crate) fn filetime_to_system_time(time: FILETIME) -> SystemTime {
+    const UNIX_EPOCH_IN_FILETIME: u64 = 11_644_473_600 * 10_000_000;
+    let ticks = (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+    if ticks >= UNIX_EPOCH_IN_FILETIME {
+        let ticks = ticks - UNIX_EPOCH_IN_FILETIME;
+        SystemTime::UNIX_EPOCH
+            + Duration::new(ticks / 10_000_000, (ticks % 10_000_000) as u32 * 100)
+    } else {
+        let ticks = UNIX_EPOCH_IN_FILETIME - ticks;
+        SystemTime::UNIX_EPOCH
+            - Duration::new(ticks / 10_000_000, (ticks % 10_000_000) as u32 * 100)
+    }
+}
+
+/// A file set of a [`BackedUpComponent`] and where it is in the shadow copy.
+#[derive(Debug, Clone)]
+pub struct ComponentFiles {
+    /// The file set as reported by the writer, with environment variables in
+    /// its path expanded.
+    pub file_set: FileSet,
+    /// The directory of the file set inside the shadow copy. Files in it that
+    /// match [`FileSet::file_specification`] should be copied.
+    pub snapshot_path: U16CString,
+}
+
+/// A file set in [`BackedUpComponent::differenced_files`].
+#[derive(Debug, Clone)]
+pub struct DifferencedFiles {
+    /// The file set as reported by the writer.
+    pub file_set: FileSet,
+    /// The directory of the file set inside the shadow copy.
+    pub snapshot_path: U16CString,
+    /// Only files that were modified after this time need to be copied.
+    pub last_modify_time: SystemTime,
+}
+
+/// A file in [`BackedUpComponent::partial_files`] of which only some byte
+/// ranges need to be backed up.
+#[derive(Debug, Clone)]
+pub struct ComponentPartialFile {
+    /// The index of the partial file in its component. Use it with
+    /// [`RestoreSession::set_ranges_file_path`] if the ranges file is
+    /// restored to a different location.
+    pub index: u32,
+    /// The directory that contains the file, as reported by the writer.
+    pub path: U16CString,
+    /// The name of the file.
+    pub file_name: U16CString,
+    /// The file inside the shadow copy.
+    pub snapshot_path: U16CString,
+    /// The parts of the file that should be backed up. `None` if the writer
+    /// didn't specify any ranges.
+    pub ranges: Option<PartialFileRanges>,
+    /// If the ranges are stored in a ranges file then this is where that file
+    /// is inside the shadow copy. The ranges file should be backed up
+    /// together with the partial file.
+    pub ranges_snapshot_path: Option<U16CString>,
+    /// Additional metadata that the writer stored for the file.
+    pub metadata: Option<String>,
+}
+impl ComponentPartialFile {
+    /// The byte ranges that should be backed up, read from the ranges file in
+    /// the shadow copy if necessary. `None` if the writer didn't specify any
+    /// ranges.
+    pub fn read_ranges(&self) -> io::Result<Option<Vec<FileRange>>> {
+        match (&self.ranges, &self.ranges_snapshot_path) {
+            (None, _) => Ok(None),
+            (Some(PartialFileRanges::Ranges(ranges)), _) => Ok(Some(ranges.clone())),
+            (Some(PartialFileRanges::RangesFile(_)), Some(path)) => {
+                let file = File::open(PathBuf::from(path.to_os_string()))?;
+                read_ranges_file(io::BufReader::new(file)).map(Some)
+            }
+            (Some(PartialFileRanges::RangesFile(path)), None) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "the ranges file {} isn't in any shadow copy",
+                    path.to_string_lossy()
+                ),
+            )),
+        }
+    }
+}
+
+/// A component that is part of a [`ComponentBackup`].
+#[derive(Debug, Clone)]
+pub struct BackedUpComponent {
+    /// The component that was added to the Backup Components Document.
+    pub component: SelectedComponent,
+    /// The files of the component and of its implicitly included
+    /// subcomponents.
+    pub files: Vec<ComponentFiles>,
+    /// File sets that the writer reported as changed since the previous
+    /// backup with `AddDifferencedFilesByLastModifyTime`. Only used by
+    /// incremental and differential backups.
+    ///
+    /// When this isn't empty only the files in these sets that were modified
+    /// after their [`DifferencedFiles::last_modify_time`] need to be copied
+    /// instead of everything in [`BackedUpComponent::files`].
+    pub differenced_files: Vec<DifferencedFiles>,
+    /// Files that the writer added with `AddPartialFile`. Only the byte
+    /// ranges returned by [`ComponentPartialFile::read_ranges`] need to be
+    /// copied for these files, for example with [`copy_file_ranges`]. Writers
+    /// only report partial files if
+    /// [`SnapshotBuilder::partial_file_support`] was enabled.
+    pub partial_files: Vec<ComponentPartialFile>,
+    succeeded: bool,
+}
+impl BackedUpComponent {
+    /// Whether the component will be reported as successfully backed up, see
+    /// [`ComponentBackup::set_backup_succeeded`].
+    pub fn backup_succeeded(&self) -> bool {
+        self.succeeded
+    }
+}
+
+/// A component-mode backup created by
+/// [`SnapshotBuilder::create_component_backup`].
+///
+/// Copy the files listed by [`ComponentBackup::components`] and then call
+/// [`ComponentBackup::finish`] to tell the writers which components were
+/// backed up. Dropping the backup without finishing it aborts it.
+pub struct ComponentBackup {
+    snapshot_set: SnapshotSet,
+    components: Vec<BackedUpComponent>,
+}
+impl ComponentBackup {
+    /// The snapshot set that contains the shadow copies of the components'
+    /// volumes.
+    pub fn snapshot_set(&self) -> &SnapshotSet {
+        &self.snapshot_set
+    }
+    /// Mutable access to the snapshot set, for example to record the copied
+    /// bytes with [`SnapshotSet::record_bytes_copied`].
+    pub fn snapshot_set_mut(&mut self) -> &mut SnapshotSet {
+        &mut self.snapshot_set
+    }
+    /// The components that are being backed up, with their files.
+    pub fn components(&self) -> &[BackedUpComponent] {
+        &self.components
+    }
+    /// Choose whether the component at `component_index` in
+    /// [`ComponentBackup::components`] is reported as successfully backed up.
+    /// Components are assumed to succeed unless this is called.
+    ///
+    /// # Panics
+    ///
+    /// If `component_index` is out of bounds.
+    pub fn set_backup_succeeded(&mut self, component_index: usize, succeeded: bool) {
+        self.components[component_index].succeeded = succeeded;
+    }
+    /// Report which components were backed up, complete the backup and delete
+    /// the shadow copies.
+    ///
+    /// Components of writers that failed while the shadow copies were created
+    /// are always reported as failed, see
+    /// [`WriterFailurePolicy::ContinueWithoutWriter`].
+    #[doc(alias = "SetBackupSucceeded")]
+    pub fn finish(mut self) -> Result<(), SessionError> {
+        self.report_backup_succeeded()?;
+        self.snapshot_set.finish()
+    }
+    /// Like [`ComponentBackup::finish`] but errors are recorded in the
+    /// returned report, see [`SnapshotSet::finish_with_report`].
+    pub fn finish_with_report(mut self) -> JobReport {
+        if let Err(e) = self.report_backup_succeeded() {
+            self.snapshot_set.record_error(e);
+        }
+        self.snapshot_set.finish_with_report()
+    }
+    fn report_backup_succeeded(&mut self) -> Result<(), SessionError> {
+        let backup_comp = self.snapshot_set.backup_components();
+        for component in &self.components {
+            let writer_failed =
+                self.snapshot_set.failed_writers().iter().any(|writer| {
+                    IsEqualGUID(&writer.instance_id, &component.component.instance_id)
+                });
+            if writer_failed {
+                // Already marked as failed when the snapshot set was created.
+                continue;
+            }
+            backup_comp
+                .component(component.component.spec())
+                .set_backup_succeeded(component.component.instance_id, component.succeeded)
+                .map_err(SessionError::vss("SetBackupSucceeded"))?;
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Partial files
+////////////////////////////////////////////////////////////////////////////////
+
+/// A part of a file that should be backed up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileRange {
+    /// The offset in bytes from the start of the file.
+    pub offset: u64,
+    /// The number of bytes.
+    pub length: u64,
+}
+
+/// The ranges of a partial file as reported by its writer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartialFileRanges {
+    /// The writer listed the ranges directly.
+    Ranges(Vec<FileRange>),
+    /// The writer stored the ranges in a ranges file at this path, see
+    /// [`read_ranges_file`] for its format.
+    RangesFile(U16CString),
+}
+impl PartialFileRanges {
+    /// Parse the ranges string that a writer passed to `AddPartialFile`. It is
+    /// either a comma separated list of `offset:length` pairs, where the
+    /// numbers are decimal or hexadecimal with a `0x` prefix, or the path of a
+    /// ranges file.
+    pub fn parse(ranges: &[u16]) -> Self {
+        fn parse_number(number: &str) -> Option<u64> {
+            let number = number.trim();
+            match number
+                .strip_prefix("0x")
+                .or_else(|| number.strip_prefix("0X"))
+            {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => number.parse().ok(),
+            }
+        }
+        let text = String::from_utf16_lossy(ranges);
+        let list = text
+            .split(',')
+            .map(|range| {
+                let mut parts = range.split(':');
+                let offset = parse_number(parts.next()?)?;
+                let length = parse_number(parts.next()?)?;
+                if parts.next().is_some() {
+                    return None;
+                }
+                Some(FileRange { offset, length })
+            })
+            .collect::<Option<Vec<_>>>();
+        match list {
+            Some(list) => Self::Ranges(list),
+            None if text.trim().is_empty() => Self::Ranges(Vec::new()),
+            None => {
+                let len = ranges.iter().position(|&c| c == 0).unwrap_or(ranges.len());
+                Self::RangesFile(
+                    U16CString::new(&ranges[..len])
+                        .expect("the path was truncated at the first nul character"),
+                )
+            }
+        }
+    }
+}
+
+/// Read the ranges from a ranges file. The file starts with the number of
+/// ranges followed by the offset and length of each range, all stored as
+/// little-endian 64 bit integers.
+pub fn read_ranges_file(mut reader: impl Read) -> io::Result<Vec<FileRange>> {
+    fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+    let count = read_u64(&mut reader)?;
+    let mut ranges = Vec::new();
+    for _ in 0..count {
+        ranges.push(FileRange {
+            offset: read_u64(&mut reader)?,
+            length: read_u64(&mut reader)?,
+        });
+    }
+    Ok(ranges)
+}
+
+/// Copy the bytes in `ranges` from `source` to `destination`, one range after
+/// the other, and return the number of copied bytes. Fails with
+/// [`io::ErrorKind::UnexpectedEof`] if a range extends past the end of
+/// `source`.
+pub fn copy_file_ranges(
+    mut source: impl Read + Seek,
+    ranges: &[FileRange],
+    mut destination: impl Write,
+) -> io::Result<u64> {
+    let mut total = 0;
+    for range in ranges {
+        source.seek(SeekFrom::Start(range.offset))?;
+        let copied = io::copy(&mut (&mut source).take(range.length), &mut destination)?;
+        if copied != range.length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file range extends past the end of the file",
+            ));
+        }
+        total += copied;
+    }
+    Ok(total)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Backup manifest
+////////////////////////////////////////////////////////////////////////////////
+
+/// The first line of a saved [`BackupManifest`], followed by the version.
+const MANIFEST_HEADER: &str = "volume_shadow_copy backup manifest";
+
+/// Everything that is needed to restore from a backup at a later time,
+/// returned by [`SnapshotSet::manifest`].
+///
+/// [`BackupManifest::save`] stores the manifest in a versioned text format
+/// that doesn't need any extra dependencies. The file starts with a header
+/// line that contains [`BackupManifest::VERSION`], followed by records that
+/// each consist of a line with a key and the length of the value in bytes,
+/// the UTF-8 encoded value and a newline. Records with unknown keys are
+/// skipped when loading so that later versions can add fields.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BackupManifest {
+    /// Identifies the snapshot set that the backup was made from.
+    pub snapshot_set_id: String,
+    /// The shadow copies in the set and the volumes they were created for.
+    pub snapshots: Vec<SnapshotReport>,
+    /// The Backup Components Document, which is passed to
+    /// `InitializeForRestore` when restoring.
+    pub backup_components_document: String,
+    /// The metadata of every writer that took part in the backup, as
+    /// returned by `IVssExamineWriterMetadata::SaveAsXML`.
+    pub writer_metadata: Vec<String>,
+    /// The backup stamps that writers stored with their components, used as
+    /// the base of the next incremental backup, see
+    /// [`SnapshotBuilder::incremental`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub backup_stamps: Vec<BackupStamp>,
+}
+impl BackupManifest {
+    /// The version of the format written by [`BackupManifest::save`].
+    pub const VERSION: u32 = 1;
+
+    /// Write the manifest to a file, replacing it if it exists.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+    /// Read a manifest that was written by [`BackupManifest::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(File::open(path)?)
+    }
+    /// Write the manifest in the format used by [`BackupManifest::save`].
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut record = |key: &str, value: &str| -> io::Result<()> {
+            writeln!(writer, "{} {}", key, value.len())?;
+            writer.write_all(value.as_bytes())?;
+            writer.write_all(b"\n")
+        };
+        record(MANIFEST_HEADER, &Self::VERSION.to_string())?;
+        record("snapshot_set_id", &self.snapshot_set_id)?;
+        for snapshot in &self.snapshots {
+            record("snapshot.volume", &snapshot.volume)?;
+            record("snapshot.snapshot_id", &snapshot.snapshot_id)?;
+            record("snapshot.device_object", &snapshot.device_object)?;
+        }
+        record(
+            "backup_components_document",
+            &self.backup_components_document,
+        )?;
+        for metadata in &self.writer_metadata {
+            record("writer_metadata", metadata)?;
+        }
+        for stamp in &self.backup_stamps {
+            record("backup_stamp.writer_id", &stamp.writer_id)?;
+            if let Some(logical_path) = &stamp.logical_path {
+                record("backup_stamp.logical_path", logical_path)?;
+            }
+            record("backup_stamp.component_name", &stamp.component_name)?;
+            record("backup_stamp.stamp", &stamp.stamp)?;
+        }
+        Ok(())
+    }
+    /// Read a manifest in the format used by [`BackupManifest::save`].
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut records = ManifestRecords { rest: &text };
+
+        match records.next()? {
+            Some((MANIFEST_HEADER, version)) => {
+                let version: u32 = version
+                    .parse()
+                    .map_err(|_| invalid_manifest("invalid version"))?;
+                if version == 0 || version > Self::VERSION {
+                    return Err(invalid_manifest(&format!(
+                        "unsupported version {}",
+                        version
+                    )));
+                }
+            }
+            _ => return Err(invalid_manifest("missing header")),
+        }
+        let mut manifest = Self::default();
+        while let Some((key, value)) = records.next()? {
+            let value = value.to_owned();
+            match key {
+                "snapshot_set_id" => manifest.snapshot_set_id = value,
+                "snapshot.volume" => manifest.snapshots.push(SnapshotReport {
+                    volume: value,
+                    snapshot_id: String::new(),
+                    device_object: String::new(),
+                }),
+                "snapshot.snapshot_id" | "snapshot.device_object" => {
+                    let snapshot = manifest
+                        .snapshots
+                        .last_mut()
+                        .ok_or_else(|| invalid_manifest("snapshot without a volume"))?;
+                    if key == "snapshot.snapshot_id" {
+                        snapshot.snapshot_id = value;
+                    } else {
+                        snapshot.device_object = value;
+                    }
+                }
+                "backup_components_document" => manifest.backup_components_document = value,
+                "writer_metadata" => manifest.writer_metadata.push(value),
+                "backup_stamp.writer_id" => manifest.backup_stamps.push(BackupStamp {
+                    writer_id: value,
+                    logical_path: None,
+                    component_name: String::new(),
+                    stamp: String::new(),
+                }),
+                "backup_stamp.logical_path"
+                | "backup_stamp.component_name"
+                | "backup_stamp.stamp" => {
+                    let stamp = manifest
+                        .backup_stamps
+                        .last_mut()
+                        .ok_or_else(|| invalid_manifest("backup stamp without a writer"))?;
+                    match key {
+                        "backup_stamp.logical_path" => stamp.logical_path = Some(value),
+                        "backup_stamp.component_name" => stamp.component_name = value,
+                        _ => stamp.stamp = value,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// The backup stamp that a writer stored with a component, see
+/// [`BackupManifest::backup_stamps`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BackupStamp {
+    /// Identifies the writer class of the component.
+    pub writer_id: String,
+    /// The logical path of the component.
+    pub logical_path: Option<String>,
+    /// The name of the component.
+    pub component_name: String,
+    /// The opaque stamp that the writer uses to find changes since the
+    /// backup.
+    pub stamp: String,
+}
+
+fn invalid_manifest(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid backup manifest: {}", reason),
+    )
+}
+
+/// Splits a saved [`BackupManifest`] into key and value pairs.
+struct ManifestRecords<'a> {
+    rest: &'a str,
+}
+impl<'a> ManifestRecords<'a> {
+    fn next(&mut self) -> io::Result<Option<(&'a str, &'a str)>> {
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        let (line, rest) = self
+            .rest
+            .split_once('\n')
+            .ok_or_else(|| invalid_manifest("truncated record"))?;
+        let (key, len) = line
+            .rsplit_once(' ')
+            .ok_or_else(|| invalid_manifest("record without a length"))?;
+        let len: usize = len
+            .parse()
+            .map_err(|_| invalid_manifest("invalid record length"))?;
+        let value = rest
+            .get(..len)
+            .filter(|_| rest[len..].starts_with('\n'))
+            .ok_or_else(|| invalid_manifest("truncated record"))?;
+        self.rest = &rest[len + 1..];
+        Ok(Some((key, value)))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Restore session
+////////////////////////////////////////////////////////////////////////////////
+
+/// The phases of a [`RestoreSession`] before it is finished, in the order
+/// that they happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RestorePhase {
+    /// The Backup Components Document has been loaded and components can be
+    /// selected for restore.
+    Initialized,
+    /// Writers have been sent the `PreRestore` event and files can be
+    /// restored.
+    PreRestored,
+}
+
+/// Guides a restore from a Backup Components Document that was saved when
+/// the backup was made.
+///
+/// The methods must be called in this order:
+///
+/// 1. [`RestoreSession::new`] loads the document and gathers writer metadata.
+/// 2. [`RestoreSession::restore_type`],
+///    [`RestoreSession::select_component`] and
+///    [`RestoreSession::add_new_target`] configure the restore.
+/// 3. [`RestoreSession::pre_restore`] notifies writers that files are about
+///    to be restored.
+/// 4. The caller restores the files and reports the outcome for each
+///    component with [`RestoreSession::set_file_restore_status`].
+/// 5. [`RestoreSession::finish`] notifies writers that the restore is done
+///    and checks their status.
+///
+/// Calling a method in the wrong phase returns [`SessionError::OutOfOrder`]
+/// without calling VSS. If the session is dropped after
+/// [`RestoreSession::pre_restore`] without calling
+/// [`RestoreSession::finish`] then writers never receive the `PostRestore`
+/// event.
+pub struct RestoreSession {
+    backup_comp: BackupComponents,
+    phase: RestorePhase,
+    timeout: Option<u32>,
+}
+impl RestoreSession {
+    /// Load a Backup Components Document, for example one returned by
+    /// [`IBackupComponents::save_as_xml`](crate::vsbackup::IBackupComponents::save_as_xml)
+    /// during the backup, and gather the metadata of the writers on this
+    /// machine.
+    ///
+    /// `timeout` is the maximum time in milliseconds to wait for each
+    /// asynchronous VSS operation. `None` waits forever.
+    #[doc(alias = "InitializeForRestore")]
+    pub fn new(
+        _com: &ComGuard,
+        document: &str,
+        timeout: Option<u32>,
+    ) -> Result<Self, SessionError> {
+        Ok(Self {
+            backup_comp: initialize_for_restore(document, timeout)?,
+            phase: RestorePhase::Initialized,
+            timeout,
+        })
+    }
+    /// Load a manifest that was saved with [`BackupManifest::save`], use its
+    /// Backup Components Document like [`RestoreSession::new`] and select
+    /// every component that was backed up successfully with
+    /// [`RestoreSession::select_backed_up_components`].
+    pub fn from_manifest(
+        com: &ComGuard,
+        path: impl AsRef<Path>,
+        timeout: Option<u32>,
+    ) -> Result<Self, SessionError> {
+        let manifest = BackupManifest::load(path).map_err(SessionError::Manifest)?;
+        let session = Self::new(com, &manifest.backup_components_document, timeout)?;
+        session.select_backed_up_components()?;
+        Ok(session)
+    }
+    /// Select every component in the Backup Components Document that was
+    /// marked as successfully backed up and return how many were selected.
+    pub fn select_backed_up_components(&self) -> Result<usize, SessionError> {
+        self.require("SetSelectedForRestore", RestorePhase::Initialized)?;
+        let writer_count = self
+            .backup_comp
+            .get_writer_components_count()
+            .map_err(SessionError::vss("GetWriterComponentsCount"))?;
+        let mut selected = 0;
+        for writer_index in 0..writer_count {
+            let writer = self
+                .backup_comp
+                .get_writer_components(writer_index)
+                .map_err(SessionError::vss("GetWriterComponents"))?;
+            let info = writer
+                .get_writer_info()
+                .map_err(SessionError::vss("GetWriterInfo"))?;
+            let component_count = writer
+                .get_component_count()
+                .map_err(SessionError::vss("GetComponentCount"))?;
+            for component_index in 0..component_count {
+                let component = writer
+                    .get_component(component_index)
+                    .map_err(SessionError::vss("GetComponent"))?;
+                let backup_succeeded = component
+                    .get_backup_succeeded()
+                    .map_err(SessionError::vss("GetBackupSucceeded"))?;
+                if !backup_succeeded {
+                    continue;
+                }
+                let component_type = component
+                    .get_component_type()
+                    .map_err(SessionError::vss("GetComponentType"))?;
+                let logical_path = component
+                    .get_logical_path()
+                    .map_err(SessionError::vss("GetLogicalPath"))?
+                    .map(|path| bstr_to_u16_cstring(&path));
+                let name = component
+                    .get_component_name()
+                    .map_err(SessionError::vss("GetComponentName"))?;
+                let name = bstr_to_u16_cstring(&name);
+                self.select_component(&ComponentSpec::new(
+                    info.writer_id,
+                    component_type,
+                    logical_path.as_deref(),
+                    &name,
+                ))?;
+                selected += 1;
+            }
+        }
+        Ok(selected)
+    }
+    /// The backup components object used for the restore.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// The current phase of the restore.
+    pub fn phase(&self) -> RestorePhase {
+        self.phase
+    }
+    fn require(&self, operation: &'static str, phase: RestorePhase) -> Result<(), SessionError> {
+        if self.phase == phase {
+            Ok(())
+        } else {
+            Err(SessionError::OutOfOrder {
+                operation,
+                phase: self.phase,
+            })
+        }
+    }
+    /// The type of restore that is performed. Defaults to
+    /// [`RestoreType::ByCopy`] if this isn't called.
+    #[doc(alias = "SetRestoreState")]
+    pub fn restore_type(&self, restore_type: RestoreType) -> Result<(), SessionError> {
+        self.require("SetRestoreState", RestorePhase::Initialized)?;
+        self.backup_comp
+            .set_restore_state(restore_type)
+            .map_err(SessionError::vss("SetRestoreState"))
+    }
+    /// Select a component that was included in the backup for restore.
+    #[doc(alias = "SetSelectedForRestore")]
+    pub fn select_component(&self, component: &ComponentSpec<'_>) -> Result<(), SessionError> {
+        self.require("SetSelectedForRestore", RestorePhase::Initialized)?;
+        self.backup_comp
+            .component(*component)
+            .set_selected_for_restore(true)
+            .map_err(SessionError::vss("SetSelectedForRestore"))
+    }
+    /// Tell the writer whether more restores of a selected component will
+    /// follow this one, for example log backups that are applied after a full
+    /// backup was restored.
+    #[doc(alias = "SetAdditionalRestores")]
+    pub fn set_additional_restores(
+        &self,
+        component: &ComponentSpec<'_>,
+        additional_restores: bool,
+    ) -> Result<(), SessionError> {
+        self.require("SetAdditionalRestores", RestorePhase::Initialized)?;
+        self.backup_comp
+            .component(*component)
+            .set_additional_restores(additional_restores)
+            .map_err(SessionError::vss("SetAdditionalRestores"))
+    }
+    /// Choose how the writer rolls a selected component forward after its
+    /// files were restored.
+    #[doc(alias = "SetRollForward")]
+    pub fn set_roll_forward(
+        &self,
+        component: &ComponentSpec<'_>,
+        roll_forward: RollForward<'_>,
+    ) -> Result<(), SessionError> {
+        self.require("SetRollForward", RestorePhase::Initialized)?;
+        let backup_comp_ex2 =
+            self.backup_comp
+                .query::<BackupComponentsEx2>()
+                .ok_or(SessionError::NotSupported {
+                    operation: "SetRollForward",
+                })?;
+        backup_comp_ex2
+            .set_roll_forward(
+                component.writer_id,
+                component.component_type,
+                component.logical_path,
+                component.component_name,
+                roll_forward,
+            )
+            .map_err(SessionError::vss("SetRollForward"))
+    }
+    /// Restore the files of `file_set` in a selected component to
+    /// `alternate_path` instead of their original directory. The new target
+    /// is stored in the Backup Components Document so that the writer knows
+    /// where to find the files, and [`RestoreSession::restore_path`] returns
+    /// the relocated paths.
+    #[doc(alias = "AddNewTarget")]
+    pub fn add_new_target(
+        &self,
+        component: &ComponentSpec<'_>,
+        file_set: &FileSet,
+        alternate_path: &U16CStr,
+    ) -> Result<(), SessionError> {
+        self.require("AddNewTarget", RestorePhase::Initialized)?;
+        let path = U16CString::new(file_set.path()).map_err(SessionError::vss("AddNewTarget"))?;
+        let file_specification = U16CString::new(file_set.file_specification())
+            .map_err(SessionError::vss("AddNewTarget"))?;
+        self.backup_comp
+            .component(*component)
+            .add_new_target(
+                &path,
+                &file_specification,
+                file_set.recursive(),
+                alternate_path,
+            )
+            .map_err(SessionError::vss("AddNewTarget"))
+    }
+    /// The new targets of a component that are stored in the Backup
+    /// Components Document.
+    #[doc(alias = "GetNewTarget")]
+    pub fn new_targets(
+        &self,
+        component: &ComponentSpec<'_>,
+    ) -> Result<Vec<NewTarget>, SessionError> {
+        let component = match find_component(&self.backup_comp, component)? {
+            Some(component) => component,
+            None => return Ok(Vec::new()),
+        };
+        let mut targets = Vec::new();
+        for target in component
+            .new_targets()
+            .map_err(SessionError::vss("GetNewTargetCount"))?
+        {
+            let target = target.map_err(SessionError::vss("GetNewTarget"))?;
+            let alternate_path = target
+                .get_alternate_location()
+                .map_err(SessionError::vss("GetAlternateLocation"))?;
+            let alternate_path = match alternate_path {
+                Some(alternate_path) => bstr_to_u16_cstring(&alternate_path),
+                None => continue,
+            };
+            targets.push(NewTarget {
+                file_set: FileSet::from_descriptor(&target)
+                    .map_err(SessionError::vss("GetNewTarget"))?,
+                alternate_path,
+            });
+        }
+        Ok(targets)
+    }
+    /// Where a file of a component that was at `path` when it was backed up
+    /// should be restored to. This is `path` itself unless the file belongs
+    /// to one of the component's [`RestoreSession::new_targets`].
+    pub fn restore_path(
+        &self,
+        component: &ComponentSpec<'_>,
+        path: &[u16],
+    ) -> Result<U16CString, SessionError> {
+        let targets = self.new_targets(component)?;
+        // Prefer the most specific target if the file sets overlap.
+        let relocated = targets
+            .iter()
+            .filter(|target| target.file_set.contains(path))
+            .max_by_key(|target| target.file_set.path().len())
+            .and_then(|target| target.relocate(path));
+        match relocated {
+            Some(relocated) => Ok(relocated),
+            None => U16CString::new(path).map_err(SessionError::vss("GetNewTarget")),
+        }
+    }
+    /// Notify writers that files are about to be restored.
+    #[doc(alias = "PreRestore")]
+    pub fn pre_restore(&mut self) -> Result<(), SessionError> {
+        self.require("PreRestore", RestorePhase::Initialized)?;
+        wait(
+            "PreRestore",
+            self.backup_comp
+                .pre_restore()
+                .map_err(SessionError::vss("PreRestore"))?,
+            self.timeout,
+        )?;
+        self.phase = RestorePhase::PreRestored;
+        Ok(())
+    }
+    /// Report whether the files of a selected component were restored.
+    #[doc(alias = "SetFileRestoreStatus")]
+    pub fn set_file_restore_status(
+        &self,
+        component: &ComponentSpec<'_>,
+        status: FileRestoreStatus,
+    ) -> Result<(), SessionError> {
+        self.require("SetFileRestoreStatus", RestorePhase::PreRestored)?;
+        self.backup_comp
+            .component(*component)
+            .set_file_restore_status(status)
+            .map_err(SessionError::vss("SetFileRestoreStatus"))
+    }
+    /// The partial files of a component that are stored in the Backup
+    /// Components Document.
+    #[doc(alias = "GetPartialFile")]
+    pub fn partial_files(
+        &self,
+        component: &ComponentSpec<'_>,
+    ) -> Result<Vec<StoredPartialFile>, SessionError> {
+        let component = match find_component(&self.backup_comp, component)? {
+            Some(component) => component,
+            None => return Ok(Vec::new()),
+        };
+        let count = component
+            .get_partial_file_count()
+            .map_err(SessionError::vss("GetPartialFileCount"))?;
+        (0..count)
+            .map(|index| {
+                let file = component
+                    .get_partial_file(index)
+                    .map_err(SessionError::vss("GetPartialFile"))?;
+                Ok(StoredPartialFile::new(index, &file))
+            })
+            .collect()
+    }
+    /// Tell the writer where the ranges file of a partial file was restored
+    /// to if it isn't at its original location. `partial_file_index` is
+    /// [`StoredPartialFile::index`] or [`ComponentPartialFile::index`] and
+    /// must be less than the number of partial files of the component,
+    /// otherwise [`SessionError::InvalidPartialFile`] is returned.
+    #[doc(alias = "SetRangesFilePath")]
+    pub fn set_ranges_file_path(
+        &self,
+        component: &ComponentSpec<'_>,
+        partial_file_index: u32,
+        ranges_file: &U16CStr,
+    ) -> Result<(), SessionError> {
+        self.require("SetRangesFilePath", RestorePhase::PreRestored)?;
+        let count = match find_component(&self.backup_comp, component)? {
+            Some(stored) => stored
+                .get_partial_file_count()
+                .map_err(SessionError::vss("GetPartialFileCount"))?,
+            None => 0,
+        };
+        if partial_file_index >= count {
+            return Err(SessionError::InvalidPartialFile {
+                index: partial_file_index,
+                count,
+            });
+        }
+        self.backup_comp
+            .component(*component)
+            .set_ranges_file_path(partial_file_index, ranges_file)
+            .map_err(SessionError::vss("SetRangesFilePath"))
+    }
+    /// Tell the writer of a component where the ranges files of its partial
+    /// files were restored to.
+    ///
+    /// `restored_path` is called with each partial file that uses a ranges
+    /// file and the path that the ranges file had at backup time. It should
+    /// return where that ranges file was restored to, or `None` if it wasn't
+    /// restored. Nothing is changed for ranges files that were restored to
+    /// their original location. Returns the number of ranges files whose
+    /// path was changed.
+    #[doc(alias = "SetRangesFilePath")]
+    pub fn set_restored_ranges_files<F>(
+        &self,
+        component: &ComponentSpec<'_>,
+        mut restored_path: F,
+    ) -> Result<usize, SessionError>
+    where
+        F: FnMut(&StoredPartialFile, &U16CStr) -> Option<U16CString>,
+    {
+        self.require("SetRangesFilePath", RestorePhase::PreRestored)?;
+        let mut changed = 0;
+        for file in self.partial_files(component)? {
+            let original = match &file.ranges {
+                Some(PartialFileRanges::RangesFile(original)) => original,
+                _ => continue,
+            };
+            let restored = match restored_path(&file, original) {
+                Some(restored) => restored,
+                None => continue,
+            };
+            if paths::eq_ignore_case(restored.as_slice(), original.as_slice()) {
+                continue;
+            }
+            self.backup_comp
+                .component(*component)
+                .set_ranges_file_path(file.index, &restored)
+                .map_err(SessionError::vss("SetRangesFilePath"))?;
+            changed += 1;
+        }
+        Ok(changed)
+    }
+    /// Notify writers that the restore is done and check whether any of them
+    /// failed to handle it, in which case [`SessionError::WriterFailed`] is
+    /// returned.
+    #[doc(alias = "PostRestore")]
+    pub fn finish(self) -> Result<(), SessionError> {
+        self.require("PostRestore", RestorePhase::PreRestored)?;
+        wait(
+            "PostRestore",
+            self.backup_comp
+                .post_restore()
+                .map_err(SessionError::vss("PostRestore"))?,
+            self.timeout,
+        )?;
+        let (_, failed) = check_writers(&self.backup_comp)?;
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(SessionError::WriterFailed(failed))
+        }
+    }
+}
+
+/// Find a component in the Backup Components Document.
+fn find_component(
+    backup_comp: &BackupComponents,
+    spec: &ComponentSpec<'_>,
+) -> Result<Option<Component>, SessionError> {
+    for writer in backup_comp
+        .writer_components_iter()
+        .map_err(SessionError::vss("GetWriterComponentsCount"))?
+    {
+        let writer = writer.map_err(SessionError::vss("GetWriterComponents"))?;
+        let info = writer
+            .get_writer_info()
+            .map_err(SessionError::vss("GetWriterInfo"))?;
+        if !IsEqualGUID(&info.writer_id, &spec.writer_id) {
+            continue;
+        }
+        for component in writer
+            .components()
+            .map_err(SessionError::vss("GetComponentCount"))?
+        {
+            let component = component.map_err(SessionError::vss("GetComponent"))?;
+            let logical_path = component
+                .get_logical_path()
+                .map_err(SessionError::vss("GetLogicalPath"))?
+                .map(|path| bstr_to_u16_cstring(&path));
+            let name = component
+                .get_component_name()
+                .map_err(SessionError::vss("GetComponentName"))?;
+            if logical_path.as_deref() == spec.logical_path
+                && bstr_to_u16_cstring(&name).as_ucstr() == spec.component_name
+            {
+                return Ok(Some(component));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// A partial file of a component in the Backup Components Document, see
+/// [`RestoreSession::partial_files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredPartialFile {
+    /// The index of the partial file in its component.
+    pub index: u32,
+    /// The directory that contained the file at backup time.
+    pub path: U16CString,
+    /// The name of the file.
+    pub file_name: U16CString,
+    /// The parts of the file that were backed up. `None` if the writer didn't
+    /// specify any ranges.
+    pub ranges: Option<PartialFileRanges>,
+    /// Additional metadata that the writer stored for the file.
+    pub metadata: Option<String>,
+}
+impl StoredPartialFile {
+    fn new(index: u32, file: &PartialFile) -> Self {
+        Self {
+            index,
+            path: bstr_to_u16_cstring(&file.path),
+            file_name: bstr_to_u16_cstring(&file.file_name),
+            ranges: file
+                .ranges
+                .as_ref()
+                .map(|ranges| PartialFileRanges::parse(bstr_as_u16_slice(ranges))),
+            metadata: file
+                .metadata
+                .as_ref()
+                .map(|metadata| String::from_utf16_lossy(bstr_as_u16_slice(metadata))),
+        }
+    }
+}
+
+/// A file set of a component that is restored to a different directory than
+/// the one it was backed up from, see [`RestoreSession::add_new_target`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewTarget {
+    /// The files and the directory that they were backed up from.
+    pub file_set: FileSet,
+    /// The directory that the files are restored to.
+    pub alternate_path: U16CString,
+}
+impl NewTarget {
+    /// Where the file that was at `path` should be restored to, or `None` if
+    /// it doesn't belong to [`NewTarget::file_set`]. Subdirectories of
+    /// recursive file sets are kept.
+    pub fn relocate(&self, path: &[u16]) -> Option<U16CString> {
+        if !self.file_set.contains(path) {
+            return None;
+        }
+        let relative_path = paths::strip_prefix_ignore_case(path, self.file_set.path())?;
+        let mut relocated = self.alternate_path.as_slice().to_vec();
+        while matches!(relocated.last(), Some(&c) if paths::is_separator(c)) {
+            relocated.pop();
+        }
+        if !relative_path.is_empty() {
+            relocated.push(u16::from(b'\\'));
+            relocated.extend_from_slice(relative_path);
+        }
+        U16CString::new(relocated).ok()
+    }
+}
+
+/// Load a Backup Components Document and gather writer metadata.
+fn initialize_for_restore(
+    document: &str,
+    timeout: Option<u32>,
+) -> Result<BackupComponents, SessionError> {
+    let document: Vec<u16> = document.encode_utf16().collect();
+    let document = u16_slice_to_bstring(&document);
+    let backup_comp =
+        BackupComponents::new().map_err(SessionError::vss("CreateVssBackupComponents"))?;
+    backup_comp
+        .initialize_for_restore(&document)
+        .map_err(SessionError::vss("InitializeForRestore"))?;
+    wait(
+        "GatherWriterMetadata",
+        backup_comp
+            .gather_writer_metadata()
+            .map_err(SessionError::vss("GatherWriterMetadata"))?,
+        timeout,
+    )?;
+    Ok(backup_comp)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Restore phases
+////////////////////////////////////////////////////////////////////////////////
+
+/// The first phase of a restore where the Backup Components Document has been
+/// loaded and components can be selected.
+///
+/// This is the compile time checked version of [`RestoreSession`]. The phases
+/// of a restore are:
+///
+/// 1. [`InitializedForRestore`]
+/// 2. [`PreRestored`]
+/// 3. [`Restoring`]
+/// 4. [`PostRestored`]
+///
+/// Like the [backup phases](InitializedForBackup), each phase has a
+/// `backup_components` method for calls that don't change the phase.
+pub struct InitializedForRestore {
+    backup_comp: BackupComponents,
+    timeout: Option<u32>,
+}
+impl InitializedForRestore {
+    /// Load a Backup Components Document and gather the metadata of the
+    /// writers on this machine, see [`RestoreSession::new`].
+    #[doc(alias = "InitializeForRestore")]
+    pub fn new(
+        _com: &ComGuard,
+        document: &str,
+        timeout: Option<u32>,
+    ) -> Result<Self, SessionError> {
+        Ok(Self {
+            backup_comp: initialize_for_restore(document, timeout)?,
+            timeout,
+        })
+    }
+    /// The backup components object used for the restore.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// The type of restore that is performed. Defaults to
+    /// [`RestoreType::ByCopy`] if this isn't called.
+    #[doc(alias = "SetRestoreState")]
+    pub fn restore_type(&self, restore_type: RestoreType) -> Result<(), SessionError> {
+        self.backup_comp
+            .set_restore_state(restore_type)
+            .map_err(SessionError::vss("SetRestoreState"))
+    }
+    /// Select a component that was included in the backup for restore.
+    #[doc(alias = "SetSelectedForRestore")]
+    pub fn select_component(&self, component: &ComponentSpec<'_>) -> Result<(), SessionError> {
+        self.backup_comp
+            .component(*component)
+            .set_selected_for_restore(true)
+            .map_err(SessionError::vss("SetSelectedForRestore"))
+    }
+    /// Notify writers that files are about to be restored.
+    #[doc(alias = "PreRestore")]
+    pub fn pre_restore(self) -> Result<PreRestored, SessionError> {
+        wait(
+            "PreRestore",
+            self.backup_comp
+                .pre_restore()
+                .map_err(SessionError::vss("PreRestore"))?,
+            self.timeout,
+        )?;
+        Ok(PreRestored {
+            backup_comp: self.backup_comp,
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// The phase of a restore where writers have received the `PreRestore`
+/// event, see [`InitializedForRestore`].
+pub struct PreRestored {
+    backup_comp: BackupComponents,
+    timeout: Option<u32>,
+}
+impl PreRestored {
+    /// The backup components object used for the restore.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Check that no writer failed to prepare for the restore before any
+    /// files are overwritten.
+    ///
+    /// If a writer failed then [`SessionError::WriterFailed`] is returned and
+    /// the restore ends without writers receiving the `PostRestore` event.
+    #[doc(alias = "GatherWriterStatus")]
+    pub fn begin_restore(self) -> Result<Restoring, SessionError> {
+        let (_, failed) = check_writers(&self.backup_comp)?;
+        if !failed.is_empty() {
+            return Err(SessionError::WriterFailed(failed));
+        }
+        Ok(Restoring {
+            backup_comp: self.backup_comp,
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// The phase of a restore where the caller restores files, see
+/// [`InitializedForRestore`].
+pub struct Restoring {
+    backup_comp: BackupComponents,
+    timeout: Option<u32>,
+}
+impl Restoring {
+    /// The backup components object used for the restore.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Report whether the files of a selected component were restored.
+    #[doc(alias = "SetFileRestoreStatus")]
+    pub fn set_file_restore_status(
+        &self,
+        component: &ComponentSpec<'_>,
+        status: FileRestoreStatus,
+    ) -> Result<(), SessionError> {
+        self.backup_comp
+            .component(*component)
+            .set_file_restore_status(status)
+            .map_err(SessionError::vss("SetFileRestoreStatus"))
+    }
+    /// Notify writers that the restore is done and gather their status.
+    #[doc(alias = "PostRestore")]
+    pub fn post_restore(self) -> Result<PostRestored, SessionError> {
+        wait(
+            "PostRestore",
+            self.backup_comp
+                .post_restore()
+                .map_err(SessionError::vss("PostRestore"))?,
+            self.timeout,
+        )?;
+        let (_, failed_writers) = check_writers(&self.backup_comp)?;
+        Ok(PostRestored {
+            backup_comp: self.backup_comp,
+            failed_writers,
+        })
+    }
+}
+
+/// The last phase of a restore where writers have received the `PostRestore`
+/// event, see [`InitializedForRestore`].
+pub struct PostRestored {
+    backup_comp: BackupComponents,
+    failed_writers: Vec<FailedWriter>,
+}
+impl PostRestored {
+    /// The backup components object used for the restore.
+    pub fn backup_components(&self) -> &BackupComponents {
+        &self.backup_comp
+    }
+    /// Writers that failed to handle the restore.
+    pub fn failed_writers(&self) -> &[FailedWriter] {
+        &self.failed_writers
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Job report
+////////////////////////////////////////////////////////////////////////////////
+
+/// Format a GUID as `{XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX}`.
+pub(crate) fn guid_to_string(guid: &GUID) -> String {
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        guid.Data1,
+        guid.Data2,
+        guid.Data3,
+        guid.Data4[0],
+        guid.Data4[1],
+        guid.Data4[2],
+        guid.Data4[3],
+        guid.Data4[4],
+        guid.Data4[5],
+        guid.Data4[6],
+        guid.Data4[7],
+    )
+}
+
+/// Parse a GUID formatted by [`guid_to_string`].
+fn guid_from_string(text: &str) -> Option<GUID> {
+    let text = text.strip_prefix('{')?.strip_suffix('}')?;
+    let parts = text.split('-').collect::<Vec<_>>();
+    let lengths = [8, 4, 4, 4, 12];
+    if parts.len() != lengths.len()
+        || parts
+            .iter()
+            .zip(&lengths)
+            .any(|(part, &len)| part.len() != len || !part.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        return None;
+    }
+    let tail = u64::from_str_radix(&[parts[3], parts[4]].concat(), 16).ok()?;
+    Some(GUID {
+        Data1: u32::from_str_radix(parts[0], 16).ok()?,
+        Data2: u16::from_str_radix(parts[1], 16).ok()?,
+        Data3: u16::from_str_radix(parts[2], 16).ok()?,
+        Data4: tail.to_be_bytes(),
+    })
+}
+
+/// The overall result of a backup job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JobOutcome {
+    /// Everything succeeded.
+    Succeeded,
+    /// The job finished but some writers failed and their data was skipped,
+    /// see [`WriterFailurePolicy::ContinueWithoutWriter`].
+    PartiallySucceeded,
+    /// The job failed, see [`JobReport::errors`].
+    Failed,
+}
+
+/// A structured summary of a backup job, returned by
+/// [`SnapshotSet::finish_with_report`].
+///
+/// All identifiers are formatted as strings so that the report can be
+/// serialized without any VSS types. Enable the `serde` feature to implement
+/// `Serialize` and `Deserialize` for the report.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JobReport {
+    /// The overall result of the job.
+    pub outcome: JobOutcome,
+    /// Identifies the snapshot set, `None` if the job failed before a
+    /// snapshot set was created.
+    pub snapshot_set_id: Option<String>,
+    /// The shadow copies that were created.
+    pub snapshots: Vec<SnapshotReport>,
+    /// The status of every writer after the shadow copies were created.
+    pub writers: Vec<WriterReport>,
+    /// The components that were included in the backup.
+    pub components: Vec<ComponentReport>,
+    /// The number of bytes passed to [`SnapshotSet::record_bytes_copied`].
+    pub bytes_copied: u64,
+    /// How long the different steps took.
+    pub durations: JobDurations,
+    /// Descriptions of the errors that happened during the job.
+    pub errors: Vec<String>,
+    /// Warnings and errors that VSS logged to the event log while the job
+    /// ran. Only collected for jobs that didn't succeed, see
+    /// [`JobReport::collect_vss_events`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub events: Vec<VssEvent>,
+}
+impl JobReport {
+    /// Create a report for a job that failed before a snapshot set could be
+    /// created, for example when [`SnapshotBuilder::create`] returned an
+    /// error.
+    pub fn from_error(error: &dyn StdError) -> Self {
+        Self {
+            outcome: JobOutcome::Failed,
+            snapshot_set_id: None,
+            snapshots: Vec::new(),
+            writers: Vec::new(),
+            components: Vec::new(),
+            bytes_copied: 0,
+            durations: JobDurations::default(),
+            errors: vec![error.to_string()],
+            events: Vec::new(),
+        }
+    }
+    /// Read the events that VSS logged since `since` into
+    /// [`JobReport::events`]. If the event log can't be read then that is
+    /// recorded in [`JobReport::errors`] instead.
+    ///
+    /// Reports returned by [`SnapshotSet::finish_with_report`] already
+    /// contain these events if the job didn't succeed. For reports created
+    /// with [`JobReport::from_error`] pass the time when the job started.
+    pub fn collect_vss_events(&mut self, since: SystemTime) {
+        match diagnostics::recent_vss_events(since) {
+            Ok(events) => self.events = events,
+            Err(e) => self.errors.push(format!(
+                "failed to read VSS events from the event log: {}",
+                e
+            )),
+        }
+    }
+}
+
+/// A shadow copy listed in a [`JobReport`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotReport {
+    /// The volume that the shadow copy was created for.
+    pub volume: String,
+    /// Identifies the shadow copy.
+    pub snapshot_id: String,
+    /// The device object that files were read from.
+    pub device_object: String,
+}
+
+/// The outcome for a single writer listed in a [`JobReport`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WriterReport {
+    /// The name of the writer.
+    pub name: String,
+    /// Identifies the writer class.
+    pub writer_id: String,
+    /// Identifies the writer instance.
+    pub instance_id: String,
+    /// The [`WriterState`] that the writer was in after the shadow copies
+    /// were created.
+    pub state: String,
+    /// The error that the writer reported, if any.
+    pub failure: Option<String>,
+    /// `false` if the writer failed.
+    pub succeeded: bool,
+}
+
+/// The outcome for a single component listed in a [`JobReport`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComponentReport {
+    /// Identifies the writer class that manages the component.
+    pub writer_id: String,
+    /// The logical path of the component.
+    pub logical_path: Option<String>,
+    /// The name of the component.
+    pub name: String,
+    /// Whether the component was marked as successfully backed up.
+    pub backup_succeeded: bool,
+}
+
+/// The result of [`SnapshotBuilder::dry_run`].
+///
+/// Like [`JobReport`] this only contains strings and plain values so that it
+/// can be serialized when the `serde` feature is enabled.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DryRunReport {
+    /// The volumes that would be shadow copied. All of them are supported by
+    /// a provider.
+    pub volumes: Vec<String>,
+    /// How consistent the shadow copies would be.
+    pub consistency: Consistency,
+    /// The writers that would take part in the backup. Empty for
+    /// [`WriterInvolvement::Writerless`] snapshots.
+    pub writers: Vec<DryRunWriter>,
+}
+
+/// A writer listed in a [`DryRunReport`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DryRunWriter {
+    /// The name of the writer.
+    pub name: String,
+    /// Identifies the writer class.
+    pub writer_id: String,
+    /// Identifies the writer instance.
+    pub instance_id: String,
+    /// The components that the writer reported in its metadata.
+    pub components: Vec<DryRunComponent>,
+}
+
+/// A component listed in a [`DryRunWriter`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DryRunComponent {
+    /// The logical path of the component.
+    pub logical_path: Option<String>,
+    /// The name of the component.
+    pub name: String,
+    /// Whether the component is selectable for backup. Components that
+    /// aren't selectable are always backed up with their writer.
+    pub selectable: bool,
+    /// The files, database files and log files of the component.
+    pub files: Vec<DryRunFile>,
+}
+
+/// A file set listed in a [`DryRunComponent`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DryRunFile {
+    /// The directory that contains the files, which can include environment
+    /// variables.
+    pub path: String,
+    /// The file name or wildcard pattern of the files.
+    pub file_specification: String,
+    /// Whether subdirectories of `path` are included.
+    pub recursive: bool,
+}
+
+/// How long most writers stay frozen before they give up and fail with
+/// `VSS_E_WRITERERROR_TIMEOUT`. Some writers, such as the SQL Server writer,
+/// can be configured with a different timeout.
+pub const WRITER_FREEZE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Timing information in a [`JobReport`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn f(snapshot_set: &volume_shadow_copy::session::SnapshotSet) {
+/// use volume_shadow_copy::session::WRITER_FREEZE_TIMEOUT;
+///
+/// let durations = snapshot_set.durations();
+/// if durations.freeze_window > WRITER_FREEZE_TIMEOUT / 2 {
+///     eprintln!(
+///         "warning: writers were frozen for up to {:?}, close to their timeout",
+///         durations.freeze_window
+///     );
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JobDurations {
+    /// The time it took to create the snapshot set.
+    pub snapshot_creation: Duration,
+    /// The time that `GatherWriterMetadata` took, zero for writerless
+    /// snapshots.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub gather_writer_metadata: Duration,
+    /// The time that `PrepareForBackup` took.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub prepare_for_backup: Duration,
+    /// The time that `DoSnapshotSet` took. Writers are frozen during part of
+    /// it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub do_snapshot_set: Duration,
+    /// The time from when `PrepareForBackup` completed until `DoSnapshotSet`
+    /// completed, including [`SessionPhase::BeforeFreeze`] hooks. This is an
+    /// upper bound for how long writers were frozen and should stay well
+    /// below [`WRITER_FREEZE_TIMEOUT`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub freeze_window: Duration,
+    /// The time it took to complete the backup and delete the shadow copies.
+    pub finish: Duration,
+    /// The time from when snapshot creation started until the job finished.
+    pub total: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_manifest() -> BackupManifest {
+        BackupManifest {
+            snapshot_set_id: "{1234ABCD-0102-FE0F-0001-0203A4B5C6D7}".to_owned(),
+            snapshots: vec![SnapshotReport {
+                volume: "C:\\".to_owned(),
+                snapshot_id: "{00000000-0000-0000-0000-000000000001}".to_owned(),
+                device_object: "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1".to_owned(),
+            }],
+            backup_components_document: "<Backup>\n  <Writer name=\"ä\"/>\n</Backup>\n".to_owned(),
+            writer_metadata: vec!["<Metadata 1/>".to_owned(), String::new()],
+            backup_stamps: vec![
+                BackupStamp {
+                    writer_id: "{00000000-0000-0000-0000-000000000002}".to_owned(),
+                    logical_path: Some("Instance".to_owned()),
+                    component_name: "Database".to_owned(),
+                    stamp: "LSN 1234".to_owned(),
+                },
+                BackupStamp {
+                    writer_id: "{00000000-0000-0000-0000-000000000003}".to_owned(),
+                    logical_path: None,
+                    component_name: "Files".to_owned(),
+                    stamp: String::new(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let manifest = example_manifest();
+        let mut data = Vec::new();
+        manifest.write_to(&mut data).unwrap();
+        let loaded = BackupManifest::read_from(&data[..]).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", manifest));
+    }
+
+    #[test]
+    fn manifest_rejects_invalid_data() {
+        let mut data = Vec::new();
+        example_manifest().write_to(&mut data).unwrap();
+        let text = String::from_utf8(data).unwrap();
+
+        let newer = text.replacen(" 1\n1\n", " 1\n2\n", 1);
+        assert_ne!(newer, text);
+        assert!(BackupManifest::read_from(newer.as_bytes()).is_err());
+        assert!(BackupManifest::read_from(&text.as_bytes()[..text.len() - 1]).is_err());
+        assert!(BackupManifest::read_from(&b"snapshot_set_id 0\n\n"[..]).is_err());
+
+        let unknown = format!("{}future_field 3\nabc\n", text);
+        assert!(BackupManifest::read_from(unknown.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn builder_defaults() {
+        let builder = SnapshotBuilder::default();
+        assert!(builder.volumes.is_empty());
+        assert_eq!(builder.writers, WriterInvolvement::Involved);
+        assert_eq!(builder.backup_type, BackupType::Copy);
+        assert_eq!(builder.writer_failure_policy, WriterFailurePolicy::FailFast);
+        assert_eq!(builder.timeout, None);
+        assert!(!builder.transportable);
+        assert!(builder.hooks.0.is_empty());
+        assert!(builder.progress.0.is_none());
+        assert!(builder.previous_backup_stamps.is_empty());
+    }
+
+    #[test]
+    fn filetime_conversion() {
+        let filetime = |ticks: u64| FILETIME {
+            dwLowDateTime: ticks as u32,
+            dwHighDateTime: (ticks >> 32) as u32,
+        };
+        assert_eq!(
+            filetime_to_system_time(filetime(116_444_736_000_000_000)),
+            SystemTime::UNIX_EPOCH
+        );
+        assert_eq!(
+            filetime_to_system_time(filetime(116_444_736_000_000_000 + 15_000_001)),
+            SystemTime::UNIX_EPOCH + Duration::new(1, 500_000_100)
+        );
+        assert_eq!(
+            filetime_to_system_time(filetime(116_444_736_000_000_000 - 10_000_000)),
+            SystemTime::UNIX_EPOCH - Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn new_target_relocation() {
+        let wide = |text: &str| U16CString::from_str(text).unwrap();
+        let target = NewTarget {
+            file_set: FileSet::new(wide("C:\\Data").as_slice(), wide("*.mdf").as_slice(), true),
+            alternate_path: wide("D:\\Restored\\"),
+        };
+        assert_eq!(
+            target.relocate(wide("C:\\data\\db.mdf").as_slice()),
+            Some(wide("D:\\Restored\\db.mdf"))
+        );
+        assert_eq!(
+            target.relocate(wide("C:\\Data\\Old\\db.mdf").as_slice()),
+            Some(wide("D:\\Restored\\Old\\db.mdf"))
+        );
+        assert_eq!(target.relocate(wide("C:\\Data\\db.ldf").as_slice()), None);
+        assert_eq!(target.relocate(wide("C:\\Other\\db.mdf").as_slice()), None);
+
+        let target = NewTarget {
+            alternate_path: wide("D:\\Restored"),
+            ..target
+        };
+        assert_eq!(
+            target.relocate(wide("C:\\Data\\db.mdf").as_slice()),
+            Some(wide("D:\\Restored\\db.mdf"))
+        );
+    }
+
+    #[test]
+    fn partial_file_ranges() {
+        let parse = |ranges: &str| {
+            PartialFileRanges::parse(&U16CString::from_str(ranges).unwrap().into_vec())
+        };
+        assert_eq!(
+            parse("0x0:0x1000, 8192:16"),
+            PartialFileRanges::Ranges(vec![
+                FileRange {
+                    offset: 0,
+                    length: 0x1000
+                },
+                FileRange {
+                    offset: 8192,
+                    length: 16
+                },
+            ])
+        );
+        assert_eq!(parse(""), PartialFileRanges::Ranges(Vec::new()));
+        assert_eq!(
+            parse("C:\\Data\\db.ranges"),
+            PartialFileRanges::RangesFile(U16CString::from_str("C:\\Data\\db.ranges").unwrap())
+        );
+
+        let mut ranges_file = Vec::new();
+        for value in &[2u64, 1, 2, 6, 3] {
+            ranges_file.extend_from_slice(&value.to_le_bytes());
+        }
+        let ranges = read_ranges_file(&ranges_file[..]).unwrap();
+        assert_eq!(
+            ranges,
+            vec![
+                FileRange {
+                    offset: 1,
+                    length: 2
+                },
+                FileRange {
+                    offset: 6,
+                    length: 3
+                },
+            ]
+        );
+        let mut copied = Vec::new();
+        let source = io::Cursor::new(b"0123456789");
+        assert_eq!(copy_file_ranges(source, &ranges, &mut copied).unwrap(), 5);
+        assert_eq!(copied, b"12678");
+        let source = io::Cursor::new(b"0123");
+        let error = copy_file_ranges(source, &ranges, io::sink()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn incremental_uses_previous_stamps() {
+        let builder = SnapshotBuilder::new().incremental(&example_manifest());
+        assert_eq!(builder.backup_type, BackupType::Incremental);
+        assert_eq!(
+            builder.previous_backup_stamps,
+            example_manifest().backup_stamps
+        );
+    }
+
+    #[test]
+    fn progress_phases_are_ordered() {
+        let phases = [
+            ProgressPhase::Initializing,
+            ProgressPhase::GatheringWriterMetadata,
+            ProgressPhase::PreparingForBackup,
+            ProgressPhase::CreatingSnapshots,
+            ProgressPhase::CheckingWriters,
+            ProgressPhase::SnapshotsCreated,
+        ];
+        assert!(phases
+            .windows(2)
+            .all(|pair| pair[0].percent() < pair[1].percent()));
+        assert_eq!(ProgressPhase::Finished.percent(), 100);
+
+        let reported = std::sync::Mutex::new(Vec::new());
+        let progress = |phase, percent, message: &str| {
+            reported
+                .lock()
+                .unwrap()
+                .push((phase, percent, message.to_owned()));
+        };
+        Progress::report(&progress, ProgressPhase::CheckingWriters, 80, "test");
+        assert_eq!(
+            *reported.lock().unwrap(),
+            [(ProgressPhase::CheckingWriters, 80, "test".to_owned())]
+        );
+    }
+
+    #[test]
+    fn builder_keeps_hooks_in_order() {
+        let builder = SnapshotBuilder::new()
+            .hook(SessionPhase::AfterSnapshot, |_| Ok(()))
+            .hook(SessionPhase::BeforeFreeze, |_| Ok(()))
+            .clone();
+        assert_eq!(
+            format!("{:?}", builder.hooks),
+            "[AfterSnapshot, BeforeFreeze]"
+        );
+    }
+
+    #[test]
+    fn builder_normalizes_volume_names() {
+        let builder = SnapshotBuilder::new().volume("C:/").volume("D:\\");
+        assert_eq!(builder.volumes, ["C:\\", "D:\\"]);
+    }
+
+    #[test]
+    fn create_rejects_invalid_volume_name() {
+        // The volume names are validated before VSS is used.
+        let com = crate::initialize_com_scoped().unwrap();
+        match SnapshotBuilder::new().volume("C:\\\0").create(&com) {
+            Err(SessionError::InvalidVolumeName(volume)) => assert_eq!(volume, "C:\\\0"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("created a snapshot of an invalid volume"),
+        }
+    }
+
+    #[test]
+    fn consistency_follows_writer_involvement() {
+        assert_eq!(
+            Consistency::from(WriterInvolvement::Involved),
+            Consistency::ApplicationConsistent
+        );
+        assert_eq!(
+            Consistency::from(WriterInvolvement::Writerless),
+            Consistency::CrashConsistent
+        );
+    }
+
+    #[test]
+    fn guid_string_round_trip() {
+        let guid = GUID {
+            Data1: 0x1234_abcd,
+            Data2: 0x0102,
+            Data3: 0xfe0f,
+            Data4: [0, 1, 2, 3, 0xa4, 0xb5, 0xc6, 0xd7],
+        };
+        let text = guid_to_string(&guid);
+        assert_eq!(text, "{1234ABCD-0102-FE0F-0001-0203A4B5C6D7}");
+        let parsed = guid_from_string(&text).unwrap();
+        assert!(IsEqualGUID(&guid, &parsed));
+        assert!(guid_from_string(&text.to_lowercase()).is_some());
+
+        assert!(guid_from_string("1234ABCD-0102-FE0F-0001-0203A4B5C6D7").is_none());
+        assert!(guid_from_string("{1234ABCD-0102-FE0F-00010-203A4B5C6D7}").is_none());
+        assert!(guid_from_string("{1234ABCD-0102-FE0F-0001-0203A4B5C6DG}").is_none());
+        assert!(guid_from_string("{+234ABCD-0102-FE0F-0001-0203A4B5C6D7}").is_none());
+    }
+    #[test]
+    fn selection_rules() {
+        let writer_id = GUID {
+            Data1: 0x1234_abcd,
+            Data2: 0x0102,
+            Data3: 0xfe0f,
+            Data4: [0, 1, 2, 3, 0xa4, 0xb5, 0xc6, 0xd7],
+        };
+        let config = SelectionConfig::new();
+        assert!(config.is_writer_selected("SqlServerWriter", &writer_id));
+
+        let config = SelectionConfig {
+            include_by_default: false,
+            ..SelectionConfig::new()
+        }
+        .rule("sqlserverwriter", None, SelectionAction::Include)
+        .rule(
+            "{1234abcd-0102-fe0f-0001-0203a4b5c6d7}",
+            Some("MSSQLSERVER/tempdb"),
+            SelectionAction::Exclude,
+        );
+        assert!(config.is_writer_selected("SqlServerWriter", &writer_id));
+        assert!(config.is_component_selected("SqlServerWriter", &writer_id, "MSSQLSERVER\\master"));
+        assert!(!config.is_component_selected(
+            "SqlServerWriter",
+            &writer_id,
+            "MSSQLSERVER\\TEMPDB"
+        ));
+        assert!(!config.is_component_selected(
+            "SqlServerWriter",
+            &writer_id,
+            "MSSQLSERVER\\tempdb\\file"
+        ));
+        assert!(config.is_component_selected(
+            "SqlServerWriter",
+            &writer_id,
+            "MSSQLSERVER\\tempdb2"
+        ));
+        let other_id = GUID {
+            Data1: 1,
+            ..writer_id
+        };
+        assert!(!config.is_writer_selected("Registry Writer", &other_id));
+    }
+}
@@ -0,0 +1,198 @@
+//! Run backups from asynchronous code that uses [`tokio`](::tokio).
+//!
+//! COM interface pointers can't be moved between threads and the long
+//! running VSS operations block while they are waited on, which doesn't fit
+//! well with tasks that are moved between the threads of a runtime.
+//! [`AsyncBackupComponents`] keeps a backup components object on a
+//! [`VssWorker`] thread and runs every call on that thread, so its handle can
+//! be used from any task.
+//!
+//! This module requires the `tokio` feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::{tokio::AsyncBackupComponents, vss::BackupType};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let backup_comp = AsyncBackupComponents::new().await?;
+//! backup_comp
+//!     .run(|backup_comp| backup_comp.initialize_for_backup(None))
+//!     .await??;
+//! backup_comp
+//!     .run(|backup_comp| backup_comp.set_backup_state(false, false, BackupType::Full, false))
+//!     .await??;
+//! backup_comp.gather_writer_metadata().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{error::Error as StdError, fmt, io};
+
+use ::tokio::{sync::oneshot, task};
+use winapi::shared::winerror::HRESULT;
+
+use crate::{
+    errors::CoInitializeExError,
+    session::{self, SessionError},
+    vsbackup::BackupComponents,
+    vss::IVssAsyncResult,
+    worker::{VssWorker, VssWorkerError, VssWorkerHandle},
+};
+
+/// Error returned by [`AsyncBackupComponents`].
+#[derive(Debug)]
+pub enum AsyncBackupError {
+    /// The worker thread couldn't be spawned.
+    Spawn(io::Error),
+    /// Failed to initialize COM on the worker thread.
+    InitializeCom(CoInitializeExError),
+    /// The worker thread has stopped because a closure passed to
+    /// [`AsyncBackupComponents::run`] panicked.
+    WorkerStopped,
+    /// A VSS call failed.
+    Vss(SessionError),
+}
+impl fmt::Display for AsyncBackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn(e) => write!(f, "failed to spawn worker thread: {}", e),
+            Self::InitializeCom(e) => write!(f, "failed to initialize COM: {}", e),
+            Self::WorkerStopped => write!(f, "the backup components worker thread has stopped"),
+            Self::Vss(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+impl StdError for AsyncBackupError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Spawn(e) => Some(e),
+            Self::InitializeCom(e) => Some(e),
+            Self::WorkerStopped => None,
+            Self::Vss(e) => Some(e),
+        }
+    }
+}
+impl From<SessionError> for AsyncBackupError {
+    fn from(e: SessionError) -> Self {
+        Self::Vss(e)
+    }
+}
+impl From<VssWorkerError> for AsyncBackupError {
+    fn from(e: VssWorkerError) -> Self {
+        match e {
+            VssWorkerError::Spawn(e) => Self::Spawn(e),
+            VssWorkerError::InitializeCom(e) => Self::InitializeCom(e),
+            VssWorkerError::InitializeState(e) => match e.downcast::<SessionError>() {
+                Ok(e) => Self::Vss(*e),
+                Err(_) => unreachable!("the worker state is created by `BackupComponents::new`"),
+            },
+            VssWorkerError::Stopped => Self::WorkerStopped,
+        }
+    }
+}
+
+/// A backup components object that lives on a worker thread and can be used
+/// from asynchronous code.
+///
+/// Calls are run one at a time in the order they were made. The handle can be
+/// cloned, the worker thread exits and the backup components object is
+/// released once the last clone has been dropped and every call that was
+/// made before that has finished.
+#[derive(Debug, Clone)]
+pub struct AsyncBackupComponents {
+    worker: VssWorkerHandle<BackupComponents>,
+    timeout: Option<u32>,
+}
+impl AsyncBackupComponents {
+    /// Start a [`VssWorker`] thread and create a backup components object on
+    /// it. Waiting for the worker to start uses [`task::spawn_blocking`].
+    ///
+    /// # Panics
+    ///
+    /// If called outside of a tokio runtime.
+    #[doc(alias = "CreateVssBackupComponents")]
+    pub async fn new() -> Result<Self, AsyncBackupError> {
+        let worker = task::spawn_blocking(|| {
+            VssWorker::with_state(|| {
+                BackupComponents::new().map_err(SessionError::vss("CreateVssBackupComponents"))
+            })
+        })
+        .await
+        .map_err(|_| AsyncBackupError::WorkerStopped)??;
+        Ok(Self {
+            worker: worker.detach(),
+            timeout: None,
+        })
+    }
+    /// The maximum time in milliseconds to wait for each asynchronous VSS
+    /// operation before it is canceled. `None` waits forever.
+    pub fn timeout(mut self, timeout: Option<u32>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Call `f` with the backup components object on the worker thread and
+    /// return its result.
+    pub async fn run<F, R>(&self, f: F) -> Result<R, AsyncBackupError>
+    where
+        F: FnOnce(&BackupComponents) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result, get_result) = oneshot::channel();
+        self.worker.spawn(move |backup_comp| {
+            let _ = result.send(f(backup_comp));
+        })?;
+        get_result
+            .await
+            .map_err(|_| AsyncBackupError::WorkerStopped)
+    }
+    /// Start an asynchronous VSS operation on the worker thread and wait for
+    /// it there.
+    async fn run_operation<F, E>(
+        &self,
+        operation: &'static str,
+        start: F,
+    ) -> Result<(), AsyncBackupError>
+    where
+        F: FnOnce(&BackupComponents) -> IVssAsyncResult<E> + Send + 'static,
+        E: From<HRESULT> + StdError + Send + Sync + 'static,
+    {
+        let timeout = self.timeout;
+        self.run(move |backup_comp| {
+            let vss_async = start(backup_comp).map_err(SessionError::vss(operation))?;
+            session::wait(operation, vss_async, timeout)
+        })
+        .await?
+        .map_err(AsyncBackupError::Vss)
+    }
+    /// Ask writers for their metadata.
+    #[doc(alias = "GatherWriterMetadata")]
+    pub async fn gather_writer_metadata(&self) -> Result<(), AsyncBackupError> {
+        self.run_operation("GatherWriterMetadata", |backup_comp| {
+            backup_comp.gather_writer_metadata()
+        })
+        .await
+    }
+    /// Notify writers that a backup is about to start.
+    #[doc(alias = "PrepareForBackup")]
+    pub async fn prepare_for_backup(&self) -> Result<(), AsyncBackupError> {
+        self.run_operation("PrepareForBackup", |backup_comp| {
+            backup_comp.prepare_for_backup()
+        })
+        .await
+    }
+    /// Create the shadow copies of the snapshot set.
+    #[doc(alias = "DoSnapshotSet")]
+    pub async fn do_snapshot_set(&self) -> Result<(), AsyncBackupError> {
+        self.run_operation("DoSnapshotSet", |backup_comp| backup_comp.do_snapshot_set())
+            .await
+    }
+    /// Notify writers that the backup is complete.
+    #[doc(alias = "BackupComplete")]
+    pub async fn backup_complete(&self) -> Result<(), AsyncBackupError> {
+        self.run_operation("BackupComplete", |backup_comp| {
+            backup_comp.backup_complete()
+        })
+        .await
+    }
+}
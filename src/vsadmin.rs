@@ -5,3 +5,299 @@
 //! # References
 //!
 //! [Vsadmin.h header - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/api/vsadmin/)
+
+use std::ptr::null_mut;
+
+use widestring::U16CStr;
+use winapi::{
+    shared::{guiddef::CLSID, ntdef::ULONGLONG},
+    um::{
+        combaseapi::{CoCreateInstance, CLSCTX_ALL},
+        vss::{self, VSS_ID},
+        winnt::LONG,
+    },
+    Interface,
+};
+
+use super::{
+    check_com, check_vss_supported,
+    errors::*,
+    impl_query_interface, raw_bitflags, trace_com_call, transparent_wrapper, unsafe_deref_to_ref,
+    unsafe_impl_as_IUnknown,
+    vss::{EnumObject, ProviderType, SnapshotContext, VolumeSnapshotAttributes},
+    ConnectError, RawBitFlags, SafeCOMComponent,
+};
+
+/// Raw declarations for the `vsadmin.h` header since `winapi` doesn't provide
+/// them.
+#[allow(non_snake_case, non_upper_case_globals, non_camel_case_types)]
+pub mod raw {
+    use winapi::{
+        shared::{
+            guiddef::CLSID,
+            ntdef::{LONG, ULONGLONG},
+        },
+        um::{
+            unknwnbase::{IUnknown, IUnknownVtbl},
+            vss::{IVssEnumObject, VSS_ID, VSS_PROVIDER_TYPE, VSS_PWSZ},
+            winnt::HRESULT,
+        },
+        DEFINE_GUID, RIDL,
+    };
+
+    DEFINE_GUID! {CLSID_VSSCoordinator,
+    0xe579ab5f, 0x1cc4, 0x44b4, 0xbe, 0xd9, 0xde, 0x09, 0x91, 0xff, 0x06, 0x23}
+
+    pub type VSS_PROVIDER_CAPABILITIES = ULONGLONG;
+    pub const VSS_PRV_CAPABILITY_LEGACY: VSS_PROVIDER_CAPABILITIES = 0x1;
+    pub const VSS_PRV_CAPABILITY_COMPLIANT: VSS_PROVIDER_CAPABILITIES = 0x2;
+    pub const VSS_PRV_CAPABILITY_LUN_REPOINT: VSS_PROVIDER_CAPABILITIES = 0x4;
+    pub const VSS_PRV_CAPABILITY_LUN_RESYNC: VSS_PROVIDER_CAPABILITIES = 0x8;
+    pub const VSS_PRV_CAPABILITY_OFFLINE_CREATION: VSS_PROVIDER_CAPABILITIES = 0x10;
+    pub const VSS_PRV_CAPABILITY_MULTIPLE_IMPORT: VSS_PROVIDER_CAPABILITIES = 0x20;
+    pub const VSS_PRV_CAPABILITY_RECYCLEBIN: VSS_PROVIDER_CAPABILITIES = 0x40;
+    pub const VSS_PRV_CAPABILITY_PLEX: VSS_PROVIDER_CAPABILITIES = 0x80;
+    pub const VSS_PRV_CAPABILITY_DIFFERENTIAL: VSS_PROVIDER_CAPABILITIES = 0x100;
+    pub const VSS_PRV_CAPABILITY_CLUSTERED: VSS_PROVIDER_CAPABILITIES = 0x200;
+
+    RIDL! {#[uuid(0x77ed5996, 0x2f63, 0x11d3, 0x8a, 0x39, 0x00, 0xc0, 0x4f, 0x72, 0xd8, 0xe3)]
+    interface IVssAdmin(IVssAdminVtbl): IUnknown(IUnknownVtbl) {
+        fn RegisterProvider(
+            pProviderId: VSS_ID,
+            ClassId: CLSID,
+            pwszProviderName: VSS_PWSZ,
+            eProviderType: VSS_PROVIDER_TYPE,
+            pwszProviderVersion: VSS_PWSZ,
+            ProviderVersionId: VSS_ID,
+        ) -> HRESULT,
+        fn UnregisterProvider(
+            ProviderId: VSS_ID,
+        ) -> HRESULT,
+        fn QueryProviders(
+            ppEnum: *mut *mut IVssEnumObject,
+        ) -> HRESULT,
+        fn AbortAllSnapshotsInProgress() -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x7858a9f8, 0xb1fa, 0x41a6, 0x96, 0x4f, 0xb9, 0xb3, 0x6b, 0x8c, 0xd8, 0xd8)]
+    interface IVssAdminEx(IVssAdminExVtbl): IVssAdmin(IVssAdminVtbl) {
+        fn GetProviderCapability(
+            pProviderId: VSS_ID,
+            pllOriginalCapabilityMask: *mut ULONGLONG,
+        ) -> HRESULT,
+        fn GetProviderContext(
+            ProviderId: VSS_ID,
+            plContext: *mut LONG,
+        ) -> HRESULT,
+        fn SetProviderContext(
+            ProviderId: VSS_ID,
+            lContext: LONG,
+        ) -> HRESULT,
+    }}
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssAdmin
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssAdmin);
+
+/// Manages providers registered with VSS.
+#[doc(alias = "IVssAdmin")]
+#[derive(Debug, Clone)]
+pub struct Admin(SafeCOMComponent<raw::IVssAdmin>);
+impl_query_interface!(Admin => raw::IVssAdmin);
+transparent_wrapper!(
+    #[doc(alias = "IVssAdmin")]
+    pub struct IAdmin(raw::IVssAdmin);
+);
+// Safety: all wrappers ensure their wrapped values are valid to use (Not released).
+unsafe_deref_to_ref!(Admin => IAdmin);
+
+impl Admin {
+    /// Connect to the VSS coordinator service.
+    ///
+    /// COM must be initialized before calling this, see [`crate::initialize_com`].
+    /// If the VSS service is disabled or can't start then
+    /// [`ConnectError::ServiceUnavailable`] is returned. 32-bit programs on
+    /// 64-bit Windows get [`ConnectError::Wow64`].
+    #[doc(alias = "CLSID_VSSCoordinator")]
+    pub fn new() -> Result<Self, ConnectError<CreateVssAdminError>> {
+        trace_com_call!("CLSID_VSSCoordinator");
+        check_vss_supported().map_err(ConnectError::Wow64)?;
+        unsafe {
+            let mut comp = null_mut::<raw::IVssAdmin>();
+            check_com(CoCreateInstance(
+                &raw::CLSID_VSSCoordinator,
+                null_mut(),
+                CLSCTX_ALL,
+                &raw::IVssAdmin::uuidof(),
+                &mut comp as *mut *mut raw::IVssAdmin as *mut _,
+            ))
+            .map_err(ConnectError::diagnose)?;
+            Ok(Self(SafeCOMComponent::new(comp)))
+        }
+    }
+}
+impl IAdmin {
+    /// Registers a new shadow copy provider.
+    #[doc(alias = "RegisterProvider")]
+    pub fn register_provider(
+        &self,
+        provider_id: VSS_ID,
+        class_id: CLSID,
+        provider_name: &U16CStr,
+        provider_type: ProviderType,
+        provider_version: &U16CStr,
+        provider_version_id: VSS_ID,
+    ) -> Result<(), RegisterProviderError> {
+        trace_com_call!("RegisterProvider");
+        check_com(unsafe {
+            self.0.RegisterProvider(
+                provider_id,
+                class_id,
+                provider_name.as_ptr() as *mut _,
+                provider_type.into(),
+                provider_version.as_ptr() as *mut _,
+                provider_version_id,
+            )
+        })?;
+        Ok(())
+    }
+    /// Unregisters an existing provider.
+    #[doc(alias = "UnregisterProvider")]
+    pub fn unregister_provider(&self, provider_id: VSS_ID) -> Result<(), UnregisterProviderError> {
+        trace_com_call!("UnregisterProvider");
+        check_com(unsafe { self.0.UnregisterProvider(provider_id) })?;
+        Ok(())
+    }
+    /// Queries all registered providers.
+    ///
+    /// The returned enumerator only contains [`crate::vss::ObjectUnion::Provider`]
+    /// objects.
+    #[doc(alias = "QueryProviders")]
+    pub fn query_providers(&self) -> Result<EnumObject, QueryProvidersError> {
+        trace_com_call!("QueryProviders");
+        let mut enumerator = null_mut::<vss::IVssEnumObject>();
+        check_com(unsafe { self.0.QueryProviders(&mut enumerator) })?;
+        Ok(EnumObject(unsafe { SafeCOMComponent::new(enumerator) }))
+    }
+    /// Cancels all snapshot-related operations that are in progress on the
+    /// system.
+    #[doc(alias = "AbortAllSnapshotsInProgress")]
+    pub fn abort_all_snapshots_in_progress(&self) -> Result<(), AbortAllSnapshotsInProgressError> {
+        trace_com_call!("AbortAllSnapshotsInProgress");
+        check_com(unsafe { self.0.AbortAllSnapshotsInProgress() })?;
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssAdminEx
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssAdminEx);
+
+/// Manages providers registered with VSS. Use
+/// [`crate::QueryInterface::query`] on an [`Admin`] to get this
+/// interface.
+#[doc(alias = "IVssAdminEx")]
+#[derive(Debug, Clone)]
+pub struct AdminEx(SafeCOMComponent<raw::IVssAdminEx>);
+impl_query_interface!(AdminEx => raw::IVssAdminEx);
+transparent_wrapper!(
+    #[doc(alias = "IVssAdminEx")]
+    pub struct IAdminEx(raw::IVssAdminEx);
+);
+// Safety: all wrappers ensure their wrapped values are valid to use (Not released).
+unsafe_deref_to_ref!(AdminEx => IAdminEx => IAdmin);
+
+impl IAdminEx {
+    /// Gets the capabilities for the specified provider.
+    ///
+    /// Note: Microsoft documents this method as reserved for future use so
+    /// it might fail with `E_NOTIMPL` on some systems.
+    #[doc(alias = "GetProviderCapability")]
+    pub fn get_provider_capability(
+        &self,
+        provider_id: VSS_ID,
+    ) -> Result<RawBitFlags<ProviderCapabilities>, GetProviderCapabilityError> {
+        trace_com_call!("GetProviderCapability");
+        let mut mask: ULONGLONG = 0;
+        check_com(unsafe { self.0.GetProviderCapability(provider_id, &mut mask) })?;
+        Ok(RawBitFlags::from_raw(mask))
+    }
+    /// Gets the context for the specified provider.
+    ///
+    /// The returned value is a combination of a [`SnapshotContext`] and
+    /// [`VolumeSnapshotAttributes`] flags.
+    #[doc(alias = "GetProviderContext")]
+    pub fn get_provider_context(
+        &self,
+        provider_id: VSS_ID,
+    ) -> Result<LONG, GetProviderContextError> {
+        trace_com_call!("GetProviderContext");
+        let mut context: LONG = 0;
+        check_com(unsafe { self.0.GetProviderContext(provider_id, &mut context) })?;
+        Ok(context)
+    }
+    /// Sets the context for subsequent shadow copy-related operations on the
+    /// specified provider.
+    ///
+    /// Note that both the context arguments implement `Default` with sensible
+    /// values.
+    #[doc(alias = "SetProviderContext")]
+    pub fn set_provider_context(
+        &self,
+        provider_id: VSS_ID,
+        context: SnapshotContext,
+        attributes: RawBitFlags<VolumeSnapshotAttributes>,
+    ) -> Result<(), SetProviderContextError> {
+        trace_com_call!("SetProviderContext");
+        let context = context.to_raw_context(attributes);
+        check_com(unsafe { self.0.SetProviderContext(provider_id, context) })?;
+        Ok(())
+    }
+}
+
+raw_bitflags! {
+    /// Specifies the set of operations that a provider supports.
+    #[doc(alias = "VSS_PROVIDER_CAPABILITIES")]
+    #[derive(Default)]
+    pub struct ProviderCapabilities: raw::VSS_PROVIDER_CAPABILITIES {
+        /// The provider does not implement the `IVssHardwareSnapshotProviderEx`
+        /// interface.
+        #[doc(alias = "VSS_PRV_CAPABILITY_LEGACY")]
+        const LEGACY = raw::VSS_PRV_CAPABILITY_LEGACY;
+        /// The provider is compliant with the provider requirements of the
+        /// current VSS version.
+        #[doc(alias = "VSS_PRV_CAPABILITY_COMPLIANT")]
+        const COMPLIANT = raw::VSS_PRV_CAPABILITY_COMPLIANT;
+        /// The provider supports LUN repointing.
+        #[doc(alias = "VSS_PRV_CAPABILITY_LUN_REPOINT")]
+        const LUN_REPOINT = raw::VSS_PRV_CAPABILITY_LUN_REPOINT;
+        /// The provider supports LUN resynchronization.
+        #[doc(alias = "VSS_PRV_CAPABILITY_LUN_RESYNC")]
+        const LUN_RESYNC = raw::VSS_PRV_CAPABILITY_LUN_RESYNC;
+        /// The provider supports creating shadow copies offline.
+        #[doc(alias = "VSS_PRV_CAPABILITY_OFFLINE_CREATION")]
+        const OFFLINE_CREATION = raw::VSS_PRV_CAPABILITY_OFFLINE_CREATION;
+        /// The provider supports importing multiple shadow copy sets at once.
+        #[doc(alias = "VSS_PRV_CAPABILITY_MULTIPLE_IMPORT")]
+        const MULTIPLE_IMPORT = raw::VSS_PRV_CAPABILITY_MULTIPLE_IMPORT;
+        /// The provider supports recycle bin shadow copies.
+        #[doc(alias = "VSS_PRV_CAPABILITY_RECYCLEBIN")]
+        const RECYCLEBIN = raw::VSS_PRV_CAPABILITY_RECYCLEBIN;
+        /// The provider supports plex shadow copies.
+        #[doc(alias = "VSS_PRV_CAPABILITY_PLEX")]
+        const PLEX = raw::VSS_PRV_CAPABILITY_PLEX;
+        /// The provider supports differential shadow copies.
+        #[doc(alias = "VSS_PRV_CAPABILITY_DIFFERENTIAL")]
+        const DIFFERENTIAL = raw::VSS_PRV_CAPABILITY_DIFFERENTIAL;
+        /// The provider supports shadow copies of clustered volumes.
+        #[doc(alias = "VSS_PRV_CAPABILITY_CLUSTERED")]
+        const CLUSTERED = raw::VSS_PRV_CAPABILITY_CLUSTERED;
+    }
+}
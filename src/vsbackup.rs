@@ -12,17 +12,21 @@
 use std::{
     borrow::Cow,
     convert::{TryFrom, TryInto},
+    error::Error as StdError,
+    ffi::{OsStr, OsString},
+    fmt,
     mem::ManuallyDrop,
     ops::Deref,
+    path::PathBuf,
     ptr::{null, null_mut},
     slice,
 };
 
-use widestring::U16CStr;
+use widestring::{NulError, U16CStr, U16CString};
 use winapi::{
     ctypes::c_void,
     shared::{
-        guiddef::REFIID,
+        guiddef::{IsEqualGUID, REFIID},
         minwindef::{BOOL, DWORD, FALSE, TRUE, UINT, ULONG},
         winerror::{S_FALSE, S_OK},
         wtypes::BSTR,
@@ -30,7 +34,7 @@ use winapi::{
     um::{
         cguid::GUID_NULL,
         vsbackup::{self, PVSSCOMPONENTINFO, VSS_COMPONENTINFO},
-        vss::{self, VSS_ID, VSS_PWSZ, VSS_SNAPSHOT_CONTEXT},
+        vss::{self, VSS_ID, VSS_PWSZ},
         vswriter,
         winnt::HRESULT,
         winnt::LONG,
@@ -39,21 +43,23 @@ use winapi::{
 use winstr::{BStr, BString};
 
 use super::{
-    check_com,
+    bstr_as_u16_slice, bstr_to_u16_cstring, check_com, check_vss_supported,
     errors::*,
-    impl_as_ref_and_borrow, impl_query_interface,
+    impl_as_ref_and_borrow, impl_query_interface, is_server_os, paths,
     safe_com_component::CustomIUnknown,
-    take_ownership_of_bstr, transparent_wrapper, unsafe_deref_to_ref, unsafe_impl_as_IUnknown,
+    take_ownership_of_bstr, trace_com_call, transparent_wrapper, unsafe_deref_to_ref,
+    unsafe_impl_as_IUnknown,
     vss::{
-        BackupSchema, BackupType, EnumObject, HardwareOptions, IVssAsyncResult, ObjectType,
-        RecoveryOptions, RestoreType, RollForwardType, SnapshotCapability, SnapshotContext,
+        AsyncStatus, BackupSchema, BackupType, EnumObject, HardwareOptions, IVssAsyncResult,
+        ObjectType, ProviderIterator, RecoveryOptions, RestoreType, RollForward,
+        SnapshotCapability, SnapshotContext, SnapshotContextConfig, SnapshotIterator,
         SnapshotProperties, VolumeSnapshotAttributes, VssAsync, WriterState,
     },
     vswriter::{
         FileRestoreStatus, IWriterComponents, RestoreMethod, SourceType, UsageType,
         VssComponentFlags, VssComponentType, WMDependency, WMFileDescriptor, WriterRestore,
     },
-    RawBitFlags, SafeCOMComponent, VssU16CString,
+    ConnectError, RawBitFlags, SafeCOMComponent, VssU16CString,
 };
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -75,14 +81,41 @@ transparent_wrapper!(
 unsafe_deref_to_ref!(BackupComponents => IBackupComponents);
 
 impl BackupComponents {
+    /// Connect to VSS by creating a new backup components object.
+    ///
+    /// If the VSS service is disabled or can't start then
+    /// [`ConnectError::ServiceUnavailable`] is returned. 32-bit programs on
+    /// 64-bit Windows get [`ConnectError::Wow64`].
     #[doc(alias = "CreateVssBackupComponents")]
-    pub fn new() -> Result<Self, CreateVssBackupComponentsError> {
+    pub fn new() -> Result<Self, ConnectError<CreateVssBackupComponentsError>> {
+        trace_com_call!("CreateVssBackupComponents");
+        check_vss_supported().map_err(ConnectError::Wow64)?;
         unsafe {
             let mut comp = null_mut::<vsbackup::IVssBackupComponents>();
-            check_com(vsbackup::CreateVssBackupComponents(&mut comp))?;
+            check_com(vsbackup::CreateVssBackupComponents(&mut comp))
+                .map_err(ConnectError::diagnose)?;
             Ok(Self(SafeCOMComponent::new(comp)))
         }
     }
+    /// Create a backup components object that is ready to add volumes to a
+    /// shadow copy set.
+    ///
+    /// This calls [`BackupComponents::new`],
+    /// [`IBackupComponents::initialize_for_backup`],
+    /// [`IBackupComponents::set_context`] and
+    /// [`IBackupComponents::set_backup_state`] using the provided `options`.
+    pub fn for_backup(options: &BackupOptions) -> Result<Self, ForBackupError> {
+        let comp = Self::new().map_err(ForBackupError::Create)?;
+        comp.initialize_for_backup(None)
+            .map_err(ForBackupError::InitializeForBackup)?;
+        comp.set_context_config(options.context)
+            .map_err(ForBackupError::SetContext)?;
+        options
+            .backup_state
+            .apply(&comp)
+            .map_err(ForBackupError::SetBackupState)?;
+        Ok(comp)
+    }
 }
 impl IBackupComponents {
     /// The `abort_backup` method notifies VSS that a backup operation was terminated.
@@ -100,9 +133,18 @@ impl IBackupComponents {
     /// of each writer through the `CVssWriter::OnAbort` method.
     #[doc(alias = "AbortBackup")]
     pub fn abort_backup(&self) -> Result<(), AbortBackupError> {
+        trace_com_call!("AbortBackup");
         check_com(unsafe { self.0.AbortBackup() })?;
         Ok(())
     }
+    /// Refer to a single component of a writer so that it can be configured
+    /// without passing its identification to every method.
+    pub fn component<'a>(&'a self, spec: ComponentSpec<'a>) -> ComponentHandle<'a> {
+        ComponentHandle {
+            components: self,
+            spec,
+        }
+    }
     /// Used by a requester to indicate that an alternate location mapping was
     /// used to restore all the members of a file set in a given component.
     #[doc(alias = "AddAlternativeLocationMapping")]
@@ -117,6 +159,7 @@ impl IBackupComponents {
         recursive: bool,
         destination: &U16CStr,
     ) -> Result<(), AddAlternativeLocationMappingError> {
+        trace_com_call!("AddAlternativeLocationMapping");
         check_com(unsafe {
             self.0.AddAlternativeLocationMapping(
                 writer_id,
@@ -140,6 +183,7 @@ impl IBackupComponents {
         logical_path: Option<&U16CStr>,
         component_name: &U16CStr,
     ) -> Result<(), AddComponentError> {
+        trace_com_call!("AddComponent");
         check_com(unsafe {
             self.0.AddComponent(
                 instance_id,
@@ -165,6 +209,7 @@ impl IBackupComponents {
         recursive: bool,
         alternate_path: &U16CStr,
     ) -> Result<(), AddNewTargetError> {
+        trace_com_call!("AddNewTarget");
         check_com(unsafe {
             self.0.AddNewTarget(
                 writer_id,
@@ -193,6 +238,7 @@ impl IBackupComponents {
         sub_component_logical_path: &U16CStr,
         sub_component_name: &U16CStr,
     ) -> Result<(), AddRestoreSubcomponentError> {
+        trace_com_call!("AddRestoreSubcomponent");
         check_com(unsafe {
             self.0.AddRestoreSubcomponent(
                 writer_id,
@@ -214,6 +260,7 @@ impl IBackupComponents {
         volume_name: &U16CStr,
         provider_id: Option<VSS_ID>,
     ) -> Result<VSS_ID, AddToSnapshotSetError> {
+        trace_com_call!("AddToSnapshotSet");
         let mut snapshot_id: VSS_ID = Default::default();
         check_com(unsafe {
             self.0.AddToSnapshotSet(
@@ -228,6 +275,7 @@ impl IBackupComponents {
     /// the backup process has completed.
     #[doc(alias = "BackupComplete")]
     pub fn backup_complete(&self) -> IVssAsyncResult<BackupCompleteError> {
+        trace_com_call!("BackupComplete");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.BackupComplete(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
@@ -235,6 +283,7 @@ impl IBackupComponents {
     /// Cause the existence of a shadow copy set to be "forgotten" by VSS.
     #[doc(alias = "BreakSnapshotSet")]
     pub fn break_snapshot_set(&self, snapshot_set_id: VSS_ID) -> Result<(), BreakSnapshotSetError> {
+        trace_com_call!("BreakSnapshotSet");
         check_com(unsafe { self.0.BreakSnapshotSet(snapshot_set_id) })?;
         Ok(())
     }
@@ -246,6 +295,7 @@ impl IBackupComponents {
         source_object_type: ObjectType,
         force_delete: bool,
     ) -> DeleteSnapshotsInfo {
+        trace_com_call!("DeleteSnapshots");
         let mut deleted_snapshots = 0;
         let mut nondeleted_snapshot_id = Default::default();
         let error = check_com(unsafe {
@@ -264,12 +314,38 @@ impl IBackupComponents {
             error: error.map(Into::into),
         }
     }
+    /// Delete a shadow copy or all shadow copies in a shadow copy set.
+    ///
+    /// Unlike [`delete_snapshots`](Self::delete_snapshots) this returns the
+    /// number of deleted shadow copies on success and only accepts the object
+    /// types that can actually be deleted.
+    #[doc(alias = "DeleteSnapshots")]
+    pub fn delete(
+        &self,
+        target: DeleteTarget,
+        force_delete: bool,
+    ) -> Result<u32, DeleteSnapshotsFailure> {
+        let (id, object_type) = match target {
+            DeleteTarget::Snapshot(id) => (id, ObjectType::Snapshot),
+            DeleteTarget::SnapshotSet(id) => (id, ObjectType::SnapshotSet),
+        };
+        let info = self.delete_snapshots(id, object_type, force_delete);
+        match (info.error, info.nondeleted_snapshot_id) {
+            (Some(error), Some(nondeleted_snapshot_id)) => Err(DeleteSnapshotsFailure {
+                deleted_snapshots: info.deleted_snapshots,
+                nondeleted_snapshot_id,
+                error,
+            }),
+            _ => Ok(info.deleted_snapshots),
+        }
+    }
     /// Prevent a specific class of writers from receiving any events.
     #[doc(alias = "DisableWriterClasses")]
     pub fn disable_writer_classes(
         &self,
         writer_class_ids: &[VSS_ID],
     ) -> Result<(), DisableWriterClassesError> {
+        trace_com_call!("DisableWriterClasses");
         let len = writer_class_ids.len();
         // The documentation says 1 or more entries, so to be safe we assert that:
         assert_ne!(len, 0);
@@ -285,6 +361,7 @@ impl IBackupComponents {
         &self,
         writer_instance_ids: &[VSS_ID],
     ) -> Result<(), DisableWriterInstancesError> {
+        trace_com_call!("DisableWriterInstances");
         let len = writer_instance_ids.len();
         // The documentation says 1 or more entries, so to be safe we assert that:
         assert_ne!(len, 0);
@@ -297,6 +374,7 @@ impl IBackupComponents {
     /// Commits all shadow copies in this set simultaneously.
     #[doc(alias = "DoSnapshotSet")]
     pub fn do_snapshot_set(&self) -> IVssAsyncResult<DoSnapshotSetError> {
+        trace_com_call!("DoSnapshotSet");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.DoSnapshotSet(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
@@ -307,6 +385,7 @@ impl IBackupComponents {
         &self,
         writer_class_ids: &[VSS_ID],
     ) -> Result<(), EnableWriterClassesError> {
+        trace_com_call!("EnableWriterClasses");
         let len = writer_class_ids.len();
         // The documentation says 1 or more entries, so to be safe we assert that:
         assert_ne!(len, 0);
@@ -328,6 +407,7 @@ impl IBackupComponents {
         attributes: RawBitFlags<VolumeSnapshotAttributes>,
         expose: Option<&U16CStr>,
     ) -> Result<VssU16CString, ExposeSnapshotError> {
+        trace_com_call!("ExposeSnapshot");
         let mut exposed: VSS_PWSZ = null_mut();
         let result = check_com(unsafe {
             self.0.ExposeSnapshot(
@@ -345,15 +425,72 @@ impl IBackupComponents {
         result?;
         Ok(exposed.expect("the returned string from `ExposeSnapshot` shouldn't be null"))
     }
+    /// Exposes a persistent shadow copy at the location described by
+    /// `exposure`.
+    ///
+    /// This picks the attributes and arguments that
+    /// [`IBackupComponents::expose_snapshot`] requires for each kind of
+    /// exposure. Returns the exposed name of the shadow copy. Use
+    /// [`IBackupComponentsEx2::unexpose_snapshot`] to remove it again.
+    #[doc(alias = "ExposeSnapshot")]
+    pub fn expose(
+        &self,
+        snapshot_id: VSS_ID,
+        exposure: &Exposure,
+    ) -> Result<OsString, ExposeError> {
+        let to_wide = |s: &OsStr| U16CString::from_os_str(s).map_err(ExposeError::InvalidName);
+        let (path_from_root, expose, attributes) = match exposure {
+            Exposure::DriveLetter(letter) => {
+                if !letter.is_ascii_alphabetic() {
+                    return Err(ExposeError::InvalidDriveLetter(*letter));
+                }
+                let drive = format!("{}:", letter.to_ascii_uppercase());
+                (
+                    None,
+                    Some(to_wide(drive.as_ref())?),
+                    VolumeSnapshotAttributes::EXPOSED_LOCALLY,
+                )
+            }
+            Exposure::MountedFolder(folder) => (
+                None,
+                Some(to_wide(folder.as_os_str())?),
+                VolumeSnapshotAttributes::EXPOSED_LOCALLY,
+            ),
+            Exposure::Share {
+                name,
+                path_from_root,
+            } => (
+                path_from_root
+                    .as_ref()
+                    .map(|path| to_wide(path.as_os_str()))
+                    .transpose()?,
+                name.as_ref()
+                    .map(|name| to_wide(name.as_ref()))
+                    .transpose()?,
+                VolumeSnapshotAttributes::EXPOSED_REMOTELY,
+            ),
+        };
+        let exposed = self
+            .expose_snapshot(
+                snapshot_id,
+                path_from_root.as_deref(),
+                attributes.into(),
+                expose.as_deref(),
+            )
+            .map_err(ExposeError::Expose)?;
+        Ok(exposed.to_os_string())
+    }
     /// Frees system resources allocated when
     /// [`IBackupComponents::gather_writer_metadata`] was called.
     #[doc(alias = "FreeWriterMetadata")]
     pub fn free_writer_metadata(&self) -> Result<(), FreeWriterMetadataError> {
+        trace_com_call!("FreeWriterMetadata");
         check_com(unsafe { self.0.FreeWriterMetadata() })?;
         Ok(())
     }
     #[doc(alias = "FreeWriterStatus")]
     pub fn free_writer_status(&self) -> Result<(), FreeWriterStatusError> {
+        trace_com_call!("FreeWriterStatus");
         // TODO: is it safe to call this method before the `IVssAsync` operation
         // from `GatherWriterStatus` completes. In other words, are we freeing
         // resources that are in use or will the interface handle that?
@@ -368,32 +505,106 @@ impl IBackupComponents {
     /// `IVssBackupComponents` object.
     #[doc(alias = "GatherWriterMetadata")]
     pub fn gather_writer_metadata(&self) -> IVssAsyncResult<GatherWriterMetadataError> {
+        trace_com_call!("GatherWriterMetadata");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.GatherWriterMetadata(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
     }
+    /// Prompt each writer to send their metadata and wait for them to
+    /// respond.
+    ///
+    /// The returned guard gives access to the metadata of each writer and
+    /// calls [`IBackupComponents::free_writer_metadata`] exactly once, either
+    /// when [`WriterMetadataSession::free`] is called or when it is dropped.
+    ///
+    /// # Remarks
+    ///
+    /// Like [`IBackupComponents::gather_writer_metadata`] this should be called
+    /// only once during the lifetime of a given `IVssBackupComponents` object.
+    /// If waiting for the operation fails then the metadata is not freed
+    /// since the operation might still be using it. A canceled operation
+    /// returns a `VSS_S_ASYNC_CANCELLED` error.
+    #[doc(alias = "GatherWriterMetadata")]
+    pub fn gather_writer_metadata_scoped(
+        &self,
+    ) -> Result<WriterMetadataSession<'_>, GatherWriterMetadataScopedError> {
+        let vss_async = self
+            .gather_writer_metadata()
+            .map_err(|e| GatherWriterMetadataScopedError(e.0))?;
+        vss_async
+            .wait(None)
+            .map_err(|e| GatherWriterMetadataScopedError(e.into()))?;
+        let session = WriterMetadataSession {
+            components: self,
+            freed: false,
+        };
+        let status = vss_async
+            .query_status()
+            .map_err(|e| GatherWriterMetadataScopedError(e.into()))?;
+        if status == AsyncStatus::Canceled {
+            return Err(GatherWriterMetadataScopedError::from_kind(
+                GatherWriterMetadataScopedErrorKind::VSS_S_ASYNC_CANCELLED,
+            ));
+        }
+        Ok(session)
+    }
     /// Prompt each writer to send a status message.
     ///
     /// # Remarks
     ///
     /// The caller of this method should also call
     /// [`IBackupComponents::free_writer_status`] after receiving the status
-    /// of each writer.
+    /// of each writer. [`IBackupComponents::gather_writer_status_scoped`]
+    /// does that automatically.
     #[doc(alias = "GatherWriterStatus")]
     pub fn gather_writer_status(&self) -> IVssAsyncResult<GatherWriterStatusError> {
-        // TODO: maybe wrap the returned `IVssAsync` operation in a wrapper
-        // that calls `free_writer_status` when the operation completes to
-        // make it harder to leak memory?
+        trace_com_call!("GatherWriterStatus");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.GatherWriterStatus(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
     }
+    /// Prompt each writer to send a status message and wait for them to
+    /// respond.
+    ///
+    /// The returned guard gives access to the status of each writer and calls
+    /// [`IBackupComponents::free_writer_status`] when it is dropped.
+    ///
+    /// # Remarks
+    ///
+    /// If waiting for the operation fails then the writer status is not freed
+    /// since the operation might still be using it. A canceled operation
+    /// returns a `VSS_S_ASYNC_CANCELLED` error.
+    #[doc(alias = "GatherWriterStatus")]
+    pub fn gather_writer_status_scoped(
+        &self,
+    ) -> Result<WriterStatusSession<'_>, GatherWriterStatusScopedError> {
+        let vss_async = self
+            .gather_writer_status()
+            .map_err(|e| GatherWriterStatusScopedError(e.0))?;
+        vss_async
+            .wait(None)
+            .map_err(|e| GatherWriterStatusScopedError(e.into()))?;
+        let session = WriterStatusSession {
+            components: self,
+            freed: false,
+        };
+        let status = vss_async
+            .query_status()
+            .map_err(|e| GatherWriterStatusScopedError(e.into()))?;
+        if status == AsyncStatus::Canceled {
+            return Err(GatherWriterStatusScopedError::from_kind(
+                GatherWriterStatusScopedErrorKind::VSS_S_ASYNC_CANCELLED,
+            ));
+        }
+        Ok(session)
+    }
     /// Gets the properties of the specified shadow copy.
     #[doc(alias = "GetSnapshotProperties")]
     pub fn get_snapshot_properties(
         &self,
         snapshot_id: VSS_ID,
     ) -> Result<SnapshotProperties, GetSnapshotPropertiesError> {
+        trace_com_call!("GetSnapshotProperties");
         let mut prop: vss::VSS_SNAPSHOT_PROP = Default::default();
         check_com(unsafe { self.0.GetSnapshotProperties(snapshot_id, &mut prop) })?;
         Ok(SnapshotProperties(prop))
@@ -405,6 +616,7 @@ impl IBackupComponents {
         &self,
         writer_index: u32,
     ) -> Result<WriterComponentsExt, GetWriterComponentsError> {
+        trace_com_call!("GetWriterComponents");
         let mut writer: *mut vsbackup::IVssWriterComponentsExt = null_mut();
         check_com(unsafe { self.0.GetWriterComponents(writer_index, &mut writer) })?;
         Ok(WriterComponentsExt(unsafe {
@@ -415,10 +627,25 @@ impl IBackupComponents {
     /// requester's Backup Components Document.
     #[doc(alias = "GetWriterComponentsCount")]
     pub fn get_writer_components_count(&self) -> Result<u32, GetWriterComponentsCountError> {
+        trace_com_call!("GetWriterComponentsCount");
         let mut components: UINT = 0;
         check_com(unsafe { self.0.GetWriterComponentsCount(&mut components) })?;
         Ok(components)
     }
+    /// Iterate over the writers whose components are stored in the Backup
+    /// Components Document, combining
+    /// [`IBackupComponents::get_writer_components_count`] and
+    /// [`IBackupComponents::get_writer_components`]. This is mostly useful
+    /// during restore.
+    pub fn writer_components_iter(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<WriterComponentsExt, GetWriterComponentsError>> + '_,
+        GetWriterComponentsCountError,
+    > {
+        let count = self.get_writer_components_count()?;
+        Ok((0..count).map(move |index| self.get_writer_components(index)))
+    }
     /// The GetWriterMetadata method returns the metadata for a specific writer
     /// running on the system.
     ///
@@ -458,6 +685,7 @@ impl IBackupComponents {
         writer_index: u32,
         writer_instance: VSS_ID,
     ) -> Result<ExamineWriterMetadata, GetWriterMetadataError> {
+        trace_com_call!("GetWriterMetadata");
         let mut metadata = null_mut::<vsbackup::IVssExamineWriterMetadata>();
         check_com(unsafe {
             self.0.GetWriterMetadata(
@@ -473,16 +701,61 @@ impl IBackupComponents {
     /// Returns the number of writers with metadata.
     #[doc(alias = "GetWriterMetadataCount")]
     pub fn get_writer_metadata_count(&self) -> Result<u32, GetWriterMetadataCountError> {
+        trace_com_call!("GetWriterMetadataCount");
         let mut writers: UINT = 0;
         check_com(unsafe { self.0.GetWriterMetadataCount(&mut writers) })?;
         Ok(writers)
     }
+    /// Iterate over the metadata of all writers that was collected by
+    /// [`IBackupComponents::gather_writer_metadata`]. Each item contains the
+    /// instance id of the writer and its metadata.
+    pub fn writer_metadata_iter(
+        &self,
+    ) -> Result<WriterMetadataIter<'_>, GetWriterMetadataCountError> {
+        Ok(WriterMetadataIter {
+            components: self,
+            index: 0,
+            count: self.get_writer_metadata_count()?,
+        })
+    }
+    /// Search the metadata that was collected by
+    /// [`IBackupComponents::gather_writer_metadata`] for a writer with the
+    /// specified display name, for example `"Microsoft Hyper-V VSS Writer"`.
+    ///
+    /// Returns the identity of the first writer whose name is an exact match or
+    /// `None` if no such writer was found. Note that the same writer class can
+    /// have multiple instances, each with its own instance id.
+    pub fn find_writer_by_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<GetIdentityInfo>, FindWriterByNameError> {
+        let count = self
+            .get_writer_metadata_count()
+            .map_err(|e| FindWriterByNameError(e.0))?;
+        for writer_index in 0..count {
+            let metadata = self
+                .get_writer_metadata(writer_index, Default::default())
+                .map_err(|e| FindWriterByNameError(e.0))?;
+            let identity = metadata
+                .get_identity()
+                .map_err(|e| FindWriterByNameError(e.0))?;
+            if bstr_as_u16_slice(&identity.writer_name)
+                .iter()
+                .copied()
+                .eq(name.encode_utf16())
+            {
+                return Ok(Some(identity));
+            }
+        }
+        Ok(None)
+    }
     /// Returns the status of the specified writer.
     #[doc(alias = "GetWriterStatus")]
     pub fn get_writer_status(
         &self,
         writer_index: u32,
     ) -> Result<GetWriterStatusInfo, GetWriterStatusError> {
+        trace_com_call!("GetWriterStatus");
         let mut instance_id: VSS_ID = Default::default();
         let mut writer_id: VSS_ID = Default::default();
         let mut writer: BSTR = null_mut();
@@ -513,16 +786,41 @@ impl IBackupComponents {
     /// Returns the number of writers with status.
     #[doc(alias = "GetWriterStatusCount")]
     pub fn get_writer_status_count(&self) -> Result<u32, GetWriterStatusCountError> {
+        trace_com_call!("GetWriterStatusCount");
         let mut writers: UINT = 0;
         check_com(unsafe { self.0.GetWriterStatusCount(&mut writers) })?;
         Ok(writers)
     }
+    /// Read the status of every writer and then free the statuses with
+    /// [`IBackupComponents::free_writer_status`].
+    ///
+    /// The operation returned by [`IBackupComponents::gather_writer_status`]
+    /// must have completed before this is called.
+    pub fn writer_statuses(&self) -> Result<Vec<WriterStatusReport>, WriterStatusesError> {
+        let statuses = (|| {
+            let count = self
+                .get_writer_status_count()
+                .map_err(|e| WriterStatusesError(e.0))?;
+            (0..count)
+                .map(|writer_index| {
+                    self.get_writer_status(writer_index)
+                        .map(WriterStatusReport::from)
+                        .map_err(|e| WriterStatusesError(e.0))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })();
+        let freed = self.free_writer_status();
+        let statuses = statuses?;
+        freed.map_err(|e| WriterStatusesError(e.0))?;
+        Ok(statuses)
+    }
     /// imports shadow copies transported from a different machine.
     ///
     /// Note: This method is supported only on Windows Server operating systems
     /// and for Volume Shadow Copy Service hardware providers.
     #[doc(alias = "ImportSnapshots")]
     pub fn import_snapshots(&self) -> IVssAsyncResult<ImportSnapshotsError> {
+        trace_com_call!("ImportSnapshots");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.ImportSnapshots(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
@@ -533,6 +831,7 @@ impl IBackupComponents {
         &self,
         xml: Option<&BStr>,
     ) -> Result<(), InitializeForBackupError> {
+        trace_com_call!("InitializeForBackup");
         // TODO: is the xml string reference used after this function returns?
         check_com(unsafe {
             self.0
@@ -544,6 +843,7 @@ impl IBackupComponents {
     /// operation.
     #[doc(alias = "InitializeForRestore")]
     pub fn initialize_for_restore(&self, xml: &BStr) -> Result<(), InitializeForRestoreError> {
+        trace_com_call!("InitializeForRestore");
         check_com(unsafe { self.0.InitializeForRestore(xml.as_bstr()) })?;
         Ok(())
     }
@@ -555,6 +855,7 @@ impl IBackupComponents {
         provider_id: Option<VSS_ID>,
         volume_name: &U16CStr,
     ) -> Result<bool, IsVolumeSupportedError> {
+        trace_com_call!("IsVolumeSupported");
         let mut supported_by_this_provider: BOOL = FALSE;
         check_com(unsafe {
             self.0.IsVolumeSupported(
@@ -569,6 +870,7 @@ impl IBackupComponents {
     /// current restore operation has finished.
     #[doc(alias = "PostRestore")]
     pub fn post_restore(&self) -> IVssAsyncResult<PostRestoreError> {
+        trace_com_call!("PostRestore");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.PostRestore(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
@@ -578,12 +880,14 @@ impl IBackupComponents {
     /// Backup Components Document available to writers.
     #[doc(alias = "PrepareForBackup")]
     pub fn prepare_for_backup(&self) -> IVssAsyncResult<PrepareForBackupError> {
+        trace_com_call!("PrepareForBackup");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.PrepareForBackup(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
     }
     #[doc(alias = "PreRestore")]
     pub fn pre_restore(&self) -> IVssAsyncResult<PreRestoreError> {
+        trace_com_call!("PreRestore");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.PreRestore(&mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
@@ -593,6 +897,7 @@ impl IBackupComponents {
     /// during backup operations.
     #[doc(alias = "Query")]
     pub fn query(&self, returned_objects_type: ObjectType) -> Result<EnumObject, QueryError> {
+        trace_com_call!("Query");
         let mut enumerator = null_mut::<vss::IVssEnumObject>();
         check_com(unsafe {
             self.0.Query(
@@ -604,9 +909,43 @@ impl IBackupComponents {
         })?;
         Ok(EnumObject(unsafe { SafeCOMComponent::new(enumerator) }))
     }
+    /// Query the completed shadow copies in the current context.
+    ///
+    /// Same as [`IBackupComponents::query`] with [`ObjectType::Snapshot`] but
+    /// the returned iterator only yields shadow copy properties.
+    pub fn query_snapshots(&self) -> Result<SnapshotIterator, QueryError> {
+        Ok(self.query(ObjectType::Snapshot)?.into_snapshots())
+    }
+    /// Query the completed shadow copies that belong to the shadow copy set
+    /// with the specified id.
+    ///
+    /// The `Query` method documents that the queried object must be
+    /// `GUID_NULL`, so VSS can't filter by shadow copy set itself. Instead all
+    /// shadow copies in the current context are queried and the ones in other
+    /// sets are skipped.
+    pub fn query_snapshot_set(
+        &self,
+        snapshot_set_id: VSS_ID,
+    ) -> Result<impl Iterator<Item = Result<SnapshotProperties, EnumObjectNextError>>, QueryError>
+    {
+        Ok(self
+            .query_snapshots()?
+            .filter(move |snapshot| match snapshot {
+                Ok(snapshot) => IsEqualGUID(&snapshot.snapshot_set_id(), &snapshot_set_id),
+                Err(_) => true,
+            }))
+    }
+    /// Query the providers on the system.
+    ///
+    /// Same as [`IBackupComponents::query`] with [`ObjectType::Provider`] but
+    /// the returned iterator only yields provider properties.
+    pub fn query_providers(&self) -> Result<ProviderIterator, QueryError> {
+        Ok(self.query(ObjectType::Provider)?.into_providers())
+    }
     /// Used to determine the status of the revert operation.
     #[doc(alias = "QueryRevertStatus")]
     pub fn query_revert_status(&self, volume: &U16CStr) -> IVssAsyncResult<QueryRevertStatusError> {
+        trace_com_call!("QueryRevertStatus");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe {
             self.0
@@ -630,6 +969,7 @@ impl IBackupComponents {
         snapshot_id: VSS_ID,
         force_dismount: bool,
     ) -> Result<(), RevertToSnapshotError> {
+        trace_com_call!("RevertToSnapshot");
         check_com(unsafe {
             self.0
                 .RevertToSnapshot(snapshot_id, if force_dismount { TRUE } else { FALSE })
@@ -642,6 +982,7 @@ impl IBackupComponents {
     /// of a backup operation.
     #[doc(alias = "SaveAsXML")]
     pub fn save_as_xml(&self) -> Result<BString, IBackupComponentsSaveAsXMLError> {
+        trace_com_call!("SaveAsXML");
         let mut xml: BSTR = null_mut();
         let hr = unsafe { self.0.SaveAsXML(&mut xml) };
 
@@ -667,6 +1008,7 @@ impl IBackupComponents {
         component_name: &U16CStr,
         additional_restores: bool,
     ) -> Result<(), SetAdditionalRestoresError> {
+        trace_com_call!("SetAdditionalRestores");
         check_com(unsafe {
             self.0.SetAdditionalRestores(
                 writer_id,
@@ -689,6 +1031,7 @@ impl IBackupComponents {
         component_name: &U16CStr,
         backup_options: &U16CStr,
     ) -> Result<(), SetBackupOptionsError> {
+        trace_com_call!("SetBackupOptions");
         check_com(unsafe {
             self.0.SetBackupOptions(
                 writer_id,
@@ -701,6 +1044,8 @@ impl IBackupComponents {
         Ok(())
     }
     /// Defines an overall configuration for a backup operation.
+    ///
+    /// [`BackupStateBuilder`] can be used instead to name each argument.
     #[doc(alias = "SetBackupState")]
     pub fn set_backup_state(
         &self,
@@ -709,6 +1054,7 @@ impl IBackupComponents {
         backup_type: BackupType,
         partial_file_support: bool,
     ) -> Result<(), SetBackupStateError> {
+        trace_com_call!("SetBackupState");
         check_com(unsafe {
             self.0.SetBackupState(
                 select_components,
@@ -731,6 +1077,7 @@ impl IBackupComponents {
         component_name: &U16CStr,
         succeeded: bool,
     ) -> Result<(), SetBackupSucceededError> {
+        trace_com_call!("SetBackupSucceeded");
         check_com(unsafe {
             self.0.SetBackupSucceeded(
                 instance_id,
@@ -746,17 +1093,29 @@ impl IBackupComponents {
     /// Sets the context for subsequent shadow copy-related operations.
     ///
     /// Note that both arguments implement `Default` with sensible values.
+    ///
+    /// The arguments are passed to VSS as is, use
+    /// [`set_context_config`](Self::set_context_config) to check the
+    /// combination first.
     #[doc(alias = "SetContext")]
     pub fn set_context(
         &self,
         context: SnapshotContext,
         attributes: RawBitFlags<VolumeSnapshotAttributes>,
     ) -> Result<(), SetContextError> {
-        let context: LONG =
-            (VSS_SNAPSHOT_CONTEXT::from(context) as LONG) | (attributes.raw() as LONG);
+        trace_com_call!("SetContext");
+        let context = context.to_raw_context(attributes);
         check_com(unsafe { self.0.SetContext(context) })?;
         Ok(())
     }
+    /// Sets the context for subsequent shadow copy-related operations using
+    /// a context that has already been validated.
+    #[doc(alias = "SetContext")]
+    pub fn set_context_config(&self, config: SnapshotContextConfig) -> Result<(), SetContextError> {
+        trace_com_call!("SetContext");
+        check_com(unsafe { self.0.SetContext(config.to_raw_context()) })?;
+        Ok(())
+    }
     /// Indicates whether some, all, or no files were successfully restored.
     #[doc(alias = "SetFileRestoreStatus")]
     pub fn set_file_restore_status(
@@ -767,6 +1126,7 @@ impl IBackupComponents {
         component_name: &U16CStr,
         status: FileRestoreStatus,
     ) -> Result<(), SetFileRestoreStatusError> {
+        trace_com_call!("SetFileRestoreStatus");
         check_com(unsafe {
             self.0.SetFileRestoreStatus(
                 writer_id,
@@ -791,6 +1151,7 @@ impl IBackupComponents {
         component_name: &U16CStr,
         previous_backup_stamp: &U16CStr,
     ) -> Result<(), SetPreviousBackupStampError> {
+        trace_com_call!("SetPreviousBackupStamp");
         check_com(unsafe {
             self.0.SetPreviousBackupStamp(
                 writer_id,
@@ -814,6 +1175,7 @@ impl IBackupComponents {
         partial_file_index: u32,
         ranges_file: &U16CStr,
     ) -> Result<(), SetRangesFilePathError> {
+        trace_com_call!("SetRangesFilePath");
         check_com(unsafe {
             self.0.SetRangesFilePath(
                 writer_id,
@@ -837,6 +1199,7 @@ impl IBackupComponents {
         component_name: &U16CStr,
         restore_options: &U16CStr,
     ) -> Result<(), SetRestoreOptionsError> {
+        trace_com_call!("SetRestoreOptions");
         check_com(unsafe {
             self.0.SetRestoreOptions(
                 writer_id,
@@ -851,6 +1214,7 @@ impl IBackupComponents {
     /// Defines an overall configuration for a restore operation.
     #[doc(alias = "SetRestoreState")]
     pub fn set_restore_state(&self, restore_type: RestoreType) -> Result<(), SetRestoreStateError> {
+        trace_com_call!("SetRestoreState");
         check_com(unsafe { self.0.SetRestoreState(restore_type.into()) })?;
         Ok(())
     }
@@ -865,6 +1229,7 @@ impl IBackupComponents {
         component_name: &U16CStr,
         selected_for_restore: bool,
     ) -> Result<(), SetSelectedForRestoreError> {
+        trace_com_call!("SetSelectedForRestore");
         check_com(unsafe {
             self.0.SetSelectedForRestore(
                 writer_id,
@@ -879,6 +1244,7 @@ impl IBackupComponents {
     /// Creates a new, empty shadow copy set.
     #[doc(alias = "StartSnapshotSet")]
     pub fn start_snapshot_set(&self) -> Result<VSS_ID, StartSnapshotSetError> {
+        trace_com_call!("StartSnapshotSet");
         let mut snapshot_set_id: VSS_ID = Default::default();
         check_com(unsafe { self.0.StartSnapshotSet(&mut snapshot_set_id) })?;
         Ok(snapshot_set_id)
@@ -897,6 +1263,804 @@ pub struct DeleteSnapshotsInfo {
     pub error: Option<DeleteSnapshotsError>,
 }
 
+/// Options for [`BackupComponents::for_backup`].
+///
+/// The default options create a full backup in the
+/// [`SnapshotContext::Backup`] context without selecting components.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BackupOptions {
+    /// The context of the shadow copies that will be created.
+    pub context: SnapshotContextConfig,
+    /// The overall configuration of the backup.
+    pub backup_state: BackupStateBuilder,
+}
+
+/// Builds the arguments for [`IBackupComponents::set_backup_state`] so that
+/// each of them is named at the call site.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn f(backup_comp: &volume_shadow_copy::vsbackup::IBackupComponents) -> Result<(), Box<dyn std::error::Error>> {
+/// use volume_shadow_copy::{vsbackup::BackupStateBuilder, vss::BackupType};
+///
+/// BackupStateBuilder::new()
+///     .select_components(true)
+///     .backup_type(BackupType::Copy)
+///     .apply(backup_comp)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct BackupStateBuilder {
+    select_components: bool,
+    backup_bootable_system_state: bool,
+    backup_type: BackupType,
+    partial_file_support: bool,
+}
+impl Default for BackupStateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl BackupStateBuilder {
+    /// Create a builder for a [`BackupType::Full`] backup without selected
+    /// components, bootable system state or partial file support.
+    pub fn new() -> Self {
+        Self {
+            select_components: false,
+            backup_bootable_system_state: false,
+            backup_type: BackupType::Full,
+            partial_file_support: false,
+        }
+    }
+    /// `true` if the backup will explicitly select the components to back up.
+    pub fn select_components(mut self, select_components: bool) -> Self {
+        self.select_components = select_components;
+        self
+    }
+    /// `true` if a bootable system state backup is being performed.
+    pub fn backup_bootable_system_state(mut self, backup_bootable_system_state: bool) -> Self {
+        self.backup_bootable_system_state = backup_bootable_system_state;
+        self
+    }
+    /// The type of backup that is being performed.
+    pub fn backup_type(mut self, backup_type: BackupType) -> Self {
+        self.backup_type = backup_type;
+        self
+    }
+    /// `true` if the requester supports backing up partial files.
+    pub fn partial_file_support(mut self, partial_file_support: bool) -> Self {
+        self.partial_file_support = partial_file_support;
+        self
+    }
+    /// Call [`IBackupComponents::set_backup_state`] with the configured
+    /// values.
+    #[doc(alias = "SetBackupState")]
+    pub fn apply(&self, components: &IBackupComponents) -> Result<(), SetBackupStateError> {
+        components.set_backup_state(
+            self.select_components,
+            self.backup_bootable_system_state,
+            self.backup_type,
+            self.partial_file_support,
+        )
+    }
+}
+
+/// Error returned by [`BackupComponents::for_backup`].
+#[derive(Debug)]
+pub enum ForBackupError {
+    /// Failed to create the backup components object.
+    Create(ConnectError<CreateVssBackupComponentsError>),
+    /// Failed to initialize the backup components for backup.
+    InitializeForBackup(InitializeForBackupError),
+    /// Failed to set the shadow copy context.
+    SetContext(SetContextError),
+    /// Failed to set the backup state.
+    SetBackupState(SetBackupStateError),
+}
+impl fmt::Display for ForBackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Create(e) => write!(f, "failed to create backup components: {}", e),
+            Self::InitializeForBackup(e) => write!(f, "failed to initialize for backup: {}", e),
+            Self::SetContext(e) => write!(f, "failed to set context: {}", e),
+            Self::SetBackupState(e) => write!(f, "failed to set backup state: {}", e),
+        }
+    }
+}
+impl StdError for ForBackupError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Create(e) => Some(e),
+            Self::InitializeForBackup(e) => Some(e),
+            Self::SetContext(e) => Some(e),
+            Self::SetBackupState(e) => Some(e),
+        }
+    }
+}
+
+/// Calls [`IBackupComponents::abort_backup`] when dropped unless the backup
+/// was completed.
+///
+/// VSS requires that a backup is aborted if it ends after
+/// [`IBackupComponents::start_snapshot_set`] and before the backup is
+/// completed, otherwise writers are left waiting for events that never
+/// arrive. Wrap the backup components object in this guard right after the
+/// shadow copy set is started and use
+/// [`PreparedBackup::into_backup_components`] once
+/// [`IBackupComponents::backup_complete`] has finished.
+///
+/// # Examples
+///
+/// ```no_run
+/// use volume_shadow_copy::vsbackup::{BackupComponents, PreparedBackup};
+///
+/// volume_shadow_copy::initialize_com()?;
+/// let comp = BackupComponents::for_backup(&Default::default())?;
+/// comp.start_snapshot_set()?;
+/// let prepared = PreparedBackup::new(comp);
+/// // Any early return from here on aborts the backup.
+/// prepared.prepare_for_backup()?.wait(None)?;
+/// prepared.do_snapshot_set()?.wait(None)?;
+/// prepared.backup_complete()?.wait(None)?;
+/// let comp = prepared.into_backup_components();
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[must_use = "the backup is aborted when the guard is dropped"]
+pub struct PreparedBackup {
+    /// `None` after the guard has been disarmed.
+    components: Option<BackupComponents>,
+}
+impl PreparedBackup {
+    /// Abort the backup using `components` if this guard is dropped.
+    pub fn new(components: BackupComponents) -> Self {
+        Self {
+            components: Some(components),
+        }
+    }
+    /// The guarded backup components object.
+    pub fn backup_components(&self) -> &BackupComponents {
+        self.components
+            .as_ref()
+            .expect("backup components are only removed when the guard is consumed")
+    }
+    /// Abort the backup now instead of when the guard is dropped so that any
+    /// error can be handled.
+    #[doc(alias = "AbortBackup")]
+    pub fn abort(mut self) -> Result<(), AbortBackupError> {
+        self.components
+            .take()
+            .expect("backup components are only removed when the guard is consumed")
+            .abort_backup()
+    }
+    /// Disarm the guard without aborting the backup, for example after the
+    /// backup was completed.
+    pub fn into_backup_components(mut self) -> BackupComponents {
+        self.components
+            .take()
+            .expect("backup components are only removed when the guard is consumed")
+    }
+}
+impl Deref for PreparedBackup {
+    type Target = BackupComponents;
+
+    fn deref(&self) -> &Self::Target {
+        self.backup_components()
+    }
+}
+impl Drop for PreparedBackup {
+    fn drop(&mut self) {
+        if let Some(components) = self.components.take() {
+            let _ = components.abort_backup();
+        }
+    }
+}
+
+/// Deletes a shadow copy when dropped unless [`TemporarySnapshot::keep`] is
+/// called.
+///
+/// Use this for ad-hoc shadow copies that should only exist while a backup
+/// program is using them, especially persistent ones that would otherwise be
+/// left behind if the program returns early or panics. VSS removes the shadow
+/// copy set itself once its last shadow copy has been deleted.
+#[must_use = "the shadow copy is deleted when the guard is dropped"]
+pub struct TemporarySnapshot<'a> {
+    components: &'a IBackupComponents,
+    snapshot_set_id: VSS_ID,
+    snapshot_id: VSS_ID,
+    deleted: bool,
+}
+impl<'a> TemporarySnapshot<'a> {
+    /// Delete the shadow copy `snapshot_id` that belongs to the shadow copy
+    /// set `snapshot_set_id` when the guard is dropped.
+    pub fn new(
+        components: &'a IBackupComponents,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+    ) -> Self {
+        Self {
+            components,
+            snapshot_set_id,
+            snapshot_id,
+            deleted: false,
+        }
+    }
+    /// Identifies the shadow copy set that the shadow copy belongs to.
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        self.snapshot_set_id
+    }
+    /// Identifies the shadow copy.
+    pub fn snapshot_id(&self) -> VSS_ID {
+        self.snapshot_id
+    }
+    /// Disarm the guard so that the shadow copy isn't deleted and return its
+    /// identifier.
+    pub fn keep(mut self) -> VSS_ID {
+        self.deleted = true;
+        self.snapshot_id
+    }
+    /// Delete the shadow copy now instead of when the guard is dropped so that
+    /// any error can be handled.
+    #[doc(alias = "DeleteSnapshots")]
+    pub fn delete(mut self) -> Result<(), DeleteSnapshotsFailure> {
+        self.deleted = true;
+        self.delete_snapshot()
+    }
+    fn delete_snapshot(&self) -> Result<(), DeleteSnapshotsFailure> {
+        self.components
+            .delete(DeleteTarget::Snapshot(self.snapshot_id), false)?;
+        Ok(())
+    }
+}
+impl Drop for TemporarySnapshot<'_> {
+    fn drop(&mut self) {
+        if !self.deleted {
+            let _ = self.delete_snapshot();
+        }
+    }
+}
+
+/// Identifies a component of a writer.
+///
+/// Most [`IBackupComponents`] methods that operate on a component take these
+/// values as separate arguments; [`IBackupComponents::component`] can be used
+/// to pass them all at once instead.
+#[derive(Clone, Copy)]
+pub struct ComponentSpec<'a> {
+    /// Writer class identifier.
+    pub writer_id: VSS_ID,
+    /// The type of the component.
+    pub component_type: VssComponentType,
+    /// The logical path of the component, if it has one.
+    pub logical_path: Option<&'a U16CStr>,
+    /// The name of the component.
+    pub component_name: &'a U16CStr,
+}
+impl<'a> ComponentSpec<'a> {
+    /// Identify a component by its writer, type, logical path and name.
+    pub fn new(
+        writer_id: VSS_ID,
+        component_type: VssComponentType,
+        logical_path: Option<&'a U16CStr>,
+        component_name: &'a U16CStr,
+    ) -> Self {
+        Self {
+            writer_id,
+            component_type,
+            logical_path,
+            component_name,
+        }
+    }
+}
+
+/// Identifies a subcomponent of a component set, see
+/// [`ComponentHandle::add_restore_subcomponent`].
+#[derive(Debug, Clone, Copy)]
+pub struct SubcomponentSpec<'a> {
+    /// The logical path of the subcomponent.
+    pub logical_path: &'a U16CStr,
+    /// The name of the subcomponent.
+    pub component_name: &'a U16CStr,
+}
+impl<'a> SubcomponentSpec<'a> {
+    /// Identify a subcomponent by its logical path and name.
+    pub fn new(logical_path: &'a U16CStr, component_name: &'a U16CStr) -> Self {
+        Self {
+            logical_path,
+            component_name,
+        }
+    }
+}
+
+/// A subcomponent returned by
+/// [`IExamineWriterMetadata::restorable_subcomponents`].
+#[derive(Debug, Clone)]
+pub struct RestorableSubcomponent {
+    /// The logical path of the subcomponent.
+    pub logical_path: U16CString,
+    /// The name of the subcomponent.
+    pub component_name: U16CString,
+    /// The description of the subcomponent.
+    pub caption: Option<U16CString>,
+}
+impl RestorableSubcomponent {
+    /// Borrow the subcomponent as a [`SubcomponentSpec`].
+    pub fn spec(&self) -> SubcomponentSpec<'_> {
+        SubcomponentSpec::new(&self.logical_path, &self.component_name)
+    }
+}
+
+/// A component of a writer returned by [`IBackupComponents::component`].
+///
+/// Each method forwards to the [`IBackupComponents`] method with the same name.
+#[derive(Clone, Copy)]
+pub struct ComponentHandle<'a> {
+    components: &'a IBackupComponents,
+    spec: ComponentSpec<'a>,
+}
+impl<'a> ComponentHandle<'a> {
+    /// The component that this handle refers to.
+    pub fn spec(&self) -> &ComponentSpec<'a> {
+        &self.spec
+    }
+    /// Record that a file set of this component was restored to an alternate
+    /// location, see [`IBackupComponents::add_alternative_location_mapping`].
+    #[doc(alias = "AddAlternativeLocationMapping")]
+    pub fn add_alternative_location_mapping(
+        &self,
+        path: &U16CStr,
+        file_specification: &U16CStr,
+        recursive: bool,
+        destination: &U16CStr,
+    ) -> Result<(), AddAlternativeLocationMappingError> {
+        let spec = &self.spec;
+        self.components.add_alternative_location_mapping(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            path,
+            file_specification,
+            recursive,
+            destination,
+        )
+    }
+    /// Add this component to the backup, see
+    /// [`IBackupComponents::add_component`].
+    #[doc(alias = "AddComponent")]
+    pub fn add_component(&self, instance_id: VSS_ID) -> Result<(), AddComponentError> {
+        let spec = &self.spec;
+        self.components.add_component(
+            instance_id,
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+        )
+    }
+    /// Restore a file set of this component to a new location, see
+    /// [`IBackupComponents::add_new_target`].
+    #[doc(alias = "AddNewTarget")]
+    pub fn add_new_target(
+        &self,
+        path: &U16CStr,
+        file_name: &U16CStr,
+        recursive: bool,
+        alternate_path: &U16CStr,
+    ) -> Result<(), AddNewTargetError> {
+        let spec = &self.spec;
+        self.components.add_new_target(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            path,
+            file_name,
+            recursive,
+            alternate_path,
+        )
+    }
+    /// Restore a subcomponent of this component, see
+    /// [`IBackupComponents::add_restore_subcomponent`].
+    #[doc(alias = "AddRestoreSubcomponent")]
+    pub fn add_restore_subcomponent(
+        &self,
+        subcomponent: &SubcomponentSpec<'_>,
+    ) -> Result<(), AddRestoreSubcomponentError> {
+        let spec = &self.spec;
+        self.components.add_restore_subcomponent(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            subcomponent.logical_path,
+            subcomponent.component_name,
+        )
+    }
+    /// Find the subcomponents of this component that can be restored on their
+    /// own using [`IExamineWriterMetadata::restorable_subcomponents`] and add
+    /// the ones that `select` returns `true` for.
+    ///
+    /// Returns the number of subcomponents that were added.
+    #[doc(alias = "AddRestoreSubcomponent")]
+    pub fn add_restorable_subcomponents<F>(
+        &self,
+        metadata: &IExamineWriterMetadata,
+        mut select: F,
+    ) -> Result<u32, AddRestorableSubcomponentsError>
+    where
+        F: FnMut(&RestorableSubcomponent) -> bool,
+    {
+        let subcomponents = metadata
+            .restorable_subcomponents(&self.spec)
+            .map_err(|e| AddRestorableSubcomponentsError(e.0))?;
+        let mut added = 0;
+        for subcomponent in subcomponents.iter().filter(|s| select(s)) {
+            self.add_restore_subcomponent(&subcomponent.spec())
+                .map_err(|e| AddRestorableSubcomponentsError(e.0))?;
+            added += 1;
+        }
+        Ok(added)
+    }
+    /// Tell the writer whether more restores of this component will follow,
+    /// see [`IBackupComponents::set_additional_restores`].
+    #[doc(alias = "SetAdditionalRestores")]
+    pub fn set_additional_restores(
+        &self,
+        additional_restores: bool,
+    ) -> Result<(), SetAdditionalRestoresError> {
+        let spec = &self.spec;
+        self.components.set_additional_restores(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            additional_restores,
+        )
+    }
+    /// Set the writer-specific backup options of this component, see
+    /// [`IBackupComponents::set_backup_options`].
+    #[doc(alias = "SetBackupOptions")]
+    pub fn set_backup_options(
+        &self,
+        backup_options: &U16CStr,
+    ) -> Result<(), SetBackupOptionsError> {
+        let spec = &self.spec;
+        self.components.set_backup_options(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            backup_options,
+        )
+    }
+    /// Report whether this component was backed up, see
+    /// [`IBackupComponents::set_backup_succeeded`].
+    #[doc(alias = "SetBackupSucceeded")]
+    pub fn set_backup_succeeded(
+        &self,
+        instance_id: VSS_ID,
+        succeeded: bool,
+    ) -> Result<(), SetBackupSucceededError> {
+        let spec = &self.spec;
+        self.components.set_backup_succeeded(
+            instance_id,
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            succeeded,
+        )
+    }
+    /// Report how many of the files of this component were restored, see
+    /// [`IBackupComponents::set_file_restore_status`].
+    #[doc(alias = "SetFileRestoreStatus")]
+    pub fn set_file_restore_status(
+        &self,
+        status: FileRestoreStatus,
+    ) -> Result<(), SetFileRestoreStatusError> {
+        let spec = &self.spec;
+        self.components.set_file_restore_status(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            status,
+        )
+    }
+    /// Set the stamp of the backup that an incremental or differential backup
+    /// of this component is based on, see
+    /// [`IBackupComponents::set_previous_backup_stamp`].
+    #[doc(alias = "SetPreviousBackupStamp")]
+    pub fn set_previous_backup_stamp(
+        &self,
+        previous_backup_stamp: &U16CStr,
+    ) -> Result<(), SetPreviousBackupStampError> {
+        let spec = &self.spec;
+        self.components.set_previous_backup_stamp(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            previous_backup_stamp,
+        )
+    }
+    /// Set where the ranges file of a partial file of this component was
+    /// restored to, see [`IBackupComponents::set_ranges_file_path`].
+    #[doc(alias = "SetRangesFilePath")]
+    pub fn set_ranges_file_path(
+        &self,
+        partial_file_index: u32,
+        ranges_file: &U16CStr,
+    ) -> Result<(), SetRangesFilePathError> {
+        let spec = &self.spec;
+        self.components.set_ranges_file_path(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            partial_file_index,
+            ranges_file,
+        )
+    }
+    /// Set the writer-specific restore options of this component, see
+    /// [`IBackupComponents::set_restore_options`].
+    #[doc(alias = "SetRestoreOptions")]
+    pub fn set_restore_options(
+        &self,
+        restore_options: &U16CStr,
+    ) -> Result<(), SetRestoreOptionsError> {
+        let spec = &self.spec;
+        self.components.set_restore_options(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            restore_options,
+        )
+    }
+    /// Select or deselect this component for restore, see
+    /// [`IBackupComponents::set_selected_for_restore`].
+    #[doc(alias = "SetSelectedForRestore")]
+    pub fn set_selected_for_restore(
+        &self,
+        selected_for_restore: bool,
+    ) -> Result<(), SetSelectedForRestoreError> {
+        let spec = &self.spec;
+        self.components.set_selected_for_restore(
+            spec.writer_id,
+            spec.component_type,
+            spec.logical_path,
+            spec.component_name,
+            selected_for_restore,
+        )
+    }
+}
+
+/// A component that was added to the Backup Components Document with
+/// [`AddedComponents::add`].
+#[derive(Clone)]
+pub struct AddedComponent {
+    /// Identifies the writer instance that manages the component.
+    pub instance_id: VSS_ID,
+    /// Writer class identifier.
+    pub writer_id: VSS_ID,
+    /// The type of the component.
+    pub component_type: VssComponentType,
+    /// The logical path of the component, if it has one.
+    pub logical_path: Option<U16CString>,
+    /// The name of the component.
+    pub component_name: U16CString,
+}
+impl AddedComponent {
+    /// Borrow the component as a [`ComponentSpec`].
+    pub fn spec(&self) -> ComponentSpec<'_> {
+        ComponentSpec::new(
+            self.writer_id,
+            self.component_type,
+            self.logical_path.as_deref(),
+            &self.component_name,
+        )
+    }
+}
+
+/// Records the components that are added to a backup so that the result of
+/// backing them up can be reported with a single call once the files have
+/// been copied.
+#[derive(Clone, Default)]
+pub struct AddedComponents {
+    components: Vec<AddedComponent>,
+}
+impl AddedComponents {
+    /// Create an empty list of components.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a component with [`IBackupComponents::add_component`] and
+    /// remember it if that succeeds.
+    #[doc(alias = "AddComponent")]
+    pub fn add(
+        &mut self,
+        backup_comp: &IBackupComponents,
+        instance_id: VSS_ID,
+        component: &ComponentSpec<'_>,
+    ) -> Result<(), AddComponentError> {
+        backup_comp
+            .component(*component)
+            .add_component(instance_id)?;
+        self.components.push(AddedComponent {
+            instance_id,
+            writer_id: component.writer_id,
+            component_type: component.component_type,
+            logical_path: component.logical_path.map(U16CStr::to_ucstring),
+            component_name: component.component_name.to_ucstring(),
+        });
+        Ok(())
+    }
+    /// The components that have been added, in the order they were added.
+    pub fn components(&self) -> &[AddedComponent] {
+        &self.components
+    }
+    /// Call [`IBackupComponents::set_backup_succeeded`] for every added
+    /// component.
+    ///
+    /// `succeeded` is used for all components unless `overrides` returns a
+    /// different result for a specific component. Stops at the first error.
+    #[doc(alias = "SetBackupSucceeded")]
+    pub fn set_backup_succeeded<F>(
+        &self,
+        backup_comp: &IBackupComponents,
+        succeeded: bool,
+        mut overrides: F,
+    ) -> Result<(), SetBackupSucceededError>
+    where
+        F: FnMut(&AddedComponent) -> Option<bool>,
+    {
+        for component in &self.components {
+            let succeeded = overrides(component).unwrap_or(succeeded);
+            backup_comp
+                .component(component.spec())
+                .set_backup_succeeded(component.instance_id, succeeded)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where [`IBackupComponents::expose`] makes a shadow copy available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exposure {
+    /// Expose the shadow copy locally as a drive letter, for example `'S'`.
+    DriveLetter(char),
+    /// Expose the shadow copy locally at an empty NTFS folder.
+    MountedFolder(PathBuf),
+    /// Expose the shadow copy, or a folder inside it, as a file share.
+    Share {
+        /// The name of the share. Defaults to the shadow copy identifier.
+        name: Option<String>,
+        /// The folder inside the shadow copy to share, relative to the root
+        /// of the volume. Defaults to the root.
+        path_from_root: Option<PathBuf>,
+    },
+}
+
+/// Error returned by [`IBackupComponents::expose`].
+#[derive(Debug)]
+pub enum ExposeError {
+    /// The drive letter was not an ASCII letter.
+    InvalidDriveLetter(char),
+    /// A path or share name contained a nul character.
+    InvalidName(NulError<u16>),
+    /// Failed to expose the shadow copy.
+    Expose(ExposeSnapshotError),
+}
+impl fmt::Display for ExposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidDriveLetter(letter) => write!(f, "invalid drive letter: {:?}", letter),
+            Self::InvalidName(e) => write!(f, "invalid exposure name: {}", e),
+            Self::Expose(e) => write!(f, "failed to expose shadow copy: {}", e),
+        }
+    }
+}
+impl StdError for ExposeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::InvalidDriveLetter(_) => None,
+            Self::InvalidName(e) => Some(e),
+            Self::Expose(e) => Some(e),
+        }
+    }
+}
+
+/// The shadow copies to delete with [`IBackupComponents::delete`].
+#[derive(Clone, Copy)]
+pub enum DeleteTarget {
+    /// Delete a single shadow copy.
+    Snapshot(VSS_ID),
+    /// Delete every shadow copy in a shadow copy set.
+    SnapshotSet(VSS_ID),
+}
+
+/// Error returned by [`IBackupComponents::delete`].
+#[derive(Clone, Copy)]
+pub struct DeleteSnapshotsFailure {
+    /// Number of shadow copies that were deleted before the error occurred.
+    pub deleted_snapshots: u32,
+    /// Identifier of the first shadow copy that could not be deleted.
+    pub nondeleted_snapshot_id: VSS_ID,
+    /// The error that stopped the deletion.
+    pub error: DeleteSnapshotsError,
+}
+impl fmt::Debug for DeleteSnapshotsFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = &self.nondeleted_snapshot_id;
+        f.debug_struct("DeleteSnapshotsFailure")
+            .field("deleted_snapshots", &self.deleted_snapshots)
+            .field(
+                "nondeleted_snapshot_id",
+                &format_args!(
+                    "{:08X}-{:04X}-{:04X}-{:02X?}",
+                    id.Data1, id.Data2, id.Data3, id.Data4
+                ),
+            )
+            .field("error", &self.error)
+            .finish()
+    }
+}
+impl fmt::Display for DeleteSnapshotsFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to delete shadow copies after deleting {}: {}",
+            self.deleted_snapshots, self.error
+        )
+    }
+}
+impl StdError for DeleteSnapshotsFailure {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Iterator returned by [`IBackupComponents::writer_metadata_iter`].
+pub struct WriterMetadataIter<'a> {
+    components: &'a IBackupComponents,
+    index: u32,
+    count: u32,
+}
+impl Iterator for WriterMetadataIter<'_> {
+    type Item = Result<(VSS_ID, ExamineWriterMetadata), GetWriterMetadataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.count {
+            return None;
+        }
+        let writer_index = self.index;
+        self.index += 1;
+        trace_com_call!("GetWriterMetadata");
+        let mut instance_id: VSS_ID = Default::default();
+        let mut metadata = null_mut::<vsbackup::IVssExamineWriterMetadata>();
+        let hr = unsafe {
+            self.components
+                .0
+                .GetWriterMetadata(writer_index, &mut instance_id, &mut metadata)
+        };
+        if let Err(hr) = check_com(hr) {
+            return Some(Err(hr.into()));
+        }
+        Some(Ok((
+            instance_id,
+            ExamineWriterMetadata(unsafe { SafeCOMComponent::new(metadata) }),
+        )))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.count - self.index) as usize;
+        (remaining, Some(remaining))
+    }
+}
+impl ExactSizeIterator for WriterMetadataIter<'_> {}
+
 ////////////////////////////////////////////////////////////////////////////////
 // IVssBackupComponentsEx
 ////////////////////////////////////////////////////////////////////////////////
@@ -922,6 +2086,7 @@ impl IBackupComponentsEx {
         &self,
         writer_index: u32,
     ) -> Result<GetWriterMetadataExInfo, GetWriterMetadataExError> {
+        trace_com_call!("GetWriterMetadataEx");
         let mut instance_id: VSS_ID = Default::default();
         let mut metadata: *mut vsbackup::IVssExamineWriterMetadataEx = null_mut();
         check_com(unsafe {
@@ -945,6 +2110,7 @@ impl IBackupComponentsEx {
         selected_for_restore: bool,
         instance_id: Option<VSS_ID>,
     ) -> Result<(), SetSelectedForRestoreExError> {
+        trace_com_call!("SetSelectedForRestoreEx");
         check_com(unsafe {
             self.0.SetSelectedForRestoreEx(
                 writer_id,
@@ -977,6 +2143,125 @@ pub struct GetWriterStatusInfo {
     pub writer_failure: Option<WriterFailureError>,
 }
 
+/// The status of a single writer returned by
+/// [`IBackupComponents::writer_statuses`].
+#[derive(Clone)]
+pub struct WriterStatusReport {
+    /// Identifies this instance of the writer.
+    pub instance_id: VSS_ID,
+    /// Identifies the writer class.
+    pub writer_id: VSS_ID,
+    /// The display name of the writer.
+    pub writer_name: BString,
+    /// The state of the writer.
+    pub state: WriterState,
+    /// The error that the writer reported, if any.
+    pub failure: Option<WriterFailureError>,
+}
+impl WriterStatusReport {
+    /// `true` if the writer is in a failed state or reported an error.
+    pub fn has_failed(&self) -> bool {
+        self.state.is_failed() || self.failure.is_some()
+    }
+}
+impl From<GetWriterStatusInfo> for WriterStatusReport {
+    fn from(info: GetWriterStatusInfo) -> Self {
+        Self {
+            instance_id: info.instance_id,
+            writer_id: info.writer_id,
+            writer_name: info.writer,
+            state: info.status,
+            failure: info.writer_failure,
+        }
+    }
+}
+
+/// Guard returned by [`IBackupComponents::gather_writer_metadata_scoped`]
+/// that frees the gathered writer metadata when dropped.
+#[must_use = "the writer metadata is freed when the session is dropped"]
+pub struct WriterMetadataSession<'a> {
+    components: &'a IBackupComponents,
+    freed: bool,
+}
+impl<'a> WriterMetadataSession<'a> {
+    /// The backup components that gathered the writer metadata.
+    pub fn components(&self) -> &'a IBackupComponents {
+        self.components
+    }
+    /// Returns the number of writers with metadata.
+    #[doc(alias = "GetWriterMetadataCount")]
+    pub fn count(&self) -> Result<u32, GetWriterMetadataCountError> {
+        self.components.get_writer_metadata_count()
+    }
+    /// Iterate over the metadata of every writer. Each item contains the
+    /// instance id of the writer and its metadata.
+    pub fn iter(&self) -> Result<WriterMetadataIter<'a>, GetWriterMetadataCountError> {
+        self.components.writer_metadata_iter()
+    }
+    /// Free the writer metadata now instead of when the session is dropped so
+    /// that any error can be handled.
+    #[doc(alias = "FreeWriterMetadata")]
+    pub fn free(mut self) -> Result<(), FreeWriterMetadataError> {
+        self.freed = true;
+        self.components.free_writer_metadata()
+    }
+}
+impl Drop for WriterMetadataSession<'_> {
+    fn drop(&mut self) {
+        if !self.freed {
+            let _ = self.components.free_writer_metadata();
+        }
+    }
+}
+
+/// Guard returned by [`IBackupComponents::gather_writer_status_scoped`] that
+/// frees the gathered writer status when dropped.
+#[must_use = "the writer status is freed when the session is dropped"]
+pub struct WriterStatusSession<'a> {
+    components: &'a IBackupComponents,
+    freed: bool,
+}
+impl<'a> WriterStatusSession<'a> {
+    /// The backup components that gathered the writer status.
+    pub fn components(&self) -> &'a IBackupComponents {
+        self.components
+    }
+    /// Returns the number of writers with status.
+    #[doc(alias = "GetWriterStatusCount")]
+    pub fn count(&self) -> Result<u32, GetWriterStatusCountError> {
+        self.components.get_writer_status_count()
+    }
+    /// Returns the status of the specified writer.
+    #[doc(alias = "GetWriterStatus")]
+    pub fn get(&self, writer_index: u32) -> Result<GetWriterStatusInfo, GetWriterStatusError> {
+        self.components.get_writer_status(writer_index)
+    }
+    /// Iterate over the status of every writer.
+    pub fn iter(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<WriterStatusReport, GetWriterStatusError>> + '_,
+        GetWriterStatusCountError,
+    > {
+        let count = self.count()?;
+        Ok((0..count).map(move |i| self.get(i).map(WriterStatusReport::from)))
+    }
+    /// Free the writer status now instead of when the session is dropped so
+    /// that any error can be handled.
+    #[doc(alias = "FreeWriterStatus")]
+    pub fn free(mut self) -> Result<(), FreeWriterStatusError> {
+        self.freed = true;
+        self.components.free_writer_status()
+    }
+}
+impl Drop for WriterStatusSession<'_> {
+    fn drop(&mut self) {
+        if !self.freed {
+            let _ = self.components.free_writer_status();
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // IVssBackupComponentsEx2
 ////////////////////////////////////////////////////////////////////////////////
@@ -996,6 +2281,63 @@ transparent_wrapper!(
 unsafe_deref_to_ref!(BackupComponentsEx2 => IBackupComponentsEx2 => IBackupComponentsEx);
 
 impl IBackupComponentsEx2 {
+    /// Select a component for an authoritative restore of a replicated data
+    /// store, such as a DFS Replication folder or Active Directory.
+    ///
+    /// This selects the component for restore with
+    /// [`IBackupComponentsEx::set_selected_for_restore_ex`], marks the restore
+    /// as authoritative and, if `restore_name` is specified, assigns it a new
+    /// logical name with [`IBackupComponentsEx2::set_restore_name`].
+    ///
+    /// The writer's [`BackupSchema`] from `metadata` is checked first so that
+    /// nothing is changed for writers that don't support authoritative
+    /// restores or renaming.
+    pub fn select_for_authoritative_restore(
+        &self,
+        metadata: &IExamineWriterMetadata,
+        component: &ComponentSpec<'_>,
+        instance_id: Option<VSS_ID>,
+        restore_name: Option<&U16CStr>,
+    ) -> Result<(), AuthoritativeRestoreError> {
+        let schema = metadata
+            .get_backup_schema()
+            .map_err(AuthoritativeRestoreError::BackupSchema)?
+            .flags();
+        if !schema.contains(BackupSchema::AUTHORITATIVE_RESTORE) {
+            return Err(AuthoritativeRestoreError::NotSupported);
+        }
+        if restore_name.is_some() && !schema.contains(BackupSchema::RESTORE_RENAME) {
+            return Err(AuthoritativeRestoreError::RenameNotSupported);
+        }
+        self.set_selected_for_restore_ex(
+            component.writer_id,
+            component.component_type,
+            component.logical_path,
+            component.component_name,
+            true,
+            instance_id,
+        )
+        .map_err(AuthoritativeRestoreError::SelectForRestore)?;
+        self.set_authoritative_restore(
+            component.writer_id,
+            component.component_type,
+            component.logical_path,
+            component.component_name,
+            true,
+        )
+        .map_err(AuthoritativeRestoreError::SetAuthoritativeRestore)?;
+        if let Some(restore_name) = restore_name {
+            self.set_restore_name(
+                component.writer_id,
+                component.component_type,
+                component.logical_path,
+                component.component_name,
+                restore_name,
+            )
+            .map_err(AuthoritativeRestoreError::SetRestoreName)?;
+        }
+        Ok(())
+    }
     /// Breaks a shadow copy set according to requester-specified options.
     #[doc(alias = "BreakSnapshotSetEx")]
     pub fn break_snapshot_set_ex(
@@ -1003,6 +2345,7 @@ impl IBackupComponentsEx2 {
         snapshot_set_id: VSS_ID,
         break_flags: RawBitFlags<HardwareOptions>,
     ) -> IVssAsyncResult<BreakSnapshotSetExError> {
+        trace_com_call!("BreakSnapshotSetEx");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe {
             self.0
@@ -1021,6 +2364,7 @@ impl IBackupComponentsEx2 {
         component_name: &U16CStr,
         authoritative: bool,
     ) -> Result<(), SetAuthoritativeRestoreError> {
+        trace_com_call!("SetAuthoritativeRestore");
         check_com(unsafe {
             self.0.SetAuthoritativeRestore(
                 writer_id,
@@ -1042,6 +2386,7 @@ impl IBackupComponentsEx2 {
         component_name: &U16CStr,
         restore_name: &U16CStr,
     ) -> Result<(), SetRestoreNameError> {
+        trace_com_call!("SetRestoreName");
         check_com(unsafe {
             self.0.SetRestoreName(
                 writer_id,
@@ -1053,7 +2398,8 @@ impl IBackupComponentsEx2 {
         })?;
         Ok(())
     }
-    /// Assigns a new logical name to a component that is being restored.
+    /// Indicates the type of roll-forward operation that the requester will
+    /// perform for a component that is being restored.
     #[doc(alias = "SetRollForward")]
     pub fn set_roll_forward(
         &self,
@@ -1061,17 +2407,20 @@ impl IBackupComponentsEx2 {
         component_type: VssComponentType,
         logical_path: Option<&U16CStr>,
         component_name: &U16CStr,
-        roll_forward_type: RollForwardType,
-        roll_forward_point: &U16CStr,
+        roll_forward: RollForward<'_>,
     ) -> Result<(), SetRollForwardError> {
+        trace_com_call!("SetRollForward");
         check_com(unsafe {
             self.0.SetRollForward(
                 writer_id,
                 component_type.into(),
                 logical_path.map(|s| s.as_ptr()).unwrap_or(null()),
                 component_name.as_ptr(),
-                roll_forward_type.into(),
-                roll_forward_point.as_ptr(),
+                roll_forward.roll_forward_type().into(),
+                roll_forward
+                    .restore_point()
+                    .map(|s| s.as_ptr())
+                    .unwrap_or(null()),
             )
         })?;
         Ok(())
@@ -1080,9 +2429,257 @@ impl IBackupComponentsEx2 {
     /// the drive letter or mounted folder.
     #[doc(alias = "UnexposeSnapshot")]
     pub fn unexpose_snapshot(&self, snapshot_id: VSS_ID) -> Result<(), UnexposeSnapshotError> {
+        trace_com_call!("UnexposeSnapshot");
         check_com(unsafe { self.0.UnexposeSnapshot(snapshot_id) })?;
         Ok(())
     }
+    /// Break a shadow copy set with [`IBackupComponentsEx2::break_snapshot_set_ex`]
+    /// so that its shadow copies become standalone volumes, and return the
+    /// names of those volumes.
+    ///
+    /// The properties of the shadow copies are queried before the set is
+    /// broken since VSS no longer knows about them afterwards. Only shadow
+    /// copies created by a hardware provider can be broken this way.
+    #[doc(alias = "BreakSnapshotSetEx")]
+    pub fn break_to_volumes(
+        &self,
+        snapshot_set_id: VSS_ID,
+        options: BreakOptions,
+    ) -> Result<Vec<BrokenSnapshot>, BreakToVolumesError> {
+        let broken = self
+            .query_snapshot_set(snapshot_set_id)
+            .map_err(BreakToVolumesError::Query)?
+            .map(|snapshot| {
+                let snapshot = snapshot.map_err(BreakToVolumesError::EnumSnapshots)?;
+                Ok(BrokenSnapshot {
+                    snapshot_id: snapshot.snapshot_id(),
+                    original_volume_name: snapshot.original_volume_name().to_ucstring(),
+                    volume_name: snapshot.snapshot_device_object().to_ucstring(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let vss_async = self
+            .break_snapshot_set_ex(snapshot_set_id, options.flags())
+            .map_err(BreakToVolumesError::Break)?;
+        vss_async
+            .wait(None)
+            .map_err(|e| BreakToVolumesError::Wait(e.async_error()))?;
+        vss_async
+            .query_status()
+            .map_err(|e| BreakToVolumesError::Break(e.underlying_error()))?;
+        Ok(broken)
+    }
+    /// Expose a shadow copy with [`IBackupComponents::expose`] and return a
+    /// guard that unexposes it again when dropped.
+    #[doc(alias = "ExposeSnapshot")]
+    pub fn mount_snapshot(
+        &self,
+        snapshot_id: VSS_ID,
+        target: &Exposure,
+    ) -> Result<MountedSnapshot<'_>, ExposeError> {
+        let exposed_name = self.expose(snapshot_id, target)?;
+        Ok(MountedSnapshot {
+            components: self,
+            snapshot_id,
+            exposed_name,
+            unexposed: false,
+        })
+    }
+}
+
+/// Guard returned by [`IBackupComponentsEx2::mount_snapshot`] that calls
+/// [`IBackupComponentsEx2::unexpose_snapshot`] when dropped.
+#[must_use = "the shadow copy is unexposed when the guard is dropped"]
+pub struct MountedSnapshot<'a> {
+    components: &'a IBackupComponentsEx2,
+    snapshot_id: VSS_ID,
+    exposed_name: OsString,
+    unexposed: bool,
+}
+impl MountedSnapshot<'_> {
+    /// Identifies the exposed shadow copy.
+    pub fn snapshot_id(&self) -> VSS_ID {
+        self.snapshot_id
+    }
+    /// The exposed name of the shadow copy. This is either a share name, a
+    /// drive letter followed by a colon, or a mounted folder.
+    pub fn exposed_name(&self) -> &OsStr {
+        &self.exposed_name
+    }
+    /// Unexpose the shadow copy now instead of when the guard is dropped so
+    /// that any error can be handled.
+    #[doc(alias = "UnexposeSnapshot")]
+    pub fn unmount(mut self) -> Result<(), UnexposeSnapshotError> {
+        self.unexposed = true;
+        self.components.unexpose_snapshot(self.snapshot_id)
+    }
+}
+impl Drop for MountedSnapshot<'_> {
+    fn drop(&mut self) {
+        if !self.unexposed {
+            let _ = self.components.unexpose_snapshot(self.snapshot_id);
+        }
+    }
+}
+
+/// What happens to the disk identifiers of the shadow copy LUNs when a shadow
+/// copy set is broken, see [`BreakOptions::revert_identity`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RevertIdentity {
+    /// Let the provider decide.
+    #[default]
+    Default,
+    /// Revert the disk identifiers of all shadow copy LUNs to those of the
+    /// original LUNs. Fails if any of the original LUNs are present on the
+    /// system.
+    All,
+    /// Don't revert any disk identifiers.
+    None,
+}
+
+/// Options for [`IBackupComponentsEx2::break_to_volumes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakOptions {
+    make_read_write: bool,
+    revert_identity: RevertIdentity,
+}
+impl Default for BreakOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl BreakOptions {
+    /// Create options that make the volumes read-write and leave the disk
+    /// identifiers to the provider.
+    pub fn new() -> Self {
+        Self {
+            make_read_write: true,
+            revert_identity: RevertIdentity::Default,
+        }
+    }
+    /// `true` to make the shadow copy LUNs read-write, otherwise they stay
+    /// read-only.
+    pub fn make_read_write(mut self, make_read_write: bool) -> Self {
+        self.make_read_write = make_read_write;
+        self
+    }
+    /// Whether the disk identifiers of the shadow copy LUNs are reverted to
+    /// those of the original LUNs.
+    pub fn revert_identity(mut self, revert_identity: RevertIdentity) -> Self {
+        self.revert_identity = revert_identity;
+        self
+    }
+    /// The flags passed to [`IBackupComponentsEx2::break_snapshot_set_ex`].
+    pub fn flags(&self) -> RawBitFlags<HardwareOptions> {
+        let mut flags = HardwareOptions::empty();
+        if self.make_read_write {
+            flags |= HardwareOptions::BREAKEX_FLAG_MAKE_READ_WRITE;
+        }
+        match self.revert_identity {
+            RevertIdentity::Default => {}
+            RevertIdentity::All => flags |= HardwareOptions::BREAKEX_FLAG_REVERT_IDENTITY_ALL,
+            RevertIdentity::None => flags |= HardwareOptions::BREAKEX_FLAG_REVERT_IDENTITY_NONE,
+        }
+        flags.into()
+    }
+}
+
+/// A shadow copy that was turned into a standalone volume by
+/// [`IBackupComponentsEx2::break_to_volumes`].
+#[derive(Clone)]
+pub struct BrokenSnapshot {
+    /// Identifies the shadow copy that the volume was created from.
+    pub snapshot_id: VSS_ID,
+    /// The name of the volume that the shadow copy was created for.
+    pub original_volume_name: U16CString,
+    /// The device name of the volume that the shadow copy became.
+    pub volume_name: U16CString,
+}
+impl fmt::Debug for BrokenSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BrokenSnapshot")
+            .field("original_volume_name", &self.original_volume_name)
+            .field("volume_name", &self.volume_name)
+            .finish()
+    }
+}
+
+/// Error returned by [`IBackupComponentsEx2::break_to_volumes`].
+#[derive(Debug)]
+pub enum BreakToVolumesError {
+    /// Failed to query the shadow copies in the set.
+    Query(QueryError),
+    /// Failed to enumerate the shadow copies in the set.
+    EnumSnapshots(EnumObjectNextError),
+    /// Failed to start breaking the shadow copy set or the operation failed.
+    Break(BreakSnapshotSetExError),
+    /// Failed to wait for the shadow copy set to be broken.
+    Wait(WaitError),
+}
+impl fmt::Display for BreakToVolumesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query(e) => write!(f, "failed to query shadow copies: {}", e),
+            Self::EnumSnapshots(e) => write!(f, "failed to enumerate shadow copies: {}", e),
+            Self::Break(e) => write!(f, "failed to break shadow copy set: {}", e),
+            Self::Wait(e) => write!(f, "failed to wait for shadow copy set to break: {}", e),
+        }
+    }
+}
+impl StdError for BreakToVolumesError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Query(e) => Some(e),
+            Self::EnumSnapshots(e) => Some(e),
+            Self::Break(e) => Some(e),
+            Self::Wait(e) => Some(e),
+        }
+    }
+}
+
+/// Error returned by [`IBackupComponentsEx2::select_for_authoritative_restore`].
+#[derive(Debug)]
+pub enum AuthoritativeRestoreError {
+    /// Failed to read the backup schema of the writer.
+    BackupSchema(GetBackupSchemaError),
+    /// The writer doesn't declare [`BackupSchema::AUTHORITATIVE_RESTORE`].
+    NotSupported,
+    /// A restore name was specified but the writer doesn't declare
+    /// [`BackupSchema::RESTORE_RENAME`].
+    RenameNotSupported,
+    /// Failed to select the component for restore.
+    SelectForRestore(SetSelectedForRestoreExError),
+    /// Failed to mark the restore as authoritative.
+    SetAuthoritativeRestore(SetAuthoritativeRestoreError),
+    /// Failed to assign the restore name.
+    SetRestoreName(SetRestoreNameError),
+}
+impl fmt::Display for AuthoritativeRestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BackupSchema(e) => write!(f, "failed to get the writer's backup schema: {}", e),
+            Self::NotSupported => write!(f, "the writer doesn't support authoritative restores"),
+            Self::RenameNotSupported => {
+                write!(f, "the writer doesn't support renaming restored components")
+            }
+            Self::SelectForRestore(e) => write!(f, "failed to select component for restore: {}", e),
+            Self::SetAuthoritativeRestore(e) => {
+                write!(f, "failed to mark restore as authoritative: {}", e)
+            }
+            Self::SetRestoreName(e) => write!(f, "failed to set restore name: {}", e),
+        }
+    }
+}
+impl StdError for AuthoritativeRestoreError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::BackupSchema(e) => Some(e),
+            Self::NotSupported | Self::RenameNotSupported => None,
+            Self::SelectForRestore(e) => Some(e),
+            Self::SetAuthoritativeRestore(e) => Some(e),
+            Self::SetRestoreName(e) => Some(e),
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -1112,6 +2709,7 @@ impl IBackupComponentsEx3 {
         snapshot_id: VSS_ID,
         destination_volume: Option<&U16CStr>,
     ) -> Result<(), AddSnapshotToRecoverySetError> {
+        trace_com_call!("AddSnapshotToRecoverySet");
         check_com(unsafe {
             self.0.AddSnapshotToRecoverySet(
                 snapshot_id,
@@ -1126,6 +2724,7 @@ impl IBackupComponentsEx3 {
     /// Returns the requester's session identifier.
     #[doc(alias = "GetSessionId")]
     pub fn get_session_id(&self) -> Result<VSS_ID, GetSessionIdError> {
+        trace_com_call!("GetSessionId");
         let mut session_id = Default::default();
         check_com(unsafe { self.0.GetSessionId(&mut session_id) })?;
         Ok(session_id)
@@ -1137,6 +2736,7 @@ impl IBackupComponentsEx3 {
         get_application_return_code: bool,
         get_application_message: bool,
     ) -> Result<GetWriterStatusExInfo, GetWriterStatusExError> {
+        trace_com_call!("GetWriterStatusEx");
         let mut instance_id: VSS_ID = Default::default();
         let mut writer_id: VSS_ID = Default::default();
         let mut writer: BSTR = null_mut();
@@ -1187,10 +2787,132 @@ impl IBackupComponentsEx3 {
         &self,
         flags: RawBitFlags<RecoveryOptions>,
     ) -> IVssAsyncResult<RecoverSetError> {
+        trace_com_call!("RecoverSet");
         let mut task = null_mut::<vss::IVssAsync>();
         check_com(unsafe { self.0.RecoverSet(flags.raw(), &mut task) })?;
         Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
     }
+    /// Resynchronize the LUNs of the specified shadow copies and wait for the
+    /// operation to complete.
+    ///
+    /// Every target is added to the recovery set with
+    /// [`IBackupComponentsEx3::add_snapshot_to_recovery_set`] before
+    /// [`IBackupComponentsEx3::recover_set`] is called. If any target can't be
+    /// added then the resynchronization isn't started and the failure of each
+    /// target is returned.
+    ///
+    /// LUN resynchronization is only supported on Windows Server, on other
+    /// editions [`LunResyncError::NotSupported`] is returned without calling
+    /// VSS.
+    #[doc(alias = "RecoverSet")]
+    pub fn resync_luns(
+        &self,
+        targets: &[ResyncTarget<'_>],
+        flags: RawBitFlags<RecoveryOptions>,
+    ) -> Result<(), LunResyncError> {
+        if !is_server_os() {
+            return Err(LunResyncError::NotSupported);
+        }
+        let failures = targets
+            .iter()
+            .filter_map(|target| {
+                self.add_snapshot_to_recovery_set(target.snapshot_id, target.destination_volume)
+                    .err()
+                    .map(|error| LunResyncFailure {
+                        snapshot_id: target.snapshot_id,
+                        destination_volume: target.destination_volume.map(U16CStr::to_ucstring),
+                        error,
+                    })
+            })
+            .collect::<Vec<_>>();
+        if !failures.is_empty() {
+            return Err(LunResyncError::AddToRecoverySet(failures));
+        }
+        let vss_async = self
+            .recover_set(flags)
+            .map_err(LunResyncError::RecoverSet)?;
+        vss_async
+            .wait(None)
+            .map_err(|e| LunResyncError::Wait(e.async_error()))?;
+        vss_async
+            .query_status()
+            .map_err(|e| LunResyncError::RecoverSet(e.underlying_error()))?;
+        Ok(())
+    }
+}
+
+/// A shadow copy whose LUNs should be resynchronized by
+/// [`IBackupComponentsEx3::resync_luns`].
+#[derive(Clone, Copy)]
+pub struct ResyncTarget<'a> {
+    /// Identifies the shadow copy.
+    pub snapshot_id: VSS_ID,
+    /// The volume that the shadow copy is resynchronized to. `None` uses the
+    /// original volume.
+    pub destination_volume: Option<&'a U16CStr>,
+}
+
+/// A shadow copy that couldn't be added to the recovery set, see
+/// [`LunResyncError::AddToRecoverySet`].
+#[derive(Clone)]
+pub struct LunResyncFailure {
+    /// Identifies the shadow copy.
+    pub snapshot_id: VSS_ID,
+    /// The destination volume that was specified for the shadow copy.
+    pub destination_volume: Option<U16CString>,
+    /// Why the shadow copy couldn't be added.
+    pub error: AddSnapshotToRecoverySetError,
+}
+impl fmt::Debug for LunResyncFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LunResyncFailure")
+            .field("destination_volume", &self.destination_volume)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// Error returned by [`IBackupComponentsEx3::resync_luns`].
+#[derive(Debug)]
+pub enum LunResyncError {
+    /// LUN resynchronization is only supported on Windows Server.
+    NotSupported,
+    /// Some shadow copies couldn't be added to the recovery set.
+    AddToRecoverySet(Vec<LunResyncFailure>),
+    /// Failed to start the resynchronization or the resynchronization
+    /// failed.
+    RecoverSet(RecoverSetError),
+    /// Failed to wait for the resynchronization to complete.
+    Wait(WaitError),
+}
+impl fmt::Display for LunResyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSupported => write!(
+                f,
+                "LUN resynchronization is only supported on Windows Server"
+            ),
+            Self::AddToRecoverySet(failures) => write!(
+                f,
+                "failed to add {} shadow copies to the recovery set",
+                failures.len()
+            ),
+            Self::RecoverSet(e) => write!(f, "LUN resynchronization failed: {}", e),
+            Self::Wait(e) => write!(f, "failed to wait for LUN resynchronization: {}", e),
+        }
+    }
+}
+impl StdError for LunResyncError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::NotSupported => None,
+            Self::AddToRecoverySet(failures) => failures
+                .first()
+                .map(|f| &f.error as &(dyn StdError + 'static)),
+            Self::RecoverSet(e) => Some(e),
+            Self::Wait(e) => Some(e),
+        }
+    }
 }
 
 /// Information returned by the [`IBackupComponentsEx3::get_writer_status_ex`]
@@ -1233,6 +2955,7 @@ impl IBackupComponentsEx4 {
         file_path: &U16CStr,
         normalize_fqdn_for_root_path: bool,
     ) -> Result<GetRootAndLogicalPrefixPathsInfo, GetRootAndLogicalPrefixPathsError> {
+        trace_com_call!("GetRootAndLogicalPrefixPaths");
         let mut root_path: VSS_PWSZ = null_mut();
         let mut logical_prefix: VSS_PWSZ = null_mut();
         let result = check_com(unsafe {
@@ -1324,6 +3047,7 @@ impl ExamineWriterMetadata {
     // method returns?
     #[doc(alias = "CreateVssExamineWriterMetadata")]
     pub fn new(xml: &BStr) -> Result<Self, CreateVssExamineWriterMetadataError> {
+        trace_com_call!("CreateVssExamineWriterMetadata");
         unsafe {
             let mut comp = null_mut::<vsbackup::IVssExamineWriterMetadata>();
             check_com(vsbackup::CreateVssExamineWriterMetadata(
@@ -1341,6 +3065,7 @@ impl IExamineWriterMetadata {
         &self,
         mapping_index: u32,
     ) -> Result<WMFileDescriptor, GetAlternateLocationMappingError> {
+        trace_com_call!("GetAlternateLocationMapping");
         let mut file_descriptor: *mut vswriter::IVssWMFiledesc = null_mut();
         check_com(unsafe {
             self.0
@@ -1354,6 +3079,7 @@ impl IExamineWriterMetadata {
     /// types of backup operations that a given writer can participate in.
     #[doc(alias = "GetBackupSchema")]
     pub fn get_backup_schema(&self) -> Result<RawBitFlags<BackupSchema>, GetBackupSchemaError> {
+        trace_com_call!("GetBackupSchema");
         let mut schema_mask: DWORD = Default::default();
         check_com(unsafe { self.0.GetBackupSchema(&mut schema_mask) })?;
         Ok(RawBitFlags::from_raw(schema_mask))
@@ -1374,6 +3100,7 @@ impl IExamineWriterMetadata {
         &self,
         component_index: u32,
     ) -> Result<WMComponent, ExamineWriterMetadataGetComponentError> {
+        trace_com_call!("GetComponent");
         let mut component = null_mut::<vsbackup::IVssWMComponent>();
         check_com(unsafe { self.0.GetComponent(component_index, &mut component) })?;
         Ok(WMComponent(unsafe { SafeCOMComponent::new(component) }))
@@ -1385,6 +3112,7 @@ impl IExamineWriterMetadata {
         &self,
         file_index: u32,
     ) -> Result<WMFileDescriptor, GetExcludeFileError> {
+        trace_com_call!("GetExcludeFile");
         let mut file_descriptor: *mut vswriter::IVssWMFiledesc = null_mut();
         check_com(unsafe { self.0.GetExcludeFile(file_index, &mut file_descriptor) })?;
         Ok(WMFileDescriptor(unsafe {
@@ -1394,6 +3122,7 @@ impl IExamineWriterMetadata {
     /// Obtains excluded files and the number of components that a writer manages.
     #[doc(alias = "GetFileCounts")]
     pub fn get_file_counts(&self) -> Result<GetFileCountsInfo, GetFileCountsError> {
+        trace_com_call!("GetFileCounts");
         let mut included_files: u32 = 0;
         let mut info = GetFileCountsInfo {
             excluded_files: 0,
@@ -1411,6 +3140,7 @@ impl IExamineWriterMetadata {
     /// Obtains basic information about a specific writer instance.
     #[doc(alias = "GetIdentity")]
     pub fn get_identity(&self) -> Result<GetIdentityInfo, GetIdentityError> {
+        trace_com_call!("GetIdentity");
         let mut instance: VSS_ID = Default::default();
         let mut writer: VSS_ID = Default::default();
         let mut writer_name: BSTR = null_mut();
@@ -1444,6 +3174,7 @@ impl IExamineWriterMetadata {
     pub fn get_restore_method(
         &self,
     ) -> Result<Option<GetRestoreMethodInfo>, GetRestoreMethodError> {
+        trace_com_call!("GetRestoreMethod");
         let mut method: vswriter::VSS_RESTOREMETHOD_ENUM = Default::default();
         let mut service: BSTR = null_mut();
         let mut user_procedure: BSTR = null_mut();
@@ -1484,6 +3215,7 @@ impl IExamineWriterMetadata {
     // method returns?
     #[doc(alias = "LoadFromXML")]
     pub fn load_from_xml(&self, xml: &BStr) -> Result<(), LoadFromXMLError> {
+        trace_com_call!("LoadFromXML");
         // TODO: update `winapi` to have the correct signature.
 
         // The Rust bindings seem to have the wrong type here, they expect
@@ -1499,6 +3231,7 @@ impl IExamineWriterMetadata {
     /// operation.
     #[doc(alias = "SaveAsXML")]
     pub fn save_as_xml(&self) -> Result<BString, ExamineWriterMetadataSaveAsXMLError> {
+        trace_com_call!("SaveAsXML");
         let mut xml: BSTR = null_mut();
         let hr = unsafe { self.0.SaveAsXML(&mut xml) };
         let xml = unsafe { take_ownership_of_bstr(xml) };
@@ -1507,6 +3240,58 @@ impl IExamineWriterMetadata {
             .unwrap()
             .expect("the xml string returned by SaveAsXML shouldn't be null"))
     }
+    /// Find the subcomponents in the component set defined by `component`
+    /// that can be restored individually, meaning they are not selectable for
+    /// backup but are selectable for restore.
+    ///
+    /// The writer id and component type of `component` are ignored.
+    pub fn restorable_subcomponents(
+        &self,
+        component: &ComponentSpec<'_>,
+    ) -> Result<Vec<RestorableSubcomponent>, RestorableSubcomponentsError> {
+        let mut set_path = Vec::new();
+        if let Some(logical_path) = component.logical_path.filter(|p| !p.is_empty()) {
+            set_path.extend_from_slice(logical_path.as_slice());
+            set_path.push(u16::from(b'\\'));
+        }
+        set_path.extend_from_slice(component.component_name.as_slice());
+
+        let count = self
+            .get_file_counts()
+            .map_err(|e| RestorableSubcomponentsError(e.0))?
+            .total_components;
+        let mut subcomponents = Vec::new();
+        for component_index in 0..count {
+            let component = self
+                .get_component(component_index)
+                .map_err(|e| RestorableSubcomponentsError(e.0))?;
+            let info = component
+                .get_component_info()
+                .map_err(|e| RestorableSubcomponentsError(e.0))?;
+            if info.selectable() || !info.selectable_for_restore() {
+                continue;
+            }
+            // Components without a logical path can't be inside a component
+            // set, and logical paths are compared case-insensitively.
+            let logical_path = match info.logical_path() {
+                Some(logical_path)
+                    if paths::starts_with_ignore_case(
+                        bstr_as_u16_slice(logical_path),
+                        &set_path,
+                    ) =>
+                {
+                    logical_path
+                }
+                _ => continue,
+            };
+            subcomponents.push(RestorableSubcomponent {
+                logical_path: bstr_to_u16_cstring(logical_path),
+                component_name: bstr_to_u16_cstring(info.component_name()),
+                caption: info.caption().map(bstr_to_u16_cstring),
+            });
+        }
+        Ok(subcomponents)
+    }
 }
 
 /// Info returned by the [`IExamineWriterMetadata::get_file_counts`] method.
@@ -1580,6 +3365,7 @@ impl IExamineWriterMetadataEx {
     /// specific writer instance.
     #[doc(alias = "GetIdentityEx")]
     pub fn get_identity_ex(&self) -> Result<GetIdentityExInfo, GetIdentityExError> {
+        trace_com_call!("GetIdentityEx");
         let mut instance: VSS_ID = Default::default();
         let mut writer: VSS_ID = Default::default();
         let mut writer_name: BSTR = null_mut();
@@ -1653,6 +3439,7 @@ impl IExamineWriterMetadataEx2 {
     /// a given shadow copy.
     #[doc(alias = "GetExcludeFromSnapshotCount")]
     pub fn get_exclude_from_snapshot_count(&self) -> Result<u32, GetExcludeFromSnapshotCountError> {
+        trace_com_call!("GetExcludeFromSnapshotCount");
         let mut excluded_from_snapshot: UINT = 0;
         check_com(unsafe {
             self.0
@@ -1667,6 +3454,7 @@ impl IExamineWriterMetadataEx2 {
         &self,
         file_index: u32,
     ) -> Result<WMFileDescriptor, GetExcludeFromSnapshotFileError> {
+        trace_com_call!("GetExcludeFromSnapshotFile");
         let mut file_description: *mut vswriter::IVssWMFiledesc = null_mut();
         check_com(unsafe {
             self.0
@@ -1679,6 +3467,7 @@ impl IExamineWriterMetadataEx2 {
     /// Obtains the version information for a writer application.
     #[doc(alias = "GetVersion")]
     pub fn get_version(&self) -> Result<VersionInfo, GetVersionError> {
+        trace_com_call!("GetVersion");
         let mut info = VersionInfo { major: 0, minor: 0 };
         check_com(unsafe { self.0.GetVersion(&mut info.major, &mut info.minor) })?;
         Ok(info)
@@ -1714,6 +3503,7 @@ impl WMComponent {
     /// Obtains basic information about the specified writer metadata component.
     #[doc(alias = "GetComponentInfo")]
     pub fn get_component_info(&self) -> Result<ComponentInfo<'_>, GetComponentInfoError> {
+        trace_com_call!("GetComponentInfo");
         let mut info = null::<vsbackup::VSS_COMPONENTINFO>();
         check_com(unsafe { self.0.GetComponentInfo(&mut info) })?;
         Ok(ComponentInfo {
@@ -1732,6 +3522,7 @@ impl WMComponent {
         &self,
         db_file_index: u32,
     ) -> Result<WMFileDescriptor, GetDatabaseFileError> {
+        trace_com_call!("GetDatabaseFile");
         let mut file_desc: *mut vswriter::IVssWMFiledesc = null_mut();
         check_com(unsafe { self.0.GetDatabaseFile(db_file_index, &mut file_desc) })?;
         Ok(WMFileDescriptor(unsafe {
@@ -1749,6 +3540,7 @@ impl WMComponent {
         &self,
         db_log_file_index: u32,
     ) -> Result<WMFileDescriptor, GetDatabaseLogFileError> {
+        trace_com_call!("GetDatabaseLogFile");
         let mut file_desc: *mut vswriter::IVssWMFiledesc = null_mut();
         check_com(unsafe { self.0.GetDatabaseLogFile(db_log_file_index, &mut file_desc) })?;
         Ok(WMFileDescriptor(unsafe {
@@ -1767,6 +3559,7 @@ impl WMComponent {
         &self,
         dependency_index: u32,
     ) -> Result<WMDependency, GetDependencyError> {
+        trace_com_call!("GetDependency");
         let mut dependency: *mut vswriter::IVssWMDependency = null_mut();
         check_com(unsafe { self.0.GetDependency(dependency_index, &mut dependency) })?;
         Ok(WMDependency(unsafe { SafeCOMComponent::new(dependency) }))
@@ -1778,12 +3571,59 @@ impl WMComponent {
     /// method.
     #[doc(alias = "GetFile")]
     pub fn get_file(&self, file_index: u32) -> Result<WMFileDescriptor, GetFileError> {
+        trace_com_call!("GetFile");
         let mut file_desc: *mut vswriter::IVssWMFiledesc = null_mut();
         check_com(unsafe { self.0.GetFile(file_index, &mut file_desc) })?;
         Ok(WMFileDescriptor(unsafe {
             SafeCOMComponent::new(file_desc)
         }))
     }
+
+    /// Iterate over the file descriptors of a file group component, combining
+    /// [`ComponentInfo::file_count`] and [`WMComponent::get_file`].
+    pub fn files(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<WMFileDescriptor, GetFileError>> + '_,
+        GetComponentInfoError,
+    > {
+        let count = self.get_component_info()?.file_count();
+        Ok((0..count).map(move |index| self.get_file(index)))
+    }
+    /// Iterate over the database files of a database component, combining
+    /// [`ComponentInfo::databases`] and [`WMComponent::get_database_file`].
+    pub fn database_files(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<WMFileDescriptor, GetDatabaseFileError>> + '_,
+        GetComponentInfoError,
+    > {
+        let count = self.get_component_info()?.databases();
+        Ok((0..count).map(move |index| self.get_database_file(index)))
+    }
+    /// Iterate over the log files of a database component, combining
+    /// [`ComponentInfo::log_files`] and [`WMComponent::get_database_log_file`].
+    pub fn log_files(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<WMFileDescriptor, GetDatabaseLogFileError>> + '_,
+        GetComponentInfoError,
+    > {
+        let count = self.get_component_info()?.log_files();
+        Ok((0..count).map(move |index| self.get_database_log_file(index)))
+    }
+    /// Iterate over the explicit writer-component dependencies of the
+    /// component, combining [`ComponentInfo::dependencies`] and
+    /// [`WMComponent::get_dependency`].
+    pub fn dependencies(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<WMDependency, GetDependencyError>> + '_,
+        GetComponentInfoError,
+    > {
+        let count = self.get_component_info()?.dependencies();
+        Ok((0..count).map(move |index| self.get_dependency(index)))
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -2113,6 +3953,7 @@ pub struct VolumeSnapshottedInfo {
 pub fn is_volume_snapshotted(
     volume_name: &U16CStr,
 ) -> Result<VolumeSnapshottedInfo, IsVolumeSnapshottedError> {
+    trace_com_call!("IsVolumeSnapshotted");
     let mut snapshot_present: BOOL = FALSE;
     let mut snapshot_capability: LONG = 0;
     check_com(unsafe {
@@ -2155,6 +3996,7 @@ pub fn is_volume_snapshotted(
 /// `HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services\VSS\Settings\WritersBlockingRevert`
 #[doc(alias = "ShouldBlockRevert")]
 pub fn should_block_revert(volume_name: &U16CStr) -> Result<bool, ShouldBlockRevertError> {
+    trace_com_call!("ShouldBlockRevert");
     let mut block = false;
     check_com(unsafe { vsbackup::ShouldBlockRevert(volume_name.as_ptr(), &mut block) })?;
     Ok(block)
@@ -2174,6 +4016,37 @@ mod tests {
         BackupComponents::new().unwrap();
     }
 
+    #[test]
+    fn error_from_kind() {
+        let error = BackupCompleteError::from_kind(BackupCompleteErrorKind::VSS_E_BAD_STATE);
+        assert_eq!(error.kind(), BackupCompleteErrorKind::VSS_E_BAD_STATE);
+        assert_eq!(HRESULT::from(error), winapi::um::vsserror::VSS_E_BAD_STATE);
+    }
+
+    #[test]
+    fn break_options_flags() {
+        assert_eq!(
+            BreakOptions::new().flags().flags(),
+            HardwareOptions::BREAKEX_FLAG_MAKE_READ_WRITE
+        );
+        assert_eq!(
+            BreakOptions::new()
+                .make_read_write(false)
+                .revert_identity(RevertIdentity::None)
+                .flags()
+                .flags(),
+            HardwareOptions::BREAKEX_FLAG_REVERT_IDENTITY_NONE
+        );
+        assert_eq!(
+            BreakOptions::new()
+                .revert_identity(RevertIdentity::All)
+                .flags()
+                .flags(),
+            HardwareOptions::BREAKEX_FLAG_MAKE_READ_WRITE
+                | HardwareOptions::BREAKEX_FLAG_REVERT_IDENTITY_ALL
+        );
+    }
+
     /// Check if AsRef and Borrow impls are good enough to write ergonomic generic
     /// code.
     #[allow(unused_variables, dead_code)]
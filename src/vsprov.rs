@@ -2,6 +2,2501 @@
 //!
 //! This is not required when developing a VSS "writer" or a VSS "requester".
 //!
+//! The [`SoftwareSnapshotProvider`] trait can be implemented to create a VSS
+//! software provider. A [`SoftwareProviderObject`] can then be used to expose
+//! the implementation as a COM object that VSS can call into. Hardware
+//! providers are implemented in the same way using the
+//! [`HardwareSnapshotProvider`] trait and a [`HardwareProviderObject`] or the
+//! [`FileShareSnapshotProvider`] trait and a [`FileShareProviderObject`]. All
+//! kinds of providers must also implement the [`CreateSnapshotSet`] trait which
+//! handles the later stages of creating a snapshot set and the
+//! [`ProviderNotifications`] trait.
+//!
+//! A software provider is hosted by an executable that uses
+//! [`crate::com_server`] to expose its COM class. Installers can use
+//! [`register_software_provider`] to make VSS aware of such a provider.
+//!
 //! # References
 //!
 //! [Vsprov.h header - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/api/vsprov/)
+
+use std::{
+    convert::{TryFrom, TryInto},
+    error::Error as StdError,
+    ffi::CStr,
+    fmt, io,
+    marker::PhantomData,
+    path::Path,
+    ptr, slice,
+    sync::{atomic::AtomicU32, Mutex},
+};
+
+use widestring::{U16CStr, U16CString};
+use winapi::{
+    ctypes::{c_char, c_void},
+    shared::{
+        guiddef::{IsEqualGUID, CLSID, GUID, IID, REFIID},
+        minwindef::{BOOL, DWORD, FALSE, TRUE, ULONG},
+        ntdef::ULONGLONG,
+        winerror::{E_INVALIDARG, E_NOTIMPL, E_POINTER, S_FALSE, S_OK},
+    },
+    um::{
+        oaidl::VARIANT,
+        unknwnbase::{IUnknown, IUnknownVtbl},
+        vss::{self, VSS_ID, VSS_PWSZ},
+        winnt::{HRESULT, LONG},
+    },
+    Interface,
+};
+
+use super::{
+    check_com,
+    com_server::{
+        add_ref, catch_panic, into_hresult, query_interface, register_local_server, release,
+        unregister_class, ServerObject,
+    },
+    errors::*,
+    impl_query_interface, sealed, trace_com_call, transparent_wrapper, unsafe_deref_to_ref,
+    unsafe_impl_as_IUnknown,
+    vsadmin::{Admin, ProviderCapabilities},
+    vsbackup::VolumeSnapshottedInfo,
+    vss::{
+        EnumObject, HardwareOptions, IVssAsyncResult, ObjectProperties, ObjectType, ProviderType,
+        SnapshotProperties, SnapshotPropertyId, VssAsync,
+    },
+    ConnectError, RawBitFlags, SafeCOMComponent,
+};
+
+/// Raw declarations for the `vsprov.h` header since `winapi` doesn't provide
+/// them.
+#[allow(non_snake_case, non_upper_case_globals, non_camel_case_types)]
+pub mod raw {
+    use winapi::{
+        ctypes::c_char,
+        shared::{
+            guiddef::GUID,
+            minwindef::{BOOL, BYTE, DWORD, ULONG},
+            ntdef::{LONG, ULONGLONG},
+        },
+        um::{
+            oaidl::VARIANT,
+            unknwnbase::{IUnknown, IUnknownVtbl},
+            vss::{
+                IVssAsync, IVssEnumObject, VSS_ID, VSS_OBJECT_TYPE, VSS_PWSZ, VSS_SNAPSHOT_PROP,
+                VSS_SNAPSHOT_PROPERTY_ID,
+            },
+            winnt::HRESULT,
+        },
+        RIDL,
+    };
+
+    pub type VDS_STORAGE_BUS_TYPE = u32;
+    pub type VDS_STORAGE_IDENTIFIER_CODE_SET = u32;
+    pub type VDS_STORAGE_IDENTIFIER_TYPE = u32;
+    pub type VDS_INTERCONNECT_ADDRESS_TYPE = u32;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct VDS_STORAGE_IDENTIFIER {
+        pub m_CodeSet: VDS_STORAGE_IDENTIFIER_CODE_SET,
+        pub m_Type: VDS_STORAGE_IDENTIFIER_TYPE,
+        pub m_cbIdentifier: ULONG,
+        pub m_rgbIdentifier: *mut BYTE,
+    }
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct VDS_STORAGE_DEVICE_ID_DESCRIPTOR {
+        pub m_version: ULONG,
+        pub m_cIdentifiers: ULONG,
+        pub m_rgIdentifiers: *mut VDS_STORAGE_IDENTIFIER,
+    }
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct VDS_INTERCONNECT {
+        pub m_addressType: VDS_INTERCONNECT_ADDRESS_TYPE,
+        pub m_cbPort: ULONG,
+        pub m_pbPort: *mut BYTE,
+        pub m_cbAddress: ULONG,
+        pub m_pbAddress: *mut BYTE,
+    }
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct VDS_LUN_INFORMATION {
+        pub m_version: ULONG,
+        pub m_DeviceType: BYTE,
+        pub m_DeviceTypeModifier: BYTE,
+        pub m_bCommandQueueing: BOOL,
+        pub m_BusType: VDS_STORAGE_BUS_TYPE,
+        pub m_szVendorId: *mut c_char,
+        pub m_szProductId: *mut c_char,
+        pub m_szProductRevision: *mut c_char,
+        pub m_szSerialNumber: *mut c_char,
+        pub m_diskSignature: GUID,
+        pub m_deviceIdDescriptor: VDS_STORAGE_DEVICE_ID_DESCRIPTOR,
+        pub m_cInterconnects: ULONG,
+        pub m_rgInterconnects: *mut VDS_INTERCONNECT,
+    }
+
+    RIDL! {#[uuid(0x609e123e, 0x2c5a, 0x44d3, 0x8f, 0x01, 0x0b, 0x1d, 0x9a, 0x47, 0xd1, 0xff)]
+    interface IVssSoftwareSnapshotProvider(IVssSoftwareSnapshotProviderVtbl): IUnknown(IUnknownVtbl) {
+        fn SetContext(
+            lContext: LONG,
+        ) -> HRESULT,
+        fn GetSnapshotProperties(
+            SnapshotId: VSS_ID,
+            pProp: *mut VSS_SNAPSHOT_PROP,
+        ) -> HRESULT,
+        fn Query(
+            QueriedObjectId: VSS_ID,
+            eQueriedObjectType: VSS_OBJECT_TYPE,
+            eReturnedObjectsType: VSS_OBJECT_TYPE,
+            ppEnum: *mut *mut IVssEnumObject,
+        ) -> HRESULT,
+        fn DeleteSnapshots(
+            SourceObjectId: VSS_ID,
+            eSourceObjectType: VSS_OBJECT_TYPE,
+            bForceDelete: BOOL,
+            plDeletedSnapshots: *mut LONG,
+            pNondeletedSnapshotID: *mut VSS_ID,
+        ) -> HRESULT,
+        fn BeginPrepareSnapshot(
+            SnapshotSetId: VSS_ID,
+            SnapshotId: VSS_ID,
+            pwszVolumeName: VSS_PWSZ,
+            lNewContext: LONG,
+        ) -> HRESULT,
+        fn IsVolumeSupported(
+            pwszVolumeName: VSS_PWSZ,
+            pbSupportedByThisProvider: *mut BOOL,
+        ) -> HRESULT,
+        fn IsVolumeSnapshotted(
+            pwszVolumeName: VSS_PWSZ,
+            pbSnapshotsPresent: *mut BOOL,
+            plSnapshotCompatibility: *mut LONG,
+        ) -> HRESULT,
+        fn SetSnapshotProperty(
+            SnapshotId: VSS_ID,
+            eSnapshotPropertyId: VSS_SNAPSHOT_PROPERTY_ID,
+            vProperty: VARIANT,
+        ) -> HRESULT,
+        fn RevertToSnapshot(
+            SnapshotId: VSS_ID,
+        ) -> HRESULT,
+        fn QueryRevertStatus(
+            pwszVolume: VSS_PWSZ,
+            ppAsync: *mut *mut IVssAsync,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xc8636060, 0x7c2e, 0x11df, 0x8c, 0x4a, 0x08, 0x00, 0x20, 0x0c, 0x9a, 0x66)]
+    interface IVssFileShareSnapshotProvider(IVssFileShareSnapshotProviderVtbl): IUnknown(IUnknownVtbl) {
+        fn SetContext(
+            lContext: LONG,
+        ) -> HRESULT,
+        fn GetSnapshotProperties(
+            SnapshotId: VSS_ID,
+            pProp: *mut VSS_SNAPSHOT_PROP,
+        ) -> HRESULT,
+        fn Query(
+            QueriedObjectId: VSS_ID,
+            eQueriedObjectType: VSS_OBJECT_TYPE,
+            eReturnedObjectsType: VSS_OBJECT_TYPE,
+            ppEnum: *mut *mut IVssEnumObject,
+        ) -> HRESULT,
+        fn DeleteSnapshots(
+            SourceObjectId: VSS_ID,
+            eSourceObjectType: VSS_OBJECT_TYPE,
+            bForceDelete: BOOL,
+            plDeletedSnapshots: *mut LONG,
+            pNondeletedSnapshotID: *mut VSS_ID,
+        ) -> HRESULT,
+        fn BeginPrepareSnapshot(
+            SnapshotSetId: VSS_ID,
+            SnapshotId: VSS_ID,
+            pwszSharePath: VSS_PWSZ,
+            lNewContext: LONG,
+            ProviderId: VSS_ID,
+        ) -> HRESULT,
+        fn IsPathSupported(
+            pwszSharePath: VSS_PWSZ,
+            pbSupportedByThisProvider: *mut BOOL,
+        ) -> HRESULT,
+        fn IsPathSnapshotted(
+            pwszSharePath: VSS_PWSZ,
+            pbSnapshotsPresent: *mut BOOL,
+            plSnapshotCompatibility: *mut LONG,
+        ) -> HRESULT,
+        fn SetSnapshotProperty(
+            SnapshotId: VSS_ID,
+            eSnapshotPropertyId: VSS_SNAPSHOT_PROPERTY_ID,
+            vProperty: VARIANT,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x9593a157, 0x44e9, 0x4344, 0xbb, 0xeb, 0x44, 0xfb, 0xf9, 0xb0, 0x6b, 0x10)]
+    interface IVssHardwareSnapshotProvider(IVssHardwareSnapshotProviderVtbl): IUnknown(IUnknownVtbl) {
+        fn AreLunsSupported(
+            lLunCount: LONG,
+            lContext: LONG,
+            rgwszDevices: *mut VSS_PWSZ,
+            pLunInformation: *mut VDS_LUN_INFORMATION,
+            pbIsSupported: *mut BOOL,
+        ) -> HRESULT,
+        fn FillInLunInfo(
+            wszDeviceName: VSS_PWSZ,
+            pLunInfo: *mut VDS_LUN_INFORMATION,
+            pbIsSupported: *mut BOOL,
+        ) -> HRESULT,
+        fn BeginPrepareSnapshot(
+            SnapshotSetId: VSS_ID,
+            SnapshotId: VSS_ID,
+            lContext: LONG,
+            lLunCount: LONG,
+            rgDeviceNames: *mut VSS_PWSZ,
+            rgLunInformation: *mut VDS_LUN_INFORMATION,
+        ) -> HRESULT,
+        fn GetTargetLuns(
+            lLunCount: LONG,
+            rgDeviceNames: *mut VSS_PWSZ,
+            rgSourceLuns: *mut VDS_LUN_INFORMATION,
+            rgDestinationLuns: *mut VDS_LUN_INFORMATION,
+        ) -> HRESULT,
+        fn LocateLuns(
+            lLunCount: LONG,
+            rgSourceLuns: *mut VDS_LUN_INFORMATION,
+        ) -> HRESULT,
+        fn OnLunEmpty(
+            wszDeviceName: VSS_PWSZ,
+            pInformation: *mut VDS_LUN_INFORMATION,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x7f5ba925, 0xcdb1, 0x4d11, 0xa7, 0x1f, 0x33, 0x9e, 0xb7, 0xe7, 0x09, 0xfd)]
+    interface IVssHardwareSnapshotProviderEx(IVssHardwareSnapshotProviderExVtbl):
+        IVssHardwareSnapshotProvider(IVssHardwareSnapshotProviderVtbl) {
+        fn GetProviderCapabilities(
+            pllOriginalCapabilityMask: *mut ULONGLONG,
+        ) -> HRESULT,
+        fn OnLunStateChange(
+            pSnapshotLuns: *mut VDS_LUN_INFORMATION,
+            pOriginalLuns: *mut VDS_LUN_INFORMATION,
+            dwCount: DWORD,
+            dwFlags: DWORD,
+        ) -> HRESULT,
+        fn ResyncLuns(
+            pSourceLuns: *mut VDS_LUN_INFORMATION,
+            pTargetLuns: *mut VDS_LUN_INFORMATION,
+            dwCount: DWORD,
+            ppAsync: *mut *mut IVssAsync,
+        ) -> HRESULT,
+        fn OnReuseLuns(
+            pSnapshotLuns: *mut VDS_LUN_INFORMATION,
+            pOriginalLuns: *mut VDS_LUN_INFORMATION,
+            dwCount: DWORD,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x5f894e5b, 0x1e39, 0x4778, 0x8e, 0x23, 0x9a, 0xba, 0xd9, 0xf0, 0xe0, 0x8c)]
+    interface IVssProviderCreateSnapshotSet(IVssProviderCreateSnapshotSetVtbl): IUnknown(IUnknownVtbl) {
+        fn EndPrepareSnapshots(
+            SnapshotSetId: VSS_ID,
+        ) -> HRESULT,
+        fn PreCommitSnapshots(
+            SnapshotSetId: VSS_ID,
+        ) -> HRESULT,
+        fn CommitSnapshots(
+            SnapshotSetId: VSS_ID,
+        ) -> HRESULT,
+        fn PostCommitSnapshots(
+            SnapshotSetId: VSS_ID,
+            lSnapshotsCount: LONG,
+        ) -> HRESULT,
+        fn PreFinalCommitSnapshots(
+            SnapshotSetId: VSS_ID,
+        ) -> HRESULT,
+        fn PostFinalCommitSnapshots(
+            SnapshotSetId: VSS_ID,
+        ) -> HRESULT,
+        fn AbortSnapshots(
+            SnapshotSetId: VSS_ID,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xe561901f, 0x03a5, 0x4afe, 0x86, 0xd0, 0x72, 0xba, 0xee, 0xce, 0x70, 0x04)]
+    interface IVssProviderNotifications(IVssProviderNotificationsVtbl): IUnknown(IUnknownVtbl) {
+        fn OnLoad(
+            pCallback: *mut IUnknown,
+        ) -> HRESULT,
+        fn OnUnload(
+            bForceUnload: BOOL,
+        ) -> HRESULT,
+    }}
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// COM object helpers
+////////////////////////////////////////////////////////////////////////////////
+
+/// Borrow a string argument passed to a COM method.
+///
+/// # Safety
+///
+/// The pointer must be null or point to a nul-terminated string.
+unsafe fn string_arg<'a>(string: VSS_PWSZ) -> Result<&'a U16CStr, HRESULT> {
+    if string.is_null() {
+        Err(E_INVALIDARG)
+    } else {
+        Ok(U16CStr::from_ptr_str(string))
+    }
+}
+
+/// A COM object implemented in Rust for a provider. Such objects expose the
+/// `IVssProviderCreateSnapshotSet` and `IVssProviderNotifications` interfaces
+/// in addition to their main interface.
+///
+/// # Safety
+///
+/// The type must be `#[repr(C)]` and start with these fields:
+///
+/// 1. The main interface, whose `IUnknown` methods manage the object's
+///    lifetime.
+/// 2. A `raw::IVssProviderCreateSnapshotSet` that uses
+///    [`CreateSnapshotSetImpl::<Self>::VTBL`](CreateSnapshotSetImpl::VTBL).
+/// 3. A `raw::IVssProviderNotifications` that uses
+///    [`ProviderNotificationsImpl::<Self>::VTBL`](ProviderNotificationsImpl::VTBL).
+unsafe trait ProviderObjectLayout: Sized + 'static {
+    type Provider: CreateSnapshotSet + ProviderNotifications;
+
+    fn provider(&self) -> &Self::Provider;
+}
+
+/// Implements `QueryInterface` for the main interface of a
+/// [`ProviderObjectLayout`]. The secondary interfaces are handled here and all
+/// other interfaces are forwarded to the [`query_interface`] helper.
+///
+/// # Safety
+///
+/// Same as [`query_interface`], the `this` pointer must also point to an
+/// object that implements [`ProviderObjectLayout`].
+unsafe fn query_provider_interface(
+    this: *mut IUnknown,
+    riid: REFIID,
+    object: *mut *mut c_void,
+    supported: &[IID],
+) -> HRESULT {
+    if object.is_null() || riid.is_null() {
+        return E_POINTER;
+    }
+    let field = if IsEqualGUID(&*riid, &raw::IVssProviderCreateSnapshotSet::uuidof()) {
+        1
+    } else if IsEqualGUID(&*riid, &raw::IVssProviderNotifications::uuidof()) {
+        2
+    } else {
+        return query_interface(this, riid, object, supported);
+    };
+    (*this).AddRef();
+    *object = (this as *mut *const c_void).add(field) as *mut c_void;
+    S_OK
+}
+
+/// Implements the methods that the `IVssSoftwareSnapshotProvider` and
+/// `IVssFileShareSnapshotProvider` vtables have in common. Must be used inside
+/// the `impl` block of a [`ProviderObjectLayout`] type that has a `from_this`
+/// function and `ref_count` and `provider` fields.
+///
+/// `is_supported` and `is_snapshotted` name both the generated methods and the
+/// provider methods they forward to, since they take a volume name for
+/// software providers and a share path for file share providers.
+macro_rules! shared_provider_methods {
+    (
+        interface: $interface:ty,
+        is_supported: $is_supported:ident,
+        is_snapshotted: $is_snapshotted:ident $(,)?
+    ) => {
+        unsafe extern "system" fn query_interface(
+            this: *mut IUnknown,
+            riid: REFIID,
+            object: *mut *mut c_void,
+        ) -> HRESULT {
+            query_provider_interface(this, riid, object, &[<$interface>::uuidof()])
+        }
+        unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+            add_ref(&Self::from_this(this).ref_count)
+        }
+        unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+            let count = release(&Self::from_this(this).ref_count);
+            if count == 0 {
+                drop(Box::from_raw(this as *mut Self));
+            }
+            count
+        }
+
+        unsafe extern "system" fn set_context(this: *mut $interface, context: LONG) -> HRESULT {
+            catch_panic(|| into_hresult(Self::from_this(this).provider.set_context(context)))
+        }
+        unsafe extern "system" fn get_snapshot_properties(
+            this: *mut $interface,
+            snapshot_id: VSS_ID,
+            prop: *mut vss::VSS_SNAPSHOT_PROP,
+        ) -> HRESULT {
+            if prop.is_null() {
+                return E_POINTER;
+            }
+            catch_panic(|| {
+                match Self::from_this(this)
+                    .provider
+                    .get_snapshot_properties(snapshot_id)
+                {
+                    Ok(properties) => {
+                        prop.write(properties.into_raw());
+                        S_OK
+                    }
+                    Err(hr) => hr,
+                }
+            })
+        }
+        unsafe extern "system" fn query(
+            this: *mut $interface,
+            queried_object_id: VSS_ID,
+            queried_object_type: vss::VSS_OBJECT_TYPE,
+            returned_objects_type: vss::VSS_OBJECT_TYPE,
+            enumerator: *mut *mut vss::IVssEnumObject,
+        ) -> HRESULT {
+            if enumerator.is_null() {
+                return E_POINTER;
+            }
+            catch_panic(|| {
+                match Self::from_this(this).provider.query(
+                    queried_object_id,
+                    queried_object_type.into(),
+                    returned_objects_type.into(),
+                ) {
+                    Ok(objects) => {
+                        let objects = EnumObject::from_objects(objects);
+                        enumerator.write(SafeCOMComponent::into_raw(objects.0));
+                        S_OK
+                    }
+                    Err(hr) => hr,
+                }
+            })
+        }
+        unsafe extern "system" fn delete_snapshots(
+            this: *mut $interface,
+            source_object_id: VSS_ID,
+            source_object_type: vss::VSS_OBJECT_TYPE,
+            force_delete: BOOL,
+            deleted_snapshots: *mut LONG,
+            nondeleted_snapshot_id: *mut VSS_ID,
+        ) -> HRESULT {
+            if deleted_snapshots.is_null() || nondeleted_snapshot_id.is_null() {
+                return E_POINTER;
+            }
+            catch_panic(|| {
+                let info = Self::from_this(this).provider.delete_snapshots(
+                    source_object_id,
+                    source_object_type.into(),
+                    force_delete != FALSE,
+                );
+                deleted_snapshots
+                    .write(LONG::try_from(info.deleted_snapshots).unwrap_or(LONG::MAX));
+                nondeleted_snapshot_id.write(info.nondeleted_snapshot_id.unwrap_or_default());
+                info.error.unwrap_or(S_OK)
+            })
+        }
+        unsafe extern "system" fn $is_supported(
+            this: *mut $interface,
+            path: VSS_PWSZ,
+            supported_by_this_provider: *mut BOOL,
+        ) -> HRESULT {
+            if supported_by_this_provider.is_null() {
+                return E_POINTER;
+            }
+            catch_panic(|| {
+                into_hresult(string_arg(path).and_then(|path| {
+                    let supported = Self::from_this(this).provider.$is_supported(path)?;
+                    supported_by_this_provider.write(if supported { TRUE } else { FALSE });
+                    Ok(())
+                }))
+            })
+        }
+        unsafe extern "system" fn $is_snapshotted(
+            this: *mut $interface,
+            path: VSS_PWSZ,
+            snapshots_present: *mut BOOL,
+            snapshot_compatibility: *mut LONG,
+        ) -> HRESULT {
+            if snapshots_present.is_null() || snapshot_compatibility.is_null() {
+                return E_POINTER;
+            }
+            catch_panic(|| {
+                into_hresult(string_arg(path).and_then(|path| {
+                    let info = Self::from_this(this).provider.$is_snapshotted(path)?;
+                    snapshots_present.write(if info.snapshot_present { TRUE } else { FALSE });
+                    snapshot_compatibility.write(info.snapshot_capability.raw());
+                    Ok(())
+                }))
+            })
+        }
+        unsafe extern "system" fn set_snapshot_property(
+            this: *mut $interface,
+            snapshot_id: VSS_ID,
+            property_id: vss::VSS_SNAPSHOT_PROPERTY_ID,
+            value: VARIANT,
+        ) -> HRESULT {
+            catch_panic(|| {
+                into_hresult(Self::from_this(this).provider.set_snapshot_property(
+                    snapshot_id,
+                    property_id.into(),
+                    &value,
+                ))
+            })
+        }
+    };
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssProviderCreateSnapshotSet
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssProviderCreateSnapshotSet);
+
+/// The snapshot set lifecycle of a provider. Can be retrieved from a provider
+/// object using [`QueryInterface::query`](crate::QueryInterface::query).
+#[doc(alias = "IVssProviderCreateSnapshotSet")]
+#[derive(Debug, Clone)]
+pub struct ProviderCreateSnapshotSet(SafeCOMComponent<raw::IVssProviderCreateSnapshotSet>);
+impl_query_interface!(ProviderCreateSnapshotSet => raw::IVssProviderCreateSnapshotSet);
+transparent_wrapper!(
+    #[doc(alias = "IVssProviderCreateSnapshotSet")]
+    pub struct IProviderCreateSnapshotSet(raw::IVssProviderCreateSnapshotSet);
+);
+// Safety: all wrappers ensure their wrapped values are valid to use (Not released).
+unsafe_deref_to_ref!(ProviderCreateSnapshotSet => IProviderCreateSnapshotSet);
+
+impl IProviderCreateSnapshotSet {
+    /// Ensures that the provider is ready to have the shadow copies in the
+    /// snapshot set committed.
+    #[doc(alias = "EndPrepareSnapshots")]
+    pub fn end_prepare_snapshots(
+        &self,
+        snapshot_set_id: VSS_ID,
+    ) -> Result<(), EndPrepareSnapshotsError> {
+        trace_com_call!("EndPrepareSnapshots");
+        check_com(unsafe { self.0.EndPrepareSnapshots(snapshot_set_id) })?;
+        Ok(())
+    }
+    /// Ensures that the provider is ready to quickly commit the prepared
+    /// shadow copies. Called while applications are frozen.
+    #[doc(alias = "PreCommitSnapshots")]
+    pub fn pre_commit_snapshots(
+        &self,
+        snapshot_set_id: VSS_ID,
+    ) -> Result<(), PreCommitSnapshotsError> {
+        trace_com_call!("PreCommitSnapshots");
+        check_com(unsafe { self.0.PreCommitSnapshots(snapshot_set_id) })?;
+        Ok(())
+    }
+    /// Quickly commits all the prepared shadow copies in the snapshot set.
+    /// Called while all I/O is blocked on the affected volumes.
+    #[doc(alias = "CommitSnapshots")]
+    pub fn commit_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), CommitSnapshotsError> {
+        trace_com_call!("CommitSnapshots");
+        check_com(unsafe { self.0.CommitSnapshots(snapshot_set_id) })?;
+        Ok(())
+    }
+    /// Called after all the shadow copies in the snapshot set have been
+    /// committed and I/O has been released.
+    #[doc(alias = "PostCommitSnapshots")]
+    pub fn post_commit_snapshots(
+        &self,
+        snapshot_set_id: VSS_ID,
+        snapshots_count: i32,
+    ) -> Result<(), PostCommitSnapshotsError> {
+        trace_com_call!("PostCommitSnapshots");
+        check_com(unsafe { self.0.PostCommitSnapshots(snapshot_set_id, snapshots_count) })?;
+        Ok(())
+    }
+    /// Called after the writers have been thawed, before the auto-recovery of
+    /// the shadow copies. Only used for auto-recovered shadow copies.
+    #[doc(alias = "PreFinalCommitSnapshots")]
+    pub fn pre_final_commit_snapshots(
+        &self,
+        snapshot_set_id: VSS_ID,
+    ) -> Result<(), PreFinalCommitSnapshotsError> {
+        trace_com_call!("PreFinalCommitSnapshots");
+        check_com(unsafe { self.0.PreFinalCommitSnapshots(snapshot_set_id) })?;
+        Ok(())
+    }
+    /// Called after the auto-recovery of the shadow copies has completed. Only
+    /// used for auto-recovered shadow copies.
+    #[doc(alias = "PostFinalCommitSnapshots")]
+    pub fn post_final_commit_snapshots(
+        &self,
+        snapshot_set_id: VSS_ID,
+    ) -> Result<(), PostFinalCommitSnapshotsError> {
+        trace_com_call!("PostFinalCommitSnapshots");
+        check_com(unsafe { self.0.PostFinalCommitSnapshots(snapshot_set_id) })?;
+        Ok(())
+    }
+    /// Ends the prepared shadow copies in the snapshot set, releasing any
+    /// resources that were allocated for them.
+    #[doc(alias = "AbortSnapshots")]
+    pub fn abort_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), AbortSnapshotsError> {
+        trace_com_call!("AbortSnapshots");
+        check_com(unsafe { self.0.AbortSnapshots(snapshot_set_id) })?;
+        Ok(())
+    }
+}
+
+/// The snapshot set lifecycle that every provider must implement.
+///
+/// VSS calls these methods in order once all volumes have been added to a
+/// snapshot set: [`end_prepare_snapshots`](Self::end_prepare_snapshots),
+/// [`pre_commit_snapshots`](Self::pre_commit_snapshots),
+/// [`commit_snapshots`](Self::commit_snapshots) and finally
+/// [`post_commit_snapshots`](Self::post_commit_snapshots). If anything fails
+/// along the way then [`abort_snapshots`](Self::abort_snapshots) is called
+/// instead.
+///
+/// This is a supertrait of [`SoftwareSnapshotProvider`],
+/// [`HardwareSnapshotProvider`] and [`FileShareSnapshotProvider`] and the
+/// provider objects will expose it as the `IVssProviderCreateSnapshotSet` interface.
+#[doc(alias = "IVssProviderCreateSnapshotSet")]
+pub trait CreateSnapshotSet: Send + Sync + 'static {
+    /// Ensures that the provider is ready to have the shadow copies in the
+    /// snapshot set committed.
+    #[doc(alias = "EndPrepareSnapshots")]
+    fn end_prepare_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), HRESULT>;
+    /// Prepare to quickly commit the shadow copies. Applications are frozen
+    /// while this method is called.
+    #[doc(alias = "PreCommitSnapshots")]
+    fn pre_commit_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), HRESULT>;
+    /// Commit the shadow copies. All I/O to the affected volumes is blocked
+    /// while this method is called so it should return as quickly as possible.
+    #[doc(alias = "CommitSnapshots")]
+    fn commit_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), HRESULT>;
+    /// All shadow copies in the snapshot set have been committed and I/O has
+    /// been released.
+    #[doc(alias = "PostCommitSnapshots")]
+    fn post_commit_snapshots(
+        &self,
+        snapshot_set_id: VSS_ID,
+        snapshots_count: i32,
+    ) -> Result<(), HRESULT>;
+    /// Called before the auto-recovery of the shadow copies.
+    ///
+    /// The default implementation does nothing.
+    #[doc(alias = "PreFinalCommitSnapshots")]
+    fn pre_final_commit_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), HRESULT> {
+        let _ = snapshot_set_id;
+        Ok(())
+    }
+    /// Called after the auto-recovery of the shadow copies has completed.
+    ///
+    /// The default implementation does nothing.
+    #[doc(alias = "PostFinalCommitSnapshots")]
+    fn post_final_commit_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), HRESULT> {
+        let _ = snapshot_set_id;
+        Ok(())
+    }
+    /// Discard the shadow copies in the snapshot set.
+    #[doc(alias = "AbortSnapshots")]
+    fn abort_snapshots(&self, snapshot_set_id: VSS_ID) -> Result<(), HRESULT>;
+}
+
+/// Implements the `IVssProviderCreateSnapshotSet` interface of a
+/// [`ProviderObjectLayout`].
+struct CreateSnapshotSetImpl<O>(PhantomData<O>);
+impl<O: ProviderObjectLayout> CreateSnapshotSetImpl<O> {
+    const VTBL: raw::IVssProviderCreateSnapshotSetVtbl = raw::IVssProviderCreateSnapshotSetVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::query_interface,
+            AddRef: Self::add_ref,
+            Release: Self::release,
+        },
+        EndPrepareSnapshots: Self::end_prepare_snapshots,
+        PreCommitSnapshots: Self::pre_commit_snapshots,
+        CommitSnapshots: Self::commit_snapshots,
+        PostCommitSnapshots: Self::post_commit_snapshots,
+        PreFinalCommitSnapshots: Self::pre_final_commit_snapshots,
+        PostFinalCommitSnapshots: Self::post_final_commit_snapshots,
+        AbortSnapshots: Self::abort_snapshots,
+    };
+
+    /// Get the main interface of the object.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to the `IVssProviderCreateSnapshotSet` field of a
+    /// live object of type `O`.
+    unsafe fn outer<T>(this: *mut T) -> *mut IUnknown {
+        (this as *mut *const c_void).sub(1) as *mut IUnknown
+    }
+    /// # Safety
+    ///
+    /// Same as [`Self::outer`].
+    unsafe fn provider<'a, T>(this: *mut T) -> &'a O::Provider {
+        (*(Self::outer(this) as *const O)).provider()
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        (*Self::outer(this)).QueryInterface(riid, object)
+    }
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+        (*Self::outer(this)).AddRef()
+    }
+    unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+        (*Self::outer(this)).Release()
+    }
+
+    unsafe extern "system" fn end_prepare_snapshots(
+        this: *mut raw::IVssProviderCreateSnapshotSet,
+        snapshot_set_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| into_hresult(Self::provider(this).end_prepare_snapshots(snapshot_set_id)))
+    }
+    unsafe extern "system" fn pre_commit_snapshots(
+        this: *mut raw::IVssProviderCreateSnapshotSet,
+        snapshot_set_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| into_hresult(Self::provider(this).pre_commit_snapshots(snapshot_set_id)))
+    }
+    unsafe extern "system" fn commit_snapshots(
+        this: *mut raw::IVssProviderCreateSnapshotSet,
+        snapshot_set_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| into_hresult(Self::provider(this).commit_snapshots(snapshot_set_id)))
+    }
+    unsafe extern "system" fn post_commit_snapshots(
+        this: *mut raw::IVssProviderCreateSnapshotSet,
+        snapshot_set_id: VSS_ID,
+        snapshots_count: LONG,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(
+                Self::provider(this).post_commit_snapshots(snapshot_set_id, snapshots_count),
+            )
+        })
+    }
+    unsafe extern "system" fn pre_final_commit_snapshots(
+        this: *mut raw::IVssProviderCreateSnapshotSet,
+        snapshot_set_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(Self::provider(this).pre_final_commit_snapshots(snapshot_set_id))
+        })
+    }
+    unsafe extern "system" fn post_final_commit_snapshots(
+        this: *mut raw::IVssProviderCreateSnapshotSet,
+        snapshot_set_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(Self::provider(this).post_final_commit_snapshots(snapshot_set_id))
+        })
+    }
+    unsafe extern "system" fn abort_snapshots(
+        this: *mut raw::IVssProviderCreateSnapshotSet,
+        snapshot_set_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| into_hresult(Self::provider(this).abort_snapshots(snapshot_set_id)))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssProviderNotifications
+////////////////////////////////////////////////////////////////////////////////
+
+/// Notifications about the provider being loaded or unloaded by the VSS
+/// service.
+///
+/// This is a supertrait of [`SoftwareSnapshotProvider`],
+/// [`HardwareSnapshotProvider`] and [`FileShareSnapshotProvider`] and the
+/// provider objects expose it as the `IVssProviderNotifications` interface.
+/// An empty `impl` block is enough if the provider doesn't care about these
+/// notifications.
+#[doc(alias = "IVssProviderNotifications")]
+pub trait ProviderNotifications: Send + Sync + 'static {
+    /// The provider was loaded by the VSS service. The reserved callback
+    /// argument of `OnLoad` isn't exposed.
+    ///
+    /// Does nothing by default.
+    #[doc(alias = "OnLoad")]
+    fn on_load(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+    /// The provider is about to be unloaded by the VSS service.
+    ///
+    /// If `force_unload` is `false` then an error can be returned to prevent
+    /// the provider from being unloaded, for example because an operation is
+    /// still in progress. Otherwise the provider will be unloaded no matter
+    /// what this method returns.
+    ///
+    /// Allows the unload by default.
+    #[doc(alias = "OnUnload")]
+    fn on_unload(&self, force_unload: bool) -> Result<(), HRESULT> {
+        let _ = force_unload;
+        Ok(())
+    }
+}
+
+/// Implements the `IVssProviderNotifications` interface of a
+/// [`ProviderObjectLayout`].
+struct ProviderNotificationsImpl<O>(PhantomData<O>);
+impl<O: ProviderObjectLayout> ProviderNotificationsImpl<O> {
+    const VTBL: raw::IVssProviderNotificationsVtbl = raw::IVssProviderNotificationsVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::query_interface,
+            AddRef: Self::add_ref,
+            Release: Self::release,
+        },
+        OnLoad: Self::on_load,
+        OnUnload: Self::on_unload,
+    };
+
+    /// Get the main interface of the object.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to the `IVssProviderNotifications` field of a
+    /// live object of type `O`.
+    unsafe fn outer<T>(this: *mut T) -> *mut IUnknown {
+        (this as *mut *const c_void).sub(2) as *mut IUnknown
+    }
+    /// # Safety
+    ///
+    /// Same as [`Self::outer`].
+    unsafe fn provider<'a, T>(this: *mut T) -> &'a O::Provider {
+        (*(Self::outer(this) as *const O)).provider()
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        (*Self::outer(this)).QueryInterface(riid, object)
+    }
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+        (*Self::outer(this)).AddRef()
+    }
+    unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+        (*Self::outer(this)).Release()
+    }
+
+    unsafe extern "system" fn on_load(
+        this: *mut raw::IVssProviderNotifications,
+        _callback: *mut IUnknown,
+    ) -> HRESULT {
+        // The callback argument is reserved for future use.
+        catch_panic(|| into_hresult(Self::provider(this).on_load()))
+    }
+    unsafe extern "system" fn on_unload(
+        this: *mut raw::IVssProviderNotifications,
+        force_unload: BOOL,
+    ) -> HRESULT {
+        catch_panic(|| into_hresult(Self::provider(this).on_unload(force_unload != FALSE)))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssSoftwareSnapshotProvider
+////////////////////////////////////////////////////////////////////////////////
+
+/// Information returned by a provider's implementation of the
+/// [`SoftwareSnapshotProvider::delete_snapshots`] and
+/// [`FileShareSnapshotProvider::delete_snapshots`] methods.
+#[derive(Clone, Copy, Default)]
+pub struct DeleteSnapshotsInfo {
+    /// Number of deleted shadow copies.
+    pub deleted_snapshots: u32,
+    /// If an error occurs, the value of this parameter is the identifier of the
+    /// first shadow copy that could not be deleted.
+    pub nondeleted_snapshot_id: Option<VSS_ID>,
+    /// An error if something went wrong.
+    pub error: Option<HRESULT>,
+}
+
+/// Implement this trait to create a VSS software provider.
+///
+/// All methods return a raw `HRESULT` on failure which will be forwarded to
+/// VSS. The Microsoft documentation for each method lists the error codes that
+/// VSS expects.
+///
+/// Use [`SoftwareProviderObject::new`] to create a COM object that forwards
+/// calls to an implementation of this trait.
+#[doc(alias = "IVssSoftwareSnapshotProvider")]
+pub trait SoftwareSnapshotProvider: CreateSnapshotSet + ProviderNotifications {
+    /// Sets the context for subsequent shadow copy-related operations.
+    ///
+    /// The context is a combination of a [`crate::vss::SnapshotContext`] and
+    /// [`crate::vss::VolumeSnapshotAttributes`] flags.
+    #[doc(alias = "SetContext")]
+    fn set_context(&self, context: LONG) -> Result<(), HRESULT>;
+    /// Gets the properties of the specified shadow copy.
+    #[doc(alias = "GetSnapshotProperties")]
+    fn get_snapshot_properties(&self, snapshot_id: VSS_ID) -> Result<SnapshotProperties, HRESULT>;
+    /// Queries the provider for information about the shadow copies that the
+    /// provider has completed.
+    ///
+    /// Currently `queried_object_id` is always `GUID_NULL` and
+    /// `queried_object_type` is always [`ObjectType::None`].
+    #[doc(alias = "Query")]
+    fn query(
+        &self,
+        queried_object_id: VSS_ID,
+        queried_object_type: ObjectType,
+        returned_objects_type: ObjectType,
+    ) -> Result<Vec<ObjectProperties>, HRESULT>;
+    /// Deletes one or more shadow copies or a shadow copy set.
+    #[doc(alias = "DeleteSnapshots")]
+    fn delete_snapshots(
+        &self,
+        source_object_id: VSS_ID,
+        source_object_type: ObjectType,
+        force_delete: bool,
+    ) -> DeleteSnapshotsInfo;
+    /// Adds a volume to the shadow copy set.
+    #[doc(alias = "BeginPrepareSnapshot")]
+    fn begin_prepare_snapshot(
+        &self,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+        volume_name: &U16CStr,
+        new_context: LONG,
+    ) -> Result<(), HRESULT>;
+    /// Determines whether the provider supports shadow copies on the specified
+    /// volume.
+    #[doc(alias = "IsVolumeSupported")]
+    fn is_volume_supported(&self, volume_name: &U16CStr) -> Result<bool, HRESULT>;
+    /// Determines whether any shadow copies exist for the specified volume.
+    #[doc(alias = "IsVolumeSnapshotted")]
+    fn is_volume_snapshotted(
+        &self,
+        volume_name: &U16CStr,
+    ) -> Result<VolumeSnapshottedInfo, HRESULT>;
+    /// Sets a property for a shadow copy.
+    #[doc(alias = "SetSnapshotProperty")]
+    fn set_snapshot_property(
+        &self,
+        snapshot_id: VSS_ID,
+        property_id: SnapshotPropertyId,
+        value: &VARIANT,
+    ) -> Result<(), HRESULT>;
+    /// Reverts a volume to a previous shadow copy.
+    ///
+    /// The default implementation returns `E_NOTIMPL` to indicate that revert
+    /// isn't supported.
+    #[doc(alias = "RevertToSnapshot")]
+    fn revert_to_snapshot(&self, snapshot_id: VSS_ID) -> Result<(), HRESULT> {
+        let _ = snapshot_id;
+        Err(E_NOTIMPL)
+    }
+    /// Returns an async object that can be used to determine the status of a
+    /// revert operation.
+    ///
+    /// The default implementation returns `E_NOTIMPL` to indicate that revert
+    /// isn't supported.
+    #[doc(alias = "QueryRevertStatus")]
+    fn query_revert_status(&self, volume: &U16CStr) -> Result<VssAsync<HRESULT>, HRESULT> {
+        let _ = volume;
+        Err(E_NOTIMPL)
+    }
+}
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssSoftwareSnapshotProvider);
+
+/// A COM object that implements the `IVssSoftwareSnapshotProvider`,
+/// `IVssProviderCreateSnapshotSet` and `IVssProviderNotifications` interfaces
+/// by forwarding calls to a [`SoftwareSnapshotProvider`].
+#[doc(alias = "IVssSoftwareSnapshotProvider")]
+#[derive(Debug, Clone)]
+pub struct SoftwareProviderObject(SafeCOMComponent<raw::IVssSoftwareSnapshotProvider>);
+impl_query_interface!(SoftwareProviderObject => raw::IVssSoftwareSnapshotProvider);
+
+impl SoftwareProviderObject {
+    /// Create a new COM object that forwards all calls to the provided
+    /// implementation.
+    pub fn new<P: SoftwareSnapshotProvider>(provider: P) -> Self {
+        let object = Box::new(ProviderObject {
+            software: raw::IVssSoftwareSnapshotProvider {
+                lpVtbl: &ProviderObject::<P>::SOFTWARE_VTBL,
+            },
+            create_snapshot_set: raw::IVssProviderCreateSnapshotSet {
+                lpVtbl: &CreateSnapshotSetImpl::<ProviderObject<P>>::VTBL,
+            },
+            notifications: raw::IVssProviderNotifications {
+                lpVtbl: &ProviderNotificationsImpl::<ProviderObject<P>>::VTBL,
+            },
+            ref_count: AtomicU32::new(1),
+            provider,
+        });
+        let comp = Box::into_raw(object) as *mut raw::IVssSoftwareSnapshotProvider;
+        // Safety: the object was just created so its reference count is `1`.
+        Self(unsafe { SafeCOMComponent::new(comp) })
+    }
+    /// Get the raw interface pointer without changing its reference count.
+    pub fn as_raw(&self) -> *mut raw::IVssSoftwareSnapshotProvider {
+        &*self.0 as *const _ as *mut _
+    }
+    /// Release ownership of the COM object without decrementing its reference
+    /// count, for example to return it from a COM method.
+    pub fn into_raw(self) -> *mut raw::IVssSoftwareSnapshotProvider {
+        SafeCOMComponent::into_raw(self.0)
+    }
+}
+impl sealed::ServerObjectInternal for SoftwareProviderObject {
+    fn into_unknown(self) -> *mut IUnknown {
+        self.into_raw() as *mut IUnknown
+    }
+}
+impl ServerObject for SoftwareProviderObject {}
+
+/// The memory layout of a [`SoftwareProviderObject`].
+#[repr(C)]
+struct ProviderObject<P> {
+    /// Must be the first field so that a pointer to the object is also a valid
+    /// interface pointer.
+    software: raw::IVssSoftwareSnapshotProvider,
+    create_snapshot_set: raw::IVssProviderCreateSnapshotSet,
+    notifications: raw::IVssProviderNotifications,
+    ref_count: AtomicU32,
+    provider: P,
+}
+// Safety: the struct has the required layout.
+unsafe impl<P: SoftwareSnapshotProvider> ProviderObjectLayout for ProviderObject<P> {
+    type Provider = P;
+
+    fn provider(&self) -> &Self::Provider {
+        &self.provider
+    }
+}
+impl<P: SoftwareSnapshotProvider> ProviderObject<P> {
+    const SOFTWARE_VTBL: raw::IVssSoftwareSnapshotProviderVtbl =
+        raw::IVssSoftwareSnapshotProviderVtbl {
+            parent: IUnknownVtbl {
+                QueryInterface: Self::query_interface,
+                AddRef: Self::add_ref,
+                Release: Self::release,
+            },
+            SetContext: Self::set_context,
+            GetSnapshotProperties: Self::get_snapshot_properties,
+            Query: Self::query,
+            DeleteSnapshots: Self::delete_snapshots,
+            BeginPrepareSnapshot: Self::begin_prepare_snapshot,
+            IsVolumeSupported: Self::is_volume_supported,
+            IsVolumeSnapshotted: Self::is_volume_snapshotted,
+            SetSnapshotProperty: Self::set_snapshot_property,
+            RevertToSnapshot: Self::revert_to_snapshot,
+            QueryRevertStatus: Self::query_revert_status,
+        };
+
+    /// # Safety
+    ///
+    /// The pointer must point to the `software` field of a live
+    /// `ProviderObject<P>`.
+    unsafe fn from_this<'a, T>(this: *mut T) -> &'a Self {
+        &*(this as *const Self)
+    }
+
+    shared_provider_methods!(
+        interface: raw::IVssSoftwareSnapshotProvider,
+        is_supported: is_volume_supported,
+        is_snapshotted: is_volume_snapshotted,
+    );
+
+    unsafe extern "system" fn begin_prepare_snapshot(
+        this: *mut raw::IVssSoftwareSnapshotProvider,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+        volume_name: VSS_PWSZ,
+        new_context: LONG,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(string_arg(volume_name).and_then(|volume_name| {
+                Self::from_this(this).provider.begin_prepare_snapshot(
+                    snapshot_set_id,
+                    snapshot_id,
+                    volume_name,
+                    new_context,
+                )
+            }))
+        })
+    }
+    unsafe extern "system" fn revert_to_snapshot(
+        this: *mut raw::IVssSoftwareSnapshotProvider,
+        snapshot_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(
+                Self::from_this(this)
+                    .provider
+                    .revert_to_snapshot(snapshot_id),
+            )
+        })
+    }
+    unsafe extern "system" fn query_revert_status(
+        this: *mut raw::IVssSoftwareSnapshotProvider,
+        volume: VSS_PWSZ,
+        async_: *mut *mut vss::IVssAsync,
+    ) -> HRESULT {
+        if async_.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            into_hresult(string_arg(volume).and_then(|volume| {
+                let task = Self::from_this(this).provider.query_revert_status(volume)?;
+                async_.write(task.into_raw());
+                Ok(())
+            }))
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssFileShareSnapshotProvider
+////////////////////////////////////////////////////////////////////////////////
+
+/// Implement this trait to create a VSS file share provider. These providers
+/// create shadow copies of file shares, for example for VSS for SMB File
+/// Shares.
+///
+/// The methods are similar to the ones of [`SoftwareSnapshotProvider`] but
+/// take a UNC path to a file share instead of a volume name.
+///
+/// Use [`FileShareProviderObject::new`] to create a COM object that forwards
+/// calls to an implementation of this trait.
+#[doc(alias = "IVssFileShareSnapshotProvider")]
+pub trait FileShareSnapshotProvider: CreateSnapshotSet + ProviderNotifications {
+    /// Sets the context for subsequent shadow copy-related operations.
+    #[doc(alias = "SetContext")]
+    fn set_context(&self, context: LONG) -> Result<(), HRESULT>;
+    /// Gets the properties of the specified shadow copy.
+    #[doc(alias = "GetSnapshotProperties")]
+    fn get_snapshot_properties(&self, snapshot_id: VSS_ID) -> Result<SnapshotProperties, HRESULT>;
+    /// Queries the provider for information about the shadow copies that the
+    /// provider has completed.
+    #[doc(alias = "Query")]
+    fn query(
+        &self,
+        queried_object_id: VSS_ID,
+        queried_object_type: ObjectType,
+        returned_objects_type: ObjectType,
+    ) -> Result<Vec<ObjectProperties>, HRESULT>;
+    /// Deletes one or more shadow copies or a shadow copy set.
+    #[doc(alias = "DeleteSnapshots")]
+    fn delete_snapshots(
+        &self,
+        source_object_id: VSS_ID,
+        source_object_type: ObjectType,
+        force_delete: bool,
+    ) -> DeleteSnapshotsInfo;
+    /// Adds a file share to the shadow copy set.
+    #[doc(alias = "BeginPrepareSnapshot")]
+    fn begin_prepare_snapshot(
+        &self,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+        share_path: &U16CStr,
+        new_context: LONG,
+        provider_id: VSS_ID,
+    ) -> Result<(), HRESULT>;
+    /// Determines whether the provider supports shadow copies on the specified
+    /// file share.
+    #[doc(alias = "IsPathSupported")]
+    fn is_path_supported(&self, share_path: &U16CStr) -> Result<bool, HRESULT>;
+    /// Determines whether any shadow copies exist for the specified file
+    /// share.
+    #[doc(alias = "IsPathSnapshotted")]
+    fn is_path_snapshotted(&self, share_path: &U16CStr) -> Result<VolumeSnapshottedInfo, HRESULT>;
+    /// Sets a property for a shadow copy.
+    #[doc(alias = "SetSnapshotProperty")]
+    fn set_snapshot_property(
+        &self,
+        snapshot_id: VSS_ID,
+        property_id: SnapshotPropertyId,
+        value: &VARIANT,
+    ) -> Result<(), HRESULT>;
+}
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssFileShareSnapshotProvider);
+
+/// A COM object that implements the `IVssFileShareSnapshotProvider`,
+/// `IVssProviderCreateSnapshotSet` and `IVssProviderNotifications` interfaces
+/// by forwarding calls to a [`FileShareSnapshotProvider`].
+#[doc(alias = "IVssFileShareSnapshotProvider")]
+#[derive(Debug, Clone)]
+pub struct FileShareProviderObject(SafeCOMComponent<raw::IVssFileShareSnapshotProvider>);
+impl_query_interface!(FileShareProviderObject => raw::IVssFileShareSnapshotProvider);
+
+impl FileShareProviderObject {
+    /// Create a new COM object that forwards all calls to the provided
+    /// implementation.
+    pub fn new<P: FileShareSnapshotProvider>(provider: P) -> Self {
+        let object = Box::new(FileShareObject {
+            file_share: raw::IVssFileShareSnapshotProvider {
+                lpVtbl: &FileShareObject::<P>::FILE_SHARE_VTBL,
+            },
+            create_snapshot_set: raw::IVssProviderCreateSnapshotSet {
+                lpVtbl: &CreateSnapshotSetImpl::<FileShareObject<P>>::VTBL,
+            },
+            notifications: raw::IVssProviderNotifications {
+                lpVtbl: &ProviderNotificationsImpl::<FileShareObject<P>>::VTBL,
+            },
+            ref_count: AtomicU32::new(1),
+            provider,
+        });
+        let comp = Box::into_raw(object) as *mut raw::IVssFileShareSnapshotProvider;
+        // Safety: the object was just created so its reference count is `1`.
+        Self(unsafe { SafeCOMComponent::new(comp) })
+    }
+    /// Get the raw interface pointer without changing its reference count.
+    pub fn as_raw(&self) -> *mut raw::IVssFileShareSnapshotProvider {
+        &*self.0 as *const _ as *mut _
+    }
+    /// Release ownership of the COM object without decrementing its reference
+    /// count, for example to return it from a COM method.
+    pub fn into_raw(self) -> *mut raw::IVssFileShareSnapshotProvider {
+        SafeCOMComponent::into_raw(self.0)
+    }
+}
+impl sealed::ServerObjectInternal for FileShareProviderObject {
+    fn into_unknown(self) -> *mut IUnknown {
+        self.into_raw() as *mut IUnknown
+    }
+}
+impl ServerObject for FileShareProviderObject {}
+
+/// The memory layout of a [`FileShareProviderObject`].
+#[repr(C)]
+struct FileShareObject<P> {
+    /// Must be the first field so that a pointer to the object is also a valid
+    /// interface pointer.
+    file_share: raw::IVssFileShareSnapshotProvider,
+    create_snapshot_set: raw::IVssProviderCreateSnapshotSet,
+    notifications: raw::IVssProviderNotifications,
+    ref_count: AtomicU32,
+    provider: P,
+}
+// Safety: the struct has the required layout.
+unsafe impl<P: FileShareSnapshotProvider> ProviderObjectLayout for FileShareObject<P> {
+    type Provider = P;
+
+    fn provider(&self) -> &Self::Provider {
+        &self.provider
+    }
+}
+impl<P: FileShareSnapshotProvider> FileShareObject<P> {
+    const FILE_SHARE_VTBL: raw::IVssFileShareSnapshotProviderVtbl =
+        raw::IVssFileShareSnapshotProviderVtbl {
+            parent: IUnknownVtbl {
+                QueryInterface: Self::query_interface,
+                AddRef: Self::add_ref,
+                Release: Self::release,
+            },
+            SetContext: Self::set_context,
+            GetSnapshotProperties: Self::get_snapshot_properties,
+            Query: Self::query,
+            DeleteSnapshots: Self::delete_snapshots,
+            BeginPrepareSnapshot: Self::begin_prepare_snapshot,
+            IsPathSupported: Self::is_path_supported,
+            IsPathSnapshotted: Self::is_path_snapshotted,
+            SetSnapshotProperty: Self::set_snapshot_property,
+        };
+
+    /// # Safety
+    ///
+    /// The pointer must point to the `file_share` field of a live
+    /// `FileShareObject<P>`.
+    unsafe fn from_this<'a, T>(this: *mut T) -> &'a Self {
+        &*(this as *const Self)
+    }
+
+    shared_provider_methods!(
+        interface: raw::IVssFileShareSnapshotProvider,
+        is_supported: is_path_supported,
+        is_snapshotted: is_path_snapshotted,
+    );
+
+    unsafe extern "system" fn begin_prepare_snapshot(
+        this: *mut raw::IVssFileShareSnapshotProvider,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+        share_path: VSS_PWSZ,
+        new_context: LONG,
+        provider_id: VSS_ID,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(string_arg(share_path).and_then(|share_path| {
+                Self::from_this(this).provider.begin_prepare_snapshot(
+                    snapshot_set_id,
+                    snapshot_id,
+                    share_path,
+                    new_context,
+                    provider_id,
+                )
+            }))
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// VDS_LUN_INFORMATION
+////////////////////////////////////////////////////////////////////////////////
+
+/// Information about a LUN. Used by hardware providers to identify the LUNs
+/// that make up a volume.
+///
+/// The strings and arrays inside the struct are allocated with
+/// `CoTaskMemAlloc` and are owned by VSS, so this type never frees them.
+#[doc(alias = "VDS_LUN_INFORMATION")]
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct LunInformation(pub raw::VDS_LUN_INFORMATION);
+/// Field getters.
+impl LunInformation {
+    /// The version of this structure.
+    #[doc(alias = "m_version")]
+    pub fn version(&self) -> u32 {
+        self.0.m_version
+    }
+    /// The SCSI-2 device type of the LUN.
+    #[doc(alias = "m_DeviceType")]
+    pub fn device_type(&self) -> u8 {
+        self.0.m_DeviceType
+    }
+    /// The SCSI-2 device type modifier of the LUN, if any.
+    #[doc(alias = "m_DeviceTypeModifier")]
+    pub fn device_type_modifier(&self) -> u8 {
+        self.0.m_DeviceTypeModifier
+    }
+    /// `true` if the LUN supports multiple outstanding commands.
+    #[doc(alias = "m_bCommandQueueing")]
+    pub fn command_queueing(&self) -> bool {
+        self.0.m_bCommandQueueing != FALSE
+    }
+    /// The raw value of the `VDS_STORAGE_BUS_TYPE` enumeration that specifies
+    /// the bus type of the LUN.
+    #[doc(alias = "m_BusType")]
+    pub fn bus_type(&self) -> u32 {
+        self.0.m_BusType
+    }
+    /// The vendor identifier of the LUN.
+    #[doc(alias = "m_szVendorId")]
+    pub fn vendor_id(&self) -> Option<&CStr> {
+        unsafe { nullable_c_str(self.0.m_szVendorId) }
+    }
+    /// The product identifier of the LUN.
+    #[doc(alias = "m_szProductId")]
+    pub fn product_id(&self) -> Option<&CStr> {
+        unsafe { nullable_c_str(self.0.m_szProductId) }
+    }
+    /// The product revision of the LUN.
+    #[doc(alias = "m_szProductRevision")]
+    pub fn product_revision(&self) -> Option<&CStr> {
+        unsafe { nullable_c_str(self.0.m_szProductRevision) }
+    }
+    /// The serial number of the LUN.
+    #[doc(alias = "m_szSerialNumber")]
+    pub fn serial_number(&self) -> Option<&CStr> {
+        unsafe { nullable_c_str(self.0.m_szSerialNumber) }
+    }
+    /// The disk signature of the LUN.
+    #[doc(alias = "m_diskSignature")]
+    pub fn disk_signature(&self) -> GUID {
+        self.0.m_diskSignature
+    }
+}
+impl LunInformation {
+    /// # Safety
+    ///
+    /// The pointer must be valid for `count` elements or `count` must be zero.
+    unsafe fn from_raw_slice<'a>(
+        luns: *mut raw::VDS_LUN_INFORMATION,
+        count: usize,
+    ) -> &'a mut [Self] {
+        if count == 0 || luns.is_null() {
+            &mut []
+        } else {
+            slice::from_raw_parts_mut(luns as *mut Self, count)
+        }
+    }
+    fn as_raw_ptr(luns: &mut [Self]) -> *mut raw::VDS_LUN_INFORMATION {
+        luns.as_mut_ptr() as *mut raw::VDS_LUN_INFORMATION
+    }
+}
+
+/// # Safety
+///
+/// The pointer must be null or point to a nul-terminated string.
+unsafe fn nullable_c_str<'a>(string: *mut c_char) -> Option<&'a CStr> {
+    if string.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(string))
+    }
+}
+
+/// Collect the pointers of some strings so they can be passed to a COM method.
+fn device_name_ptrs(device_names: &[&U16CStr]) -> Vec<VSS_PWSZ> {
+    device_names
+        .iter()
+        .map(|name| name.as_ptr() as VSS_PWSZ)
+        .collect()
+}
+
+/// # Safety
+///
+/// The pointer must be valid for `count` elements or `count` must be zero and
+/// each element must be a nul-terminated string.
+unsafe fn device_names_arg<'a>(
+    device_names: *mut VSS_PWSZ,
+    count: LONG,
+) -> Result<Vec<&'a U16CStr>, HRESULT> {
+    let count = usize::try_from(count).map_err(|_| E_INVALIDARG)?;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    if device_names.is_null() {
+        return Err(E_INVALIDARG);
+    }
+    slice::from_raw_parts(device_names, count)
+        .iter()
+        .map(|&name| string_arg(name))
+        .collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssHardwareSnapshotProvider
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssHardwareSnapshotProvider);
+
+transparent_wrapper!(
+    #[doc(alias = "IVssHardwareSnapshotProvider")]
+    pub struct IHardwareSnapshotProvider(raw::IVssHardwareSnapshotProvider);
+);
+
+impl IHardwareSnapshotProvider {
+    /// Determines whether the hardware provider supports shadow copy creation
+    /// for all LUNs that contribute to the volume.
+    ///
+    /// # Panics
+    ///
+    /// If `devices` and `luns` have different lengths.
+    #[doc(alias = "AreLunsSupported")]
+    pub fn are_luns_supported(
+        &self,
+        context: LONG,
+        devices: &[&U16CStr],
+        luns: &mut [LunInformation],
+    ) -> Result<bool, AreLunsSupportedError> {
+        trace_com_call!("AreLunsSupported");
+        assert_eq!(devices.len(), luns.len());
+        let mut devices = device_name_ptrs(devices);
+        let mut is_supported: BOOL = FALSE;
+        check_com(unsafe {
+            self.0.AreLunsSupported(
+                luns.len().try_into().unwrap(),
+                context,
+                devices.as_mut_ptr(),
+                LunInformation::as_raw_ptr(luns),
+                &mut is_supported,
+            )
+        })?;
+        Ok(is_supported != FALSE)
+    }
+    /// Prompts the hardware provider to indicate whether it supports the
+    /// corresponding disk device and correct any omissions in the LUN
+    /// information.
+    #[doc(alias = "FillInLunInfo")]
+    pub fn fill_in_lun_info(
+        &self,
+        device_name: &U16CStr,
+        lun: &mut LunInformation,
+    ) -> Result<bool, FillInLunInfoError> {
+        trace_com_call!("FillInLunInfo");
+        let mut is_supported: BOOL = FALSE;
+        check_com(unsafe {
+            self.0.FillInLunInfo(
+                device_name.as_ptr() as *mut _,
+                &mut lun.0,
+                &mut is_supported,
+            )
+        })?;
+        Ok(is_supported != FALSE)
+    }
+    /// Adds LUNs to the shadow copy set.
+    ///
+    /// # Panics
+    ///
+    /// If `device_names` and `luns` have different lengths.
+    #[doc(alias = "BeginPrepareSnapshot")]
+    pub fn begin_prepare_snapshot(
+        &self,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+        context: LONG,
+        device_names: &[&U16CStr],
+        luns: &mut [LunInformation],
+    ) -> Result<(), HardwareSnapshotProviderBeginPrepareSnapshotError> {
+        trace_com_call!("BeginPrepareSnapshot");
+        assert_eq!(device_names.len(), luns.len());
+        let mut device_names = device_name_ptrs(device_names);
+        check_com(unsafe {
+            self.0.BeginPrepareSnapshot(
+                snapshot_set_id,
+                snapshot_id,
+                context,
+                luns.len().try_into().unwrap(),
+                device_names.as_mut_ptr(),
+                LunInformation::as_raw_ptr(luns),
+            )
+        })?;
+        Ok(())
+    }
+    /// Gets the hardware identification information for each newly created
+    /// LUN.
+    ///
+    /// # Panics
+    ///
+    /// If the slices have different lengths.
+    #[doc(alias = "GetTargetLuns")]
+    pub fn get_target_luns(
+        &self,
+        device_names: &[&U16CStr],
+        source_luns: &mut [LunInformation],
+        destination_luns: &mut [LunInformation],
+    ) -> Result<(), GetTargetLunsError> {
+        trace_com_call!("GetTargetLuns");
+        assert_eq!(device_names.len(), source_luns.len());
+        assert_eq!(device_names.len(), destination_luns.len());
+        let mut device_names = device_name_ptrs(device_names);
+        check_com(unsafe {
+            self.0.GetTargetLuns(
+                source_luns.len().try_into().unwrap(),
+                device_names.as_mut_ptr(),
+                LunInformation::as_raw_ptr(source_luns),
+                LunInformation::as_raw_ptr(destination_luns),
+            )
+        })?;
+        Ok(())
+    }
+    /// Performs any necessary RAID subsystem unmasking and zoning operations
+    /// so that the LUNs of a transported shadow copy become visible.
+    #[doc(alias = "LocateLuns")]
+    pub fn locate_luns(&self, source_luns: &mut [LunInformation]) -> Result<(), LocateLunsError> {
+        trace_com_call!("LocateLuns");
+        check_com(unsafe {
+            self.0.LocateLuns(
+                source_luns.len().try_into().unwrap(),
+                LunInformation::as_raw_ptr(source_luns),
+            )
+        })?;
+        Ok(())
+    }
+    /// Notifies the hardware provider that a LUN no longer contains any shadow
+    /// copies and can be freed.
+    #[doc(alias = "OnLunEmpty")]
+    pub fn on_lun_empty(
+        &self,
+        device_name: &U16CStr,
+        lun: &mut LunInformation,
+    ) -> Result<(), OnLunEmptyError> {
+        trace_com_call!("OnLunEmpty");
+        check_com(unsafe {
+            self.0
+                .OnLunEmpty(device_name.as_ptr() as *mut _, &mut lun.0)
+        })?;
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssHardwareSnapshotProviderEx
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssHardwareSnapshotProviderEx);
+
+/// A hardware provider. This can be used to call into a hardware provider
+/// implementation, for example one created with [`HardwareProviderObject`].
+#[doc(alias = "IVssHardwareSnapshotProviderEx")]
+#[derive(Debug, Clone)]
+pub struct HardwareSnapshotProviderEx(SafeCOMComponent<raw::IVssHardwareSnapshotProviderEx>);
+impl_query_interface!(HardwareSnapshotProviderEx => raw::IVssHardwareSnapshotProviderEx);
+transparent_wrapper!(
+    #[doc(alias = "IVssHardwareSnapshotProviderEx")]
+    pub struct IHardwareSnapshotProviderEx(raw::IVssHardwareSnapshotProviderEx);
+);
+// Safety: all wrappers ensure their wrapped values are valid to use (Not released).
+unsafe_deref_to_ref!(HardwareSnapshotProviderEx => IHardwareSnapshotProviderEx => IHardwareSnapshotProvider);
+
+impl IHardwareSnapshotProviderEx {
+    /// Gets a mask of the capabilities of the hardware provider.
+    #[doc(alias = "GetProviderCapabilities")]
+    pub fn get_provider_capabilities(
+        &self,
+    ) -> Result<RawBitFlags<ProviderCapabilities>, GetProviderCapabilitiesError> {
+        trace_com_call!("GetProviderCapabilities");
+        let mut mask: ULONGLONG = 0;
+        check_com(unsafe { self.0.GetProviderCapabilities(&mut mask) })?;
+        Ok(RawBitFlags::from_raw(mask))
+    }
+    /// Notifies the provider that the state of the LUNs changed, for example
+    /// after a shadow copy was broken or reverted.
+    ///
+    /// # Panics
+    ///
+    /// If the slices have different lengths.
+    #[doc(alias = "OnLunStateChange")]
+    pub fn on_lun_state_change(
+        &self,
+        snapshot_luns: &mut [LunInformation],
+        original_luns: &mut [LunInformation],
+        flags: RawBitFlags<HardwareOptions>,
+    ) -> Result<(), OnLunStateChangeError> {
+        trace_com_call!("OnLunStateChange");
+        assert_eq!(snapshot_luns.len(), original_luns.len());
+        check_com(unsafe {
+            self.0.OnLunStateChange(
+                LunInformation::as_raw_ptr(snapshot_luns),
+                LunInformation::as_raw_ptr(original_luns),
+                snapshot_luns.len().try_into().unwrap(),
+                flags.raw(),
+            )
+        })?;
+        Ok(())
+    }
+    /// Starts the resynchronization of the contents of the target LUNs with
+    /// the contents of the source LUNs.
+    ///
+    /// # Panics
+    ///
+    /// If the slices have different lengths.
+    #[doc(alias = "ResyncLuns")]
+    pub fn resync_luns(
+        &self,
+        source_luns: &mut [LunInformation],
+        target_luns: &mut [LunInformation],
+    ) -> IVssAsyncResult<ResyncLunsError> {
+        trace_com_call!("ResyncLuns");
+        assert_eq!(source_luns.len(), target_luns.len());
+        let mut task = ptr::null_mut::<vss::IVssAsync>();
+        check_com(unsafe {
+            self.0.ResyncLuns(
+                LunInformation::as_raw_ptr(source_luns),
+                LunInformation::as_raw_ptr(target_luns),
+                source_luns.len().try_into().unwrap(),
+                &mut task,
+            )
+        })?;
+        Ok(VssAsync::new(unsafe { SafeCOMComponent::new(task) }))
+    }
+    /// Notifies the provider that the LUNs of a shadow copy will be reused,
+    /// for example because the shadow copy is being deleted.
+    ///
+    /// # Panics
+    ///
+    /// If the slices have different lengths.
+    #[doc(alias = "OnReuseLuns")]
+    pub fn on_reuse_luns(
+        &self,
+        snapshot_luns: &mut [LunInformation],
+        original_luns: &mut [LunInformation],
+    ) -> Result<(), OnReuseLunsError> {
+        trace_com_call!("OnReuseLuns");
+        assert_eq!(snapshot_luns.len(), original_luns.len());
+        check_com(unsafe {
+            self.0.OnReuseLuns(
+                LunInformation::as_raw_ptr(snapshot_luns),
+                LunInformation::as_raw_ptr(original_luns),
+                snapshot_luns.len().try_into().unwrap(),
+            )
+        })?;
+        Ok(())
+    }
+}
+
+/// Implement this trait to create a VSS hardware provider.
+///
+/// The methods mirror the `IVssHardwareSnapshotProvider` and
+/// `IVssHardwareSnapshotProviderEx` interfaces. All methods return a raw
+/// `HRESULT` on failure which will be forwarded to VSS.
+///
+/// Use [`HardwareProviderObject::new`] to create a COM object that forwards
+/// calls to an implementation of this trait.
+#[doc(alias = "IVssHardwareSnapshotProvider")]
+#[doc(alias = "IVssHardwareSnapshotProviderEx")]
+pub trait HardwareSnapshotProvider: CreateSnapshotSet + ProviderNotifications {
+    /// Determines whether the provider supports shadow copy creation for all
+    /// LUNs that contribute to the volume.
+    #[doc(alias = "AreLunsSupported")]
+    fn are_luns_supported(
+        &self,
+        context: LONG,
+        devices: &[&U16CStr],
+        luns: &mut [LunInformation],
+    ) -> Result<bool, HRESULT>;
+    /// Indicate whether the provider supports the corresponding disk device
+    /// and correct any omissions in the LUN information.
+    #[doc(alias = "FillInLunInfo")]
+    fn fill_in_lun_info(
+        &self,
+        device_name: &U16CStr,
+        lun: &mut LunInformation,
+    ) -> Result<bool, HRESULT>;
+    /// Adds LUNs to the shadow copy set.
+    #[doc(alias = "BeginPrepareSnapshot")]
+    fn begin_prepare_snapshot(
+        &self,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+        context: LONG,
+        device_names: &[&U16CStr],
+        luns: &mut [LunInformation],
+    ) -> Result<(), HRESULT>;
+    /// Fill in the hardware identification information for each newly created
+    /// LUN.
+    #[doc(alias = "GetTargetLuns")]
+    fn get_target_luns(
+        &self,
+        device_names: &[&U16CStr],
+        source_luns: &[LunInformation],
+        destination_luns: &mut [LunInformation],
+    ) -> Result<(), HRESULT>;
+    /// Make the LUNs of a transported shadow copy visible to the computer.
+    #[doc(alias = "LocateLuns")]
+    fn locate_luns(&self, source_luns: &[LunInformation]) -> Result<(), HRESULT>;
+    /// A LUN no longer contains any shadow copies and can be freed.
+    #[doc(alias = "OnLunEmpty")]
+    fn on_lun_empty(&self, device_name: &U16CStr, lun: &LunInformation) -> Result<(), HRESULT>;
+    /// Gets a mask of the capabilities of the provider.
+    #[doc(alias = "GetProviderCapabilities")]
+    fn get_provider_capabilities(&self) -> Result<RawBitFlags<ProviderCapabilities>, HRESULT>;
+    /// The state of the LUNs changed.
+    ///
+    /// The default implementation returns `E_NOTIMPL`.
+    #[doc(alias = "OnLunStateChange")]
+    fn on_lun_state_change(
+        &self,
+        snapshot_luns: &mut [LunInformation],
+        original_luns: &mut [LunInformation],
+        flags: RawBitFlags<HardwareOptions>,
+    ) -> Result<(), HRESULT> {
+        let _ = (snapshot_luns, original_luns, flags);
+        Err(E_NOTIMPL)
+    }
+    /// Resynchronize the contents of the target LUNs with the contents of the
+    /// source LUNs.
+    ///
+    /// The default implementation returns `E_NOTIMPL`.
+    #[doc(alias = "ResyncLuns")]
+    fn resync_luns(
+        &self,
+        source_luns: &mut [LunInformation],
+        target_luns: &mut [LunInformation],
+    ) -> Result<VssAsync<HRESULT>, HRESULT> {
+        let _ = (source_luns, target_luns);
+        Err(E_NOTIMPL)
+    }
+    /// The LUNs of a shadow copy will be reused.
+    ///
+    /// The default implementation returns `E_NOTIMPL`.
+    #[doc(alias = "OnReuseLuns")]
+    fn on_reuse_luns(
+        &self,
+        snapshot_luns: &mut [LunInformation],
+        original_luns: &mut [LunInformation],
+    ) -> Result<(), HRESULT> {
+        let _ = (snapshot_luns, original_luns);
+        Err(E_NOTIMPL)
+    }
+}
+
+/// A COM object that implements the `IVssHardwareSnapshotProvider`,
+/// `IVssHardwareSnapshotProviderEx`, `IVssProviderCreateSnapshotSet` and
+/// `IVssProviderNotifications` interfaces by forwarding calls to a
+/// [`HardwareSnapshotProvider`].
+#[derive(Debug, Clone)]
+pub struct HardwareProviderObject(SafeCOMComponent<raw::IVssHardwareSnapshotProviderEx>);
+impl_query_interface!(HardwareProviderObject => raw::IVssHardwareSnapshotProviderEx);
+
+impl HardwareProviderObject {
+    /// Create a new COM object that forwards all calls to the provided
+    /// implementation.
+    pub fn new<P: HardwareSnapshotProvider>(provider: P) -> Self {
+        let object = Box::new(HardwareObject {
+            hardware: raw::IVssHardwareSnapshotProviderEx {
+                lpVtbl: &HardwareObject::<P>::HARDWARE_VTBL,
+            },
+            create_snapshot_set: raw::IVssProviderCreateSnapshotSet {
+                lpVtbl: &CreateSnapshotSetImpl::<HardwareObject<P>>::VTBL,
+            },
+            notifications: raw::IVssProviderNotifications {
+                lpVtbl: &ProviderNotificationsImpl::<HardwareObject<P>>::VTBL,
+            },
+            ref_count: AtomicU32::new(1),
+            provider,
+        });
+        let comp = Box::into_raw(object) as *mut raw::IVssHardwareSnapshotProviderEx;
+        // Safety: the object was just created so its reference count is `1`.
+        Self(unsafe { SafeCOMComponent::new(comp) })
+    }
+    /// Get the raw interface pointer without changing its reference count.
+    pub fn as_raw(&self) -> *mut raw::IVssHardwareSnapshotProviderEx {
+        &*self.0 as *const _ as *mut _
+    }
+    /// Release ownership of the COM object without decrementing its reference
+    /// count, for example to return it from a COM method.
+    pub fn into_raw(self) -> *mut raw::IVssHardwareSnapshotProviderEx {
+        SafeCOMComponent::into_raw(self.0)
+    }
+    /// Use the object through the safe consumer-side wrapper.
+    pub fn as_interface(&self) -> &IHardwareSnapshotProviderEx {
+        // Safety: the wrapped object is valid for as long as `self` is alive.
+        unsafe { IHardwareSnapshotProviderEx::from_inner_ref(&self.0) }
+    }
+}
+impl sealed::ServerObjectInternal for HardwareProviderObject {
+    fn into_unknown(self) -> *mut IUnknown {
+        self.into_raw() as *mut IUnknown
+    }
+}
+impl ServerObject for HardwareProviderObject {}
+
+/// The memory layout of a [`HardwareProviderObject`].
+#[repr(C)]
+struct HardwareObject<P> {
+    /// Must be the first field so that a pointer to the object is also a valid
+    /// interface pointer.
+    hardware: raw::IVssHardwareSnapshotProviderEx,
+    create_snapshot_set: raw::IVssProviderCreateSnapshotSet,
+    notifications: raw::IVssProviderNotifications,
+    ref_count: AtomicU32,
+    provider: P,
+}
+// Safety: the struct has the required layout.
+unsafe impl<P: HardwareSnapshotProvider> ProviderObjectLayout for HardwareObject<P> {
+    type Provider = P;
+
+    fn provider(&self) -> &Self::Provider {
+        &self.provider
+    }
+}
+impl<P: HardwareSnapshotProvider> HardwareObject<P> {
+    const HARDWARE_VTBL: raw::IVssHardwareSnapshotProviderExVtbl =
+        raw::IVssHardwareSnapshotProviderExVtbl {
+            parent: raw::IVssHardwareSnapshotProviderVtbl {
+                parent: IUnknownVtbl {
+                    QueryInterface: Self::query_interface,
+                    AddRef: Self::add_ref,
+                    Release: Self::release,
+                },
+                AreLunsSupported: Self::are_luns_supported,
+                FillInLunInfo: Self::fill_in_lun_info,
+                BeginPrepareSnapshot: Self::begin_prepare_snapshot,
+                GetTargetLuns: Self::get_target_luns,
+                LocateLuns: Self::locate_luns,
+                OnLunEmpty: Self::on_lun_empty,
+            },
+            GetProviderCapabilities: Self::get_provider_capabilities,
+            OnLunStateChange: Self::on_lun_state_change,
+            ResyncLuns: Self::resync_luns,
+            OnReuseLuns: Self::on_reuse_luns,
+        };
+
+    /// # Safety
+    ///
+    /// The pointer must point to the `hardware` field of a live
+    /// `HardwareObject<P>`.
+    unsafe fn from_this<'a, T>(this: *mut T) -> &'a Self {
+        &*(this as *const Self)
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        query_provider_interface(
+            this,
+            riid,
+            object,
+            &[
+                raw::IVssHardwareSnapshotProvider::uuidof(),
+                raw::IVssHardwareSnapshotProviderEx::uuidof(),
+            ],
+        )
+    }
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+        add_ref(&Self::from_this(this).ref_count)
+    }
+    unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+        let count = release(&Self::from_this(this).ref_count);
+        if count == 0 {
+            drop(Box::from_raw(this as *mut Self));
+        }
+        count
+    }
+
+    unsafe extern "system" fn are_luns_supported(
+        this: *mut raw::IVssHardwareSnapshotProvider,
+        lun_count: LONG,
+        context: LONG,
+        devices: *mut VSS_PWSZ,
+        luns: *mut raw::VDS_LUN_INFORMATION,
+        is_supported: *mut BOOL,
+    ) -> HRESULT {
+        if is_supported.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            into_hresult(device_names_arg(devices, lun_count).and_then(|devices| {
+                let luns = LunInformation::from_raw_slice(luns, devices.len());
+                let supported = Self::from_this(this)
+                    .provider
+                    .are_luns_supported(context, &devices, luns)?;
+                is_supported.write(if supported { TRUE } else { FALSE });
+                Ok(())
+            }))
+        })
+    }
+    unsafe extern "system" fn fill_in_lun_info(
+        this: *mut raw::IVssHardwareSnapshotProvider,
+        device_name: VSS_PWSZ,
+        lun: *mut raw::VDS_LUN_INFORMATION,
+        is_supported: *mut BOOL,
+    ) -> HRESULT {
+        if lun.is_null() || is_supported.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            into_hresult(string_arg(device_name).and_then(|device_name| {
+                let lun = &mut *(lun as *mut LunInformation);
+                let supported = Self::from_this(this)
+                    .provider
+                    .fill_in_lun_info(device_name, lun)?;
+                is_supported.write(if supported { TRUE } else { FALSE });
+                Ok(())
+            }))
+        })
+    }
+    unsafe extern "system" fn begin_prepare_snapshot(
+        this: *mut raw::IVssHardwareSnapshotProvider,
+        snapshot_set_id: VSS_ID,
+        snapshot_id: VSS_ID,
+        context: LONG,
+        lun_count: LONG,
+        device_names: *mut VSS_PWSZ,
+        luns: *mut raw::VDS_LUN_INFORMATION,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(
+                device_names_arg(device_names, lun_count).and_then(|device_names| {
+                    let luns = LunInformation::from_raw_slice(luns, device_names.len());
+                    Self::from_this(this).provider.begin_prepare_snapshot(
+                        snapshot_set_id,
+                        snapshot_id,
+                        context,
+                        &device_names,
+                        luns,
+                    )
+                }),
+            )
+        })
+    }
+    unsafe extern "system" fn get_target_luns(
+        this: *mut raw::IVssHardwareSnapshotProvider,
+        lun_count: LONG,
+        device_names: *mut VSS_PWSZ,
+        source_luns: *mut raw::VDS_LUN_INFORMATION,
+        destination_luns: *mut raw::VDS_LUN_INFORMATION,
+    ) -> HRESULT {
+        catch_panic(|| {
+            into_hresult(
+                device_names_arg(device_names, lun_count).and_then(|device_names| {
+                    let source_luns =
+                        LunInformation::from_raw_slice(source_luns, device_names.len());
+                    let destination_luns =
+                        LunInformation::from_raw_slice(destination_luns, device_names.len());
+                    Self::from_this(this).provider.get_target_luns(
+                        &device_names,
+                        source_luns,
+                        destination_luns,
+                    )
+                }),
+            )
+        })
+    }
+    unsafe extern "system" fn locate_luns(
+        this: *mut raw::IVssHardwareSnapshotProvider,
+        lun_count: LONG,
+        source_luns: *mut raw::VDS_LUN_INFORMATION,
+    ) -> HRESULT {
+        let lun_count = match usize::try_from(lun_count) {
+            Ok(count) => count,
+            Err(_) => return E_INVALIDARG,
+        };
+        catch_panic(|| {
+            let source_luns = LunInformation::from_raw_slice(source_luns, lun_count);
+            into_hresult(Self::from_this(this).provider.locate_luns(source_luns))
+        })
+    }
+    unsafe extern "system" fn on_lun_empty(
+        this: *mut raw::IVssHardwareSnapshotProvider,
+        device_name: VSS_PWSZ,
+        lun: *mut raw::VDS_LUN_INFORMATION,
+    ) -> HRESULT {
+        if lun.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            into_hresult(string_arg(device_name).and_then(|device_name| {
+                let lun = &*(lun as *const LunInformation);
+                Self::from_this(this)
+                    .provider
+                    .on_lun_empty(device_name, lun)
+            }))
+        })
+    }
+    unsafe extern "system" fn get_provider_capabilities(
+        this: *mut raw::IVssHardwareSnapshotProviderEx,
+        mask: *mut ULONGLONG,
+    ) -> HRESULT {
+        if mask.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            into_hresult(
+                Self::from_this(this)
+                    .provider
+                    .get_provider_capabilities()
+                    .map(|capabilities| mask.write(capabilities.raw())),
+            )
+        })
+    }
+    unsafe extern "system" fn on_lun_state_change(
+        this: *mut raw::IVssHardwareSnapshotProviderEx,
+        snapshot_luns: *mut raw::VDS_LUN_INFORMATION,
+        original_luns: *mut raw::VDS_LUN_INFORMATION,
+        count: DWORD,
+        flags: DWORD,
+    ) -> HRESULT {
+        catch_panic(|| {
+            let snapshot_luns = LunInformation::from_raw_slice(snapshot_luns, count as usize);
+            let original_luns = LunInformation::from_raw_slice(original_luns, count as usize);
+            into_hresult(Self::from_this(this).provider.on_lun_state_change(
+                snapshot_luns,
+                original_luns,
+                RawBitFlags::from_raw(flags),
+            ))
+        })
+    }
+    unsafe extern "system" fn resync_luns(
+        this: *mut raw::IVssHardwareSnapshotProviderEx,
+        source_luns: *mut raw::VDS_LUN_INFORMATION,
+        target_luns: *mut raw::VDS_LUN_INFORMATION,
+        count: DWORD,
+        async_: *mut *mut vss::IVssAsync,
+    ) -> HRESULT {
+        if async_.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            let source_luns = LunInformation::from_raw_slice(source_luns, count as usize);
+            let target_luns = LunInformation::from_raw_slice(target_luns, count as usize);
+            into_hresult(
+                Self::from_this(this)
+                    .provider
+                    .resync_luns(source_luns, target_luns)
+                    .map(|task| async_.write(task.into_raw())),
+            )
+        })
+    }
+    unsafe extern "system" fn on_reuse_luns(
+        this: *mut raw::IVssHardwareSnapshotProviderEx,
+        snapshot_luns: *mut raw::VDS_LUN_INFORMATION,
+        original_luns: *mut raw::VDS_LUN_INFORMATION,
+        count: DWORD,
+    ) -> HRESULT {
+        catch_panic(|| {
+            let snapshot_luns = LunInformation::from_raw_slice(snapshot_luns, count as usize);
+            let original_luns = LunInformation::from_raw_slice(original_luns, count as usize);
+            into_hresult(
+                Self::from_this(this)
+                    .provider
+                    .on_reuse_luns(snapshot_luns, original_luns),
+            )
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssEnumObject implementation
+////////////////////////////////////////////////////////////////////////////////
+
+impl EnumObject {
+    /// Create an enumerator that is implemented in Rust and that will return
+    /// copies of the provided objects. This can be used to return objects from
+    /// a provider's [`SoftwareSnapshotProvider::query`] method.
+    pub fn from_objects(objects: Vec<ObjectProperties>) -> Self {
+        let object = Box::new(EnumObjectImpl {
+            enumerator: vss::IVssEnumObject {
+                lpVtbl: &EnumObjectImpl::VTBL,
+            },
+            ref_count: AtomicU32::new(1),
+            state: Mutex::new(EnumObjectState {
+                objects,
+                position: 0,
+            }),
+        });
+        let comp = Box::into_raw(object) as *mut vss::IVssEnumObject;
+        // Safety: the object was just created so its reference count is `1`.
+        EnumObject(unsafe { SafeCOMComponent::new(comp) })
+    }
+}
+
+struct EnumObjectState {
+    objects: Vec<ObjectProperties>,
+    position: usize,
+}
+// Safety: the objects only contain memory allocated with `CoTaskMemAlloc`
+// which can be freed from any thread.
+unsafe impl Send for EnumObjectState {}
+
+/// The memory layout of a COM object created by [`EnumObject::from_objects`].
+#[repr(C)]
+struct EnumObjectImpl {
+    /// Must be the first field so that a pointer to the object is also a valid
+    /// interface pointer.
+    enumerator: vss::IVssEnumObject,
+    ref_count: AtomicU32,
+    state: Mutex<EnumObjectState>,
+}
+impl EnumObjectImpl {
+    const VTBL: vss::IVssEnumObjectVtbl = vss::IVssEnumObjectVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::query_interface,
+            AddRef: Self::add_ref,
+            Release: Self::release,
+        },
+        Next: Self::next,
+        Skip: Self::skip,
+        Reset: Self::reset,
+        Clone: Self::clone,
+    };
+
+    /// # Safety
+    ///
+    /// The pointer must point to a live `EnumObjectImpl`.
+    unsafe fn from_this<'a, T>(this: *mut T) -> &'a Self {
+        &*(this as *const Self)
+    }
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, EnumObjectState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        query_interface(this, riid, object, &[vss::IVssEnumObject::uuidof()])
+    }
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+        add_ref(&Self::from_this(this).ref_count)
+    }
+    unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+        let count = release(&Self::from_this(this).ref_count);
+        if count == 0 {
+            drop(Box::from_raw(this as *mut Self));
+        }
+        count
+    }
+    unsafe extern "system" fn next(
+        this: *mut vss::IVssEnumObject,
+        count: ULONG,
+        elements: *mut vss::VSS_OBJECT_PROP,
+        fetched: *mut ULONG,
+    ) -> HRESULT {
+        if elements.is_null() || fetched.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            let mut state = Self::from_this(this).lock_state();
+            let start = state.position.min(state.objects.len());
+            let end = start
+                .saturating_add(count as usize)
+                .min(state.objects.len());
+            for (index, object) in state.objects[start..end].iter().enumerate() {
+                elements.add(index).write(object.clone().into_raw());
+            }
+            state.position = end;
+            let written = (end - start) as ULONG;
+            fetched.write(written);
+            if written == count {
+                S_OK
+            } else {
+                S_FALSE
+            }
+        })
+    }
+    unsafe extern "system" fn skip(this: *mut vss::IVssEnumObject, count: ULONG) -> HRESULT {
+        catch_panic(|| {
+            let mut state = Self::from_this(this).lock_state();
+            let remaining = state.objects.len().saturating_sub(state.position);
+            if remaining >= count as usize {
+                state.position += count as usize;
+                S_OK
+            } else {
+                state.position = state.objects.len();
+                S_FALSE
+            }
+        })
+    }
+    unsafe extern "system" fn reset(this: *mut vss::IVssEnumObject) -> HRESULT {
+        catch_panic(|| {
+            Self::from_this(this).lock_state().position = 0;
+            S_OK
+        })
+    }
+    unsafe extern "system" fn clone(
+        this: *mut vss::IVssEnumObject,
+        enumerator: *mut *mut vss::IVssEnumObject,
+    ) -> HRESULT {
+        if enumerator.is_null() {
+            return E_POINTER;
+        }
+        catch_panic(|| {
+            let (objects, position) = {
+                let state = Self::from_this(this).lock_state();
+                (state.objects.clone(), state.position)
+            };
+            let cloned = EnumObject::from_objects(objects);
+            Self::from_this(cloned.0.as_ref() as *const _ as *mut vss::IVssEnumObject)
+                .lock_state()
+                .position = position;
+            enumerator.write(SafeCOMComponent::into_raw(cloned.0));
+            S_OK
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Registration
+////////////////////////////////////////////////////////////////////////////////
+
+/// Error returned by [`register_software_provider`].
+#[derive(Debug)]
+pub enum RegisterSoftwareProviderError {
+    /// Failed to register the COM class that implements the provider.
+    RegisterClass(io::Error),
+    /// Failed to connect to the VSS service.
+    CreateAdmin(ConnectError<CreateVssAdminError>),
+    /// The VSS service refused to register the provider. The COM class
+    /// registration has been removed again.
+    RegisterProvider(RegisterProviderError),
+}
+impl fmt::Display for RegisterSoftwareProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RegisterClass(e) => write!(f, "failed to register provider COM class: {}", e),
+            Self::CreateAdmin(e) => write!(f, "failed to connect to VSS: {}", e),
+            Self::RegisterProvider(e) => write!(f, "failed to register provider with VSS: {}", e),
+        }
+    }
+}
+impl StdError for RegisterSoftwareProviderError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::RegisterClass(e) => Some(e),
+            Self::CreateAdmin(e) => Some(e),
+            Self::RegisterProvider(e) => Some(e),
+        }
+    }
+}
+
+/// Error returned by [`unregister_software_provider`].
+#[derive(Debug)]
+pub enum UnregisterSoftwareProviderError {
+    /// Failed to connect to the VSS service.
+    CreateAdmin(ConnectError<CreateVssAdminError>),
+    /// The VSS service refused to unregister the provider. The COM class is
+    /// still registered.
+    UnregisterProvider(UnregisterProviderError),
+    /// The provider was unregistered from VSS but its COM class couldn't be
+    /// removed.
+    UnregisterClass(io::Error),
+}
+impl fmt::Display for UnregisterSoftwareProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateAdmin(e) => write!(f, "failed to connect to VSS: {}", e),
+            Self::UnregisterProvider(e) => {
+                write!(f, "failed to unregister provider from VSS: {}", e)
+            }
+            Self::UnregisterClass(e) => {
+                write!(f, "failed to unregister provider COM class: {}", e)
+            }
+        }
+    }
+}
+impl StdError for UnregisterSoftwareProviderError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::CreateAdmin(e) => Some(e),
+            Self::UnregisterProvider(e) => Some(e),
+            Self::UnregisterClass(e) => Some(e),
+        }
+    }
+}
+
+/// Information needed to register a software provider with
+/// [`register_software_provider`].
+#[derive(Clone, Copy)]
+pub struct SoftwareProviderRegistration<'a> {
+    /// Uniquely identifies the provider. This should never change for a
+    /// provider.
+    pub provider_id: VSS_ID,
+    /// The class id of the COM object that implements the provider. The
+    /// executable should register a [`ClassFactory`] for this class when it
+    /// starts.
+    ///
+    /// [`ClassFactory`]: crate::com_server::ClassFactory
+    pub class_id: CLSID,
+    /// The name of the provider, also used as the description of the COM
+    /// class.
+    pub name: &'a str,
+    /// The version of the provider, for example `1.0.0`.
+    pub version: &'a str,
+    /// Uniquely identifies this version of the provider.
+    pub version_id: VSS_ID,
+    /// The executable that hosts the provider.
+    pub executable: &'a Path,
+}
+
+/// Register a software provider that is implemented by an executable. This
+/// registers the provider's COM class using
+/// [`register_local_server`](crate::com_server::register_local_server) and then
+/// informs VSS about the provider using [`IAdmin::register_provider`].
+///
+/// This requires administrator privileges and COM must be initialized on the
+/// current thread, see [`crate::initialize_com`]. The registration persists
+/// until [`unregister_software_provider`] is called, so this is usually done
+/// by an installer.
+pub fn register_software_provider(
+    registration: &SoftwareProviderRegistration<'_>,
+) -> Result<(), RegisterSoftwareProviderError> {
+    let invalid_arg = || {
+        RegisterSoftwareProviderError::RegisterProvider(RegisterProviderError::from_kind(
+            RegisterProviderErrorKind::E_INVALIDARG,
+        ))
+    };
+    let name = U16CString::from_str(registration.name).map_err(|_| invalid_arg())?;
+    let version = U16CString::from_str(registration.version).map_err(|_| invalid_arg())?;
+
+    register_local_server(
+        &registration.class_id,
+        registration.name,
+        registration.executable,
+    )
+    .map_err(RegisterSoftwareProviderError::RegisterClass)?;
+
+    let result = Admin::new()
+        .map_err(RegisterSoftwareProviderError::CreateAdmin)
+        .and_then(|admin| {
+            admin
+                .register_provider(
+                    registration.provider_id,
+                    registration.class_id,
+                    &name,
+                    ProviderType::Software,
+                    &version,
+                    registration.version_id,
+                )
+                .map_err(RegisterSoftwareProviderError::RegisterProvider)
+        });
+    if result.is_err() {
+        // Don't leave a COM class behind that nothing uses:
+        let _ = unregister_class(&registration.class_id);
+    }
+    result
+}
+
+/// Undo a registration made by [`register_software_provider`]. Succeeds if the
+/// provider wasn't registered.
+///
+/// This requires administrator privileges and COM must be initialized on the
+/// current thread, see [`crate::initialize_com`].
+pub fn unregister_software_provider(
+    provider_id: VSS_ID,
+    class_id: &CLSID,
+) -> Result<(), UnregisterSoftwareProviderError> {
+    let admin = Admin::new().map_err(UnregisterSoftwareProviderError::CreateAdmin)?;
+    match admin.unregister_provider(provider_id) {
+        Ok(()) => {}
+        Err(e) if e.kind() == UnregisterProviderErrorKind::VSS_E_PROVIDER_NOT_REGISTERED => {}
+        Err(e) => return Err(UnregisterSoftwareProviderError::UnregisterProvider(e)),
+    }
+    unregister_class(class_id).map_err(UnregisterSoftwareProviderError::UnregisterClass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vss::ObjectUnion;
+
+    #[test]
+    #[ignore = "requires administrator privileges and modifies the system"]
+    fn register_software_provider_round_trip() {
+        crate::initialize_com().unwrap();
+
+        // Random ids generated for this test:
+        let provider_id = GUID {
+            Data1: 0x3c1f_5a7e,
+            Data2: 0x91d2,
+            Data3: 0x4b8e,
+            Data4: [0xa6, 0x0f, 0x27, 0x5d, 0xc3, 0x48, 0x9b, 0x12],
+        };
+        let class_id = GUID {
+            Data1: 0x7e2b_04c9,
+            Data2: 0x5f31,
+            Data3: 0x4a6d,
+            Data4: [0x8c, 0x17, 0xe9, 0x42, 0x0b, 0xd6, 0x73, 0xaf],
+        };
+        let executable = std::env::current_exe().unwrap();
+        register_software_provider(&SoftwareProviderRegistration {
+            provider_id,
+            class_id,
+            name: "volume_shadow_copy test provider",
+            version: "1.0.0",
+            version_id: provider_id,
+            executable: &executable,
+        })
+        .unwrap();
+
+        let providers = Admin::new().unwrap().query_providers().unwrap();
+        let registered = providers
+            .iter(4)
+            .any(|object| match object.unwrap().into_object() {
+                Some(ObjectUnion::Provider(provider)) => {
+                    IsEqualGUID(&provider.provider_id(), &provider_id)
+                }
+                _ => false,
+            });
+
+        unregister_software_provider(provider_id, &class_id).unwrap();
+        assert!(registered, "provider wasn't listed by VSS");
+
+        // Unregistering twice is allowed:
+        unregister_software_provider(provider_id, &class_id).unwrap();
+    }
+}
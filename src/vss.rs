@@ -14,14 +14,30 @@ use std::{
     marker::PhantomData,
     mem::ManuallyDrop,
     ptr::{self, null_mut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "futures")]
+use std::{
+    future::{Future, IntoFuture},
+    pin::Pin,
+    sync::{Condvar, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
 };
 
-use widestring::U16CStr;
+#[cfg(feature = "futures")]
+use futures_core::Stream;
+
+use widestring::{NulError, U16CStr, U16CString};
 use winapi::{
     ctypes::c_void,
     shared::{
         guiddef::GUID,
-        minwindef::ULONG,
+        minwindef::{INT, ULONG},
         winerror::{S_FALSE, S_OK},
     },
     um::{
@@ -35,8 +51,9 @@ use winapi::{
 };
 
 use super::{
-    check_com, errors::*, impl_query_interface, raw_bitflags, transparent_wrapper,
-    unsafe_deref_to_ref, unsafe_impl_as_IUnknown, with_from, RawBitFlags, SafeCOMComponent,
+    check_com, errors::*, impl_query_interface, paths::is_separator, raw_bitflags, trace_com_call,
+    transparent_wrapper, unsafe_deref_to_ref, unsafe_impl_as_IUnknown, with_from, RawBitFlags,
+    SafeCOMComponent, VssU16CString,
 };
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -73,7 +90,9 @@ where
     }
 }
 impl<A, E> Clone for VssAsyncError<A, E> {
-    fn clone(&self) -> Self { *self }
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 impl<A, E> Copy for VssAsyncError<A, E> {}
 impl<A, E> fmt::Debug for VssAsyncError<A, E>
@@ -140,6 +159,9 @@ impl<E> VssAsync<E> {
     pub(crate) fn new(com: SafeCOMComponent<vss::IVssAsync>) -> Self {
         Self(com, PhantomData)
     }
+    pub(crate) fn into_raw(self) -> *mut vss::IVssAsync {
+        SafeCOMComponent::into_raw(self.0)
+    }
 }
 /// Change the error type of the operation.
 impl<E> VssAsync<E> {
@@ -161,25 +183,434 @@ where
     /// Waits until an incomplete asynchronous operation finishes.
     #[doc(alias = "Wait")]
     pub fn wait(&self, milliseconds: Option<u32>) -> Result<(), VssAsyncError<WaitError, E>> {
+        trace_com_call!("Wait");
         let milliseconds = milliseconds.unwrap_or(INFINITE);
         check_com(unsafe { self.0.Wait(milliseconds) })?;
         Ok(())
     }
+    /// Waits until an incomplete asynchronous operation finishes or
+    /// `timeout` has elapsed. `None` waits forever.
+    ///
+    /// The timeout is rounded up to whole milliseconds and timeouts that
+    /// don't fit in the `u32` that [`wait`](Self::wait) takes are treated as
+    /// waiting forever.
+    #[doc(alias = "Wait")]
+    pub fn wait_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<(), VssAsyncError<WaitError, E>> {
+        trace_com_call!("Wait");
+        check_com(unsafe { self.0.Wait(timeout_to_millis(timeout)) })?;
+        Ok(())
+    }
     /// Queries the status of an asynchronous operation.
     #[doc(alias = "QueryStatus")]
     pub fn query_status(&self) -> Result<AsyncStatus, VssAsyncError<QueryStatusError, E>> {
+        trace_com_call!("QueryStatus");
         let mut result: HRESULT = S_OK;
         check_com(unsafe { self.0.QueryStatus(&mut result, null_mut()) })?;
         Ok(AsyncStatus::try_from(result).map_err(|_| result)?)
     }
+    /// Queries the status of an asynchronous operation together with the
+    /// value that VSS writes to the reserved argument of `QueryStatus`.
+    ///
+    /// VSS doesn't report a completion percentage. The reserved value is
+    /// undocumented and is passed through as is, so don't treat it as a
+    /// measure of how far along the operation is.
+    #[doc(alias = "QueryStatus")]
+    pub fn query_progress(&self) -> Result<AsyncProgress, VssAsyncError<QueryStatusError, E>> {
+        trace_com_call!("QueryStatus");
+        let mut result: HRESULT = S_OK;
+        let mut reserved: INT = 0;
+        check_com(unsafe { self.0.QueryStatus(&mut result, &mut reserved) })?;
+        Ok(AsyncProgress {
+            status: AsyncStatus::try_from(result).map_err(|_| result)?,
+            reserved,
+        })
+    }
+    /// Wait for the operation to finish by querying its status every
+    /// `interval`. `callback` is called with the result of each query,
+    /// including the last one where the operation is no longer pending.
+    /// See [`query_progress`](Self::query_progress) for what the reported
+    /// [`AsyncProgress`] contains.
+    ///
+    /// Returns the final status of the operation.
+    pub fn wait_with_progress<F>(
+        &self,
+        interval: Duration,
+        callback: F,
+    ) -> Result<AsyncStatus, VssAsyncError<QueryStatusError, E>>
+    where
+        F: FnMut(AsyncProgress),
+    {
+        self.wait_with_cancellation(interval, &CancellationToken::new(), callback)
+    }
+    /// Like [`wait_with_progress`](Self::wait_with_progress) but the
+    /// operation is [canceled](Self::cancel) once `token` is canceled.
+    ///
+    /// The status is still queried after the operation has been canceled, so
+    /// this returns [`AsyncStatus::Canceled`] unless the operation finished
+    /// before VSS noticed the cancellation.
+    pub fn wait_with_cancellation<F>(
+        &self,
+        interval: Duration,
+        token: &CancellationToken,
+        mut callback: F,
+    ) -> Result<AsyncStatus, VssAsyncError<QueryStatusError, E>>
+    where
+        F: FnMut(AsyncProgress),
+    {
+        let mut cancel_requested = false;
+        loop {
+            let progress = self.query_progress()?;
+            callback(progress);
+            if progress.status != AsyncStatus::Pending {
+                return Ok(progress.status);
+            }
+            if !cancel_requested && token.is_canceled() {
+                cancel_requested = true;
+                // Fails if the operation has already finished, which the
+                // next query will report.
+                let _ = self.cancel();
+                continue;
+            }
+            thread::sleep(interval);
+        }
+    }
     /// Cancel an incomplete asynchronous operation.
     #[doc(alias = "Cancel")]
     pub fn cancel(&self) -> Result<(), VssAsyncError<CancelError, E>> {
+        trace_com_call!("Cancel");
         check_com(unsafe { self.0.Cancel() })?;
         Ok(())
     }
 }
 
+/// A handle that can be used to cancel asynchronous operations from another
+/// thread, for example when the user presses a cancel button.
+///
+/// Clones share the same state, so canceling one of them cancels all of
+/// them. A token can't be reset after it has been canceled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+    /// Create a token that hasn't been canceled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Request that operations that use this token are canceled.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+    /// `true` if [`cancel`](Self::cancel) has been called on this token or
+    /// one of its clones.
+    pub fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Wait for several asynchronous operations to finish, for example operations
+/// started by different backup components objects.
+///
+/// The operations are waited on one after another and `timeout` limits the
+/// total time spent waiting. Operations that are still running when the
+/// timeout elapses are left running and reported as
+/// [`AsyncStatus::Pending`].
+pub fn wait_all(operations: &[VssAsync<HRESULT>], timeout: Option<Duration>) -> WaitAllResult {
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let statuses = operations
+        .iter()
+        .map(|operation| {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            operation.wait_timeout(remaining).map_err(HRESULT::from)?;
+            operation.query_status().map_err(HRESULT::from)
+        })
+        .collect();
+    WaitAllResult { statuses }
+}
+
+/// The outcome of [`wait_all`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaitAllResult {
+    /// The status of each operation in the order they were given. An error
+    /// is either the error code that the operation failed with or the error
+    /// returned while waiting on it.
+    pub statuses: Vec<Result<AsyncStatus, HRESULT>>,
+}
+impl WaitAllResult {
+    /// The index and error code of the first operation that failed.
+    pub fn first_failure(&self) -> Option<(usize, HRESULT)> {
+        self.statuses
+            .iter()
+            .enumerate()
+            .find_map(|(index, status)| status.err().map(|hresult| (index, hresult)))
+    }
+    /// `true` if every operation finished successfully.
+    pub fn all_finished(&self) -> bool {
+        self.statuses
+            .iter()
+            .all(|status| *status == Ok(AsyncStatus::Finished))
+    }
+}
+
+/// Convert a timeout to the milliseconds expected by `IVssAsync::Wait`.
+fn timeout_to_millis(timeout: Option<Duration>) -> u32 {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return INFINITE,
+    };
+    let millis = timeout.as_millis() + u128::from(timeout.subsec_nanos() % 1_000_000 != 0);
+    u32::try_from(millis).unwrap_or(INFINITE)
+}
+
+/// Wakes a task from a background thread once a poll interval has passed.
+///
+/// The thread is started the first time the timer is armed and is reused for
+/// every later interval. Polls before the interval has passed only replace
+/// the waker that it will wake. The thread exits when the timer is dropped.
+#[cfg(feature = "futures")]
+#[derive(Debug, Default)]
+struct PollTimer {
+    /// State shared with the timer thread, `None` until the thread is started.
+    shared: Option<Arc<PollTimerShared>>,
+}
+#[cfg(feature = "futures")]
+impl PollTimer {
+    /// `true` if the operation should be queried again. Otherwise the
+    /// current task is woken when the interval has passed.
+    fn is_due(&mut self, cx: &mut Context<'_>) -> bool {
+        let shared = match &self.shared {
+            Some(shared) => shared,
+            None => return true,
+        };
+        let mut state = shared.lock();
+        match state.due {
+            Some(due) if Instant::now() < due => {
+                if !matches!(&state.waker, Some(waker) if waker.will_wake(cx.waker())) {
+                    state.waker = Some(cx.waker().clone());
+                }
+                false
+            }
+            _ => {
+                state.due = None;
+                true
+            }
+        }
+    }
+    /// Wake the current task after `interval`.
+    fn start(&mut self, interval: Duration, cx: &mut Context<'_>) {
+        let shared = self.shared.get_or_insert_with(|| {
+            let shared = Arc::new(PollTimerShared::default());
+            let thread_shared = Arc::clone(&shared);
+            thread::spawn(move || thread_shared.run());
+            shared
+        });
+        {
+            let mut state = shared.lock();
+            state.due = Some(Instant::now() + interval);
+            state.waker = Some(cx.waker().clone());
+        }
+        shared.changed.notify_one();
+    }
+}
+#[cfg(feature = "futures")]
+impl Drop for PollTimer {
+    fn drop(&mut self) {
+        if let Some(shared) = &self.shared {
+            shared.lock().stopped = true;
+            shared.changed.notify_one();
+        }
+    }
+}
+
+/// The part of a [`PollTimer`] that its thread has access to.
+#[cfg(feature = "futures")]
+#[derive(Debug, Default)]
+struct PollTimerShared {
+    state: Mutex<PollTimerState>,
+    /// Notified when the state changes.
+    changed: Condvar,
+}
+#[cfg(feature = "futures")]
+#[derive(Debug, Default)]
+struct PollTimerState {
+    /// When to wake the task, `None` if the timer isn't armed.
+    due: Option<Instant>,
+    waker: Option<Waker>,
+    /// Set when the timer is dropped to stop the thread.
+    stopped: bool,
+}
+#[cfg(feature = "futures")]
+impl PollTimerShared {
+    fn lock(&self) -> MutexGuard<'_, PollTimerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// The body of the timer thread.
+    fn run(&self) {
+        let mut state = self.lock();
+        while !state.stopped {
+            let now = Instant::now();
+            state = match state.due {
+                None => self.changed.wait(state).unwrap_or_else(|e| e.into_inner()),
+                Some(due) if now < due => {
+                    self.changed
+                        .wait_timeout(state, due - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                Some(_) => {
+                    state.due = None;
+                    let waker = state.waker.take();
+                    // Don't hold the lock while the executor runs.
+                    drop(state);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                    self.lock()
+                }
+            };
+        }
+    }
+}
+
+/// A future that completes when an asynchronous VSS operation finishes.
+///
+/// The operation is polled with [`VssAsync::query_status`] and the task is
+/// woken from a background thread after the poll interval if the operation
+/// is still pending, so this works with any executor. COM must be
+/// initialized on the threads that poll the future.
+///
+/// Created by [`VssAsync::into_future`] or [`VssAsyncFuture::new`].
+#[cfg(feature = "futures")]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[derive(Debug)]
+pub struct VssAsyncFuture<E> {
+    operation: VssAsync<E>,
+    poll_interval: Duration,
+    timer: PollTimer,
+}
+#[cfg(feature = "futures")]
+impl<E> VssAsyncFuture<E> {
+    /// The poll interval used by [`VssAsync::into_future`].
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Wait for `operation` and check if it has finished every
+    /// `poll_interval`.
+    pub fn new(operation: VssAsync<E>, poll_interval: Duration) -> Self {
+        Self {
+            operation,
+            poll_interval,
+            timer: PollTimer::default(),
+        }
+    }
+    /// The operation that is being waited on, for example to
+    /// [cancel](VssAsync::cancel) it.
+    pub fn operation(&self) -> &VssAsync<E> {
+        &self.operation
+    }
+}
+// The future never relies on being pinned.
+#[cfg(feature = "futures")]
+impl<E> Unpin for VssAsyncFuture<E> {}
+#[cfg(feature = "futures")]
+impl<E> Future for VssAsyncFuture<E>
+where
+    E: From<HRESULT>,
+{
+    type Output = Result<AsyncStatus, VssAsyncError<QueryStatusError, E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if !this.timer.is_due(cx) {
+            return Poll::Pending;
+        }
+        match this.operation.query_status() {
+            Ok(AsyncStatus::Pending) => {
+                this.timer.start(this.poll_interval, cx);
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+}
+#[cfg(feature = "futures")]
+impl<E> VssAsync<E> {
+    /// A stream of the operation's status, queried every `interval`.
+    ///
+    /// The first status is yielded immediately. The stream ends after
+    /// yielding a status other than [`AsyncStatus::Pending`] or an error.
+    pub fn status_stream(&self, interval: Duration) -> VssAsyncStatusStream<E> {
+        VssAsyncStatusStream {
+            operation: VssAsync(self.0.clone(), PhantomData),
+            interval,
+            timer: PollTimer::default(),
+            done: false,
+        }
+    }
+}
+
+/// A stream of the status of an asynchronous VSS operation.
+///
+/// Created by [`VssAsync::status_stream`]. Like [`VssAsyncFuture`] the task
+/// is woken from a background thread, so this works with any executor.
+#[cfg(feature = "futures")]
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct VssAsyncStatusStream<E> {
+    operation: VssAsync<E>,
+    interval: Duration,
+    timer: PollTimer,
+    done: bool,
+}
+#[cfg(feature = "futures")]
+impl<E> VssAsyncStatusStream<E> {
+    /// The operation whose status is being queried.
+    pub fn operation(&self) -> &VssAsync<E> {
+        &self.operation
+    }
+}
+// The stream never relies on being pinned.
+#[cfg(feature = "futures")]
+impl<E> Unpin for VssAsyncStatusStream<E> {}
+#[cfg(feature = "futures")]
+impl<E> Stream for VssAsyncStatusStream<E>
+where
+    E: From<HRESULT>,
+{
+    type Item = Result<AsyncStatus, VssAsyncError<QueryStatusError, E>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if !this.timer.is_due(cx) {
+            return Poll::Pending;
+        }
+        let status = this.operation.query_status();
+        if let Ok(AsyncStatus::Pending) = status {
+            this.timer.start(this.interval, cx);
+        } else {
+            this.done = true;
+        }
+        Poll::Ready(Some(status))
+    }
+}
+/// Await the operation with [`VssAsyncFuture::DEFAULT_POLL_INTERVAL`].
+#[cfg(feature = "futures")]
+impl<E> IntoFuture for VssAsync<E>
+where
+    E: From<HRESULT>,
+{
+    type Output = Result<AsyncStatus, VssAsyncError<QueryStatusError, E>>;
+    type IntoFuture = VssAsyncFuture<E>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        VssAsyncFuture::new(self, VssAsyncFuture::<E>::DEFAULT_POLL_INTERVAL)
+    }
+}
+
 with_from!(
     [raw = HRESULT],
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -197,6 +628,18 @@ with_from!(
     }
 );
 
+/// The status of an asynchronous operation returned by
+/// [`VssAsync::query_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncProgress {
+    /// The status of the operation.
+    pub status: AsyncStatus,
+    /// The value of the reserved argument of `IVssAsync::QueryStatus`. Its
+    /// meaning is undocumented and it is usually zero, so it is not a
+    /// completion percentage.
+    pub reserved: i32,
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // IVssEnumObject
 ////////////////////////////////////////////////////////////////////////////////
@@ -226,6 +669,7 @@ impl IEnumObject {
     /// a copy of the `EnumObject` enumerator object.
     #[doc(alias = "Clone")]
     pub fn clone_enumerator(&self) -> Result<EnumObject, EnumObjectCloneError> {
+        trace_com_call!("Clone");
         let mut enumerator: *mut vss::IVssEnumObject = null_mut();
         check_com(unsafe { self.0.Clone(&mut enumerator) })?;
         Ok(EnumObject(unsafe { SafeCOMComponent::new(enumerator) }))
@@ -245,6 +689,7 @@ impl IEnumObject {
         &self,
         buffer: &mut [ObjectProperties],
     ) -> Result<EnumObjectNextResult, EnumObjectNextError> {
+        trace_com_call!("Next");
         let wanted = buffer.len().try_into().unwrap_or(ULONG::MAX);
         let mut fetched: ULONG = 0;
 
@@ -266,6 +711,7 @@ impl IEnumObject {
     /// enumerated object.
     #[doc(alias = "Reset")]
     pub fn reset(&self) -> Result<(), EnumObjectResetError> {
+        trace_com_call!("Reset");
         check_com(unsafe { self.0.Reset() })?;
         Ok(())
     }
@@ -275,6 +721,7 @@ impl IEnumObject {
     /// end of the list of items; otherwise returns `false`.
     #[doc(alias = "Skip")]
     pub fn skip(&self, element_count: u32) -> Result<bool, EnumObjectSkipError> {
+        trace_com_call!("Skip");
         let hr = unsafe { self.0.Skip(element_count) };
         let too_far = hr == S_FALSE;
         if hr == S_OK || too_far {
@@ -298,6 +745,56 @@ impl IEnumObject {
         EnumObjectIterator::new(self, buffer_size)
     }
 }
+impl EnumObject {
+    /// Return an iterator over the shadow copies in this enumerator. Objects
+    /// of any other type are skipped.
+    pub fn into_snapshots(self) -> SnapshotIterator {
+        SnapshotIterator(EnumObjectIterator::new(self, TYPED_ITERATOR_BUFFER_SIZE))
+    }
+    /// Return an iterator over the providers in this enumerator. Objects of
+    /// any other type are skipped.
+    pub fn into_providers(self) -> ProviderIterator {
+        ProviderIterator(EnumObjectIterator::new(self, TYPED_ITERATOR_BUFFER_SIZE))
+    }
+}
+
+/// The number of objects that the typed iterators read from an enumerator at
+/// a time.
+const TYPED_ITERATOR_BUFFER_SIZE: usize = 16;
+
+/// An iterator over the shadow copies of an [`EnumObject`], created by
+/// [`EnumObject::into_snapshots`].
+pub struct SnapshotIterator(EnumObjectIterator<EnumObject>);
+impl Iterator for SnapshotIterator {
+    type Item = Result<SnapshotProperties, EnumObjectNextError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next()?.map(ObjectProperties::into_object) {
+                Ok(Some(ObjectUnion::Snapshot(snapshot))) => return Some(Ok(snapshot)),
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// An iterator over the providers of an [`EnumObject`], created by
+/// [`EnumObject::into_providers`].
+pub struct ProviderIterator(EnumObjectIterator<EnumObject>);
+impl Iterator for ProviderIterator {
+    type Item = Result<ProviderProperties, EnumObjectNextError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next()?.map(ObjectProperties::into_object) {
+                Ok(Some(ObjectUnion::Provider(provider))) => return Some(Ok(provider)),
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
 
 enum EnumObjectIteratorBuffer {
     /// Optimization to not require allocation when buffer is small.
@@ -523,6 +1020,41 @@ impl SnapshotProperties {
         self.0.m_eStatus.into()
     }
 }
+impl SnapshotProperties {
+    /// Get the path of a file inside the shadow copy as a nul terminated UTF-16
+    /// string that can be passed directly to Windows APIs such as
+    /// `CreateFileW`. The path is never converted to UTF-8 so file names that
+    /// aren't valid Unicode are preserved.
+    ///
+    /// `relative_path` is relative to the root of the original volume, for
+    /// example `Users\name\file.txt` for the file `C:\Users\name\file.txt`
+    /// in a shadow copy of `C:\`. Leading separators are ignored and `/` is
+    /// treated the same as `\`.
+    ///
+    /// Returns an error if `relative_path` contains a nul character.
+    pub fn to_wide_path(&self, relative_path: &[u16]) -> Result<U16CString, NulError<u16>> {
+        let device = self.snapshot_device_object().as_slice();
+        let start = relative_path
+            .iter()
+            .position(|&c| !is_separator(c))
+            .unwrap_or(relative_path.len());
+        let relative_path = &relative_path[start..];
+
+        let mut path = Vec::with_capacity(device.len() + 1 + relative_path.len());
+        path.extend_from_slice(device);
+        if !relative_path.is_empty() && !matches!(device.last(), Some(&c) if is_separator(c)) {
+            path.push(u16::from(b'\\'));
+        }
+        path.extend(relative_path.iter().map(|&c| {
+            if c == u16::from(b'/') {
+                u16::from(b'\\')
+            } else {
+                c
+            }
+        }));
+        U16CString::new(path)
+    }
+}
 impl SnapshotProperties {
     /// Free all memory used by the content of the struct.
     ///
@@ -551,6 +1083,98 @@ impl Drop for SnapshotProperties {
         }
     }
 }
+impl Clone for SnapshotProperties {
+    fn clone(&self) -> Self {
+        Self::new(SnapshotPropertiesInfo {
+            snapshot_id: self.snapshot_id(),
+            snapshot_set_id: self.snapshot_set_id(),
+            snapshots_count: self.snapshots_count(),
+            snapshot_device_object: self.snapshot_device_object(),
+            original_volume_name: self.original_volume_name(),
+            originating_machine: self.originating_machine(),
+            service_machine: self.service_machine(),
+            exposed_name: self.exposed_name(),
+            exposed_path: self.exposed_path(),
+            provider_id: self.provider_id(),
+            snapshot_attributes: self.snapshot_attributes(),
+            creation_timestamp: self.creation_timestamp(),
+            status: self.status(),
+        })
+        .with_raw_status(self.0.m_eStatus)
+    }
+}
+impl SnapshotProperties {
+    /// Create new snapshot properties. The strings are copied into memory
+    /// allocated with `CoTaskMemAlloc` so that the properties can be returned
+    /// from a VSS provider.
+    pub fn new(info: SnapshotPropertiesInfo<'_>) -> Self {
+        Self(vss::VSS_SNAPSHOT_PROP {
+            m_SnapshotId: info.snapshot_id,
+            m_SnapshotSetId: info.snapshot_set_id,
+            m_lSnapshotsCount: info.snapshots_count,
+            m_pwszSnapshotDeviceObject: alloc_string(info.snapshot_device_object),
+            m_pwszOriginalVolumeName: alloc_string(info.original_volume_name),
+            m_pwszOriginatingMachine: alloc_string(info.originating_machine),
+            m_pwszServiceMachine: alloc_string(info.service_machine),
+            m_pwszExposedName: info.exposed_name.map(alloc_string).unwrap_or(null_mut()),
+            m_pwszExposedPath: info.exposed_path.map(alloc_string).unwrap_or(null_mut()),
+            m_ProviderId: info.provider_id,
+            m_lSnapshotAttributes: info.snapshot_attributes.raw() as LONG,
+            m_tsCreationTimestamp: info.creation_timestamp,
+            m_eStatus: info.status.into(),
+        })
+    }
+    /// Preserve status values that aren't known by the [`SnapshotState`] enum.
+    fn with_raw_status(mut self, status: vss::VSS_SNAPSHOT_STATE) -> Self {
+        self.0.m_eStatus = status;
+        self
+    }
+    /// Release ownership of the wrapped struct. The strings inside it must be
+    /// freed with the `VssFreeSnapshotProperties` function.
+    pub fn into_raw(self) -> vss::VSS_SNAPSHOT_PROP {
+        let this = ManuallyDrop::new(self);
+        // Safety: `this` will never be dropped so the strings won't be freed.
+        unsafe { ptr::read(&this.0) }
+    }
+}
+
+/// Data used to create a new [`SnapshotProperties`] value, see the getters of
+/// that type for info about each field.
+pub struct SnapshotPropertiesInfo<'a> {
+    /// Identifies the shadow copy.
+    pub snapshot_id: VSS_ID,
+    /// Identifies the shadow copy set that contains the shadow copy.
+    pub snapshot_set_id: VSS_ID,
+    /// Number of volumes in the shadow copy set when it was created.
+    pub snapshots_count: i32,
+    /// Device object of the shadow copy, without a trailing `\`.
+    pub snapshot_device_object: &'a U16CStr,
+    /// Name of the volume that was shadow copied.
+    pub original_volume_name: &'a U16CStr,
+    /// Machine that contains the original volume.
+    pub originating_machine: &'a U16CStr,
+    /// Machine running the VSS service that created the shadow copy.
+    pub service_machine: &'a U16CStr,
+    /// Drive letter, mounted folder or share name that the shadow copy is
+    /// exposed as, if it is exposed.
+    pub exposed_name: Option<&'a U16CStr>,
+    /// Part of the volume that is exposed when the shadow copy is exposed as a
+    /// share.
+    pub exposed_path: Option<&'a U16CStr>,
+    /// Identifies the provider that created the shadow copy.
+    pub provider_id: VSS_ID,
+    /// Attributes of the shadow copy.
+    pub snapshot_attributes: RawBitFlags<VolumeSnapshotAttributes>,
+    /// When the shadow copy was created, in the `VSS_TIMESTAMP` format.
+    pub creation_timestamp: i64,
+    /// Current creation status of the shadow copy.
+    pub status: SnapshotState,
+}
+
+/// Copy a string into memory allocated with `CoTaskMemAlloc`.
+fn alloc_string(string: &U16CStr) -> vss::VSS_PWSZ {
+    VssU16CString::from_u16_cstr(string).into_raw()
+}
 
 /// Specifies shadow copy provider properties.
 #[doc(alias = "VSS_PROVIDER_PROP")]
@@ -619,6 +1243,56 @@ impl Drop for ProviderProperties {
         }
     }
 }
+impl Clone for ProviderProperties {
+    fn clone(&self) -> Self {
+        Self::new(ProviderPropertiesInfo {
+            provider_id: self.provider_id(),
+            provider_name: self.provider_name(),
+            provider_type: self.provider_type(),
+            provider_version: self.provider_version(),
+            provider_version_id: self.provider_version_id(),
+            class_id: self.class_id(),
+        })
+    }
+}
+impl ProviderProperties {
+    /// Create new provider properties. The strings are copied into memory
+    /// allocated with `CoTaskMemAlloc`.
+    pub fn new(info: ProviderPropertiesInfo<'_>) -> Self {
+        Self(vss::VSS_PROVIDER_PROP {
+            m_ProviderId: info.provider_id,
+            m_pwszProviderName: alloc_string(info.provider_name),
+            m_eProviderType: info.provider_type.into(),
+            m_pwszProviderVersion: alloc_string(info.provider_version),
+            m_ProviderVersionId: info.provider_version_id,
+            m_ClassId: info.class_id,
+        })
+    }
+    /// Release ownership of the wrapped struct. The strings inside it must be
+    /// freed with the `CoTaskMemFree` function.
+    pub fn into_raw(self) -> vss::VSS_PROVIDER_PROP {
+        let this = ManuallyDrop::new(self);
+        // Safety: `this` will never be dropped so the strings won't be freed.
+        unsafe { ptr::read(&this.0) }
+    }
+}
+
+/// Data used to create a new [`ProviderProperties`] value, see the getters of
+/// that type for info about each field.
+pub struct ProviderPropertiesInfo<'a> {
+    /// Identifies the provider.
+    pub provider_id: VSS_ID,
+    /// Name of the provider.
+    pub provider_name: &'a U16CStr,
+    /// Type of the provider.
+    pub provider_type: ProviderType,
+    /// Version of the provider in readable format.
+    pub provider_version: &'a U16CStr,
+    /// Identifies the version of the provider.
+    pub provider_version_id: VSS_ID,
+    /// Class identifier of the provider's COM component.
+    pub class_id: GUID,
+}
 
 /// Defines the properties of a provider, volume, shadow copy, or shadow copy set.
 #[doc(alias = "VSS_OBJECT_PROP")]
@@ -748,6 +1422,52 @@ impl Drop for ObjectProperties {
         }
     }
 }
+impl Clone for ObjectProperties {
+    fn clone(&self) -> Self {
+        match self.as_object() {
+            Some(ObjectUnionRef::Snapshot(v)) => v.clone().into(),
+            Some(ObjectUnionRef::Provider(v)) => v.clone().into(),
+            None => Self::default(),
+        }
+    }
+}
+impl ObjectProperties {
+    /// Release ownership of the wrapped struct. The strings inside it must be
+    /// freed as specified by the docs for `VSS_OBJECT_PROP`.
+    pub fn into_raw(self) -> vss::VSS_OBJECT_PROP {
+        let this = ManuallyDrop::new(self);
+        // Safety: `this` will never be dropped so the strings won't be freed.
+        unsafe { ptr::read(&this.0) }
+    }
+}
+impl From<SnapshotProperties> for ObjectProperties {
+    fn from(value: SnapshotProperties) -> Self {
+        let mut raw = vss::VSS_OBJECT_PROP {
+            Type: ObjectType::Snapshot.into(),
+            ..Default::default()
+        };
+        unsafe { *raw.Obj.Snap_mut() = value.into_raw() };
+        Self(raw)
+    }
+}
+impl From<ProviderProperties> for ObjectProperties {
+    fn from(value: ProviderProperties) -> Self {
+        let mut raw = vss::VSS_OBJECT_PROP {
+            Type: ObjectType::Provider.into(),
+            ..Default::default()
+        };
+        unsafe { *raw.Obj.Prov_mut() = value.into_raw() };
+        Self(raw)
+    }
+}
+impl From<ObjectUnion> for ObjectProperties {
+    fn from(value: ObjectUnion) -> Self {
+        match value {
+            ObjectUnion::Snapshot(v) => v.into(),
+            ObjectUnion::Provider(v) => v.into(),
+        }
+    }
+}
 
 ////////////////////////////////////////////////////////////////////////////////
 // Enumerations
@@ -961,6 +1681,208 @@ with_from!(
         All = vss::VSS_CTX_ALL,
     }
 );
+impl SnapshotContext {
+    /// The contexts that can be recognized inside a raw context value. Ordered
+    /// so that contexts that set more attribute bits come first.
+    const PARSE_ORDER: [SnapshotContext; 6] = [
+        SnapshotContext::ClientAccessible,
+        SnapshotContext::NasRollback,
+        SnapshotContext::ClientAccessibleWriters,
+        SnapshotContext::AppRollback,
+        SnapshotContext::FileShareBackup,
+        SnapshotContext::Backup,
+    ];
+
+    /// Combine a context with extra attributes into the raw value that is
+    /// passed to `SetContext`. This is what
+    /// [`IBackupComponents::set_context`](crate::vsbackup::IBackupComponents::set_context)
+    /// uses.
+    ///
+    /// Most contexts are themselves a combination of
+    /// [`VolumeSnapshotAttributes`] so the same raw value can often be
+    /// produced by several different arguments.
+    pub fn to_raw_context(self, attributes: RawBitFlags<VolumeSnapshotAttributes>) -> LONG {
+        (vss::VSS_SNAPSHOT_CONTEXT::from(self) | attributes.raw()) as LONG
+    }
+
+    /// Split a raw context value, for example the value returned by
+    /// [`SnapshotProperties::snapshot_attributes`], into the most specific
+    /// context that it contains and the attributes that remain.
+    ///
+    /// Passing the result to [`SnapshotContext::to_raw_context`] always gives
+    /// back the original value.
+    pub fn from_raw_context(raw: LONG) -> (Self, RawBitFlags<VolumeSnapshotAttributes>) {
+        let raw = raw as vss::VSS_SNAPSHOT_CONTEXT;
+        if raw == vss::VSS_CTX_ALL {
+            return (SnapshotContext::All, RawBitFlags::from_raw(0));
+        }
+        let context = Self::PARSE_ORDER
+            .iter()
+            .copied()
+            .find(|&context| {
+                let bits = vss::VSS_SNAPSHOT_CONTEXT::from(context);
+                raw & bits == bits
+            })
+            .unwrap_or(SnapshotContext::Backup);
+        let bits = vss::VSS_SNAPSHOT_CONTEXT::from(context);
+        (context, RawBitFlags::from_raw(raw & !bits))
+    }
+
+    /// Check that `attributes` can be combined with this context when it is
+    /// passed to `SetContext`.
+    ///
+    /// The documentation for `SetContext` only allows a context to be
+    /// combined with [`VolumeSnapshotAttributes::TRANSPORTABLE`],
+    /// [`VolumeSnapshotAttributes::ROLLBACK_RECOVERY`] and either
+    /// [`VolumeSnapshotAttributes::DIFFERENTIAL`] or
+    /// [`VolumeSnapshotAttributes::PLEX`]. Attributes that turn the context
+    /// into another supported context are also accepted.
+    pub fn validate_attributes(
+        self,
+        attributes: RawBitFlags<VolumeSnapshotAttributes>,
+    ) -> Result<(), InvalidSnapshotContext> {
+        if self == SnapshotContext::All {
+            return Ok(());
+        }
+        let modifiers = vss::VSS_VOLSNAP_ATTR_TRANSPORTABLE
+            | vss::VSS_VOLSNAP_ATTR_ROLLBACK_RECOVERY
+            | vss::VSS_VOLSNAP_ATTR_DIFFERENTIAL
+            | vss::VSS_VOLSNAP_ATTR_PLEX;
+        let raw = self.to_raw_context(attributes) as vss::VSS_SNAPSHOT_CONTEXT;
+        let is_supported = Self::PARSE_ORDER
+            .iter()
+            .any(|&context| vss::VSS_SNAPSHOT_CONTEXT::from(context) == raw & !modifiers);
+        if !is_supported {
+            return Err(InvalidSnapshotContext::UnsupportedAttributes {
+                context: self,
+                attributes,
+            });
+        }
+        let hardware = vss::VSS_VOLSNAP_ATTR_DIFFERENTIAL | vss::VSS_VOLSNAP_ATTR_PLEX;
+        if raw & hardware == hardware {
+            return Err(InvalidSnapshotContext::DifferentialAndPlex);
+        }
+        Ok(())
+    }
+}
+
+/// A [`SnapshotContext`] combined with attributes that are known to be a
+/// valid argument for `SetContext`.
+///
+/// Use one of the presets or [`SnapshotContextConfig::new`] and pass the
+/// result to
+/// [`IBackupComponents::set_context_config`](crate::vsbackup::IBackupComponents::set_context_config).
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotContextConfig {
+    context: SnapshotContext,
+    attributes: RawBitFlags<VolumeSnapshotAttributes>,
+}
+impl Default for SnapshotContextConfig {
+    fn default() -> Self {
+        Self::backup_with_writers()
+    }
+}
+impl SnapshotContextConfig {
+    /// Combine a context with attributes after checking that `SetContext`
+    /// supports the combination.
+    pub fn new(
+        context: SnapshotContext,
+        attributes: RawBitFlags<VolumeSnapshotAttributes>,
+    ) -> Result<Self, InvalidSnapshotContext> {
+        context.validate_attributes(attributes)?;
+        Ok(Self {
+            context,
+            attributes,
+        })
+    }
+    fn preset(context: SnapshotContext) -> Self {
+        Self {
+            context,
+            attributes: RawBitFlags::from_raw(0),
+        }
+    }
+    /// An auto-release shadow copy created with writer involvement. This is
+    /// the default context.
+    pub fn backup_with_writers() -> Self {
+        Self::preset(SnapshotContext::Backup)
+    }
+    /// An auto-release shadow copy created without writer involvement.
+    pub fn backup_without_writers() -> Self {
+        Self::preset(SnapshotContext::FileShareBackup)
+    }
+    /// A persistent shadow copy created with writer involvement.
+    pub fn persistent_with_writers() -> Self {
+        Self::preset(SnapshotContext::AppRollback)
+    }
+    /// A persistent shadow copy created without writer involvement.
+    pub fn persistent_no_writers() -> Self {
+        Self::preset(SnapshotContext::NasRollback)
+    }
+    /// A persistent shadow copy that can be accessed by users through the
+    /// "Previous Versions" feature. Writers are not involved.
+    pub fn client_accessible() -> Self {
+        Self::preset(SnapshotContext::ClientAccessible)
+    }
+    /// Like [`SnapshotContextConfig::client_accessible`] but with writer
+    /// involvement.
+    pub fn client_accessible_writers() -> Self {
+        Self::preset(SnapshotContext::ClientAccessibleWriters)
+    }
+    /// Make the shadow copies transportable so that they can be imported on
+    /// another machine.
+    pub fn transportable(mut self) -> Self {
+        self.attributes =
+            RawBitFlags::from_raw(self.attributes.raw() | vss::VSS_VOLSNAP_ATTR_TRANSPORTABLE);
+        self
+    }
+    /// The snapshot context without the extra attributes.
+    pub fn context(&self) -> SnapshotContext {
+        self.context
+    }
+    /// The attributes that are combined with the [`context`](Self::context).
+    pub fn attributes(&self) -> RawBitFlags<VolumeSnapshotAttributes> {
+        self.attributes
+    }
+    /// The raw value that is passed to `SetContext`.
+    pub fn to_raw_context(&self) -> LONG {
+        self.context.to_raw_context(self.attributes)
+    }
+}
+
+/// Error returned when a [`SnapshotContext`] is combined with attributes that
+/// `SetContext` doesn't support.
+#[derive(Debug, Clone, Copy)]
+pub enum InvalidSnapshotContext {
+    /// The attributes can't be combined with the context.
+    UnsupportedAttributes {
+        /// The context that was validated.
+        context: SnapshotContext,
+        /// The attributes that were rejected for `context`.
+        attributes: RawBitFlags<VolumeSnapshotAttributes>,
+    },
+    /// Only one of [`VolumeSnapshotAttributes::DIFFERENTIAL`] and
+    /// [`VolumeSnapshotAttributes::PLEX`] can be specified.
+    DifferentialAndPlex,
+}
+impl fmt::Display for InvalidSnapshotContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedAttributes {
+                context,
+                attributes,
+            } => write!(
+                f,
+                "the snapshot context {:?} can't be combined with the attributes {:?}",
+                context, attributes
+            ),
+            Self::DifferentialAndPlex => write!(
+                f,
+                "a snapshot context can't have both the differential and plex attributes"
+            ),
+        }
+    }
+}
+impl StdError for InvalidSnapshotContext {}
 
 with_from!(
     [raw = vss::VSS_BACKUP_TYPE, fallback = Undefined],
@@ -1055,6 +1977,25 @@ with_from!(
         COUNT = vss::VSS_WS_COUNT,
     }
 );
+impl WriterState {
+    /// `true` if the writer failed during one of the backup or restore
+    /// events.
+    pub fn is_failed(self) -> bool {
+        matches!(
+            self,
+            Self::FailedAtIdentify
+                | Self::FailedAtPrepareBackup
+                | Self::FailedAtPrepareSnapshot
+                | Self::FailedAtFreeze
+                | Self::FailedAtThaw
+                | Self::FailedAtPostSnapshot
+                | Self::FailedAtBackupComplete
+                | Self::FailedAtPreRestore
+                | Self::FailedAtPostRestore
+                | Self::FailedAtBackupShutdown
+        )
+    }
+}
 
 with_from!(
     [raw = vss::VSS_ROLLFORWARD_TYPE, fallback = Undefined],
@@ -1074,6 +2015,43 @@ with_from!(
     }
 );
 
+/// A roll-forward operation together with the restore point that it needs.
+///
+/// Used by
+/// [`IBackupComponentsEx2::set_roll_forward`](crate::vsbackup::IBackupComponentsEx2::set_roll_forward)
+/// so that a restore point is only given for a partial roll-forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollForward<'a> {
+    /// No roll-forward.
+    #[doc(alias = "VSS_RF_NONE")]
+    None,
+    /// Roll forward to the end of the available logs.
+    #[doc(alias = "VSS_RF_ALL")]
+    All,
+    /// Roll forward to a specific restore point. The format of the restore
+    /// point is defined by the writer.
+    #[doc(alias = "VSS_RF_PARTIAL")]
+    Partial { restore_point: &'a U16CStr },
+}
+impl<'a> RollForward<'a> {
+    /// The kind of roll-forward without the restore point, as passed to
+    /// `SetRollForward`.
+    pub fn roll_forward_type(&self) -> RollForwardType {
+        match self {
+            RollForward::None => RollForwardType::None,
+            RollForward::All => RollForwardType::All,
+            RollForward::Partial { .. } => RollForwardType::Partial,
+        }
+    }
+    /// The restore point of a [`RollForward::Partial`] roll-forward.
+    pub fn restore_point(&self) -> Option<&'a U16CStr> {
+        match *self {
+            RollForward::Partial { restore_point } => Some(restore_point),
+            _ => None,
+        }
+    }
+}
+
 with_from!(
     [raw = vss::VSS_PROVIDER_TYPE, fallback = Unknown],
     #[doc(alias = "VSS_PROVIDER_TYPE")]
@@ -1148,6 +2126,44 @@ with_from!(
     }
 );
 
+with_from!(
+    [raw = vss::VSS_SNAPSHOT_PROPERTY_ID, fallback = Unknown],
+    /// Specifies the field of the [`SnapshotProperties`] structure that is to be
+    /// modified.
+    #[doc(alias = "VSS_SNAPSHOT_PROPERTY_ID")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum SnapshotPropertyId {
+        #[doc(alias = "VSS_SPROPID_UNKNOWN")]
+        Unknown = vss::VSS_SPROPID_UNKNOWN,
+        #[doc(alias = "VSS_SPROPID_SNAPSHOT_ID")]
+        SnapshotId = vss::VSS_SPROPID_SNAPSHOT_ID,
+        #[doc(alias = "VSS_SPROPID_SNAPSHOT_SET_ID")]
+        SnapshotSetId = vss::VSS_SPROPID_SNAPSHOT_SET_ID,
+        #[doc(alias = "VSS_SPROPID_SNAPSHOTS_COUNT")]
+        SnapshotsCount = vss::VSS_SPROPID_SNAPSHOTS_COUNT,
+        #[doc(alias = "VSS_SPROPID_SNAPSHOT_DEVICE")]
+        SnapshotDevice = vss::VSS_SPROPID_SNAPSHOT_DEVICE,
+        #[doc(alias = "VSS_SPROPID_ORIGINAL_VOLUME")]
+        OriginalVolume = vss::VSS_SPROPID_ORIGINAL_VOLUME,
+        #[doc(alias = "VSS_SPROPID_ORIGINATING_MACHINE")]
+        OriginatingMachine = vss::VSS_SPROPID_ORIGINATING_MACHINE,
+        #[doc(alias = "VSS_SPROPID_SERVICE_MACHINE")]
+        ServiceMachine = vss::VSS_SPROPID_SERVICE_MACHINE,
+        #[doc(alias = "VSS_SPROPID_EXPOSED_NAME")]
+        ExposedName = vss::VSS_SPROPID_EXPOSED_NAME,
+        #[doc(alias = "VSS_SPROPID_EXPOSED_PATH")]
+        ExposedPath = vss::VSS_SPROPID_EXPOSED_PATH,
+        #[doc(alias = "VSS_SPROPID_PROVIDER_ID")]
+        ProviderId = vss::VSS_SPROPID_PROVIDER_ID,
+        #[doc(alias = "VSS_SPROPID_SNAPSHOT_ATTRIBUTES")]
+        SnapshotAttributes = vss::VSS_SPROPID_SNAPSHOT_ATTRIBUTES,
+        #[doc(alias = "VSS_SPROPID_CREATION_TIMESTAMP")]
+        CreationTimestamp = vss::VSS_SPROPID_CREATION_TIMESTAMP,
+        #[doc(alias = "VSS_SPROPID_STATUS")]
+        Status = vss::VSS_SPROPID_STATUS,
+    }
+);
+
 with_from!(
     [
         raw = vss::VSS_RESTORE_TYPE,
@@ -1169,3 +2185,280 @@ with_from!(
         Other = vss::VSS_RTYPE_OTHER,
     }
 );
+
+with_from!(
+    [raw = vss::VSS_APPLICATION_LEVEL, fallback = Unknown],
+    /// Indicates during which `Freeze` event a writer will be notified, which
+    /// determines the order that applications are frozen in.
+    #[doc(alias = "VSS_APPLICATION_LEVEL")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum ApplicationLevel {
+        #[doc(alias = "VSS_APP_UNKNOWN")]
+        Unknown = vss::VSS_APP_UNKNOWN,
+        #[doc(alias = "VSS_APP_SYSTEM")]
+        System = vss::VSS_APP_SYSTEM,
+        #[doc(alias = "VSS_APP_BACK_END")]
+        BackEnd = vss::VSS_APP_BACK_END,
+        #[doc(alias = "VSS_APP_FRONT_END")]
+        FrontEnd = vss::VSS_APP_FRONT_END,
+        #[doc(alias = "VSS_APP_SYSTEM_RM")]
+        SystemRm = vss::VSS_APP_SYSTEM_RM,
+        #[doc(alias = "VSS_APP_AUTO")]
+        Auto = vss::VSS_APP_AUTO,
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXTS: [SnapshotContext; 6] = [
+        SnapshotContext::Backup,
+        SnapshotContext::FileShareBackup,
+        SnapshotContext::NasRollback,
+        SnapshotContext::AppRollback,
+        SnapshotContext::ClientAccessible,
+        SnapshotContext::ClientAccessibleWriters,
+    ];
+
+    #[test]
+    fn wait_timeout_millis() {
+        assert_eq!(timeout_to_millis(None), INFINITE);
+        assert_eq!(timeout_to_millis(Some(Duration::ZERO)), 0);
+        assert_eq!(timeout_to_millis(Some(Duration::from_micros(1))), 1);
+        assert_eq!(timeout_to_millis(Some(Duration::from_micros(1500))), 2);
+        assert_eq!(timeout_to_millis(Some(Duration::from_secs(2))), 2000);
+        assert_eq!(
+            timeout_to_millis(Some(Duration::from_millis(u64::from(u32::MAX) - 1))),
+            u32::MAX - 1
+        );
+        assert_eq!(timeout_to_millis(Some(Duration::MAX)), INFINITE);
+    }
+
+    #[test]
+    fn cancellation_token_is_shared() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_canceled());
+        token.cancel();
+        assert!(clone.is_canceled());
+        assert!(!CancellationToken::new().is_canceled());
+    }
+
+    #[test]
+    fn wait_all_result() {
+        let result = WaitAllResult {
+            statuses: vec![
+                Ok(AsyncStatus::Finished),
+                Ok(AsyncStatus::Pending),
+                Err(vsserror::VSS_E_WRITERERROR_TIMEOUT),
+                Err(vsserror::VSS_E_BAD_STATE),
+            ],
+        };
+        assert_eq!(
+            result.first_failure(),
+            Some((2, vsserror::VSS_E_WRITERERROR_TIMEOUT))
+        );
+        assert!(!result.all_finished());
+
+        let result = WaitAllResult {
+            statuses: vec![Ok(AsyncStatus::Finished); 2],
+        };
+        assert_eq!(result.first_failure(), None);
+        assert!(result.all_finished());
+    }
+
+    #[test]
+    fn raw_context_without_attributes() {
+        for &context in CONTEXTS.iter() {
+            let raw = context.to_raw_context(RawBitFlags::default());
+            assert_eq!(raw, vss::VSS_SNAPSHOT_CONTEXT::from(context) as LONG);
+
+            let (parsed, attributes) = SnapshotContext::from_raw_context(raw);
+            assert_eq!(parsed, context);
+            assert_eq!(attributes.raw(), 0);
+        }
+    }
+
+    #[test]
+    fn raw_context_all() {
+        let raw =
+            SnapshotContext::All.to_raw_context(VolumeSnapshotAttributes::TRANSPORTABLE.into());
+        assert_eq!(raw, vss::VSS_CTX_ALL as LONG);
+
+        let (parsed, attributes) = SnapshotContext::from_raw_context(raw);
+        assert_eq!(parsed, SnapshotContext::All);
+        assert_eq!(attributes.raw(), 0);
+    }
+
+    #[test]
+    fn context_presets_are_valid() {
+        let presets = [
+            SnapshotContextConfig::backup_with_writers(),
+            SnapshotContextConfig::backup_without_writers(),
+            SnapshotContextConfig::persistent_with_writers(),
+            SnapshotContextConfig::persistent_no_writers(),
+            SnapshotContextConfig::client_accessible(),
+            SnapshotContextConfig::client_accessible_writers(),
+        ];
+        for preset in presets.iter() {
+            preset
+                .context()
+                .validate_attributes(preset.attributes())
+                .unwrap();
+            preset
+                .context()
+                .validate_attributes(preset.transportable().attributes())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn context_validation() {
+        // Turns into `FileShareBackup`:
+        SnapshotContext::Backup
+            .validate_attributes(VolumeSnapshotAttributes::NO_WRITERS.into())
+            .unwrap();
+        SnapshotContext::AppRollback
+            .validate_attributes(
+                (VolumeSnapshotAttributes::TRANSPORTABLE | VolumeSnapshotAttributes::PLEX).into(),
+            )
+            .unwrap();
+        assert!(matches!(
+            SnapshotContext::Backup.validate_attributes(
+                (VolumeSnapshotAttributes::DIFFERENTIAL | VolumeSnapshotAttributes::PLEX).into()
+            ),
+            Err(InvalidSnapshotContext::DifferentialAndPlex)
+        ));
+        assert!(matches!(
+            SnapshotContext::Backup
+                .validate_attributes(VolumeSnapshotAttributes::CLIENT_ACCESSIBLE.into()),
+            Err(InvalidSnapshotContext::UnsupportedAttributes { .. })
+        ));
+        assert!(matches!(
+            SnapshotContext::Backup
+                .validate_attributes(VolumeSnapshotAttributes::EXPOSED_LOCALLY.into()),
+            Err(InvalidSnapshotContext::UnsupportedAttributes { .. })
+        ));
+    }
+
+    #[test]
+    fn raw_context_round_trip() {
+        for &context in CONTEXTS.iter() {
+            let context_bits = vss::VSS_SNAPSHOT_CONTEXT::from(context);
+            for flag in (0..32).map(|bit| 1 << bit) {
+                let raw = context.to_raw_context(RawBitFlags::from_raw(flag));
+                let (parsed, attributes) = SnapshotContext::from_raw_context(raw);
+                assert_eq!(parsed.to_raw_context(attributes), raw);
+                if flag & context_bits != 0 {
+                    // Attribute was already part of the context:
+                    assert_eq!(parsed, context);
+                    assert_eq!(attributes.raw(), 0);
+                } else if parsed == context {
+                    assert_eq!(attributes.raw(), flag);
+                } else {
+                    // The extra attribute turned it into a more specific context:
+                    assert!(vss::VSS_SNAPSHOT_CONTEXT::from(parsed) & context_bits == context_bits);
+                    assert_eq!(attributes.raw() & flag, attributes.raw());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn raw_context_documented_attributes() {
+        let (context, attributes) = SnapshotContext::from_raw_context(
+            SnapshotContext::Backup.to_raw_context(
+                (VolumeSnapshotAttributes::PERSISTENT | VolumeSnapshotAttributes::NO_AUTO_RELEASE)
+                    .into(),
+            ),
+        );
+        assert_eq!(context, SnapshotContext::AppRollback);
+        assert_eq!(attributes.raw(), 0);
+
+        let (context, attributes) = SnapshotContext::from_raw_context(
+            SnapshotContext::ClientAccessibleWriters
+                .to_raw_context(VolumeSnapshotAttributes::NO_WRITERS.into()),
+        );
+        assert_eq!(context, SnapshotContext::ClientAccessible);
+        assert_eq!(attributes.raw(), 0);
+
+        let (context, attributes) = SnapshotContext::from_raw_context(
+            SnapshotContext::FileShareBackup.to_raw_context(
+                (VolumeSnapshotAttributes::TRANSPORTABLE
+                    | VolumeSnapshotAttributes::DIFFERENTIAL
+                    | VolumeSnapshotAttributes::HARDWARE_ASSISTED)
+                    .into(),
+            ),
+        );
+        assert_eq!(context, SnapshotContext::FileShareBackup);
+        assert_eq!(
+            attributes.flags(),
+            VolumeSnapshotAttributes::TRANSPORTABLE
+                | VolumeSnapshotAttributes::DIFFERENTIAL
+                | VolumeSnapshotAttributes::HARDWARE_ASSISTED
+        );
+    }
+
+    fn snapshot_with_device(device: &str) -> SnapshotProperties {
+        let device = U16CString::from_str(device).unwrap();
+        let volume =
+            U16CString::from_str("\\\\?\\Volume{00000000-0000-0000-0000-000000000000}\\").unwrap();
+        let machine = U16CString::from_str("host").unwrap();
+        SnapshotProperties::new(SnapshotPropertiesInfo {
+            snapshot_id: Default::default(),
+            snapshot_set_id: Default::default(),
+            snapshots_count: 1,
+            snapshot_device_object: &device,
+            original_volume_name: &volume,
+            originating_machine: &machine,
+            service_machine: &machine,
+            exposed_name: None,
+            exposed_path: None,
+            provider_id: Default::default(),
+            snapshot_attributes: RawBitFlags::from_raw(0),
+            creation_timestamp: 0,
+            status: SnapshotState::Created,
+        })
+    }
+
+    #[test]
+    fn wide_paths_in_snapshot() {
+        let wide = |text: &str| text.encode_utf16().collect::<Vec<u16>>();
+        let path = |snapshot: &SnapshotProperties, relative: &str| {
+            snapshot
+                .to_wide_path(&wide(relative))
+                .unwrap()
+                .to_string_lossy()
+        };
+        let snapshot = snapshot_with_device("\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1");
+
+        // Relative path:
+        assert_eq!(
+            path(&snapshot, "Users\\name\\file.txt"),
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\Users\\name\\file.txt"
+        );
+        assert_eq!(
+            path(&snapshot, "/Users/name/file.txt"),
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\Users\\name\\file.txt"
+        );
+        assert_eq!(
+            path(&snapshot, ""),
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1"
+        );
+        // Trailing separator:
+        assert_eq!(
+            path(&snapshot, "Users\\"),
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\Users\\"
+        );
+        // Verbatim device path that already ends with a separator:
+        let snapshot =
+            snapshot_with_device("\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy2\\");
+        assert_eq!(
+            path(&snapshot, "\\Users"),
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy2\\Users"
+        );
+
+        assert!(snapshot.to_wide_path(&[u16::from(b'a'), 0]).is_err());
+    }
+}
@@ -1,18 +1,23 @@
 //! Bindings for the `VSwriter.h` header.
 //!
 //! This is required when developing a VSS "writer" or a VSS "requester".
+//! The [`framework`] module can be used to implement a writer.
 //!
 //! # References
 //!
 //! [Vswriter.h header - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/api/vswriter/)
 
-use std::ptr::{null, null_mut};
+use std::{
+    convert::TryFrom,
+    ops::Deref,
+    ptr::{null, null_mut},
+};
 
 use widestring::U16CStr;
 use winapi::{
     shared::{
-        minwindef::{DWORD, UINT},
-        winerror::{HRESULT, S_OK},
+        minwindef::{BOOL, DWORD, FALSE, FILETIME, TRUE, UINT},
+        winerror::{HRESULT, S_FALSE, S_OK},
         wtypes::BSTR,
     },
     um::{
@@ -23,11 +28,221 @@ use winapi::{
 use winstr::BString;
 
 use super::{
-    check_com, errors::*, impl_query_interface, raw_bitflags, take_ownership_of_bstr,
-    transparent_wrapper, unsafe_deref_to_ref, unsafe_impl_as_IUnknown,
-    vss::FileSpecificationBackupType, with_from, RawBitFlags, SafeCOMComponent,
+    check_com,
+    errors::*,
+    impl_as_ref_and_borrow, impl_query_interface, raw_bitflags, take_ownership_of_bstr,
+    trace_com_call, transparent_wrapper, unsafe_deref_to_ref, unsafe_impl_as_IUnknown,
+    vss::{BackupSchema, FileSpecificationBackupType, RollForwardType},
+    with_from, RawBitFlags, SafeCOMComponent,
 };
 
+/// Raw declarations for interfaces in the `vswriter.h` header that `winapi`
+/// doesn't provide.
+#[allow(
+    non_snake_case,
+    non_upper_case_globals,
+    non_camel_case_types,
+    clippy::too_many_arguments
+)]
+pub mod raw {
+    use winapi::{
+        ctypes::c_void,
+        shared::{
+            minwindef::{BYTE, DWORD, UINT},
+            wtypes::BSTR,
+        },
+        um::{
+            unknwnbase::{IUnknown, IUnknownVtbl},
+            vss::VSS_ID,
+            vswriter::{
+                VSS_COMPONENT_TYPE, VSS_RESTOREMETHOD_ENUM, VSS_USAGE_TYPE, VSS_WRITERRESTORE_ENUM,
+            },
+            winnt::{HRESULT, LPCWSTR},
+        },
+        RIDL,
+    };
+
+    // `winapi` declares this interface too but its `AddComponent` method is
+    // missing the `bSelectable` parameter, which shifts every argument after
+    // it. The other methods match the SDK.
+    RIDL! {#[uuid(0x00000000, 0x0000, 0x0000, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00)]
+    interface IVssCreateWriterMetadata(IVssCreateWriterMetadataVtbl) {
+        fn AddIncludeFiles(
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            bRecursive: bool,
+            wszAlternateLocation: LPCWSTR,
+        ) -> HRESULT,
+        fn AddExcludeFiles(
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            bRecursive: bool,
+        ) -> HRESULT,
+        fn AddComponent(
+            ct: VSS_COMPONENT_TYPE,
+            wszLogicalPath: LPCWSTR,
+            wszComponentName: LPCWSTR,
+            wszCaption: LPCWSTR,
+            pbIcon: *const BYTE,
+            cbIcon: UINT,
+            bRestoreMetadata: bool,
+            bNotifyOnBackupComplete: bool,
+            bSelectable: bool,
+            bSelectableForRestore: bool,
+            dwComponentFlags: DWORD,
+        ) -> HRESULT,
+        fn AddDatabaseFiles(
+            wszLogicalPath: LPCWSTR,
+            wszDatabaseName: LPCWSTR,
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            dwBackupTypeMask: DWORD,
+        ) -> HRESULT,
+        fn AddDatabaseLogFiles(
+            wszLogicalPath: LPCWSTR,
+            wszDatabaseName: LPCWSTR,
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            dwBackupTypeMask: DWORD,
+        ) -> HRESULT,
+        fn AddFilesToFileGroup(
+            wszLogicalPath: LPCWSTR,
+            wszGroupName: LPCWSTR,
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            bRecursive: bool,
+            wszAlternateLocation: LPCWSTR,
+            dwBackupTypeMask: DWORD,
+        ) -> HRESULT,
+        fn SetRestoreMethod(
+            method: VSS_RESTOREMETHOD_ENUM,
+            wszService: LPCWSTR,
+            wszUserProcedure: LPCWSTR,
+            writerRestore: VSS_WRITERRESTORE_ENUM,
+            bRebootRequired: bool,
+        ) -> HRESULT,
+        fn AddAlternateLocationMapping(
+            wszSourcePath: LPCWSTR,
+            wszSourceFilespec: LPCWSTR,
+            bRecursive: bool,
+            wszDestination: LPCWSTR,
+        ) -> HRESULT,
+        fn AddComponentDependency(
+            wszForLogicalPath: LPCWSTR,
+            wszForComponentName: LPCWSTR,
+            onWriterId: VSS_ID,
+            wszOnLogicalPath: LPCWSTR,
+            wszOnComponentName: LPCWSTR,
+        ) -> HRESULT,
+        fn SetBackupSchema(
+            dwSchemaMask: DWORD,
+        ) -> HRESULT,
+        fn GetDocument(
+            pDoc: *mut *mut c_void,
+        ) -> HRESULT,
+        fn SaveAsXML(
+            pbstrXML: *mut BSTR,
+        ) -> HRESULT,
+    }}
+
+    // The C++ class also inherits from `IUnknown` which places a second
+    // vtable pointer after `lpVtbl`. We never access it, so it is left out
+    // of the layout here.
+    RIDL! {#[uuid(0x9f21981d, 0xd469, 0x4349, 0xb8, 0x07, 0x39, 0xe6, 0x4e, 0x46, 0x74, 0xe1)]
+    interface IVssCreateWriterMetadataEx(IVssCreateWriterMetadataExVtbl):
+        IVssCreateWriterMetadata(IVssCreateWriterMetadataVtbl) {
+        fn AddExcludeFilesFromSnapshot(
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            bRecursive: bool,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x9c772e77, 0xb26e, 0x427f, 0x92, 0xdd, 0xc9, 0x96, 0xf4, 0x1e, 0xa5, 0xe3)]
+    interface IVssCreateExpressWriterMetadata(IVssCreateExpressWriterMetadataVtbl):
+        IUnknown(IUnknownVtbl) {
+        fn AddExcludeFiles(
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            bRecursive: bool,
+        ) -> HRESULT,
+        fn AddComponent(
+            ct: VSS_COMPONENT_TYPE,
+            wszLogicalPath: LPCWSTR,
+            wszComponentName: LPCWSTR,
+            wszCaption: LPCWSTR,
+            pbIcon: *const BYTE,
+            cbIcon: UINT,
+            bRestoreMetadata: bool,
+            bNotifyOnBackupComplete: bool,
+            bSelectable: bool,
+            bSelectableForRestore: bool,
+            dwComponentFlags: DWORD,
+        ) -> HRESULT,
+        fn AddFilesToFileGroup(
+            wszLogicalPath: LPCWSTR,
+            wszGroupName: LPCWSTR,
+            wszPath: LPCWSTR,
+            wszFilespec: LPCWSTR,
+            bRecursive: bool,
+            wszAlternateLocation: LPCWSTR,
+            dwBackupTypeMask: DWORD,
+        ) -> HRESULT,
+        fn SetRestoreMethod(
+            method: VSS_RESTOREMETHOD_ENUM,
+            wszService: LPCWSTR,
+            wszUserProcedure: LPCWSTR,
+            writerRestore: VSS_WRITERRESTORE_ENUM,
+            bRebootRequired: bool,
+        ) -> HRESULT,
+        fn AddComponentDependency(
+            wszForLogicalPath: LPCWSTR,
+            wszForComponentName: LPCWSTR,
+            onWriterId: VSS_ID,
+            wszOnLogicalPath: LPCWSTR,
+            wszOnComponentName: LPCWSTR,
+        ) -> HRESULT,
+        fn SetBackupSchema(
+            dwSchemaMask: DWORD,
+        ) -> HRESULT,
+        fn SaveAsXML(
+            pbstrXML: *mut BSTR,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xe33affdc, 0x59c7, 0x47b1, 0x97, 0xd5, 0x42, 0x66, 0x59, 0x8f, 0x62, 0x35)]
+    interface IVssExpressWriter(IVssExpressWriterVtbl): IUnknown(IUnknownVtbl) {
+        fn CreateMetadata(
+            writerId: VSS_ID,
+            writerName: LPCWSTR,
+            usageType: VSS_USAGE_TYPE,
+            versionMajor: DWORD,
+            versionMinor: DWORD,
+            reserved: DWORD,
+            ppMetadata: *mut *mut IVssCreateExpressWriterMetadata,
+        ) -> HRESULT,
+        fn LoadMetadata(
+            metadata: LPCWSTR,
+            reserved: DWORD,
+        ) -> HRESULT,
+        fn Register() -> HRESULT,
+        fn Unregister(
+            writerId: VSS_ID,
+        ) -> HRESULT,
+    }}
+
+    #[link(name = "vssapi")]
+    extern "system" {
+        #[link_name = "CreateVssExpressWriterInternal"]
+        pub fn CreateVssExpressWriter(ppWriter: *mut *mut IVssExpressWriter) -> HRESULT;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub mod framework;
+#[cfg(all(feature = "service", target_arch = "x86_64"))]
+pub mod service;
+
 ////////////////////////////////////////////////////////////////////////////////
 // IVssWMDependency
 ////////////////////////////////////////////////////////////////////////////////
@@ -54,6 +269,7 @@ impl IWMDependency {
     /// in an explicit writer-component dependency.
     #[doc(alias = "GetComponentName")]
     pub fn get_component_name(&self) -> Result<BString, GetComponentNameError> {
+        trace_com_call!("GetComponentName");
         let mut component_name: BSTR = null_mut();
         let hr = unsafe { self.0.GetComponentName(&mut component_name) };
         let component_name = unsafe { take_ownership_of_bstr(component_name) };
@@ -66,6 +282,7 @@ impl IWMDependency {
     /// depends on in explicit writer-component dependency.
     #[doc(alias = "GetLogicalPath")]
     pub fn get_logical_path(&self) -> Result<BString, GetLogicalPathError> {
+        trace_com_call!("GetLogicalPath");
         let mut logical_path: BSTR = null_mut();
         let hr = unsafe { self.0.GetLogicalPath(&mut logical_path) };
         let component_name = unsafe { take_ownership_of_bstr(logical_path) };
@@ -78,6 +295,7 @@ impl IWMDependency {
     /// component depends on in an explicit writer-component dependency.
     #[doc(alias = "GetWriterId")]
     pub fn get_writer_id(&self) -> Result<VSS_ID, GetWriterIdError> {
+        trace_com_call!("GetWriterId");
         let mut writer_id: VSS_ID = Default::default();
         check_com(unsafe { self.0.GetWriterId(&mut writer_id) })?;
         Ok(writer_id)
@@ -108,6 +326,7 @@ impl IWMFileDescriptor {
     /// Obtains an alternate location for a file set.
     #[doc(alias = "GetAlternateLocation")]
     pub fn get_alternate_location(&self) -> Result<Option<BString>, GetAlternateLocationError> {
+        trace_com_call!("GetAlternateLocation");
         let mut alternate_location: BSTR = null_mut();
         let result = check_com(unsafe { self.0.GetAlternateLocation(&mut alternate_location) });
         let alternate_location = unsafe { take_ownership_of_bstr(alternate_location) };
@@ -123,6 +342,7 @@ impl IWMFileDescriptor {
     pub fn get_backup_type_mask(
         &self,
     ) -> Result<RawBitFlags<FileSpecificationBackupType>, GetBackupTypeMaskError> {
+        trace_com_call!("GetBackupTypeMask");
         let mut type_mask: vss::VSS_FILE_SPEC_BACKUP_TYPE = 0;
         check_com(unsafe { self.0.GetBackupTypeMask(&mut type_mask) })?;
         Ok(RawBitFlags::from_raw(type_mask))
@@ -137,6 +357,7 @@ impl IWMFileDescriptor {
     /// no backslashes) but can contain the ? and * wildcard characters.
     #[doc(alias = "GetFilespec")]
     pub fn get_file_specification(&self) -> Result<BString, GetFilespecError> {
+        trace_com_call!("GetFilespec");
         let mut file_spec: BSTR = null_mut();
         let result = check_com(unsafe { self.0.GetFilespec(&mut file_spec) });
         let file_spec = unsafe { take_ownership_of_bstr(file_spec) };
@@ -161,6 +382,7 @@ impl IWMFileDescriptor {
     /// [Naming a File]: https://docs.microsoft.com/en-us/windows/desktop/FileIO/naming-a-file
     #[doc(alias = "GetPath")]
     pub fn get_path(&self) -> Result<BString, GetPathError> {
+        trace_com_call!("GetPath");
         let mut path: BSTR = null_mut();
         let result = check_com(unsafe { self.0.GetPath(&mut path) });
         let path = unsafe { take_ownership_of_bstr(path) };
@@ -181,6 +403,7 @@ impl IWMFileDescriptor {
     /// [Working with Mounted Folders and Reparse Points](https://docs.microsoft.com/en-us/windows/desktop/VSS/working-with-reparse-and-mount-points).
     #[doc(alias = "GetRecursive")]
     pub fn get_recursive(&self) -> Result<bool, GetRecursiveError> {
+        trace_com_call!("GetRecursive");
         let mut recursive = false;
         check_com(unsafe { self.0.GetRecursive(&mut recursive) })?;
         Ok(recursive)
@@ -204,6 +427,7 @@ impl IWriterComponents {
         &self,
         component_index: u32,
     ) -> Result<Component, WriterComponentsGetComponentError> {
+        trace_com_call!("GetComponent");
         let mut component: *mut vswriter::IVssComponent = null_mut();
         check_com(unsafe { self.0.GetComponent(component_index, &mut component) })?;
         Ok(Component(unsafe { SafeCOMComponent::new(component) }))
@@ -212,14 +436,29 @@ impl IWriterComponents {
     /// the Backup Components Document.
     #[doc(alias = "GetComponentCount")]
     pub fn get_component_count(&self) -> Result<u32, GetComponentCountError> {
+        trace_com_call!("GetComponentCount");
         let mut components: UINT = 0;
         check_com(unsafe { self.0.GetComponentCount(&mut components) })?;
         Ok(components)
     }
+    /// Iterate over the writer's components that are stored in the Backup
+    /// Components Document, combining
+    /// [`IWriterComponents::get_component_count`] and
+    /// [`IWriterComponents::get_component`].
+    pub fn components(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<Component, WriterComponentsGetComponentError>> + '_,
+        GetComponentCountError,
+    > {
+        let count = self.get_component_count()?;
+        Ok((0..count).map(move |index| self.get_component(index)))
+    }
     /// Gets the instance and class identifier of the writer responsible for the
     /// components.
     #[doc(alias = "GetWriterInfo")]
     pub fn get_writer_info(&self) -> Result<WriterInfo, GetWriterInfoError> {
+        trace_com_call!("GetWriterInfo");
         let mut info = WriterInfo {
             instance_id: Default::default(),
             writer_id: Default::default(),
@@ -258,6 +497,674 @@ transparent_wrapper!(
 // Safety: all wrappers ensure their wrapped values are valid to use (Not released).
 unsafe_deref_to_ref!(Component => IComponent);
 
+impl IComponent {
+    /// Obtains the logical path of the component. Returns `None` if the
+    /// component doesn't have a logical path.
+    #[doc(alias = "GetLogicalPath")]
+    pub fn get_logical_path(&self) -> Result<Option<BString>, ComponentGetLogicalPathError> {
+        trace_com_call!("GetLogicalPath");
+        let mut logical_path: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetLogicalPath(&mut logical_path) });
+        let logical_path = unsafe { take_ownership_of_bstr(logical_path) };
+        result?;
+        Ok(logical_path.unwrap())
+    }
+    /// Obtains the logical name of the component.
+    #[doc(alias = "GetComponentName")]
+    pub fn get_component_name(&self) -> Result<BString, ComponentGetComponentNameError> {
+        trace_com_call!("GetComponentName");
+        let mut component_name: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetComponentName(&mut component_name) });
+        let component_name = unsafe { take_ownership_of_bstr(component_name) };
+        result?;
+        Ok(component_name
+            .unwrap()
+            .expect("The component name returned from GetComponentName shouldn't be null"))
+    }
+    /// Obtains the type of the component.
+    #[doc(alias = "GetComponentType")]
+    pub fn get_component_type(&self) -> Result<VssComponentType, GetComponentTypeError> {
+        trace_com_call!("GetComponentType");
+        let mut component_type: vswriter::VSS_COMPONENT_TYPE = vswriter::VSS_CT_UNDEFINED;
+        check_com(unsafe { self.0.GetComponentType(&mut component_type) })?;
+        Ok(component_type.into())
+    }
+    /// Returns the status of a complete attempt at backing up all the files of
+    /// a selected component or component set as set by the requester using
+    /// [`IBackupComponents::set_backup_succeeded`].
+    ///
+    /// [`IBackupComponents::set_backup_succeeded`]: crate::vsbackup::IBackupComponents::set_backup_succeeded
+    #[doc(alias = "GetBackupSucceeded")]
+    pub fn get_backup_succeeded(&self) -> Result<bool, GetBackupSucceededError> {
+        trace_com_call!("GetBackupSucceeded");
+        let mut succeeded = false;
+        check_com(unsafe { self.0.GetBackupSucceeded(&mut succeeded) })?;
+        Ok(succeeded)
+    }
+    /// Returns the number of alternate location mappings used in a file restore
+    /// operation.
+    #[doc(alias = "GetAlternateLocationMappingCount")]
+    pub fn get_alternate_location_mapping_count(
+        &self,
+    ) -> Result<u32, GetAlternateLocationMappingCountError> {
+        trace_com_call!("GetAlternateLocationMappingCount");
+        let mut count: UINT = 0;
+        check_com(unsafe { self.0.GetAlternateLocationMappingCount(&mut count) })?;
+        Ok(count)
+    }
+    /// Returns a file descriptor for an alternate location mapping of a file to
+    /// be used during a restore operation. The index must be less than the
+    /// value returned by [`IComponent::get_alternate_location_mapping_count`].
+    #[doc(alias = "GetAlternateLocationMapping")]
+    pub fn get_alternate_location_mapping(
+        &self,
+        mapping_index: u32,
+    ) -> Result<WMFileDescriptor, ComponentGetAlternateLocationMappingError> {
+        trace_com_call!("GetAlternateLocationMapping");
+        let mut file_desc: *mut vswriter::IVssWMFiledesc = null_mut();
+        check_com(unsafe {
+            self.0
+                .GetAlternateLocationMapping(mapping_index, &mut file_desc)
+        })?;
+        Ok(WMFileDescriptor(unsafe {
+            SafeCOMComponent::new(file_desc)
+        }))
+    }
+    /// Retrieves the private metadata that the writer stored with the component
+    /// during the backup operation. Returns `None` if no metadata was stored.
+    #[doc(alias = "GetBackupMetadata")]
+    pub fn get_backup_metadata(&self) -> Result<Option<BString>, GetBackupMetadataError> {
+        trace_com_call!("GetBackupMetadata");
+        let mut metadata: BSTR = null_mut();
+        let hr = unsafe { self.0.GetBackupMetadata(&mut metadata) };
+        let metadata = unsafe { take_ownership_of_bstr(metadata) };
+        if hr == S_FALSE {
+            return Ok(None);
+        }
+        check_com(hr)?;
+        Ok(metadata.unwrap())
+    }
+    /// Returns the number of partial files associated with the component.
+    #[doc(alias = "GetPartialFileCount")]
+    pub fn get_partial_file_count(&self) -> Result<u32, GetPartialFileCountError> {
+        trace_com_call!("GetPartialFileCount");
+        let mut count: UINT = 0;
+        check_com(unsafe { self.0.GetPartialFileCount(&mut count) })?;
+        Ok(count)
+    }
+    /// Obtains information about a file with a partial backup. The index must
+    /// be less than the value returned by
+    /// [`IComponent::get_partial_file_count`].
+    #[doc(alias = "GetPartialFile")]
+    pub fn get_partial_file(
+        &self,
+        partial_file_index: u32,
+    ) -> Result<PartialFile, GetPartialFileError> {
+        trace_com_call!("GetPartialFile");
+        let mut path: BSTR = null_mut();
+        let mut file_name: BSTR = null_mut();
+        let mut ranges: BSTR = null_mut();
+        let mut metadata: BSTR = null_mut();
+        let result = check_com(unsafe {
+            self.0.GetPartialFile(
+                partial_file_index,
+                &mut path,
+                &mut file_name,
+                &mut ranges,
+                &mut metadata,
+            )
+        });
+        let path = unsafe { take_ownership_of_bstr(path) };
+        let file_name = unsafe { take_ownership_of_bstr(file_name) };
+        let ranges = unsafe { take_ownership_of_bstr(ranges) };
+        let metadata = unsafe { take_ownership_of_bstr(metadata) };
+        result?;
+        Ok(PartialFile {
+            path: path
+                .unwrap()
+                .expect("The path returned by GetPartialFile shouldn't be null"),
+            file_name: file_name
+                .unwrap()
+                .expect("The file name returned by GetPartialFile shouldn't be null"),
+            ranges: ranges.unwrap(),
+            metadata: metadata.unwrap(),
+        })
+    }
+    /// Determines whether the component has been selected to be restored.
+    #[doc(alias = "IsSelectedForRestore")]
+    pub fn is_selected_for_restore(&self) -> Result<bool, IsSelectedForRestoreError> {
+        trace_com_call!("IsSelectedForRestore");
+        let mut selected = false;
+        check_com(unsafe { self.0.IsSelectedForRestore(&mut selected) })?;
+        Ok(selected)
+    }
+    /// Determines whether the requester has marked the restore of the
+    /// component as authoritative for a replicated data store and whether
+    /// additional restores will follow, see
+    /// [`IBackupComponents::set_additional_restores`].
+    ///
+    /// [`IBackupComponents::set_additional_restores`]: crate::vsbackup::IBackupComponents::set_additional_restores
+    #[doc(alias = "GetAdditionalRestores")]
+    pub fn get_additional_restores(&self) -> Result<bool, GetAdditionalRestoresError> {
+        trace_com_call!("GetAdditionalRestores");
+        let mut additional_restores = false;
+        check_com(unsafe { self.0.GetAdditionalRestores(&mut additional_restores) })?;
+        Ok(additional_restores)
+    }
+    /// Returns the number of alternate locations that were used when restoring
+    /// the component's files.
+    #[doc(alias = "GetNewTargetCount")]
+    pub fn get_new_target_count(&self) -> Result<u32, GetNewTargetCountError> {
+        trace_com_call!("GetNewTargetCount");
+        let mut count: UINT = 0;
+        check_com(unsafe { self.0.GetNewTargetCount(&mut count) })?;
+        Ok(count)
+    }
+    /// Returns a file descriptor for a new file restoration location. The index
+    /// must be less than the value returned by
+    /// [`IComponent::get_new_target_count`].
+    #[doc(alias = "GetNewTarget")]
+    pub fn get_new_target(
+        &self,
+        new_target_index: u32,
+    ) -> Result<WMFileDescriptor, GetNewTargetError> {
+        trace_com_call!("GetNewTarget");
+        let mut file_desc: *mut vswriter::IVssWMFiledesc = null_mut();
+        check_com(unsafe { self.0.GetNewTarget(new_target_index, &mut file_desc) })?;
+        Ok(WMFileDescriptor(unsafe {
+            SafeCOMComponent::new(file_desc)
+        }))
+    }
+    /// Iterate over the new restore locations of the component's files,
+    /// combining [`IComponent::get_new_target_count`] and
+    /// [`IComponent::get_new_target`].
+    pub fn new_targets(
+        &self,
+    ) -> Result<
+        impl ExactSizeIterator<Item = Result<WMFileDescriptor, GetNewTargetError>> + '_,
+        GetNewTargetCountError,
+    > {
+        let count = self.get_new_target_count()?;
+        Ok((0..count).map(move |index| self.get_new_target(index)))
+    }
+    /// Returns the number of directed target restore operations specified for
+    /// the component.
+    #[doc(alias = "GetDirectedTargetCount")]
+    pub fn get_directed_target_count(&self) -> Result<u32, GetDirectedTargetCountError> {
+        trace_com_call!("GetDirectedTargetCount");
+        let mut count: UINT = 0;
+        check_com(unsafe { self.0.GetDirectedTargetCount(&mut count) })?;
+        Ok(count)
+    }
+    /// Obtains information about how the contents of a file should be mapped
+    /// to new locations during a directed target restore. The index must be
+    /// less than the value returned by
+    /// [`IComponent::get_directed_target_count`].
+    #[doc(alias = "GetDirectedTarget")]
+    pub fn get_directed_target(
+        &self,
+        directed_target_index: u32,
+    ) -> Result<DirectedTarget, GetDirectedTargetError> {
+        trace_com_call!("GetDirectedTarget");
+        let mut source_path: BSTR = null_mut();
+        let mut source_file_name: BSTR = null_mut();
+        let mut source_ranges: BSTR = null_mut();
+        let mut destination_path: BSTR = null_mut();
+        let mut destination_file_name: BSTR = null_mut();
+        let mut destination_ranges: BSTR = null_mut();
+        let result = check_com(unsafe {
+            self.0.GetDirectedTarget(
+                directed_target_index,
+                &mut source_path,
+                &mut source_file_name,
+                &mut source_ranges,
+                &mut destination_path,
+                &mut destination_file_name,
+                &mut destination_ranges,
+            )
+        });
+        let source_path = unsafe { take_ownership_of_bstr(source_path) };
+        let source_file_name = unsafe { take_ownership_of_bstr(source_file_name) };
+        let source_ranges = unsafe { take_ownership_of_bstr(source_ranges) };
+        let destination_path = unsafe { take_ownership_of_bstr(destination_path) };
+        let destination_file_name = unsafe { take_ownership_of_bstr(destination_file_name) };
+        let destination_ranges = unsafe { take_ownership_of_bstr(destination_ranges) };
+        result?;
+        Ok(DirectedTarget {
+            source_path: source_path
+                .unwrap()
+                .expect("The source path returned by GetDirectedTarget shouldn't be null"),
+            source_file_name: source_file_name
+                .unwrap()
+                .expect("The source file name returned by GetDirectedTarget shouldn't be null"),
+            source_ranges: source_ranges.unwrap(),
+            destination_path: destination_path
+                .unwrap()
+                .expect("The destination path returned by GetDirectedTarget shouldn't be null"),
+            destination_file_name: destination_file_name.unwrap().expect(
+                "The destination file name returned by GetDirectedTarget shouldn't be null",
+            ),
+            destination_ranges: destination_ranges.unwrap(),
+        })
+    }
+    /// Retrieves the private restore metadata that was set by the writer
+    /// during a `PreRestore` event. Returns `None` if no metadata was set.
+    #[doc(alias = "GetRestoreMetadata")]
+    pub fn get_restore_metadata(&self) -> Result<Option<BString>, GetRestoreMetadataError> {
+        trace_com_call!("GetRestoreMetadata");
+        let mut metadata: BSTR = null_mut();
+        let hr = unsafe { self.0.GetRestoreMetadata(&mut metadata) };
+        let metadata = unsafe { take_ownership_of_bstr(metadata) };
+        if hr == S_FALSE {
+            return Ok(None);
+        }
+        check_com(hr)?;
+        Ok(metadata.unwrap())
+    }
+    /// Returns the restore target that the writer specified for the component.
+    #[doc(alias = "GetRestoreTarget")]
+    pub fn get_restore_target(&self) -> Result<RestoreTarget, GetRestoreTargetError> {
+        trace_com_call!("GetRestoreTarget");
+        let mut target: vswriter::VSS_RESTORE_TARGET = vswriter::VSS_RT_UNDEFINED;
+        check_com(unsafe { self.0.GetRestoreTarget(&mut target) })?;
+        Ok(target.into())
+    }
+    /// Obtains the error message that the writer set if the component failed
+    /// during a `PreRestore` event.
+    #[doc(alias = "GetPreRestoreFailureMsg")]
+    pub fn get_pre_restore_failure_msg(
+        &self,
+    ) -> Result<Option<BString>, GetPreRestoreFailureMsgError> {
+        trace_com_call!("GetPreRestoreFailureMsg");
+        let mut message: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetPreRestoreFailureMsg(&mut message) });
+        let message = unsafe { take_ownership_of_bstr(message) };
+        result?;
+        Ok(message.unwrap())
+    }
+    /// Obtains the error message that the writer set if the component failed
+    /// during a `PostRestore` event.
+    #[doc(alias = "GetPostRestoreFailureMsg")]
+    pub fn get_post_restore_failure_msg(
+        &self,
+    ) -> Result<Option<BString>, GetPostRestoreFailureMsgError> {
+        trace_com_call!("GetPostRestoreFailureMsg");
+        let mut message: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetPostRestoreFailureMsg(&mut message) });
+        let message = unsafe { take_ownership_of_bstr(message) };
+        result?;
+        Ok(message.unwrap())
+    }
+    /// Obtains the backup stamp that the writer stored with the component
+    /// during the backup. Requesters store this value and pass it back with
+    /// [`IBackupComponents::set_previous_backup_stamp`] for incremental and
+    /// differential backups.
+    ///
+    /// [`IBackupComponents::set_previous_backup_stamp`]: crate::vsbackup::IBackupComponents::set_previous_backup_stamp
+    #[doc(alias = "GetBackupStamp")]
+    pub fn get_backup_stamp(&self) -> Result<Option<BString>, GetBackupStampError> {
+        trace_com_call!("GetBackupStamp");
+        let mut stamp: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetBackupStamp(&mut stamp) });
+        let stamp = unsafe { take_ownership_of_bstr(stamp) };
+        result?;
+        Ok(stamp.unwrap())
+    }
+    /// Obtains the backup stamp of an earlier backup that the requester set
+    /// for an incremental or differential backup.
+    #[doc(alias = "GetPreviousBackupStamp")]
+    pub fn get_previous_backup_stamp(
+        &self,
+    ) -> Result<Option<BString>, GetPreviousBackupStampError> {
+        trace_com_call!("GetPreviousBackupStamp");
+        let mut stamp: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetPreviousBackupStamp(&mut stamp) });
+        let stamp = unsafe { take_ownership_of_bstr(stamp) };
+        result?;
+        Ok(stamp.unwrap())
+    }
+    /// Obtains the backup options that the requester specified for the
+    /// component using [`IBackupComponents::set_backup_options`].
+    ///
+    /// [`IBackupComponents::set_backup_options`]: crate::vsbackup::IBackupComponents::set_backup_options
+    #[doc(alias = "GetBackupOptions")]
+    pub fn get_backup_options(&self) -> Result<Option<BString>, GetBackupOptionsError> {
+        trace_com_call!("GetBackupOptions");
+        let mut options: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetBackupOptions(&mut options) });
+        let options = unsafe { take_ownership_of_bstr(options) };
+        result?;
+        Ok(options.unwrap())
+    }
+    /// Obtains the restore options that the requester specified for the
+    /// component using [`IBackupComponents::set_restore_options`].
+    ///
+    /// [`IBackupComponents::set_restore_options`]: crate::vsbackup::IBackupComponents::set_restore_options
+    #[doc(alias = "GetRestoreOptions")]
+    pub fn get_restore_options(&self) -> Result<Option<BString>, GetRestoreOptionsError> {
+        trace_com_call!("GetRestoreOptions");
+        let mut options: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetRestoreOptions(&mut options) });
+        let options = unsafe { take_ownership_of_bstr(options) };
+        result?;
+        Ok(options.unwrap())
+    }
+    /// Returns the number of subcomponents of the component that have been
+    /// added for restore using [`IBackupComponents::add_restore_subcomponent`].
+    ///
+    /// [`IBackupComponents::add_restore_subcomponent`]: crate::vsbackup::IBackupComponents::add_restore_subcomponent
+    #[doc(alias = "GetRestoreSubcomponentCount")]
+    pub fn get_restore_subcomponent_count(&self) -> Result<u32, GetRestoreSubcomponentCountError> {
+        trace_com_call!("GetRestoreSubcomponentCount");
+        let mut count: UINT = 0;
+        check_com(unsafe { self.0.GetRestoreSubcomponentCount(&mut count) })?;
+        Ok(count)
+    }
+    /// Returns the specified subcomponent that was added for restore. The
+    /// index must be less than the value returned by
+    /// [`IComponent::get_restore_subcomponent_count`].
+    #[doc(alias = "GetRestoreSubcomponent")]
+    pub fn get_restore_subcomponent(
+        &self,
+        subcomponent_index: u32,
+    ) -> Result<RestoreSubcomponent, GetRestoreSubcomponentError> {
+        trace_com_call!("GetRestoreSubcomponent");
+        let mut logical_path: BSTR = null_mut();
+        let mut component_name: BSTR = null_mut();
+        let mut repair = false;
+        let result = check_com(unsafe {
+            self.0.GetRestoreSubcomponent(
+                subcomponent_index,
+                &mut logical_path,
+                &mut component_name,
+                &mut repair,
+            )
+        });
+        let logical_path = unsafe { take_ownership_of_bstr(logical_path) };
+        let component_name = unsafe { take_ownership_of_bstr(component_name) };
+        result?;
+        Ok(RestoreSubcomponent {
+            logical_path: logical_path.unwrap(),
+            component_name: component_name
+                .unwrap()
+                .expect("The component name returned by GetRestoreSubcomponent shouldn't be null"),
+            repair,
+        })
+    }
+    /// Returns the status of a restore of the component's files, as set by the
+    /// requester using [`IBackupComponents::set_file_restore_status`].
+    ///
+    /// [`IBackupComponents::set_file_restore_status`]: crate::vsbackup::IBackupComponents::set_file_restore_status
+    #[doc(alias = "GetFileRestoreStatus")]
+    pub fn get_file_restore_status(&self) -> Result<FileRestoreStatus, GetFileRestoreStatusError> {
+        trace_com_call!("GetFileRestoreStatus");
+        let mut status: vswriter::VSS_FILE_RESTORE_STATUS = vswriter::VSS_RS_UNDEFINED;
+        check_com(unsafe { self.0.GetFileRestoreStatus(&mut status) })?;
+        Ok(status.into())
+    }
+    /// Returns the number of file sets that the writer marked as changed since
+    /// an earlier backup, for use in incremental and differential backups.
+    #[doc(alias = "GetDifferencedFilesCount")]
+    pub fn get_differenced_files_count(&self) -> Result<u32, GetDifferencedFilesCountError> {
+        trace_com_call!("GetDifferencedFilesCount");
+        let mut count: UINT = 0;
+        check_com(unsafe { self.0.GetDifferencedFilesCount(&mut count) })?;
+        Ok(count)
+    }
+    /// Returns information about a file set that should participate in an
+    /// incremental or differential backup. The index must be less than the
+    /// value returned by [`IComponent::get_differenced_files_count`].
+    #[doc(alias = "GetDifferencedFile")]
+    pub fn get_differenced_file(
+        &self,
+        differenced_file_index: u32,
+    ) -> Result<DifferencedFile, GetDifferencedFileError> {
+        trace_com_call!("GetDifferencedFile");
+        let mut path: BSTR = null_mut();
+        let mut file_spec: BSTR = null_mut();
+        let mut recursive: BOOL = FALSE;
+        let mut lsn: BSTR = null_mut();
+        let mut last_modify_time = FILETIME::default();
+        let result = check_com(unsafe {
+            self.0.GetDifferencedFile(
+                differenced_file_index,
+                &mut path,
+                &mut file_spec,
+                &mut recursive,
+                &mut lsn,
+                &mut last_modify_time,
+            )
+        });
+        let path = unsafe { take_ownership_of_bstr(path) };
+        let file_spec = unsafe { take_ownership_of_bstr(file_spec) };
+        let lsn = unsafe { take_ownership_of_bstr(lsn) };
+        result?;
+        Ok(DifferencedFile {
+            path: path
+                .unwrap()
+                .expect("The path returned by GetDifferencedFile shouldn't be null"),
+            file_specification: file_spec
+                .unwrap()
+                .expect("The file specification returned by GetDifferencedFile shouldn't be null"),
+            recursive: recursive != FALSE,
+            lsn: lsn.unwrap(),
+            last_modify_time,
+        })
+    }
+}
+
+/// Methods that are only valid to call from a writer while it handles a VSS
+/// event.
+impl IComponent {
+    /// Sets backup metadata for the component that the requester will store
+    /// together with the backup. The data can later be retrieved with
+    /// [`IComponent::get_backup_metadata`].
+    ///
+    /// Can only be called while handling a `PrepareForBackup` event.
+    #[doc(alias = "SetBackupMetadata")]
+    pub fn set_backup_metadata(&self, data: &U16CStr) -> Result<(), SetBackupMetadataError> {
+        trace_com_call!("SetBackupMetadata");
+        check_com(unsafe { self.0.SetBackupMetadata(data.as_ptr()) })?;
+        Ok(())
+    }
+    /// Indicates that only ranges in the file are to be backed up.
+    ///
+    /// The `ranges` argument is either a comma-separated list of offsets and
+    /// lengths of the parts of the file that should be backed up or the path
+    /// of a file containing such a list.
+    ///
+    /// Can only be called while handling a `PrepareForBackup` event.
+    #[doc(alias = "AddPartialFile")]
+    pub fn add_partial_file(
+        &self,
+        path: &U16CStr,
+        file_name: &U16CStr,
+        ranges: &U16CStr,
+        metadata: Option<&U16CStr>,
+    ) -> Result<(), AddPartialFileError> {
+        trace_com_call!("AddPartialFile");
+        check_com(unsafe {
+            self.0.AddPartialFile(
+                path.as_ptr(),
+                file_name.as_ptr(),
+                ranges.as_ptr(),
+                metadata.map(|s| s.as_ptr()).unwrap_or(null()),
+            )
+        })?;
+        Ok(())
+    }
+    /// Indicates that the data of a file should be restored to a different
+    /// file, optionally only remapping parts of the file.
+    ///
+    /// Can only be called while handling a `PreRestore` event.
+    #[doc(alias = "AddDirectedTarget")]
+    pub fn add_directed_target(
+        &self,
+        source_path: &U16CStr,
+        source_file_name: &U16CStr,
+        source_ranges: &U16CStr,
+        destination_path: &U16CStr,
+        destination_file_name: &U16CStr,
+        destination_ranges: &U16CStr,
+    ) -> Result<(), AddDirectedTargetError> {
+        trace_com_call!("AddDirectedTarget");
+        check_com(unsafe {
+            self.0.AddDirectedTarget(
+                source_path.as_ptr(),
+                source_file_name.as_ptr(),
+                source_ranges.as_ptr(),
+                destination_path.as_ptr(),
+                destination_file_name.as_ptr(),
+                destination_ranges.as_ptr(),
+            )
+        })?;
+        Ok(())
+    }
+    /// Sets restore metadata for the component that the requester can read
+    /// with [`IComponent::get_restore_metadata`].
+    ///
+    /// Can only be called while handling a `PreRestore` event.
+    #[doc(alias = "SetRestoreMetadata")]
+    pub fn set_restore_metadata(&self, data: &U16CStr) -> Result<(), SetRestoreMetadataError> {
+        trace_com_call!("SetRestoreMetadata");
+        check_com(unsafe { self.0.SetRestoreMetadata(data.as_ptr()) })?;
+        Ok(())
+    }
+    /// Sets how the files of the component should be restored.
+    ///
+    /// Can only be called while handling a `PreRestore` event.
+    #[doc(alias = "SetRestoreTarget")]
+    pub fn set_restore_target(&self, target: RestoreTarget) -> Result<(), SetRestoreTargetError> {
+        trace_com_call!("SetRestoreTarget");
+        check_com(unsafe { self.0.SetRestoreTarget(target.into()) })?;
+        Ok(())
+    }
+    /// Sets a message describing why the component failed to prepare for a
+    /// restore.
+    ///
+    /// Can only be called while handling a `PreRestore` event.
+    #[doc(alias = "SetPreRestoreFailureMsg")]
+    pub fn set_pre_restore_failure_msg(
+        &self,
+        message: &U16CStr,
+    ) -> Result<(), SetPreRestoreFailureMsgError> {
+        trace_com_call!("SetPreRestoreFailureMsg");
+        check_com(unsafe { self.0.SetPreRestoreFailureMsg(message.as_ptr()) })?;
+        Ok(())
+    }
+    /// Sets a message describing why the component failed to finish a
+    /// restore.
+    ///
+    /// Can only be called while handling a `PostRestore` event.
+    #[doc(alias = "SetPostRestoreFailureMsg")]
+    pub fn set_post_restore_failure_msg(
+        &self,
+        message: &U16CStr,
+    ) -> Result<(), SetPostRestoreFailureMsgError> {
+        trace_com_call!("SetPostRestoreFailureMsg");
+        check_com(unsafe { self.0.SetPostRestoreFailureMsg(message.as_ptr()) })?;
+        Ok(())
+    }
+    /// Sets a string that identifies the time of this backup. A requester
+    /// passes it back through [`IBackupComponents::set_previous_backup_stamp`]
+    /// for later incremental or differential backups.
+    ///
+    /// Can only be called while handling a `PrepareForBackup` or
+    /// `PostSnapshot` event.
+    ///
+    /// [`IBackupComponents::set_previous_backup_stamp`]: crate::vsbackup::IBackupComponents::set_previous_backup_stamp
+    #[doc(alias = "SetBackupStamp")]
+    pub fn set_backup_stamp(&self, backup_stamp: &U16CStr) -> Result<(), SetBackupStampError> {
+        trace_com_call!("SetBackupStamp");
+        check_com(unsafe { self.0.SetBackupStamp(backup_stamp.as_ptr()) })?;
+        Ok(())
+    }
+    /// Indicates that files matching the file specification that were
+    /// modified after `last_modify_time` should be included in an
+    /// incremental or differential backup.
+    ///
+    /// Can only be called while handling a `PrepareForBackup` event.
+    #[doc(alias = "AddDifferencedFilesByLastModifyTime")]
+    pub fn add_differenced_files_by_last_modify_time(
+        &self,
+        path: &U16CStr,
+        file_specification: &U16CStr,
+        recursive: bool,
+        last_modify_time: FILETIME,
+    ) -> Result<(), AddDifferencedFilesByLastModifyTimeError> {
+        trace_com_call!("AddDifferencedFilesByLastModifyTime");
+        check_com(unsafe {
+            self.0.AddDifferencedFilesByLastModifyTime(
+                path.as_ptr(),
+                file_specification.as_ptr(),
+                if recursive { TRUE } else { FALSE },
+                last_modify_time,
+            )
+        })?;
+        Ok(())
+    }
+}
+
+/// Info returned by the [`IComponent::get_partial_file`] method.
+pub struct PartialFile {
+    /// The path of the file.
+    pub path: BString,
+    /// The name of the file.
+    pub file_name: BString,
+    /// Either a list of offsets and lengths indicating the file support ranges
+    /// (the parts of the file that need to be backed up) or the path of a file
+    /// containing such a list.
+    pub ranges: Option<BString>,
+    /// Any additional metadata that the writer stored for the file.
+    pub metadata: Option<BString>,
+}
+
+/// Info returned by the [`IComponent::get_directed_target`] method.
+pub struct DirectedTarget {
+    /// The path to the directory that at backup time contained the file to be
+    /// restored (the source file).
+    pub source_path: BString,
+    /// The name of the file (at backup time) that is to be remapped during a
+    /// restore.
+    pub source_file_name: BString,
+    /// A comma-separated list of file offsets and lengths indicating the source
+    /// file support range.
+    pub source_ranges: Option<BString>,
+    /// The path to which source file data will be remapped at restore time.
+    pub destination_path: BString,
+    /// The name of the file to which source file data will be remapped at
+    /// restore time.
+    pub destination_file_name: BString,
+    /// A comma-separated list of file offsets and lengths indicating the
+    /// destination file support range.
+    pub destination_ranges: Option<BString>,
+}
+
+/// Info returned by the [`IComponent::get_restore_subcomponent`] method.
+pub struct RestoreSubcomponent {
+    /// The logical path of the subcomponent.
+    pub logical_path: Option<BString>,
+    /// The name of the subcomponent.
+    pub component_name: BString,
+    /// Reserved for future use.
+    pub repair: bool,
+}
+
+/// Info returned by the [`IComponent::get_differenced_file`] method.
+pub struct DifferencedFile {
+    /// The root directory of the file set.
+    pub path: BString,
+    /// The file specification of the file set, can contain wildcards.
+    pub file_specification: BString,
+    /// `true` if the path should be traversed recursively.
+    pub recursive: bool,
+    /// Reserved for future use.
+    pub lsn: Option<BString>,
+    /// The time that the files were last modified. Only files changed after
+    /// this time need to be backed up.
+    pub last_modify_time: FILETIME,
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // IVssComponentEx
 ////////////////////////////////////////////////////////////////////////////////
@@ -276,6 +1183,111 @@ transparent_wrapper!(
 // Safety: all wrappers ensure their wrapped values are valid to use (Not released).
 unsafe_deref_to_ref!(ComponentEx => IComponentEx => IComponent);
 
+impl IComponentEx {
+    /// Used by a writer to describe why the component failed while handling a
+    /// `PrepareForBackup` event.
+    #[doc(alias = "SetPrepareForBackupFailureMsg")]
+    pub fn set_prepare_for_backup_failure_msg(
+        &self,
+        message: &U16CStr,
+    ) -> Result<(), SetPrepareForBackupFailureMsgError> {
+        trace_com_call!("SetPrepareForBackupFailureMsg");
+        check_com(unsafe { self.0.SetPrepareForBackupFailureMsg(message.as_ptr()) })?;
+        Ok(())
+    }
+    /// Used by a writer to describe why the component failed while handling a
+    /// `PostSnapshot` event.
+    #[doc(alias = "SetPostSnapshotFailureMsg")]
+    pub fn set_post_snapshot_failure_msg(
+        &self,
+        message: &U16CStr,
+    ) -> Result<(), SetPostSnapshotFailureMsgError> {
+        trace_com_call!("SetPostSnapshotFailureMsg");
+        check_com(unsafe { self.0.SetPostSnapshotFailureMsg(message.as_ptr()) })?;
+        Ok(())
+    }
+    /// Obtains the error message that the writer set if the component failed
+    /// during a `PrepareForBackup` event.
+    #[doc(alias = "GetPrepareForBackupFailureMsg")]
+    pub fn get_prepare_for_backup_failure_msg(
+        &self,
+    ) -> Result<Option<BString>, GetPrepareForBackupFailureMsgError> {
+        trace_com_call!("GetPrepareForBackupFailureMsg");
+        let mut message: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetPrepareForBackupFailureMsg(&mut message) });
+        let message = unsafe { take_ownership_of_bstr(message) };
+        result?;
+        Ok(message.unwrap())
+    }
+    /// Obtains the error message that the writer set if the component failed
+    /// during a `PostSnapshot` event.
+    #[doc(alias = "GetPostSnapshotFailureMsg")]
+    pub fn get_post_snapshot_failure_msg(
+        &self,
+    ) -> Result<Option<BString>, GetPostSnapshotFailureMsgError> {
+        trace_com_call!("GetPostSnapshotFailureMsg");
+        let mut message: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetPostSnapshotFailureMsg(&mut message) });
+        let message = unsafe { take_ownership_of_bstr(message) };
+        result?;
+        Ok(message.unwrap())
+    }
+    /// Returns `true` if the requester marked the restore of the component as
+    /// authoritative with [`IBackupComponentsEx2::set_authoritative_restore`].
+    ///
+    /// [`IBackupComponentsEx2::set_authoritative_restore`]: crate::vsbackup::IBackupComponentsEx2::set_authoritative_restore
+    #[doc(alias = "GetAuthoritativeRestore")]
+    pub fn get_authoritative_restore(&self) -> Result<bool, GetAuthoritativeRestoreError> {
+        trace_com_call!("GetAuthoritativeRestore");
+        let mut authoritative = false;
+        check_com(unsafe { self.0.GetAuthoritativeRestore(&mut authoritative) })?;
+        Ok(authoritative)
+    }
+    /// Obtains the roll-forward operation type and restore point that the
+    /// requester set for the component with
+    /// [`IBackupComponentsEx2::set_roll_forward`].
+    ///
+    /// [`IBackupComponentsEx2::set_roll_forward`]: crate::vsbackup::IBackupComponentsEx2::set_roll_forward
+    #[doc(alias = "GetRollForward")]
+    pub fn get_roll_forward(&self) -> Result<GetRollForwardInfo, GetRollForwardError> {
+        trace_com_call!("GetRollForward");
+        let mut roll_forward_type: vss::VSS_ROLLFORWARD_TYPE = vss::VSS_RF_UNDEFINED;
+        let mut restore_point: BSTR = null_mut();
+        let result = check_com(unsafe {
+            self.0
+                .GetRollForward(&mut roll_forward_type, &mut restore_point)
+        });
+        let restore_point = unsafe { take_ownership_of_bstr(restore_point) };
+        result?;
+        Ok(GetRollForwardInfo {
+            roll_forward_type: roll_forward_type.into(),
+            restore_point: restore_point.unwrap(),
+        })
+    }
+    /// Obtains the logical name that the requester assigned to the restore
+    /// with [`IBackupComponentsEx2::set_restore_name`].
+    ///
+    /// [`IBackupComponentsEx2::set_restore_name`]: crate::vsbackup::IBackupComponentsEx2::set_restore_name
+    #[doc(alias = "GetRestoreName")]
+    pub fn get_restore_name(&self) -> Result<Option<BString>, GetRestoreNameError> {
+        trace_com_call!("GetRestoreName");
+        let mut name: BSTR = null_mut();
+        let result = check_com(unsafe { self.0.GetRestoreName(&mut name) });
+        let name = unsafe { take_ownership_of_bstr(name) };
+        result?;
+        Ok(name.unwrap())
+    }
+}
+
+/// Information returned by the [`IComponentEx::get_roll_forward`] method.
+pub struct GetRollForwardInfo {
+    /// The type of roll-forward operation that should be performed.
+    pub roll_forward_type: RollForwardType,
+    /// The restore point to roll forward to, only set for a
+    /// [`RollForwardType::Partial`] roll-forward.
+    pub restore_point: Option<BString>,
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // IVssComponentEx2
 ////////////////////////////////////////////////////////////////////////////////
@@ -301,6 +1313,7 @@ impl IComponentEx2 {
     /// reported by writers.
     #[doc(alias = "GetFailure")]
     pub fn get_failure(&self) -> Result<GetFailureInfo, GetFailureError> {
+        trace_com_call!("GetFailure");
         let mut failure: HRESULT = 0;
         let mut application_return_code: HRESULT = 0;
         let mut application_message: BSTR = null_mut();
@@ -329,6 +1342,7 @@ impl IComponentEx2 {
         application_return_code: Option<HRESULT>,
         application_message: Option<&U16CStr>,
     ) -> Result<(), IComponentEx2SetFailureError> {
+        trace_com_call!("SetFailure");
         check_com(unsafe {
             self.0.SetFailure(
                 failure.map(HRESULT::from).unwrap_or(S_OK),
@@ -364,13 +1378,520 @@ pub struct GetFailureInfo {
 ////////////////////////////////////////////////////////////////////////////////
 
 // Safety: The type implements `Interface` correctly.
-unsafe impl crate::safe_com_component::CorrectInterface for vswriter::IVssCreateWriterMetadata {}
+unsafe impl crate::safe_com_component::CorrectInterface for raw::IVssCreateWriterMetadata {}
 
 transparent_wrapper!(
     #[doc(alias = "IVssCreateWriterMetadata")]
-    pub struct ICreateWriterMetadata(vswriter::IVssCreateWriterMetadata);
+    pub struct ICreateWriterMetadata(raw::IVssCreateWriterMetadata);
 );
 
+/// Implements the methods that `IVssCreateWriterMetadata` and
+/// `IVssCreateExpressWriterMetadata` have in common. Each method is
+/// documented with the attributes in front of its name.
+macro_rules! shared_create_metadata_methods {
+    (
+        $(#[$add_exclude_files:meta])*
+        fn add_exclude_files;
+        $(#[$add_component:meta])*
+        fn add_component;
+        $(#[$add_files_to_file_group:meta])*
+        fn add_files_to_file_group;
+        $(#[$set_restore_method:meta])*
+        fn set_restore_method;
+        $(#[$add_component_dependency:meta])*
+        fn add_component_dependency;
+        $(#[$set_backup_schema:meta])*
+        fn set_backup_schema;
+        $(#[$save_as_xml:meta])*
+        fn save_as_xml;
+    ) => {
+        $(#[$add_exclude_files])*
+        #[doc(alias = "AddExcludeFiles")]
+        pub fn add_exclude_files(
+            &self,
+            path: &U16CStr,
+            file_specification: &U16CStr,
+            recursive: bool,
+        ) -> Result<(), AddExcludeFilesError> {
+            trace_com_call!("AddExcludeFiles");
+            check_com(unsafe {
+                self.0
+                    .AddExcludeFiles(path.as_ptr(), file_specification.as_ptr(), recursive)
+            })?;
+            Ok(())
+        }
+        $(#[$add_component])*
+        #[doc(alias = "AddComponent")]
+        #[allow(clippy::too_many_arguments)]
+        pub fn add_component(
+            &self,
+            component_type: VssComponentType,
+            logical_path: Option<&U16CStr>,
+            component_name: &U16CStr,
+            caption: Option<&U16CStr>,
+            icon: Option<&[u8]>,
+            restore_metadata: bool,
+            notify_on_backup_complete: bool,
+            selectable: bool,
+            selectable_for_restore: bool,
+            component_flags: RawBitFlags<VssComponentFlags>,
+        ) -> Result<(), CreateWriterMetadataAddComponentError> {
+            trace_com_call!("AddComponent");
+            let icon = icon.unwrap_or(&[]);
+            let icon_len = UINT::try_from(icon.len()).map_err(|_| {
+                CreateWriterMetadataAddComponentError::from_kind(
+                    CreateWriterMetadataAddComponentErrorKind::E_INVALIDARG,
+                )
+            })?;
+            check_com(unsafe {
+                self.0.AddComponent(
+                    component_type.into(),
+                    logical_path.map(|s| s.as_ptr()).unwrap_or(null()),
+                    component_name.as_ptr(),
+                    caption.map(|s| s.as_ptr()).unwrap_or(null()),
+                    if icon.is_empty() {
+                        null()
+                    } else {
+                        icon.as_ptr()
+                    },
+                    icon_len,
+                    restore_metadata,
+                    notify_on_backup_complete,
+                    selectable,
+                    selectable_for_restore,
+                    component_flags.raw(),
+                )
+            })?;
+            Ok(())
+        }
+        $(#[$add_files_to_file_group])*
+        #[doc(alias = "AddFilesToFileGroup")]
+        #[allow(clippy::too_many_arguments)]
+        pub fn add_files_to_file_group(
+            &self,
+            logical_path: Option<&U16CStr>,
+            group_name: &U16CStr,
+            path: &U16CStr,
+            file_specification: &U16CStr,
+            recursive: bool,
+            alternate_location: Option<&U16CStr>,
+            backup_type_mask: RawBitFlags<FileSpecificationBackupType>,
+        ) -> Result<(), AddFilesToFileGroupError> {
+            trace_com_call!("AddFilesToFileGroup");
+            check_com(unsafe {
+                self.0.AddFilesToFileGroup(
+                    logical_path.map(|s| s.as_ptr()).unwrap_or(null()),
+                    group_name.as_ptr(),
+                    path.as_ptr(),
+                    file_specification.as_ptr(),
+                    recursive,
+                    alternate_location.map(|s| s.as_ptr()).unwrap_or(null()),
+                    backup_type_mask.raw(),
+                )
+            })?;
+            Ok(())
+        }
+        $(#[$set_restore_method])*
+        #[doc(alias = "SetRestoreMethod")]
+        pub fn set_restore_method(
+            &self,
+            method: RestoreMethod,
+            service: Option<&U16CStr>,
+            user_procedure: Option<&U16CStr>,
+            writer_restore: WriterRestore,
+            reboot_required: bool,
+        ) -> Result<(), SetRestoreMethodError> {
+            trace_com_call!("SetRestoreMethod");
+            check_com(unsafe {
+                self.0.SetRestoreMethod(
+                    method.into(),
+                    service.map(|s| s.as_ptr()).unwrap_or(null()),
+                    user_procedure.map(|s| s.as_ptr()).unwrap_or(null()),
+                    writer_restore.into(),
+                    reboot_required,
+                )
+            })?;
+            Ok(())
+        }
+        $(#[$add_component_dependency])*
+        #[doc(alias = "AddComponentDependency")]
+        pub fn add_component_dependency(
+            &self,
+            for_logical_path: Option<&U16CStr>,
+            for_component_name: &U16CStr,
+            on_writer_id: VSS_ID,
+            on_logical_path: Option<&U16CStr>,
+            on_component_name: &U16CStr,
+        ) -> Result<(), AddComponentDependencyError> {
+            trace_com_call!("AddComponentDependency");
+            check_com(unsafe {
+                self.0.AddComponentDependency(
+                    for_logical_path.map(|s| s.as_ptr()).unwrap_or(null()),
+                    for_component_name.as_ptr(),
+                    on_writer_id,
+                    on_logical_path.map(|s| s.as_ptr()).unwrap_or(null()),
+                    on_component_name.as_ptr(),
+                )
+            })?;
+            Ok(())
+        }
+        $(#[$set_backup_schema])*
+        #[doc(alias = "SetBackupSchema")]
+        pub fn set_backup_schema(
+            &self,
+            schema: RawBitFlags<BackupSchema>,
+        ) -> Result<(), SetBackupSchemaError> {
+            trace_com_call!("SetBackupSchema");
+            check_com(unsafe { self.0.SetBackupSchema(schema.raw()) })?;
+            Ok(())
+        }
+        $(#[$save_as_xml])*
+        #[doc(alias = "SaveAsXML")]
+        pub fn save_as_xml(&self) -> Result<BString, CreateWriterMetadataSaveAsXMLError> {
+            trace_com_call!("SaveAsXML");
+            let mut xml: BSTR = null_mut();
+            let hr = unsafe { self.0.SaveAsXML(&mut xml) };
+            let xml = unsafe { take_ownership_of_bstr(xml) };
+            check_com(hr)?;
+            Ok(xml
+                .unwrap()
+                .expect("the xml string returned by SaveAsXML shouldn't be null"))
+        }
+    };
+}
+
+impl ICreateWriterMetadata {
+    /// Adds a file set (a specified file or files) to the Writer Metadata
+    /// Document that should be included in the backup of the writer's
+    /// components.
+    ///
+    /// This method is deprecated by Microsoft, use
+    /// [`ICreateWriterMetadata::add_files_to_file_group`],
+    /// [`ICreateWriterMetadata::add_database_files`] or
+    /// [`ICreateWriterMetadata::add_database_log_files`] instead.
+    #[doc(alias = "AddIncludeFiles")]
+    pub fn add_include_files(
+        &self,
+        path: &U16CStr,
+        file_specification: &U16CStr,
+        recursive: bool,
+        alternate_location: Option<&U16CStr>,
+    ) -> Result<(), AddIncludeFilesError> {
+        trace_com_call!("AddIncludeFiles");
+        check_com(unsafe {
+            self.0.AddIncludeFiles(
+                path.as_ptr(),
+                file_specification.as_ptr(),
+                recursive,
+                alternate_location.map(|s| s.as_ptr()).unwrap_or(null()),
+            )
+        })?;
+        Ok(())
+    }
+    /// Indicates the file set (the specified file or files) that make up a
+    /// database component.
+    #[doc(alias = "AddDatabaseFiles")]
+    pub fn add_database_files(
+        &self,
+        logical_path: Option<&U16CStr>,
+        database_name: &U16CStr,
+        path: &U16CStr,
+        file_specification: &U16CStr,
+        backup_type_mask: RawBitFlags<FileSpecificationBackupType>,
+    ) -> Result<(), AddDatabaseFilesError> {
+        trace_com_call!("AddDatabaseFiles");
+        check_com(unsafe {
+            self.0.AddDatabaseFiles(
+                logical_path.map(|s| s.as_ptr()).unwrap_or(null()),
+                database_name.as_ptr(),
+                path.as_ptr(),
+                file_specification.as_ptr(),
+                backup_type_mask.raw(),
+            )
+        })?;
+        Ok(())
+    }
+    /// Indicates the log files that are associated with a database component.
+    #[doc(alias = "AddDatabaseLogFiles")]
+    pub fn add_database_log_files(
+        &self,
+        logical_path: Option<&U16CStr>,
+        database_name: &U16CStr,
+        path: &U16CStr,
+        file_specification: &U16CStr,
+        backup_type_mask: RawBitFlags<FileSpecificationBackupType>,
+    ) -> Result<(), AddDatabaseLogFilesError> {
+        trace_com_call!("AddDatabaseLogFiles");
+        check_com(unsafe {
+            self.0.AddDatabaseLogFiles(
+                logical_path.map(|s| s.as_ptr()).unwrap_or(null()),
+                database_name.as_ptr(),
+                path.as_ptr(),
+                file_specification.as_ptr(),
+                backup_type_mask.raw(),
+            )
+        })?;
+        Ok(())
+    }
+    /// Creates an alternate location mapping which tells the requester where
+    /// files can be restored to if they can't be restored to their original
+    /// location.
+    #[doc(alias = "AddAlternateLocationMapping")]
+    pub fn add_alternate_location_mapping(
+        &self,
+        source_path: &U16CStr,
+        source_file_specification: &U16CStr,
+        recursive: bool,
+        destination: &U16CStr,
+    ) -> Result<(), AddAlternateLocationMappingError> {
+        trace_com_call!("AddAlternateLocationMapping");
+        check_com(unsafe {
+            self.0.AddAlternateLocationMapping(
+                source_path.as_ptr(),
+                source_file_specification.as_ptr(),
+                recursive,
+                destination.as_ptr(),
+            )
+        })?;
+        Ok(())
+    }
+    shared_create_metadata_methods! {
+        /// Adds a file set to the Writer Metadata Document that should be
+        /// explicitly excluded from the backup.
+        fn add_exclude_files;
+        /// Adds a database or file group component to the Writer Metadata
+        /// Document.
+        ///
+        /// # Parameters
+        ///
+        /// - `logical_path`: the logical path of the component, `None` if the
+        ///   component has no logical path.
+        /// - `caption`: a description of the component that can be shown to
+        ///   users.
+        /// - `icon`: the bytes of an icon that represents the component.
+        /// - `restore_metadata`: whether private metadata needs to be passed
+        ///   back to the writer during restore with
+        ///   [`IComponent::set_restore_metadata`].
+        /// - `notify_on_backup_complete`: whether the writer wants the
+        ///   requester to report if the backup of the component succeeded.
+        /// - `selectable`: whether the component can be selected for backup on
+        ///   its own.
+        /// - `selectable_for_restore`: whether the component can be restored on
+        ///   its own.
+        fn add_component;
+        /// Adds a file set to a file group component.
+        fn add_files_to_file_group;
+        /// Sets how the writer's data should be restored.
+        ///
+        /// `service` is the name of the service to stop before and start
+        /// after the restore when using [`RestoreMethod::StopRestoreStart`] or
+        /// [`RestoreMethod::RestoreStopStart`]. `user_procedure` is a URL or
+        /// text describing a [`RestoreMethod::Custom`] restore.
+        fn set_restore_method;
+        /// Indicates that the component identified by `for_logical_path` and
+        /// `for_component_name` depends on a component managed by another
+        /// writer.
+        fn add_component_dependency;
+        /// Sets the types of backup operations that the writer supports.
+        fn set_backup_schema;
+        /// Saves the Writer Metadata Document as an XML string.
+        fn save_as_xml;
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssCreateWriterMetadataEx
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe impl crate::safe_com_component::CorrectInterface for raw::IVssCreateWriterMetadataEx {}
+
+transparent_wrapper!(
+    #[doc(alias = "IVssCreateWriterMetadataEx")]
+    pub struct ICreateWriterMetadataEx(raw::IVssCreateWriterMetadataEx);
+);
+
+// `IVssCreateWriterMetadataEx` stores the `IVssCreateWriterMetadata` vtable
+// at the start of its own vtable so the raw type derefs into it.
+impl Deref for ICreateWriterMetadataEx {
+    type Target = ICreateWriterMetadata;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: this wrapper ensures the interface is valid to use.
+        unsafe { ICreateWriterMetadata::from_inner_ref(&self.0) }
+    }
+}
+impl_as_ref_and_borrow!(ICreateWriterMetadataEx => ICreateWriterMetadata);
+
+impl ICreateWriterMetadataEx {
+    /// Excludes a file set from the shadow copy itself, not just from the
+    /// backup. Requesters can read these file sets with
+    /// [`IExamineWriterMetadataEx2::get_exclude_from_snapshot_file`].
+    ///
+    /// [`IExamineWriterMetadataEx2::get_exclude_from_snapshot_file`]: crate::vsbackup::IExamineWriterMetadataEx2::get_exclude_from_snapshot_file
+    #[doc(alias = "AddExcludeFilesFromSnapshot")]
+    pub fn add_exclude_files_from_snapshot(
+        &self,
+        path: &U16CStr,
+        file_specification: &U16CStr,
+        recursive: bool,
+    ) -> Result<(), AddExcludeFilesFromSnapshotError> {
+        trace_com_call!("AddExcludeFilesFromSnapshot");
+        check_com(unsafe {
+            self.0.AddExcludeFilesFromSnapshot(
+                path.as_ptr(),
+                file_specification.as_ptr(),
+                recursive,
+            )
+        })?;
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssCreateExpressWriterMetadata
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssCreateExpressWriterMetadata);
+
+/// Describes the files and components of an express writer. Created with
+/// [`IExpressWriter::create_metadata`].
+#[doc(alias = "IVssCreateExpressWriterMetadata")]
+#[derive(Debug, Clone)]
+pub struct CreateExpressWriterMetadata(SafeCOMComponent<raw::IVssCreateExpressWriterMetadata>);
+impl_query_interface!(CreateExpressWriterMetadata => raw::IVssCreateExpressWriterMetadata);
+transparent_wrapper!(
+    #[doc(alias = "IVssCreateExpressWriterMetadata")]
+    pub struct ICreateExpressWriterMetadata(raw::IVssCreateExpressWriterMetadata);
+);
+// Safety: all wrappers ensure their wrapped values are valid to use (Not released).
+unsafe_deref_to_ref!(CreateExpressWriterMetadata => ICreateExpressWriterMetadata);
+
+impl ICreateExpressWriterMetadata {
+    shared_create_metadata_methods! {
+        /// Adds a file set to the express writer's metadata that should be
+        /// explicitly excluded from the backup.
+        fn add_exclude_files;
+        /// Adds a file group component to the express writer's metadata.
+        /// Express writers only support [`VssComponentType::FileGroup`]
+        /// components.
+        ///
+        /// The parameters are the same as for
+        /// [`ICreateWriterMetadata::add_component`].
+        fn add_component;
+        /// Adds a file set to a file group component.
+        fn add_files_to_file_group;
+        /// Sets how the express writer's data should be restored, see
+        /// [`ICreateWriterMetadata::set_restore_method`].
+        fn set_restore_method;
+        /// Indicates that a component of the express writer depends on a
+        /// component managed by another writer.
+        fn add_component_dependency;
+        /// Sets the types of backup operations that the express writer
+        /// supports.
+        fn set_backup_schema;
+        /// Saves the express writer's metadata as an XML string which can be
+        /// passed to [`IExpressWriter::load_metadata`].
+        fn save_as_xml;
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IVssExpressWriter
+////////////////////////////////////////////////////////////////////////////////
+
+// Safety: The type implements `Interface` correctly.
+unsafe_impl_as_IUnknown!(raw::IVssExpressWriter);
+
+/// An express writer lets an application describe its files to VSS without
+/// running a writer process. Once registered, VSS responds to backup and
+/// restore events on the writer's behalf.
+///
+/// Express writers are supported starting with Windows 8 and Windows Server
+/// 2012.
+#[doc(alias = "IVssExpressWriter")]
+#[derive(Debug, Clone)]
+pub struct ExpressWriter(SafeCOMComponent<raw::IVssExpressWriter>);
+impl_query_interface!(ExpressWriter => raw::IVssExpressWriter);
+transparent_wrapper!(
+    #[doc(alias = "IVssExpressWriter")]
+    pub struct IExpressWriter(raw::IVssExpressWriter);
+);
+// Safety: all wrappers ensure their wrapped values are valid to use (Not released).
+unsafe_deref_to_ref!(ExpressWriter => IExpressWriter);
+
+impl ExpressWriter {
+    /// Create a new express writer object.
+    ///
+    /// COM must be initialized before calling this, see [`crate::initialize_com`].
+    #[doc(alias = "CreateVssExpressWriter")]
+    pub fn new() -> Result<Self, CreateVssExpressWriterError> {
+        trace_com_call!("CreateVssExpressWriter");
+        unsafe {
+            let mut comp = null_mut::<raw::IVssExpressWriter>();
+            check_com(raw::CreateVssExpressWriter(&mut comp))?;
+            Ok(Self(SafeCOMComponent::new(comp)))
+        }
+    }
+}
+impl IExpressWriter {
+    /// Creates an empty metadata document for an express writer. Describe the
+    /// writer's components using the returned object and then pass its XML to
+    /// [`IExpressWriter::load_metadata`].
+    #[doc(alias = "CreateMetadata")]
+    pub fn create_metadata(
+        &self,
+        writer_id: VSS_ID,
+        writer_name: &U16CStr,
+        usage_type: UsageType,
+        version_major: u32,
+        version_minor: u32,
+    ) -> Result<CreateExpressWriterMetadata, ExpressWriterCreateMetadataError> {
+        trace_com_call!("CreateMetadata");
+        let mut metadata = null_mut::<raw::IVssCreateExpressWriterMetadata>();
+        check_com(unsafe {
+            self.0.CreateMetadata(
+                writer_id,
+                writer_name.as_ptr(),
+                usage_type.into(),
+                version_major,
+                version_minor,
+                0,
+                &mut metadata,
+            )
+        })?;
+        Ok(CreateExpressWriterMetadata(unsafe {
+            SafeCOMComponent::new(metadata)
+        }))
+    }
+    /// Loads an XML metadata document that describes an express writer. The
+    /// document is usually created with
+    /// [`IExpressWriter::create_metadata`].
+    #[doc(alias = "LoadMetadata")]
+    pub fn load_metadata(&self, metadata: &U16CStr) -> Result<(), ExpressWriterLoadMetadataError> {
+        trace_com_call!("LoadMetadata");
+        check_com(unsafe { self.0.LoadMetadata(metadata.as_ptr(), 0) })?;
+        Ok(())
+    }
+    /// Registers the express writer whose metadata was loaded with
+    /// [`IExpressWriter::load_metadata`]. The registration remains until it
+    /// is removed with [`IExpressWriter::unregister`].
+    #[doc(alias = "Register")]
+    pub fn register(&self) -> Result<(), ExpressWriterRegisterError> {
+        trace_com_call!("Register");
+        check_com(unsafe { self.0.Register() })?;
+        Ok(())
+    }
+    /// Removes a previously registered express writer.
+    #[doc(alias = "Unregister")]
+    pub fn unregister(&self, writer_id: VSS_ID) -> Result<(), ExpressWriterUnregisterError> {
+        trace_com_call!("Unregister");
+        check_com(unsafe { self.0.Unregister(writer_id) })?;
+        Ok(())
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Enumerations
 ////////////////////////////////////////////////////////////////////////////////
@@ -442,6 +1963,35 @@ with_from!(
     }
 );
 
+with_from!(
+    [raw = vswriter::VSS_RESTORE_TARGET, fallback = Undefined],
+    /// Used by a writer to indicate how the files of a component should be
+    /// restored.
+    #[doc(alias = "VSS_RESTORE_TARGET")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum RestoreTarget {
+        /// No target is defined.
+        ///
+        /// This value indicates an error on the part of the writer.
+        #[doc(alias = "VSS_RT_UNDEFINED")]
+        Undefined = vswriter::VSS_RT_UNDEFINED,
+        /// The restore target should be the original location of the files.
+        #[doc(alias = "VSS_RT_ORIGINAL")]
+        Original = vswriter::VSS_RT_ORIGINAL,
+        /// The restore target should be the alternate location mappings of the
+        /// writer's metadata.
+        #[doc(alias = "VSS_RT_ALTERNATE")]
+        Alternate = vswriter::VSS_RT_ALTERNATE,
+        /// Data should be restored using directed targets.
+        #[doc(alias = "VSS_RT_DIRECTED")]
+        Directed = vswriter::VSS_RT_DIRECTED,
+        /// The files should be restored to the location they had at the time
+        /// of the backup, even if that has since changed.
+        #[doc(alias = "VSS_RT_ORIGINAL_LOCATION")]
+        OriginalLocation = vswriter::VSS_RT_ORIGINAL_LOCATION,
+    }
+);
+
 with_from!(
     [raw = vswriter::VSS_USAGE_TYPE, fallback = Undefined],
     /// Specifies how the host system uses the data managed by a writer involved
@@ -0,0 +1,1045 @@
+//! A framework for implementing VSS writers in Rust, the equivalent of the
+//! `CVssWriter` C++ class.
+//!
+//! Implement the [`WriterEvents`] trait and pass it to [`VssWriter::new`].
+//! After [`VssWriter::subscribe`] has been called VSS will notify the writer
+//! about backup and restore events until the writer is unsubscribed or
+//! dropped.
+//!
+//! VSS only waits a limited time between the `Freeze` and `Thaw` events. Use
+//! [`CallbackThreading::WorkerThread`] to have slow callbacks reported as
+//! timeouts instead of silently holding up the shadow copy.
+//!
+//! `CVssWriter` is exported from `vssapi.dll` using C++ name mangling which
+//! differs between architectures, so this module is only available when
+//! targeting 64-bit x86.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::{
+//!     errors::ReportableWriterFailureError,
+//!     vswriter::{
+//!         framework::{VssWriter, WriterContext, WriterEvents, WriterOptions},
+//!         SourceType, UsageType,
+//!     },
+//! };
+//! use widestring::U16CString;
+//!
+//! struct MyWriter;
+//! impl WriterEvents for MyWriter {
+//!     fn on_prepare_snapshot(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         Ok(())
+//!     }
+//!     fn on_freeze(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         // Flush data to disk and stop writing.
+//!         Ok(())
+//!     }
+//!     fn on_thaw(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         // Resume writing.
+//!         Ok(())
+//!     }
+//!     fn on_abort(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! volume_shadow_copy::initialize_com()?;
+//! let name = U16CString::from_str("My Writer")?;
+//! let options = WriterOptions::new(
+//!     Default::default(), // Use a constant GUID for the writer class here.
+//!     &name,
+//!     UsageType::UserData,
+//!     SourceType::NonTransactedDb,
+//! );
+//! let mut writer = VssWriter::new(&options, MyWriter)?;
+//! writer.subscribe(Default::default())?;
+//! // Keep the process running while the writer should be available.
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # References
+//!
+//! [CVssWriter (vswriter.h) - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/api/vswriter/nl-vswriter-cvsswriter)
+
+use std::{
+    cmp,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    ptr::{self, NonNull},
+    slice,
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use widestring::U16CStr;
+use winapi::{
+    ctypes::c_void,
+    shared::minwindef::{DWORD, UINT},
+    um::{
+        vss::{self, VSS_ID, VSS_PWSZ},
+        vswriter::IVssWriterComponents,
+        winnt::{LONG, LPCWSTR},
+    },
+};
+
+use super::{
+    raw::IVssCreateWriterMetadata, ICreateWriterMetadata, IWriterComponents, SourceType, UsageType,
+};
+use crate::{
+    check_com,
+    errors::*,
+    raw_bitflags, trace_com_call,
+    vss::{ApplicationLevel, BackupType, RestoreType},
+    with_from, BorrowedComponent, RawBitFlags,
+};
+
+/// Raw declarations for the `CVssWriter` class since `winapi` doesn't provide
+/// them.
+#[allow(non_snake_case, non_upper_case_globals, non_camel_case_types)]
+pub mod raw {
+    use winapi::{
+        ctypes::c_void,
+        shared::minwindef::{DWORD, UINT},
+        um::{
+            vss::{VSS_APPLICATION_LEVEL, VSS_BACKUP_TYPE, VSS_ID, VSS_PWSZ, VSS_RESTORE_TYPE},
+            vswriter::{IVssWriterComponents, VSS_SOURCE_TYPE, VSS_USAGE_TYPE},
+            winnt::{HRESULT, LONG, LPCWSTR},
+        },
+    };
+
+    use crate::vswriter::raw::IVssCreateWriterMetadata;
+
+    pub type VSS_ALTERNATE_WRITER_STATE = u32;
+    pub const VSS_AWS_UNDEFINED: VSS_ALTERNATE_WRITER_STATE = 0;
+    pub const VSS_AWS_NO_ALTERNATE_WRITER: VSS_ALTERNATE_WRITER_STATE = 1;
+    pub const VSS_AWS_ALTERNATE_WRITER_EXISTS: VSS_ALTERNATE_WRITER_STATE = 2;
+    pub const VSS_AWS_THIS_IS_ALTERNATE_WRITER: VSS_ALTERNATE_WRITER_STATE = 3;
+
+    pub type VSS_SUBSCRIBE_MASK = DWORD;
+    pub const VSS_SM_POST_SNAPSHOT_FLAG: VSS_SUBSCRIBE_MASK = 0x1;
+    pub const VSS_SM_BACKUP_EVENTS_FLAG: VSS_SUBSCRIBE_MASK = 0x2;
+    pub const VSS_SM_RESTORE_EVENTS_FLAG: VSS_SUBSCRIBE_MASK = 0x4;
+    pub const VSS_SM_IO_THROTTLING_FLAG: VSS_SUBSCRIBE_MASK = 0x8;
+    pub const VSS_SM_ALL_FLAGS: VSS_SUBSCRIBE_MASK = 0xffff_ffff;
+
+    /// The virtual methods of `CVssWriter` in declaration order.
+    #[repr(C)]
+    pub struct CVssWriterVtbl {
+        pub ScalarDeletingDestructor:
+            unsafe extern "system" fn(This: *mut CVssWriter, flags: UINT) -> *mut c_void,
+        pub OnIdentify: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            pMetadata: *mut IVssCreateWriterMetadata,
+        ) -> bool,
+        pub OnPrepareBackup: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            pComponent: *mut IVssWriterComponents,
+        ) -> bool,
+        pub OnPrepareSnapshot: unsafe extern "system" fn(This: *mut CVssWriter) -> bool,
+        pub OnFreeze: unsafe extern "system" fn(This: *mut CVssWriter) -> bool,
+        pub OnThaw: unsafe extern "system" fn(This: *mut CVssWriter) -> bool,
+        pub OnAbort: unsafe extern "system" fn(This: *mut CVssWriter) -> bool,
+        pub OnBackupComplete: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            pComponent: *mut IVssWriterComponents,
+        ) -> bool,
+        pub OnBackupShutdown:
+            unsafe extern "system" fn(This: *mut CVssWriter, SnapshotSetId: VSS_ID) -> bool,
+        pub OnPreRestore: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            pComponent: *mut IVssWriterComponents,
+        ) -> bool,
+        pub OnPostRestore: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            pComponent: *mut IVssWriterComponents,
+        ) -> bool,
+        pub OnPostSnapshot: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            pComponent: *mut IVssWriterComponents,
+        ) -> bool,
+        pub OnBackOffIOOnVolume: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            wszVolumeName: VSS_PWSZ,
+            snapshotId: VSS_ID,
+            providerId: VSS_ID,
+        ) -> bool,
+        pub OnContinueIOOnVolume: unsafe extern "system" fn(
+            This: *mut CVssWriter,
+            wszVolumeName: VSS_PWSZ,
+            snapshotId: VSS_ID,
+            providerId: VSS_ID,
+        ) -> bool,
+        pub OnVSSShutdown: unsafe extern "system" fn(This: *mut CVssWriter) -> bool,
+        pub OnVSSApplicationStartup: unsafe extern "system" fn(This: *mut CVssWriter) -> bool,
+    }
+
+    /// The data members of the `CVssWriter` class.
+    #[repr(C)]
+    pub struct CVssWriter {
+        pub lpVtbl: *const CVssWriterVtbl,
+        pub m_pWrapper: *mut c_void,
+    }
+
+    #[link(name = "vssapi")]
+    extern "system" {
+        #[link_name = "??0CVssWriter@@QEAA@XZ"]
+        pub fn CVssWriter_CVssWriter(This: *mut CVssWriter) -> *mut CVssWriter;
+        #[link_name = "??1CVssWriter@@UEAA@XZ"]
+        pub fn CVssWriter_Destructor(This: *mut CVssWriter);
+        #[link_name = "?Initialize@CVssWriter@@QEAAJU_GUID@@PEBGW4VSS_USAGE_TYPE@@W4VSS_SOURCE_TYPE@@W4_VSS_APPLICATION_LEVEL@@KW4VSS_ALTERNATE_WRITER_STATE@@_N1@Z"]
+        pub fn CVssWriter_Initialize(
+            This: *mut CVssWriter,
+            WriterID: VSS_ID,
+            wszWriterName: LPCWSTR,
+            ut: VSS_USAGE_TYPE,
+            st: VSS_SOURCE_TYPE,
+            nLevel: VSS_APPLICATION_LEVEL,
+            dwTimeoutFreeze: DWORD,
+            aws: VSS_ALTERNATE_WRITER_STATE,
+            bIOThrottlingOnly: bool,
+            wszReserved: LPCWSTR,
+        ) -> HRESULT;
+        #[link_name = "?Subscribe@CVssWriter@@QEAAJK@Z"]
+        pub fn CVssWriter_Subscribe(This: *mut CVssWriter, dwEventFlags: DWORD) -> HRESULT;
+        #[link_name = "?Unsubscribe@CVssWriter@@QEAAJXZ"]
+        pub fn CVssWriter_Unsubscribe(This: *mut CVssWriter) -> HRESULT;
+        #[link_name = "?GetCurrentVolumeArray@CVssWriter@@IEBAPEAPEBGXZ"]
+        pub fn CVssWriter_GetCurrentVolumeArray(This: *const CVssWriter) -> *mut LPCWSTR;
+        #[link_name = "?GetCurrentVolumeCount@CVssWriter@@IEBAIXZ"]
+        pub fn CVssWriter_GetCurrentVolumeCount(This: *const CVssWriter) -> UINT;
+        #[link_name = "?GetSnapshotDeviceName@CVssWriter@@IEBAJPEBGPEAPEBG@Z"]
+        pub fn CVssWriter_GetSnapshotDeviceName(
+            This: *const CVssWriter,
+            wszOriginalVolume: LPCWSTR,
+            ppwszSnapshotDevice: *mut LPCWSTR,
+        ) -> HRESULT;
+        /// Member functions return structs through a pointer that is passed
+        /// after `This`, the returned value is that same pointer.
+        #[link_name = "?GetCurrentSnapshotSetId@CVssWriter@@IEBA?AU_GUID@@XZ"]
+        pub fn CVssWriter_GetCurrentSnapshotSetId(
+            This: *const CVssWriter,
+            result: *mut VSS_ID,
+        ) -> *mut VSS_ID;
+        #[link_name = "?GetContext@CVssWriter@@IEBAJXZ"]
+        pub fn CVssWriter_GetContext(This: *const CVssWriter) -> LONG;
+        #[link_name = "?GetCurrentLevel@CVssWriter@@IEBA?AW4_VSS_APPLICATION_LEVEL@@XZ"]
+        pub fn CVssWriter_GetCurrentLevel(This: *const CVssWriter) -> VSS_APPLICATION_LEVEL;
+        #[link_name = "?IsPathAffected@CVssWriter@@IEBA_NPEBG@Z"]
+        pub fn CVssWriter_IsPathAffected(This: *const CVssWriter, wszPath: LPCWSTR) -> bool;
+        #[link_name = "?IsBootableSystemStateBackedUp@CVssWriter@@IEBA_NXZ"]
+        pub fn CVssWriter_IsBootableSystemStateBackedUp(This: *const CVssWriter) -> bool;
+        #[link_name = "?AreComponentsSelected@CVssWriter@@IEBA_NXZ"]
+        pub fn CVssWriter_AreComponentsSelected(This: *const CVssWriter) -> bool;
+        #[link_name = "?GetBackupType@CVssWriter@@IEBA?AW4_VSS_BACKUP_TYPE@@XZ"]
+        pub fn CVssWriter_GetBackupType(This: *const CVssWriter) -> VSS_BACKUP_TYPE;
+        #[link_name = "?GetRestoreType@CVssWriter@@IEBA?AW4_VSS_RESTORE_TYPE@@XZ"]
+        pub fn CVssWriter_GetRestoreType(This: *const CVssWriter) -> VSS_RESTORE_TYPE;
+        #[link_name = "?IsPartialFileSupportEnabled@CVssWriter@@IEBA_NXZ"]
+        pub fn CVssWriter_IsPartialFileSupportEnabled(This: *const CVssWriter) -> bool;
+        #[link_name = "?SetWriterFailure@CVssWriter@@IEAAJJ@Z"]
+        pub fn CVssWriter_SetWriterFailure(This: *mut CVssWriter, hr: HRESULT) -> HRESULT;
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Options
+////////////////////////////////////////////////////////////////////////////////
+
+with_from!(
+    [raw = raw::VSS_ALTERNATE_WRITER_STATE, fallback = Undefined],
+    /// Indicates whether a writer has an alternate writer that can be used
+    /// when the writer itself isn't running.
+    #[doc(alias = "VSS_ALTERNATE_WRITER_STATE")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum AlternateWriterState {
+        #[doc(alias = "VSS_AWS_UNDEFINED")]
+        Undefined = raw::VSS_AWS_UNDEFINED,
+        #[doc(alias = "VSS_AWS_NO_ALTERNATE_WRITER")]
+        NoAlternateWriter = raw::VSS_AWS_NO_ALTERNATE_WRITER,
+        #[doc(alias = "VSS_AWS_ALTERNATE_WRITER_EXISTS")]
+        AlternateWriterExists = raw::VSS_AWS_ALTERNATE_WRITER_EXISTS,
+        #[doc(alias = "VSS_AWS_THIS_IS_ALTERNATE_WRITER")]
+        ThisIsAlternateWriter = raw::VSS_AWS_THIS_IS_ALTERNATE_WRITER,
+    }
+);
+
+raw_bitflags! {
+    /// The events that a writer subscribes to with [`VssWriter::subscribe`].
+    /// The default is to subscribe to backup and restore events.
+    #[doc(alias = "VSS_SUBSCRIBE_MASK")]
+    pub struct SubscribeFlags: DWORD {
+        #[doc(alias = "VSS_SM_POST_SNAPSHOT_FLAG")]
+        const POST_SNAPSHOT = raw::VSS_SM_POST_SNAPSHOT_FLAG;
+        #[doc(alias = "VSS_SM_BACKUP_EVENTS_FLAG")]
+        const BACKUP_EVENTS = raw::VSS_SM_BACKUP_EVENTS_FLAG;
+        #[doc(alias = "VSS_SM_RESTORE_EVENTS_FLAG")]
+        const RESTORE_EVENTS = raw::VSS_SM_RESTORE_EVENTS_FLAG;
+        #[doc(alias = "VSS_SM_IO_THROTTLING_FLAG")]
+        const IO_THROTTLING = raw::VSS_SM_IO_THROTTLING_FLAG;
+        #[doc(alias = "VSS_SM_ALL_FLAGS")]
+        const ALL = raw::VSS_SM_ALL_FLAGS;
+    }
+}
+impl Default for SubscribeFlags {
+    fn default() -> Self {
+        Self::BACKUP_EVENTS | Self::RESTORE_EVENTS
+    }
+}
+
+/// The arguments to `CVssWriter::Initialize`, used by [`VssWriter::new`].
+#[derive(Clone, Copy)]
+pub struct WriterOptions<'a> {
+    /// Identifies the writer class. Should be the same every time the
+    /// application runs.
+    pub writer_id: VSS_ID,
+    /// The name of the writer.
+    pub writer_name: &'a U16CStr,
+    /// The type of data that the writer manages.
+    pub usage_type: UsageType,
+    /// The type of data source that the writer manages.
+    pub source_type: SourceType,
+    /// Which `Freeze` event the writer will respond to.
+    pub level: ApplicationLevel,
+    /// The maximum number of milliseconds that the writer will wait between
+    /// the `Freeze` and `Thaw` events.
+    pub freeze_timeout: u32,
+    /// Whether the writer has an alternate writer.
+    pub alternate_writer_state: AlternateWriterState,
+    /// `true` if the writer only handles I/O throttling events.
+    pub io_throttling_only: bool,
+    /// The thread that the [`WriterEvents`] callbacks run on.
+    pub callback_threading: CallbackThreading,
+}
+impl<'a> WriterOptions<'a> {
+    /// Create options with the same defaults as `CVssWriter::Initialize`.
+    pub fn new(
+        writer_id: VSS_ID,
+        writer_name: &'a U16CStr,
+        usage_type: UsageType,
+        source_type: SourceType,
+    ) -> Self {
+        Self {
+            writer_id,
+            writer_name,
+            usage_type,
+            source_type,
+            level: ApplicationLevel::FrontEnd,
+            freeze_timeout: 60 * 1000,
+            alternate_writer_state: AlternateWriterState::NoAlternateWriter,
+            io_throttling_only: false,
+            callback_threading: CallbackThreading::Inline,
+        }
+    }
+}
+
+/// Determines which thread runs the [`WriterEvents`] callbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CallbackThreading {
+    /// Run every callback on the thread that VSS used to deliver the event.
+    /// A callback that blocks delays VSS without any notice.
+    #[default]
+    Inline,
+    /// Run the `PrepareSnapshot`, `Freeze`, `Thaw` and `Abort` callbacks on a
+    /// worker thread while the VSS thread waits for at most `timeout`.
+    ///
+    /// The `Freeze` and `Thaw` callbacks are also limited to what remains of
+    /// the writer's [freeze timeout](WriterOptions::freeze_timeout). If a
+    /// callback takes too long the writer reports
+    /// `VSS_E_WRITERERROR_TIMEOUT` right away and
+    /// [`WriterEvents::on_callback_timeout`] is called. The event still
+    /// completes only once the callback has returned since the callback
+    /// borrows state that is owned by the event.
+    WorkerThread {
+        /// The longest time that a callback may run.
+        timeout: Duration,
+    },
+}
+
+/// The events that VSS can deliver to a writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WriterEvent {
+    /// [`WriterEvents::on_identify`]
+    Identify,
+    /// [`WriterEvents::on_prepare_backup`]
+    PrepareBackup,
+    /// [`WriterEvents::on_prepare_snapshot`]
+    PrepareSnapshot,
+    /// [`WriterEvents::on_freeze`]
+    Freeze,
+    /// [`WriterEvents::on_thaw`]
+    Thaw,
+    /// [`WriterEvents::on_post_snapshot`]
+    PostSnapshot,
+    /// [`WriterEvents::on_abort`]
+    Abort,
+    /// [`WriterEvents::on_backup_complete`]
+    BackupComplete,
+    /// [`WriterEvents::on_backup_shutdown`]
+    BackupShutdown,
+    /// [`WriterEvents::on_pre_restore`]
+    PreRestore,
+    /// [`WriterEvents::on_post_restore`]
+    PostRestore,
+}
+impl WriterEvent {
+    /// `true` for the events that are run on the worker thread when using
+    /// [`CallbackThreading::WorkerThread`].
+    pub fn is_timed(self) -> bool {
+        matches!(
+            self,
+            Self::PrepareSnapshot | Self::Freeze | Self::Thaw | Self::Abort
+        )
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Events
+////////////////////////////////////////////////////////////////////////////////
+
+/// The state of the writer while it handles an event, gives access to the
+/// protected methods of `CVssWriter`.
+pub struct WriterContext<'a> {
+    writer: *mut raw::CVssWriter,
+    frozen_at: Option<Instant>,
+    freeze_timeout: Duration,
+    _marker: PhantomData<&'a raw::CVssWriter>,
+}
+impl<'a> WriterContext<'a> {
+    /// The time since the writer received the `Freeze` event, `None` if the
+    /// writer isn't frozen.
+    pub fn freeze_elapsed(&self) -> Option<Duration> {
+        self.frozen_at.map(|frozen_at| frozen_at.elapsed())
+    }
+    /// The time that remains until the writer's freeze timeout expires and
+    /// VSS aborts the shadow copy, `None` if the writer isn't frozen.
+    pub fn freeze_time_remaining(&self) -> Option<Duration> {
+        self.freeze_elapsed()
+            .map(|elapsed| self.freeze_timeout.checked_sub(elapsed).unwrap_or_default())
+    }
+    /// The names of the volumes in the current shadow copy set.
+    #[doc(alias = "GetCurrentVolumeArray")]
+    #[doc(alias = "GetCurrentVolumeCount")]
+    pub fn current_volumes(&self) -> Vec<&'a U16CStr> {
+        unsafe {
+            let count = raw::CVssWriter_GetCurrentVolumeCount(self.writer);
+            let array = raw::CVssWriter_GetCurrentVolumeArray(self.writer);
+            if array.is_null() {
+                return Vec::new();
+            }
+            slice::from_raw_parts(array, count as usize)
+                .iter()
+                .filter(|name| !name.is_null())
+                .map(|&name| U16CStr::from_ptr_str(name))
+                .collect()
+        }
+    }
+    /// Get the device name of the shadow copy of a volume in the current
+    /// shadow copy set.
+    #[doc(alias = "GetSnapshotDeviceName")]
+    pub fn snapshot_device_name(
+        &self,
+        original_volume: &U16CStr,
+    ) -> Result<&'a U16CStr, GetSnapshotDeviceNameError> {
+        trace_com_call!("GetSnapshotDeviceName");
+        let mut device: LPCWSTR = ptr::null();
+        check_com(unsafe {
+            raw::CVssWriter_GetSnapshotDeviceName(
+                self.writer,
+                original_volume.as_ptr(),
+                &mut device,
+            )
+        })?;
+        Ok(unsafe { U16CStr::from_ptr_str(device) })
+    }
+    /// Identifies the current shadow copy set.
+    #[doc(alias = "GetCurrentSnapshotSetId")]
+    pub fn snapshot_set_id(&self) -> VSS_ID {
+        let mut id = VSS_ID::default();
+        unsafe { raw::CVssWriter_GetCurrentSnapshotSetId(self.writer, &mut id) };
+        id
+    }
+    /// The context of the current shadow copy operation, see
+    /// [`SnapshotContext::from_raw_context`](crate::vss::SnapshotContext::from_raw_context).
+    #[doc(alias = "GetContext")]
+    pub fn context(&self) -> LONG {
+        unsafe { raw::CVssWriter_GetContext(self.writer) }
+    }
+    /// The `Freeze` event that the writer is currently handling.
+    #[doc(alias = "GetCurrentLevel")]
+    pub fn current_level(&self) -> ApplicationLevel {
+        unsafe { raw::CVssWriter_GetCurrentLevel(self.writer) }.into()
+    }
+    /// `true` if the path is on a volume that is part of the current shadow
+    /// copy set.
+    #[doc(alias = "IsPathAffected")]
+    pub fn is_path_affected(&self, path: &U16CStr) -> bool {
+        unsafe { raw::CVssWriter_IsPathAffected(self.writer, path.as_ptr()) }
+    }
+    /// `true` if the requester is backing up the bootable system state.
+    #[doc(alias = "IsBootableSystemStateBackedUp")]
+    pub fn is_bootable_system_state_backed_up(&self) -> bool {
+        unsafe { raw::CVssWriter_IsBootableSystemStateBackedUp(self.writer) }
+    }
+    /// `true` if the requester selects individual components instead of
+    /// backing up whole volumes.
+    #[doc(alias = "AreComponentsSelected")]
+    pub fn are_components_selected(&self) -> bool {
+        unsafe { raw::CVssWriter_AreComponentsSelected(self.writer) }
+    }
+    /// The type of backup that is being performed.
+    #[doc(alias = "GetBackupType")]
+    pub fn backup_type(&self) -> BackupType {
+        unsafe { raw::CVssWriter_GetBackupType(self.writer) }.into()
+    }
+    /// The type of restore that is being performed.
+    #[doc(alias = "GetRestoreType")]
+    pub fn restore_type(&self) -> RestoreType {
+        unsafe { raw::CVssWriter_GetRestoreType(self.writer) }.into()
+    }
+    /// `true` if the requester supports backing up partial files.
+    #[doc(alias = "IsPartialFileSupportEnabled")]
+    pub fn is_partial_file_support_enabled(&self) -> bool {
+        unsafe { raw::CVssWriter_IsPartialFileSupportEnabled(self.writer) }
+    }
+}
+
+/// Callbacks for the events that VSS sends to a writer, the overridable
+/// methods of `CVssWriter`.
+///
+/// Returning an error from a callback reports it with `SetWriterFailure` and
+/// tells VSS that the event failed. A panic is reported as
+/// `VSS_E_WRITERERROR_NONRETRYABLE`.
+///
+/// The methods that have a default implementation do nothing and succeed.
+pub trait WriterEvents: Send + Sync + 'static {
+    /// Describe the writer's components in its Writer Metadata Document.
+    #[doc(alias = "OnIdentify")]
+    fn on_identify(
+        &self,
+        writer: &WriterContext<'_>,
+        metadata: &ICreateWriterMetadata,
+    ) -> Result<(), ReportableWriterFailureError> {
+        let _ = (writer, metadata);
+        Ok(())
+    }
+    /// A backup is starting, the components that will be backed up are
+    /// known.
+    #[doc(alias = "OnPrepareBackup")]
+    fn on_prepare_backup(
+        &self,
+        writer: &WriterContext<'_>,
+        components: &IWriterComponents,
+    ) -> Result<(), ReportableWriterFailureError> {
+        let _ = (writer, components);
+        Ok(())
+    }
+    /// Prepare for the shadow copies to be created.
+    #[doc(alias = "OnPrepareSnapshot")]
+    fn on_prepare_snapshot(
+        &self,
+        writer: &WriterContext<'_>,
+    ) -> Result<(), ReportableWriterFailureError>;
+    /// Flush data to disk and stop writing until
+    /// [`on_thaw`](Self::on_thaw) is called.
+    #[doc(alias = "OnFreeze")]
+    fn on_freeze(&self, writer: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError>;
+    /// The shadow copies have been created, writing can resume.
+    #[doc(alias = "OnThaw")]
+    fn on_thaw(&self, writer: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError>;
+    /// The shadow copies have been created and can be modified before they
+    /// are backed up.
+    #[doc(alias = "OnPostSnapshot")]
+    fn on_post_snapshot(
+        &self,
+        writer: &WriterContext<'_>,
+        components: &IWriterComponents,
+    ) -> Result<(), ReportableWriterFailureError> {
+        let _ = (writer, components);
+        Ok(())
+    }
+    /// The current backup or restore was aborted. Undo any preparations,
+    /// including resuming writes if the writer is frozen.
+    #[doc(alias = "OnAbort")]
+    fn on_abort(&self, writer: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError>;
+    /// The requester has finished the backup.
+    #[doc(alias = "OnBackupComplete")]
+    fn on_backup_complete(
+        &self,
+        writer: &WriterContext<'_>,
+        components: &IWriterComponents,
+    ) -> Result<(), ReportableWriterFailureError> {
+        let _ = (writer, components);
+        Ok(())
+    }
+    /// The backup has completed or the requester shut down.
+    #[doc(alias = "OnBackupShutdown")]
+    fn on_backup_shutdown(
+        &self,
+        writer: &WriterContext<'_>,
+        snapshot_set_id: VSS_ID,
+    ) -> Result<(), ReportableWriterFailureError> {
+        let _ = (writer, snapshot_set_id);
+        Ok(())
+    }
+    /// A restore is about to start.
+    #[doc(alias = "OnPreRestore")]
+    fn on_pre_restore(
+        &self,
+        writer: &WriterContext<'_>,
+        components: &IWriterComponents,
+    ) -> Result<(), ReportableWriterFailureError> {
+        let _ = (writer, components);
+        Ok(())
+    }
+    /// The files have been restored.
+    #[doc(alias = "OnPostRestore")]
+    fn on_post_restore(
+        &self,
+        writer: &WriterContext<'_>,
+        components: &IWriterComponents,
+    ) -> Result<(), ReportableWriterFailureError> {
+        let _ = (writer, components);
+        Ok(())
+    }
+    /// Called on the VSS thread when a callback that runs on the worker thread
+    /// didn't finish within its time limit, see
+    /// [`CallbackThreading::WorkerThread`]. The writer failure has already
+    /// been reported to VSS when this is called, but the event only returns
+    /// to VSS once the callback has finished.
+    ///
+    /// The default implementation does nothing.
+    fn on_callback_timeout(&self, event: WriterEvent, timeout: Duration) {
+        let _ = (event, timeout);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Writer object
+////////////////////////////////////////////////////////////////////////////////
+
+/// The memory layout of the object that VSS calls into.
+#[repr(C)]
+struct WriterObject<W> {
+    /// Must be the first field so that a pointer to the object is also a valid
+    /// `CVssWriter` pointer.
+    base: raw::CVssWriter,
+    events: W,
+    threading: CallbackThreading,
+    freeze_timeout: Duration,
+    /// Set when the `Freeze` event is received and cleared by `Thaw` or
+    /// `Abort`.
+    frozen_at: Mutex<Option<Instant>>,
+}
+
+/// The result of a callback that ran on the worker thread.
+enum WorkerOutcome {
+    /// The callback returned in time, or panicked.
+    Finished(Result<(), ReportableWriterFailureError>),
+    /// The callback didn't finish within its time limit. The failure has
+    /// already been reported to VSS.
+    TimedOut,
+}
+
+/// A writer pointer that can be moved to the worker thread.
+struct WriterPtr(*mut raw::CVssWriter);
+// Safety: the `CVssWriter` methods that `WriterContext` calls can be used from
+// any thread and the VSS thread waits for the worker before it returns.
+unsafe impl Send for WriterPtr {}
+impl WriterPtr {
+    fn get(&self) -> *mut raw::CVssWriter {
+        self.0
+    }
+}
+
+impl<W: WriterEvents> WriterObject<W> {
+    const VTBL: raw::CVssWriterVtbl = raw::CVssWriterVtbl {
+        ScalarDeletingDestructor: Self::scalar_deleting_destructor,
+        OnIdentify: Self::on_identify,
+        OnPrepareBackup: Self::on_prepare_backup,
+        OnPrepareSnapshot: Self::on_prepare_snapshot,
+        OnFreeze: Self::on_freeze,
+        OnThaw: Self::on_thaw,
+        OnAbort: Self::on_abort,
+        OnBackupComplete: Self::on_backup_complete,
+        OnBackupShutdown: Self::on_backup_shutdown,
+        OnPreRestore: Self::on_pre_restore,
+        OnPostRestore: Self::on_post_restore,
+        OnPostSnapshot: Self::on_post_snapshot,
+        OnBackOffIOOnVolume: Self::on_io_throttling,
+        OnContinueIOOnVolume: Self::on_io_throttling,
+        OnVSSShutdown: Self::on_vss_notification,
+        OnVSSApplicationStartup: Self::on_vss_notification,
+    };
+
+    /// # Safety
+    ///
+    /// `this` must point to a live `WriterObject<W>`.
+    unsafe fn from_raw<'a>(this: *mut raw::CVssWriter) -> &'a Self {
+        &*(this as *const Self)
+    }
+
+    fn frozen_at(&self) -> Option<Instant> {
+        *self.frozen_at.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn set_frozen_at(&self, value: Option<Instant>) {
+        *self.frozen_at.lock().unwrap_or_else(|e| e.into_inner()) = value;
+    }
+
+    /// Call an event handler while catching panics.
+    fn call(
+        events: &W,
+        context: &WriterContext<'_>,
+        f: impl FnOnce(&W, &WriterContext<'_>) -> Result<(), ReportableWriterFailureError>,
+    ) -> Result<(), ReportableWriterFailureError> {
+        panic::catch_unwind(AssertUnwindSafe(|| f(events, context))).unwrap_or_else(|_| {
+            Err(ReportableWriterFailureError::from_kind(
+                ReportableWriterFailureErrorKind::VSS_E_WRITERERROR_NONRETRYABLE,
+            ))
+        })
+    }
+
+    /// Translate the result of an event handler for VSS.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a live `WriterObject<W>`.
+    unsafe fn report(
+        this: *mut raw::CVssWriter,
+        result: Result<(), ReportableWriterFailureError>,
+    ) -> bool {
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                raw::CVssWriter_SetWriterFailure(this, e.into());
+                false
+            }
+        }
+    }
+
+    /// Call an event handler on the current thread.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a live `WriterObject<W>`.
+    unsafe fn dispatch(
+        this: *mut raw::CVssWriter,
+        f: impl FnOnce(&W, &WriterContext<'_>) -> Result<(), ReportableWriterFailureError>,
+    ) -> bool {
+        let object = Self::from_raw(this);
+        let context = WriterContext {
+            writer: this,
+            frozen_at: object.frozen_at(),
+            freeze_timeout: object.freeze_timeout,
+            _marker: PhantomData,
+        };
+        Self::report(this, Self::call(&object.events, &context, f))
+    }
+
+    /// Call the handler of an event that takes part in the freeze window,
+    /// using the worker thread if the writer is configured to.
+    ///
+    /// A timeout is reported to VSS as soon as it happens, but this still
+    /// waits for the worker thread since the callback borrows `this`.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a live `WriterObject<W>`.
+    unsafe fn dispatch_timed(
+        this: *mut raw::CVssWriter,
+        event: WriterEvent,
+        f: impl FnOnce(&W, &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> + Send,
+    ) -> bool {
+        let object = Self::from_raw(this);
+        let timeout = match object.threading {
+            CallbackThreading::Inline => return Self::dispatch(this, f),
+            CallbackThreading::WorkerThread { timeout } => timeout,
+        };
+        let frozen_at = object.frozen_at();
+        let freeze_timeout = object.freeze_timeout;
+        let timeout = match event {
+            WriterEvent::Freeze => cmp::min(timeout, freeze_timeout),
+            WriterEvent::Thaw => match frozen_at {
+                Some(frozen_at) => cmp::min(
+                    timeout,
+                    freeze_timeout
+                        .checked_sub(frozen_at.elapsed())
+                        .unwrap_or_default(),
+                ),
+                None => timeout,
+            },
+            _ => timeout,
+        };
+
+        let events = &object.events;
+        let writer = WriterPtr(this);
+        let outcome = thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            scope.spawn(move || {
+                let context = WriterContext {
+                    writer: writer.get(),
+                    frozen_at,
+                    freeze_timeout,
+                    _marker: PhantomData,
+                };
+                let _ = tx.send(Self::call(events, &context, f));
+            });
+            match rx.recv_timeout(timeout) {
+                Ok(result) => WorkerOutcome::Finished(result),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let error = ReportableWriterFailureError::from_kind(
+                        ReportableWriterFailureErrorKind::VSS_E_WRITERERROR_TIMEOUT,
+                    );
+                    raw::CVssWriter_SetWriterFailure(this, error.into());
+                    // Nothing can be reported for a panic here, the failure
+                    // was already set above.
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+                        events.on_callback_timeout(event, timeout)
+                    }));
+                    // The callback borrows state owned by this event so it
+                    // must finish before we return to VSS.
+                    let _ = rx.recv();
+                    WorkerOutcome::TimedOut
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    WorkerOutcome::Finished(Err(ReportableWriterFailureError::from_kind(
+                        ReportableWriterFailureErrorKind::VSS_E_WRITERERROR_NONRETRYABLE,
+                    )))
+                }
+            }
+        });
+        match outcome {
+            WorkerOutcome::Finished(result) => Self::report(this, result),
+            WorkerOutcome::TimedOut => false,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `this` must point to a live `WriterObject<W>` and `components` must be
+    /// valid if it isn't null.
+    unsafe fn dispatch_components(
+        this: *mut raw::CVssWriter,
+        components: *mut IVssWriterComponents,
+        f: impl FnOnce(
+            &W,
+            &WriterContext<'_>,
+            &IWriterComponents,
+        ) -> Result<(), ReportableWriterFailureError>,
+    ) -> bool {
+        let components = match BorrowedComponent::<IWriterComponents>::from_raw(components) {
+            Some(components) => components,
+            None => {
+                return Self::report(
+                    this,
+                    Err(ReportableWriterFailureError::from_kind(
+                        ReportableWriterFailureErrorKind::VSS_E_WRITERERROR_NONRETRYABLE,
+                    )),
+                )
+            }
+        };
+        Self::dispatch(this, |events, context| f(events, context, &components))
+    }
+
+    /// The object is owned by a [`VssWriter`] which destroys it, VSS never
+    /// deletes writers.
+    unsafe extern "system" fn scalar_deleting_destructor(
+        this: *mut raw::CVssWriter,
+        _flags: UINT,
+    ) -> *mut c_void {
+        this as *mut c_void
+    }
+    unsafe extern "system" fn on_identify(
+        this: *mut raw::CVssWriter,
+        metadata: *mut IVssCreateWriterMetadata,
+    ) -> bool {
+        let metadata = match BorrowedComponent::<ICreateWriterMetadata>::from_raw(metadata) {
+            Some(metadata) => metadata,
+            None => return false,
+        };
+        Self::dispatch(this, |events, context| {
+            events.on_identify(context, &metadata)
+        })
+    }
+    unsafe extern "system" fn on_prepare_backup(
+        this: *mut raw::CVssWriter,
+        components: *mut IVssWriterComponents,
+    ) -> bool {
+        Self::dispatch_components(this, components, |events, context, components| {
+            events.on_prepare_backup(context, components)
+        })
+    }
+    unsafe extern "system" fn on_prepare_snapshot(this: *mut raw::CVssWriter) -> bool {
+        Self::dispatch_timed(this, WriterEvent::PrepareSnapshot, |events, context| {
+            events.on_prepare_snapshot(context)
+        })
+    }
+    unsafe extern "system" fn on_freeze(this: *mut raw::CVssWriter) -> bool {
+        Self::from_raw(this).set_frozen_at(Some(Instant::now()));
+        Self::dispatch_timed(this, WriterEvent::Freeze, |events, context| {
+            events.on_freeze(context)
+        })
+    }
+    unsafe extern "system" fn on_thaw(this: *mut raw::CVssWriter) -> bool {
+        let result = Self::dispatch_timed(this, WriterEvent::Thaw, |events, context| {
+            events.on_thaw(context)
+        });
+        Self::from_raw(this).set_frozen_at(None);
+        result
+    }
+    unsafe extern "system" fn on_abort(this: *mut raw::CVssWriter) -> bool {
+        let result = Self::dispatch_timed(this, WriterEvent::Abort, |events, context| {
+            events.on_abort(context)
+        });
+        Self::from_raw(this).set_frozen_at(None);
+        result
+    }
+    unsafe extern "system" fn on_backup_complete(
+        this: *mut raw::CVssWriter,
+        components: *mut IVssWriterComponents,
+    ) -> bool {
+        Self::dispatch_components(this, components, |events, context, components| {
+            events.on_backup_complete(context, components)
+        })
+    }
+    unsafe extern "system" fn on_backup_shutdown(
+        this: *mut raw::CVssWriter,
+        snapshot_set_id: VSS_ID,
+    ) -> bool {
+        Self::dispatch(this, |events, context| {
+            events.on_backup_shutdown(context, snapshot_set_id)
+        })
+    }
+    unsafe extern "system" fn on_pre_restore(
+        this: *mut raw::CVssWriter,
+        components: *mut IVssWriterComponents,
+    ) -> bool {
+        Self::dispatch_components(this, components, |events, context, components| {
+            events.on_pre_restore(context, components)
+        })
+    }
+    unsafe extern "system" fn on_post_restore(
+        this: *mut raw::CVssWriter,
+        components: *mut IVssWriterComponents,
+    ) -> bool {
+        Self::dispatch_components(this, components, |events, context, components| {
+            events.on_post_restore(context, components)
+        })
+    }
+    unsafe extern "system" fn on_post_snapshot(
+        this: *mut raw::CVssWriter,
+        components: *mut IVssWriterComponents,
+    ) -> bool {
+        Self::dispatch_components(this, components, |events, context, components| {
+            events.on_post_snapshot(context, components)
+        })
+    }
+    unsafe extern "system" fn on_io_throttling(
+        _this: *mut raw::CVssWriter,
+        _volume_name: VSS_PWSZ,
+        _snapshot_id: VSS_ID,
+        _provider_id: VSS_ID,
+    ) -> bool {
+        true
+    }
+    unsafe extern "system" fn on_vss_notification(_this: *mut raw::CVssWriter) -> bool {
+        true
+    }
+}
+
+/// A VSS writer implemented by a [`WriterEvents`] type.
+///
+/// The writer is unsubscribed and destroyed when this value is dropped.
+#[doc(alias = "CVssWriter")]
+pub struct VssWriter<W: WriterEvents> {
+    object: NonNull<WriterObject<W>>,
+    subscribed: bool,
+}
+// Safety: the events are `Send + Sync` and `CVssWriter` can be used from any
+// thread.
+unsafe impl<W: WriterEvents> Send for VssWriter<W> {}
+unsafe impl<W: WriterEvents> Sync for VssWriter<W> {}
+impl<W: WriterEvents> VssWriter<W> {
+    /// Create and initialize a writer. It won't receive any events until
+    /// [`VssWriter::subscribe`] is called.
+    ///
+    /// COM must be initialized on the current thread.
+    #[doc(alias = "Initialize")]
+    pub fn new(options: &WriterOptions<'_>, events: W) -> Result<Self, WriterInitializeError> {
+        trace_com_call!("Initialize");
+        let object = Box::into_raw(Box::new(WriterObject {
+            base: raw::CVssWriter {
+                lpVtbl: ptr::null(),
+                m_pWrapper: ptr::null_mut(),
+            },
+            events,
+            threading: options.callback_threading,
+            freeze_timeout: Duration::from_millis(options.freeze_timeout.into()),
+            frozen_at: Mutex::new(None),
+        }));
+        let this = object as *mut raw::CVssWriter;
+        let writer = unsafe {
+            raw::CVssWriter_CVssWriter(this);
+            // Replace the base class vtable to override the virtual methods.
+            (*this).lpVtbl = &WriterObject::<W>::VTBL;
+            Self {
+                object: NonNull::new_unchecked(object),
+                subscribed: false,
+            }
+        };
+        check_com(unsafe {
+            raw::CVssWriter_Initialize(
+                this,
+                options.writer_id,
+                options.writer_name.as_ptr(),
+                options.usage_type.into(),
+                options.source_type.into(),
+                vss::VSS_APPLICATION_LEVEL::from(options.level),
+                options.freeze_timeout,
+                options.alternate_writer_state.into(),
+                options.io_throttling_only,
+                // Reserved, writer instance names require `CVssWriterEx`.
+                ptr::null(),
+            )
+        })?;
+        Ok(writer)
+    }
+    fn as_raw(&self) -> *mut raw::CVssWriter {
+        self.object.as_ptr() as *mut raw::CVssWriter
+    }
+    /// The event handlers that were passed to [`VssWriter::new`].
+    pub fn events(&self) -> &W {
+        unsafe { &self.object.as_ref().events }
+    }
+    /// Start receiving events from VSS.
+    #[doc(alias = "Subscribe")]
+    pub fn subscribe(
+        &mut self,
+        flags: RawBitFlags<SubscribeFlags>,
+    ) -> Result<(), WriterSubscribeError> {
+        trace_com_call!("Subscribe");
+        check_com(unsafe { raw::CVssWriter_Subscribe(self.as_raw(), flags.raw()) })?;
+        self.subscribed = true;
+        Ok(())
+    }
+    /// Stop receiving events from VSS.
+    #[doc(alias = "Unsubscribe")]
+    pub fn unsubscribe(&mut self) -> Result<(), WriterUnsubscribeError> {
+        trace_com_call!("Unsubscribe");
+        if self.subscribed {
+            check_com(unsafe { raw::CVssWriter_Unsubscribe(self.as_raw()) })?;
+            self.subscribed = false;
+        }
+        Ok(())
+    }
+}
+impl<W: WriterEvents> Drop for VssWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.unsubscribe();
+        unsafe {
+            raw::CVssWriter_Destructor(self.as_raw());
+            drop(Box::from_raw(self.object.as_ptr()));
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Host a [`VssWriter`] inside a Windows service.
+//!
+//! VSS writers usually run for as long as the application whose data they
+//! describe, which is often a service. [`run_writer_service`] connects the
+//! current process to the service control manager, creates and subscribes
+//! the writer when the service starts and unsubscribes it when the service is
+//! stopped.
+//!
+//! This module requires the `service` feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::{
+//!     errors::ReportableWriterFailureError,
+//!     vswriter::{
+//!         framework::{VssWriter, WriterContext, WriterEvents, WriterOptions},
+//!         service::run_writer_service,
+//!         SourceType, UsageType,
+//!     },
+//! };
+//! use widestring::U16CString;
+//!
+//! struct MyWriter;
+//! impl WriterEvents for MyWriter {
+//!     fn on_prepare_snapshot(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         Ok(())
+//!     }
+//!     fn on_freeze(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         Ok(())
+//!     }
+//!     fn on_thaw(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         Ok(())
+//!     }
+//!     fn on_abort(&self, _: &WriterContext<'_>) -> Result<(), ReportableWriterFailureError> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! run_writer_service("MyWriterService", Default::default(), || {
+//!     let name = U16CString::from_str("My Writer").unwrap();
+//!     let options = WriterOptions::new(
+//!         Default::default(),
+//!         &name,
+//!         UsageType::UserData,
+//!         SourceType::NonTransactedDb,
+//!     );
+//!     VssWriter::new(&options, MyWriter)
+//! })?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # References
+//!
+//! [Service Programs - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/services/service-programs)
+
+use std::{
+    error::Error as StdError,
+    fmt, io, ptr,
+    sync::{mpsc, Mutex},
+};
+
+use widestring::{NulError, U16CString};
+use winapi::{
+    shared::{
+        minwindef::{DWORD, LPVOID},
+        winerror::{ERROR_CALL_NOT_IMPLEMENTED, ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR},
+    },
+    um::{
+        combaseapi::CoUninitialize,
+        winnt::{LPWSTR, SERVICE_WIN32_OWN_PROCESS},
+        winsvc::{
+            RegisterServiceCtrlHandlerExW, SetServiceStatus, StartServiceCtrlDispatcherW,
+            SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP, SERVICE_CONTROL_INTERROGATE,
+            SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP, SERVICE_RUNNING, SERVICE_START_PENDING,
+            SERVICE_STATUS, SERVICE_STATUS_HANDLE, SERVICE_STOPPED, SERVICE_STOP_PENDING,
+            SERVICE_TABLE_ENTRYW,
+        },
+    },
+};
+
+use super::framework::{SubscribeFlags, VssWriter, WriterEvents};
+use crate::{errors::*, initialize_com, RawBitFlags};
+
+/// Error returned by [`run_writer_service`].
+#[derive(Debug)]
+pub enum WriterServiceError {
+    /// The service name contained a nul character.
+    InvalidName(NulError<u16>),
+    /// The process couldn't connect to the service control manager, for
+    /// example because it was started from a console instead of by the
+    /// service control manager.
+    Dispatcher(io::Error),
+    /// Failed to register the handler for service control requests.
+    RegisterHandler(io::Error),
+    /// Failed to initialize COM on the service thread.
+    InitializeCom(CoInitializeExError),
+    /// Failed to create the writer.
+    CreateWriter(WriterInitializeError),
+    /// Failed to subscribe the writer to VSS events.
+    Subscribe(WriterSubscribeError),
+    /// Failed to unsubscribe the writer when the service was stopped.
+    Unsubscribe(WriterUnsubscribeError),
+    /// The service control manager returned without starting the service,
+    /// so the writer never ran.
+    NotStarted,
+}
+impl fmt::Display for WriterServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(e) => write!(f, "invalid service name: {}", e),
+            Self::Dispatcher(e) => {
+                write!(f, "failed to connect to the service control manager: {}", e)
+            }
+            Self::RegisterHandler(e) => write!(f, "failed to register service handler: {}", e),
+            Self::InitializeCom(e) => write!(f, "failed to initialize COM: {}", e),
+            Self::CreateWriter(e) => write!(f, "failed to create writer: {}", e),
+            Self::Subscribe(e) => write!(f, "failed to subscribe writer: {}", e),
+            Self::Unsubscribe(e) => write!(f, "failed to unsubscribe writer: {}", e),
+            Self::NotStarted => write!(f, "the service was never started"),
+        }
+    }
+}
+impl StdError for WriterServiceError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::InvalidName(e) => Some(e),
+            Self::Dispatcher(e) => Some(e),
+            Self::RegisterHandler(e) => Some(e),
+            Self::InitializeCom(e) => Some(e),
+            Self::CreateWriter(e) => Some(e),
+            Self::Subscribe(e) => Some(e),
+            Self::Unsubscribe(e) => Some(e),
+            Self::NotStarted => None,
+        }
+    }
+}
+
+/// A subscribed writer with its event handler type erased.
+trait RunningWriter {
+    fn stop(self: Box<Self>) -> Result<(), WriterServiceError>;
+}
+impl<W: WriterEvents> RunningWriter for VssWriter<W> {
+    fn stop(mut self: Box<Self>) -> Result<(), WriterServiceError> {
+        self.unsubscribe().map_err(WriterServiceError::Unsubscribe)
+    }
+}
+
+type StartWriter = Box<dyn FnOnce() -> Result<Box<dyn RunningWriter>, WriterServiceError> + Send>;
+
+/// The service that [`service_main`] should run. The service control
+/// dispatcher calls it without any user data so it is passed through a
+/// global.
+struct PendingService {
+    name: U16CString,
+    start: StartWriter,
+    result: mpsc::Sender<Result<(), WriterServiceError>>,
+}
+static PENDING_SERVICE: Mutex<Option<PendingService>> = Mutex::new(None);
+
+/// Run the current process as a service that hosts a VSS writer.
+///
+/// When the service starts, COM is initialized on the service thread,
+/// `create_writer` is called and the writer is subscribed using `flags`. The
+/// writer is unsubscribed and dropped when the service is stopped or the
+/// system shuts down.
+///
+/// This blocks until the service has stopped. The process must have been
+/// started by the service control manager and the service must be
+/// registered with the `SERVICE_WIN32_OWN_PROCESS` type.
+#[doc(alias = "StartServiceCtrlDispatcher")]
+pub fn run_writer_service<W, F>(
+    service_name: &str,
+    flags: RawBitFlags<SubscribeFlags>,
+    create_writer: F,
+) -> Result<(), WriterServiceError>
+where
+    W: WriterEvents,
+    F: FnOnce() -> Result<VssWriter<W>, WriterInitializeError> + Send + 'static,
+{
+    let name = U16CString::from_str(service_name).map_err(WriterServiceError::InvalidName)?;
+    let (tx, rx) = mpsc::channel();
+    *PENDING_SERVICE.lock().unwrap_or_else(|e| e.into_inner()) = Some(PendingService {
+        name: name.clone(),
+        start: Box::new(move || {
+            let mut writer = create_writer().map_err(WriterServiceError::CreateWriter)?;
+            writer
+                .subscribe(flags)
+                .map_err(WriterServiceError::Subscribe)?;
+            Ok(Box::new(writer) as Box<dyn RunningWriter>)
+        }),
+        result: tx,
+    });
+
+    let table = [
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: name.as_ptr(),
+            lpServiceProc: Some(service_main),
+        },
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: ptr::null(),
+            lpServiceProc: None,
+        },
+    ];
+    let dispatched = if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    };
+    // Drop the sender if the service was never started so that `recv` below
+    // doesn't block forever.
+    PENDING_SERVICE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    dispatched.map_err(WriterServiceError::Dispatcher)?;
+    // The service main function sends its result before it reports that the
+    // service has stopped, which is what lets the dispatcher return.
+    rx.recv().unwrap_or(Err(WriterServiceError::NotStarted))
+}
+
+/// State shared with [`control_handler`].
+struct HandlerContext {
+    stop: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+fn set_status(handle: SERVICE_STATUS_HANDLE, state: DWORD, exit_code: DWORD) {
+    let mut status = SERVICE_STATUS {
+        dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+        dwCurrentState: state,
+        dwControlsAccepted: if state == SERVICE_RUNNING {
+            SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+        } else {
+            0
+        },
+        dwWin32ExitCode: if exit_code == 0 {
+            NO_ERROR
+        } else {
+            ERROR_SERVICE_SPECIFIC_ERROR
+        },
+        dwServiceSpecificExitCode: exit_code,
+        dwCheckPoint: 0,
+        dwWaitHint: if state == SERVICE_START_PENDING || state == SERVICE_STOP_PENDING {
+            30 * 1000
+        } else {
+            0
+        },
+    };
+    unsafe { SetServiceStatus(handle, &mut status) };
+}
+
+unsafe extern "system" fn control_handler(
+    control: DWORD,
+    _event_type: DWORD,
+    _event_data: LPVOID,
+    context: LPVOID,
+) -> DWORD {
+    let context = &*(context as *const HandlerContext);
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            if let Some(stop) = context
+                .stop
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take()
+            {
+                let _ = stop.send(());
+            }
+            NO_ERROR
+        }
+        SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+        _ => ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+unsafe extern "system" fn service_main(_argc: DWORD, _argv: *mut LPWSTR) {
+    let pending = match PENDING_SERVICE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        Some(pending) => pending,
+        None => return,
+    };
+    let (stop_tx, stop_rx) = mpsc::channel();
+    // The handler can be called until the service has stopped, so the
+    // context is leaked instead of freed when this function returns.
+    let context: &'static HandlerContext = Box::leak(Box::new(HandlerContext {
+        stop: Mutex::new(Some(stop_tx)),
+    }));
+    let handle = RegisterServiceCtrlHandlerExW(
+        pending.name.as_ptr(),
+        Some(control_handler),
+        context as *const HandlerContext as LPVOID,
+    );
+    if handle.is_null() {
+        let _ = pending.result.send(Err(WriterServiceError::RegisterHandler(
+            io::Error::last_os_error(),
+        )));
+        return;
+    }
+    set_status(handle, SERVICE_START_PENDING, 0);
+
+    let result = match initialize_com() {
+        Ok(()) => {
+            let result = (pending.start)().and_then(|writer| {
+                set_status(handle, SERVICE_RUNNING, 0);
+                let _ = stop_rx.recv();
+                set_status(handle, SERVICE_STOP_PENDING, 0);
+                writer.stop()
+            });
+            CoUninitialize();
+            result
+        }
+        Err(e) => Err(WriterServiceError::InitializeCom(e)),
+    };
+
+    let exit_code = if result.is_ok() { 0 } else { 1 };
+    let _ = pending.result.send(result);
+    set_status(handle, SERVICE_STOPPED, exit_code);
+}
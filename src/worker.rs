@@ -0,0 +1,230 @@
+//! Run VSS calls on a dedicated thread.
+//!
+//! The COM interface pointers in this crate can't be sent to other threads,
+//! so a program that uses several threads must keep every VSS object on the
+//! thread that created it. A [`VssWorker`] owns a thread with COM initialized
+//! together with some state, for example a
+//! [`BackupComponents`](crate::vsbackup::BackupComponents) object, and runs
+//! closures that are sent to it from any thread.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::{vsbackup::BackupComponents, vss::BackupType, worker::VssWorker};
+//!
+//! let worker = VssWorker::with_state(BackupComponents::new)?;
+//! let handle = worker.handle();
+//! std::thread::spawn(move || {
+//!     handle.run(|backup_comp| backup_comp.initialize_for_backup(None))
+//! })
+//! .join()
+//! .unwrap()??;
+//! worker.run(|backup_comp| backup_comp.set_backup_state(false, false, BackupType::Full, false))??;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    convert::Infallible,
+    error::Error as StdError,
+    fmt, io,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{errors::CoInitializeExError, initialize_com_scoped};
+
+/// Error returned by [`VssWorker`].
+#[derive(Debug)]
+pub enum VssWorkerError {
+    /// The worker thread couldn't be spawned.
+    Spawn(io::Error),
+    /// Failed to initialize COM on the worker thread.
+    InitializeCom(CoInitializeExError),
+    /// The closure that creates the state of the worker failed.
+    InitializeState(Box<dyn StdError + Send + Sync + 'static>),
+    /// The worker thread has stopped, either because the [`VssWorker`] was
+    /// dropped or because a closure that it ran panicked.
+    Stopped,
+}
+impl fmt::Display for VssWorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn(e) => write!(f, "failed to spawn worker thread: {}", e),
+            Self::InitializeCom(e) => write!(f, "failed to initialize COM: {}", e),
+            Self::InitializeState(e) => write!(f, "failed to initialize worker state: {}", e),
+            Self::Stopped => write!(f, "the worker thread has stopped"),
+        }
+    }
+}
+impl StdError for VssWorkerError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Spawn(e) => Some(e),
+            Self::InitializeCom(e) => Some(e),
+            Self::InitializeState(e) => Some(&**e),
+            Self::Stopped => None,
+        }
+    }
+}
+
+type Job<S> = Box<dyn FnOnce(&mut S) + Send>;
+
+enum Message<S> {
+    Run(Job<S>),
+    /// Sent when the [`VssWorker`] is dropped.
+    Stop,
+}
+
+/// A thread with COM initialized that owns a value of type `S` and runs
+/// closures with it.
+///
+/// Closures run one at a time in the order they were sent. Dropping the
+/// worker waits for the closures that were queued before it to finish, then
+/// drops the state and uninitializes COM on the worker thread. Handles
+/// returned by [`handle`](Self::handle) return [`VssWorkerError::Stopped`]
+/// after that.
+pub struct VssWorker<S = ()> {
+    handle: VssWorkerHandle<S>,
+    thread: Option<JoinHandle<()>>,
+}
+impl VssWorker {
+    /// Start a worker without any state.
+    pub fn new() -> Result<Self, VssWorkerError> {
+        Self::with_state(|| Ok::<_, Infallible>(()))
+    }
+}
+impl<S: 'static> VssWorker<S> {
+    /// Start a worker whose state is created by `init` on the worker thread
+    /// after COM has been initialized.
+    pub fn with_state<F, E>(init: F) -> Result<Self, VssWorkerError>
+    where
+        F: FnOnce() -> Result<S, E> + Send + 'static,
+        E: StdError + Send + Sync + 'static,
+    {
+        let (jobs, receiver) = mpsc::channel::<Message<S>>();
+        let (ready, is_ready) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("vss-worker".to_owned())
+            .spawn(move || {
+                // Declared before the state so that COM is uninitialized after
+                // the state is dropped, even if a closure panics.
+                let _com = match initialize_com_scoped() {
+                    Ok(guard) => guard,
+                    Err(e) => {
+                        let _ = ready.send(Err(VssWorkerError::InitializeCom(e)));
+                        return;
+                    }
+                };
+                match init() {
+                    Ok(mut state) => {
+                        let _ = ready.send(Ok(()));
+                        for message in receiver {
+                            match message {
+                                Message::Run(job) => job(&mut state),
+                                Message::Stop => break,
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        let _ = ready.send(Err(VssWorkerError::InitializeState(Box::new(e))));
+                    }
+                }
+            })
+            .map_err(VssWorkerError::Spawn)?;
+        match is_ready.recv() {
+            Ok(Ok(())) => Ok(Self {
+                handle: VssWorkerHandle { jobs },
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            Err(_) => Err(VssWorkerError::Stopped),
+        }
+    }
+    /// A handle that can send closures to this worker from other threads.
+    pub fn handle(&self) -> VssWorkerHandle<S> {
+        self.handle.clone()
+    }
+    /// Keep the worker running until every handle to it has been dropped
+    /// instead of stopping it when this value is dropped.
+    ///
+    /// Nothing waits for the worker thread to exit after that.
+    pub fn detach(mut self) -> VssWorkerHandle<S> {
+        // Dropping the join handle detaches the thread.
+        self.thread = None;
+        self.handle.clone()
+    }
+    /// Run `f` on the worker thread and wait for its result.
+    pub fn run<F, R>(&self, f: F) -> Result<R, VssWorkerError>
+    where
+        F: FnOnce(&mut S) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.handle.run(f)
+    }
+    /// Queue `f` to run on the worker thread without waiting for it.
+    pub fn spawn<F>(&self, f: F) -> Result<(), VssWorkerError>
+    where
+        F: FnOnce(&mut S) + Send + 'static,
+    {
+        self.handle.spawn(f)
+    }
+}
+impl<S> fmt::Debug for VssWorker<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VssWorker")
+            .field("thread", &self.thread)
+            .finish()
+    }
+}
+impl<S> Drop for VssWorker<S> {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = self.handle.jobs.send(Message::Stop);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Sends closures to a [`VssWorker`]. Can be cloned and sent to other
+/// threads.
+pub struct VssWorkerHandle<S> {
+    jobs: mpsc::Sender<Message<S>>,
+}
+impl<S: 'static> VssWorkerHandle<S> {
+    /// Run `f` on the worker thread and wait for its result.
+    pub fn run<F, R>(&self, f: F) -> Result<R, VssWorkerError>
+    where
+        F: FnOnce(&mut S) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result, get_result) = mpsc::channel();
+        self.spawn(move |state| {
+            let _ = result.send(f(state));
+        })?;
+        get_result.recv().map_err(|_| VssWorkerError::Stopped)
+    }
+    /// Queue `f` to run on the worker thread without waiting for it.
+    pub fn spawn<F>(&self, f: F) -> Result<(), VssWorkerError>
+    where
+        F: FnOnce(&mut S) + Send + 'static,
+    {
+        self.jobs
+            .send(Message::Run(Box::new(f)))
+            .map_err(|_| VssWorkerError::Stopped)
+    }
+}
+impl<S> Clone for VssWorkerHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+impl<S> fmt::Debug for VssWorkerHandle<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VssWorkerHandle").finish()
+    }
+}
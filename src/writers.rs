@@ -0,0 +1,476 @@
+//! Helpers for the VSS writers that ship with common Windows applications.
+//!
+//! Each writer describes its data in its own way, for example by using
+//! logical paths that encode application specific names. The modules here
+//! translate between those conventions and the types used by the
+//! [`session`](crate::session) module.
+//!
+//! The constants in this module identify well-known writers, for example to
+//! leave them out of a backup:
+//!
+//! ```no_run
+//! use volume_shadow_copy::{vsbackup::IBackupComponents, writers};
+//!
+//! fn skip_optional_writers(backup_comp: &IBackupComponents) -> Result<(), Box<dyn std::error::Error>> {
+//!     backup_comp.disable_writer_classes(&[writers::ASR.id, writers::SHADOW_COPY_OPTIMIZATION.id])?;
+//!     Ok(())
+//! }
+//! ```
+
+pub mod database;
+pub mod hyperv;
+pub mod sql;
+
+use std::fmt;
+
+use widestring::U16CString;
+use winapi::shared::guiddef::{IsEqualGUID, GUID};
+
+use crate::{
+    bstr_to_u16_cstring,
+    paths::FileSet,
+    session::{guid_to_string, SelectionAction, SelectionConfig, SessionError},
+    vsbackup::{ComponentInfo, ComponentSpec, GetIdentityInfo, IBackupComponents, WMComponent},
+    vswriter::VssComponentType,
+    VSS_ID,
+};
+
+/// A writer that ships with Windows or with a Microsoft application.
+#[derive(Clone, Copy)]
+pub struct KnownWriter {
+    /// The name that the writer reports, for example in the output of
+    /// `vssadmin list writers`.
+    pub name: &'static str,
+    /// The writer class id.
+    pub id: VSS_ID,
+}
+impl KnownWriter {
+    /// Find the writer in [`ALL`] with the writer class id `id`.
+    pub fn from_id(id: &VSS_ID) -> Option<&'static KnownWriter> {
+        ALL.iter().find(|writer| IsEqualGUID(&writer.id, id))
+    }
+    /// Find the writer in [`ALL`] with the name `name`. Case is ignored.
+    pub fn from_name(name: &str) -> Option<&'static KnownWriter> {
+        ALL.iter()
+            .find(|writer| writer.name.eq_ignore_ascii_case(name))
+    }
+}
+impl fmt::Debug for KnownWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KnownWriter")
+            .field("name", &self.name)
+            .field("id", &guid_to_string(&self.id))
+            .finish()
+    }
+}
+
+const fn guid(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> GUID {
+    GUID {
+        Data1: data1,
+        Data2: data2,
+        Data3: data3,
+        Data4: data4,
+    }
+}
+
+/// Backs up the operating system files and installed programs.
+pub const SYSTEM: KnownWriter = KnownWriter {
+    name: "System Writer",
+    id: guid(
+        0xe813_2975,
+        0x6f93,
+        0x4464,
+        [0xa5, 0x3e, 0x10, 0x50, 0x25, 0x3a, 0xe2, 0x20],
+    ),
+};
+/// Backs up the registry hives.
+pub const REGISTRY: KnownWriter = KnownWriter {
+    name: "Registry Writer",
+    id: guid(
+        0xafba_b4a2,
+        0x367d,
+        0x4d15,
+        [0xa5, 0x86, 0x71, 0xdb, 0xb1, 0x8f, 0x84, 0x85],
+    ),
+};
+/// Backs up the WMI repository.
+pub const WMI: KnownWriter = KnownWriter {
+    name: "WMI Writer",
+    id: guid(
+        0xa6ad_56c2,
+        0xb509,
+        0x4e6c,
+        [0xbb, 0x19, 0x49, 0xd8, 0xf4, 0x35, 0x32, 0xf0],
+    ),
+};
+/// Backs up the COM+ registration database.
+pub const COM_PLUS_REGDB: KnownWriter = KnownWriter {
+    name: "COM+ REGDB Writer",
+    id: guid(
+        0x542d_a469,
+        0xd3e1,
+        0x473c,
+        [0x9f, 0x4f, 0x78, 0x47, 0xf0, 0x1f, 0xc6, 0x4f],
+    ),
+};
+/// Reports the disk layout for Automated System Recovery.
+pub const ASR: KnownWriter = KnownWriter {
+    name: "ASR Writer",
+    id: guid(
+        0xbe00_0cbe,
+        0x11fe,
+        0x4426,
+        [0x9c, 0x58, 0x53, 0x1a, 0xa6, 0x35, 0x5f, 0xc4],
+    ),
+};
+/// Excludes files such as the page file from shadow copies so that they use less space.
+pub const SHADOW_COPY_OPTIMIZATION: KnownWriter = KnownWriter {
+    name: "Shadow Copy Optimization Writer",
+    id: guid(
+        0x4dc3_bdd4,
+        0xab48,
+        0x4d07,
+        [0xad, 0xb0, 0x3b, 0xee, 0x29, 0x26, 0xfd, 0x7f],
+    ),
+};
+/// Excludes the temporary files of Background Intelligent Transfer Service downloads.
+pub const BITS: KnownWriter = KnownWriter {
+    name: "BITS Writer",
+    id: guid(
+        0x4969_d978,
+        0xbe47,
+        0x48b0,
+        [0xb1, 0x00, 0xf3, 0x28, 0xf0, 0x7a, 0xc1, 0xe0],
+    ),
+};
+/// Backs up the scheduled tasks.
+pub const TASK_SCHEDULER: KnownWriter = KnownWriter {
+    name: "Task Scheduler Writer",
+    id: guid(
+        0xd61d_61c8,
+        0xd73a,
+        0x4eee,
+        [0x8c, 0xdd, 0xf6, 0xf9, 0x78, 0x6b, 0x71, 0x24],
+    ),
+};
+/// Backs up the metadata of express writers.
+pub const VSS_METADATA_STORE: KnownWriter = KnownWriter {
+    name: "VSS Metadata Store Writer",
+    id: guid(
+        0x75df_b225,
+        0xe2e4,
+        0x4d39,
+        [0x9a, 0xc9, 0xff, 0xaf, 0xf6, 0x5d, 0xdf, 0x06],
+    ),
+};
+/// Backs up the performance counter configuration.
+pub const PERFORMANCE_COUNTERS: KnownWriter = KnownWriter {
+    name: "Performance Counters Writer",
+    id: guid(
+        0x0bad_a1de,
+        0x01a9,
+        0x4625,
+        [0x82, 0x78, 0x69, 0xe7, 0x35, 0xf3, 0x9d, 0xd2],
+    ),
+};
+/// Backs up SQL Server databases, see [`sql`].
+pub const SQL_SERVER: KnownWriter = KnownWriter {
+    name: "SqlServerWriter",
+    id: guid(
+        0xa65f_aa63,
+        0x5ea8,
+        0x4ebc,
+        [0x9d, 0xbd, 0xa0, 0xc4, 0xdb, 0x26, 0x91, 0x2a],
+    ),
+};
+/// Backs up Hyper-V virtual machines, see [`hyperv`].
+pub const HYPER_V: KnownWriter = KnownWriter {
+    name: "Microsoft Hyper-V VSS Writer",
+    id: guid(
+        0x6684_1cd4,
+        0x6ded,
+        0x4f4b,
+        [0x8f, 0x17, 0xfd, 0x23, 0xf8, 0xdd, 0xc3, 0xde],
+    ),
+};
+/// Backs up Exchange mailbox databases, see [`database`].
+pub const EXCHANGE: KnownWriter = KnownWriter {
+    name: "Microsoft Exchange Writer",
+    id: guid(
+        0x76fe_1ac4,
+        0x15f7,
+        0x4bcd,
+        [0x98, 0x7e, 0x8e, 0x1a, 0xcb, 0x46, 0x2f, 0xb7],
+    ),
+};
+/// Backs up the Active Directory database on domain controllers.
+pub const NTDS: KnownWriter = KnownWriter {
+    name: "NTDS",
+    id: guid(
+        0xb201_4c9e,
+        0x8711,
+        0x4c5c,
+        [0xa5, 0xa9, 0x3c, 0xf3, 0x84, 0x48, 0x47, 0x57],
+    ),
+};
+/// Backs up the IIS configuration.
+pub const IIS_CONFIG: KnownWriter = KnownWriter {
+    name: "IIS Config Writer",
+    id: guid(
+        0x2a40_fd15,
+        0xdfca,
+        0x4aa8,
+        [0xa6, 0x54, 0x1f, 0x8c, 0x65, 0x46, 0x03, 0xf6],
+    ),
+};
+/// Backs up the IIS 6 compatible metabase.
+pub const IIS_METABASE: KnownWriter = KnownWriter {
+    name: "IIS Metabase Writer",
+    id: guid(
+        0x59b1_f0cf,
+        0x90ef,
+        0x465f,
+        [0x96, 0x09, 0x6c, 0xa8, 0xb2, 0x93, 0x83, 0x66],
+    ),
+};
+/// Excludes the Windows Search index.
+pub const MS_SEARCH: KnownWriter = KnownWriter {
+    name: "MSSearch Service Writer",
+    id: guid(
+        0xcd3f_2362,
+        0x8bef,
+        0x46c7,
+        [0x91, 0x81, 0xd6, 0x28, 0x44, 0xcd, 0xc0, 0xb2],
+    ),
+};
+/// Backs up replicated folders of DFS Replication.
+pub const DFS_REPLICATION: KnownWriter = KnownWriter {
+    name: "DFS Replication service writer",
+    id: guid(
+        0x2707_761b,
+        0x2324,
+        0x473d,
+        [0x88, 0xeb, 0xeb, 0x00, 0x7a, 0x35, 0x95, 0x33],
+    ),
+};
+/// Backs up the failover cluster configuration.
+pub const CLUSTER_DATABASE: KnownWriter = KnownWriter {
+    name: "Cluster Database",
+    id: guid(
+        0x41e1_2264,
+        0x35d8,
+        0x479b,
+        [0x8e, 0x5c, 0x9b, 0x23, 0xd1, 0xda, 0xd3, 0x7e],
+    ),
+};
+
+/// Every writer that has a constant in this module.
+pub const ALL: &[KnownWriter] = &[
+    SYSTEM,
+    REGISTRY,
+    WMI,
+    COM_PLUS_REGDB,
+    ASR,
+    SHADOW_COPY_OPTIMIZATION,
+    BITS,
+    TASK_SCHEDULER,
+    VSS_METADATA_STORE,
+    PERFORMANCE_COUNTERS,
+    SQL_SERVER,
+    HYPER_V,
+    EXCHANGE,
+    NTDS,
+    IIS_CONFIG,
+    IIS_METABASE,
+    MS_SEARCH,
+    DFS_REPLICATION,
+    CLUSTER_DATABASE,
+];
+
+/// Identifies a component in a writer's metadata, shared by the types of the
+/// submodules.
+#[derive(Clone)]
+struct WriterComponent {
+    instance_id: VSS_ID,
+    writer_id: VSS_ID,
+    component_type: VssComponentType,
+    logical_path: Option<U16CString>,
+    component_name: U16CString,
+}
+impl WriterComponent {
+    fn new(instance_id: VSS_ID, writer_id: VSS_ID, info: &ComponentInfo<'_>) -> Self {
+        Self {
+            instance_id,
+            writer_id,
+            component_type: info.component_type(),
+            logical_path: info.logical_path().map(bstr_to_u16_cstring),
+            component_name: bstr_to_u16_cstring(info.component_name()),
+        }
+    }
+    fn spec(&self) -> ComponentSpec<'_> {
+        ComponentSpec::new(
+            self.writer_id,
+            self.component_type,
+            self.logical_path.as_deref(),
+            &self.component_name,
+        )
+    }
+    /// The logical path of the component followed by its name, as used by
+    /// [`SelectionRule::component`](crate::session::SelectionRule::component).
+    fn component_path(&self) -> String {
+        match &self.logical_path {
+            Some(logical_path) => format!(
+                "{}\\{}",
+                logical_path.to_string_lossy(),
+                self.component_name.to_string_lossy()
+            ),
+            None => self.component_name.to_string_lossy(),
+        }
+    }
+    fn select_for_backup(&self, backup_comp: &IBackupComponents) -> Result<(), SessionError> {
+        backup_comp
+            .component(self.spec())
+            .add_component(self.instance_id)
+            .map_err(SessionError::vss("AddComponent"))
+    }
+}
+
+/// Call `f` for every component in the metadata of the writers that
+/// `include_writer` accepts. Components of the same writer instance are
+/// visited one after another.
+///
+/// Writer metadata must have been gathered first.
+fn for_each_component<I, F>(
+    backup_comp: &IBackupComponents,
+    mut include_writer: I,
+    mut f: F,
+) -> Result<(), SessionError>
+where
+    I: FnMut(&GetIdentityInfo) -> bool,
+    F: FnMut(
+        &GetIdentityInfo,
+        WriterComponent,
+        &ComponentInfo<'_>,
+        &WMComponent,
+    ) -> Result<(), SessionError>,
+{
+    for metadata in backup_comp
+        .writer_metadata_iter()
+        .map_err(SessionError::vss("GetWriterMetadataCount"))?
+    {
+        let (instance_id, metadata) = metadata.map_err(SessionError::vss("GetWriterMetadata"))?;
+        let identity = metadata
+            .get_identity()
+            .map_err(SessionError::vss("GetIdentity"))?;
+        if !include_writer(&identity) {
+            continue;
+        }
+        let counts = metadata
+            .get_file_counts()
+            .map_err(SessionError::vss("GetFileCounts"))?;
+        for component_index in 0..counts.total_components {
+            let component = metadata
+                .get_component(component_index)
+                .map_err(SessionError::vss("GetComponent"))?;
+            let info = component
+                .get_component_info()
+                .map_err(SessionError::vss("GetComponentInfo"))?;
+            let writer_component = WriterComponent::new(instance_id, identity.writer, &info);
+            f(&identity, writer_component, &info, &component)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a [`SelectionConfig`] that only backs up `components` of the writer
+/// named `writer_name`.
+fn selection<'a, I>(writer_name: &str, components: I) -> SelectionConfig
+where
+    I: IntoIterator<Item = &'a WriterComponent>,
+{
+    let mut selection = SelectionConfig::new();
+    selection.include_by_default = false;
+    for component in components {
+        selection = selection.rule(
+            writer_name,
+            Some(&component.component_path()),
+            SelectionAction::Include,
+        );
+    }
+    selection
+}
+
+/// The file sets of a component in a writer's metadata.
+struct ComponentFileSets {
+    files: Vec<FileSet>,
+    database_files: Vec<FileSet>,
+    log_files: Vec<FileSet>,
+}
+impl ComponentFileSets {
+    fn read(component: &WMComponent) -> Result<Self, SessionError> {
+        let mut file_sets = Self {
+            files: Vec::new(),
+            database_files: Vec::new(),
+            log_files: Vec::new(),
+        };
+        for file in component
+            .files()
+            .map_err(SessionError::vss("GetComponentInfo"))?
+        {
+            let file = file.map_err(SessionError::vss("GetFile"))?;
+            file_sets
+                .files
+                .push(FileSet::from_descriptor(&file).map_err(SessionError::vss("GetFile"))?);
+        }
+        for file in component
+            .database_files()
+            .map_err(SessionError::vss("GetComponentInfo"))?
+        {
+            let file = file.map_err(SessionError::vss("GetDatabaseFile"))?;
+            file_sets.database_files.push(
+                FileSet::from_descriptor(&file).map_err(SessionError::vss("GetDatabaseFile"))?,
+            );
+        }
+        for file in component
+            .log_files()
+            .map_err(SessionError::vss("GetComponentInfo"))?
+        {
+            let file = file.map_err(SessionError::vss("GetDatabaseLogFile"))?;
+            file_sets.log_files.push(
+                FileSet::from_descriptor(&file).map_err(SessionError::vss("GetDatabaseLogFile"))?,
+            );
+        }
+        Ok(file_sets)
+    }
+    /// Every file set: the files of a file group followed by the database and
+    /// log files of a database.
+    fn into_vec(self) -> Vec<FileSet> {
+        let mut file_sets = self.files;
+        file_sets.extend(self.database_files);
+        file_sets.extend(self.log_files);
+        file_sets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_writers_are_unique() {
+        for (index, writer) in ALL.iter().enumerate() {
+            for other in &ALL[index + 1..] {
+                assert!(!IsEqualGUID(&writer.id, &other.id), "{:?}", other);
+                assert_ne!(writer.name, other.name);
+            }
+        }
+        assert_eq!(
+            KnownWriter::from_name("sqlserverwriter").map(|writer| writer.name),
+            Some(SQL_SERVER.name)
+        );
+        assert_eq!(
+            KnownWriter::from_id(&HYPER_V.id).map(|writer| writer.name),
+            Some("Microsoft Hyper-V VSS Writer")
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Back up databases of writers that manage transacted databases, such as
+//! the Microsoft Exchange writer.
+//!
+//! Writers whose [`SourceType`] is [`SourceType::TransactedDb`] describe each
+//! database with the files that store its data and the transaction logs that
+//! were written since the last full backup. When such a backup completes
+//! successfully the writer truncates the logs, but only for backup types that
+//! include the logs, see [`truncates_logs`]. A component that wasn't backed
+//! up completely must therefore be reported as failed with
+//! [`ComponentBackup::set_backup_succeeded`] so that logs that are still
+//! needed aren't deleted.
+//!
+//! [`ComponentBackup::set_backup_succeeded`]: crate::session::ComponentBackup::set_backup_succeeded
+
+use std::fmt;
+
+use winapi::shared::guiddef::IsEqualGUID;
+
+use super::{for_each_component, ComponentFileSets, WriterComponent};
+use crate::{
+    bstr_as_u16_slice,
+    paths::FileSet,
+    session::{SelectionConfig, SessionError, SnapshotBuilder},
+    vsbackup::{ComponentSpec, IBackupComponents},
+    vss::BackupType,
+    vswriter::{SourceType, UsageType},
+    VSS_ID,
+};
+
+/// A writer that manages transacted databases.
+#[derive(Clone)]
+pub struct DatabaseWriter {
+    /// Identifier of the writer instance.
+    pub instance_id: VSS_ID,
+    /// Identifier of the writer class.
+    pub writer_id: VSS_ID,
+    /// The name of the writer, for example `"Microsoft Exchange Writer"`.
+    pub name: String,
+    /// How the data of the writer is used on the system.
+    pub usage: UsageType,
+    /// The databases that the writer reported.
+    pub databases: Vec<Database>,
+}
+impl fmt::Debug for DatabaseWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatabaseWriter")
+            .field("name", &self.name)
+            .field("usage", &self.usage)
+            .field("databases", &self.databases)
+            .finish()
+    }
+}
+
+/// A database component of a [`DatabaseWriter`].
+#[derive(Clone)]
+pub struct Database {
+    /// Identifier of the writer instance that reported the database.
+    pub instance_id: VSS_ID,
+    /// Identifier of the writer class.
+    pub writer_id: VSS_ID,
+    /// Whether the database can be selected for backup.
+    pub selectable: bool,
+    /// The files that store the data of the database.
+    pub database_files: Vec<FileSet>,
+    /// The transaction logs of the database. These must be backed up together
+    /// with the database files since the writer may truncate them when the
+    /// backup completes.
+    pub log_files: Vec<FileSet>,
+    /// Other files of the component, for example checkpoint files that the
+    /// writer added to a file group.
+    pub other_files: Vec<FileSet>,
+    component: WriterComponent,
+}
+impl Database {
+    /// Identifies the database's component in calls to
+    /// [`IBackupComponents`] methods.
+    pub fn spec(&self) -> ComponentSpec<'_> {
+        self.component.spec()
+    }
+    /// The name of the database's component.
+    pub fn name(&self) -> String {
+        self.component.component_name.to_string_lossy()
+    }
+    /// The logical path of the component followed by its name, as used by
+    /// [`SelectionRule::component`](crate::session::SelectionRule::component).
+    pub fn component_path(&self) -> String {
+        self.component.component_path()
+    }
+    /// Every file set of the database: data files, then logs and then other
+    /// files.
+    pub fn files(&self) -> impl Iterator<Item = &FileSet> + '_ {
+        self.database_files
+            .iter()
+            .chain(&self.log_files)
+            .chain(&self.other_files)
+    }
+}
+impl fmt::Debug for Database {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Database")
+            .field("logical_path", &self.component.logical_path)
+            .field("component_name", &self.component.component_name)
+            .field("selectable", &self.selectable)
+            .field("database_files", &self.database_files)
+            .field("log_files", &self.log_files)
+            .field("other_files", &self.other_files)
+            .finish()
+    }
+}
+
+/// Whether writers of transacted databases truncate their logs when a backup
+/// of this type completes successfully.
+///
+/// Full and incremental backups include the logs so they are truncated
+/// afterwards, as are log backups. Differential and copy backups leave the
+/// logs alone so that they don't interfere with the regular backup schedule.
+pub fn truncates_logs(backup_type: BackupType) -> bool {
+    matches!(
+        backup_type,
+        BackupType::Full | BackupType::Incremental | BackupType::Log
+    )
+}
+
+/// Choose the backup type of a builder depending on whether the writers
+/// should truncate their logs: [`BackupType::Full`] if they should and
+/// [`BackupType::Copy`] otherwise.
+pub fn configure(builder: SnapshotBuilder, truncate_logs: bool) -> SnapshotBuilder {
+    builder.backup_type(if truncate_logs {
+        BackupType::Full
+    } else {
+        BackupType::Copy
+    })
+}
+
+/// List every writer with the [`SourceType::TransactedDb`] source type that
+/// has database components, together with those components. Only writers
+/// with one of the `usages` are included, or all of them if `usages` is
+/// empty.
+///
+/// Writer metadata must have been gathered with
+/// [`IBackupComponents::gather_writer_metadata`] first.
+pub fn database_writers(
+    backup_comp: &IBackupComponents,
+    usages: &[UsageType],
+) -> Result<Vec<DatabaseWriter>, SessionError> {
+    let mut writers = Vec::<DatabaseWriter>::new();
+    for_each_component(
+        backup_comp,
+        |identity| {
+            identity.source == SourceType::TransactedDb
+                && (usages.is_empty() || usages.contains(&identity.usage))
+        },
+        |identity, component, info, wm_component| {
+            let file_sets = ComponentFileSets::read(wm_component)?;
+            if file_sets.database_files.is_empty() && file_sets.log_files.is_empty() {
+                return Ok(());
+            }
+            // The components of a writer are visited one after another.
+            let is_new_writer = !matches!(
+                writers.last(),
+                Some(writer) if IsEqualGUID(&writer.instance_id, &component.instance_id)
+            );
+            if is_new_writer {
+                writers.push(DatabaseWriter {
+                    instance_id: component.instance_id,
+                    writer_id: identity.writer,
+                    name: String::from_utf16_lossy(bstr_as_u16_slice(&identity.writer_name)),
+                    usage: identity.usage,
+                    databases: Vec::new(),
+                });
+            }
+            let writer = writers.last_mut().expect("a writer was just added");
+            writer.databases.push(Database {
+                instance_id: component.instance_id,
+                writer_id: identity.writer,
+                selectable: info.selectable(),
+                database_files: file_sets.database_files,
+                log_files: file_sets.log_files,
+                other_files: file_sets.files,
+                component,
+            });
+            Ok(())
+        },
+    )?;
+    Ok(writers)
+}
+
+/// Create a [`SelectionConfig`] that only backs up `databases` of `writer`,
+/// for use with [`SnapshotBuilder::create_component_backup`].
+pub fn selection<'a, I>(writer: &DatabaseWriter, databases: I) -> SelectionConfig
+where
+    I: IntoIterator<Item = &'a Database>,
+{
+    super::selection(
+        &writer.name,
+        databases.into_iter().map(|database| &database.component),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_truncating_backup_types() {
+        assert!(truncates_logs(BackupType::Full));
+        assert!(truncates_logs(BackupType::Incremental));
+        assert!(truncates_logs(BackupType::Log));
+        assert!(!truncates_logs(BackupType::Differential));
+        assert!(!truncates_logs(BackupType::Copy));
+    }
+}
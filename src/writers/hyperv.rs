@@ -0,0 +1,297 @@
+//! Back up virtual machines through the Hyper-V VSS writer.
+//!
+//! The Hyper-V writer adds one component for each virtual machine. The
+//! component name is the id of the virtual machine and the caption is its
+//! name prefixed with how the writer will back it up, for example
+//! `Online\Web Server`. [`virtual_machines`] reads this from the gathered
+//! writer metadata so that virtual machines can be chosen by name.
+//!
+//! Online backups of running virtual machines need auto-recovered shadow
+//! copies: the writer changes the virtual disks in the shadow copy after it
+//! was created so that they are consistent. Such components have the
+//! [`VssComponentFlags::BACKUP_RECOVERY`] flag and the shadow copies can't be
+//! transportable on older Windows versions, which [`configure`] takes care
+//! of.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::{
+//!     session::SnapshotBuilder, vsbackup::IBackupComponents, writers::hyperv, ComGuard,
+//! };
+//!
+//! // `backup_comp` must have gathered writer metadata.
+//! fn back_up_web_server(
+//!     com: &ComGuard,
+//!     backup_comp: &IBackupComponents,
+//! ) -> Result<(), Box<dyn std::error::Error>> {
+//!     let machines = hyperv::virtual_machines(backup_comp)?;
+//!     let machines = machines
+//!         .iter()
+//!         .filter(|machine| machine.matches("Web Server"))
+//!         .collect::<Vec<_>>();
+//!     let backup = hyperv::configure(SnapshotBuilder::new(), machines.iter().copied())
+//!         .create_component_backup(com, &hyperv::selection(machines))?;
+//!     for component in backup.components() {
+//!         for disk in hyperv::virtual_disks(component) {
+//!             println!("{}", disk.snapshot_path.to_string_lossy());
+//!         }
+//!     }
+//!     backup.finish()?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # References
+//!
+//! [About Hyper-V VSS writer - Win32 apps | Microsoft Docs](https://docs.microsoft.com/en-us/windows/win32/hyperv_v2/hyper-v-volume-shadow-copy-service-writer)
+
+use std::fmt;
+
+use winapi::shared::guiddef::IsEqualGUID;
+
+use super::{for_each_component, ComponentFileSets, WriterComponent};
+use crate::{
+    bstr_as_u16_slice,
+    paths::FileSet,
+    session::{BackedUpComponent, ComponentFiles, SelectionConfig, SessionError, SnapshotBuilder},
+    vsbackup::{ComponentSpec, IBackupComponents},
+    vswriter::VssComponentFlags,
+    VSS_ID,
+};
+
+/// The writer class id of the Hyper-V writer.
+pub const WRITER_ID: VSS_ID = super::HYPER_V.id;
+/// The name of the Hyper-V writer.
+pub const WRITER_NAME: &str = super::HYPER_V.name;
+
+/// How the Hyper-V writer backs up a virtual machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupMethod {
+    /// The virtual machine keeps running and the guest's own writers are
+    /// used to make its data consistent. Captions start with `Online` or
+    /// `Backup Using Child Partition Snapshot`.
+    Online,
+    /// The virtual machine is saved while the shadow copy is created. Captions
+    /// start with `Offline` or `Backup Using Saved State`.
+    Offline,
+    /// The caption didn't start with a known prefix.
+    Unknown,
+}
+
+/// A virtual machine that the Hyper-V writer reported in its metadata.
+#[derive(Clone)]
+pub struct VirtualMachine {
+    /// Identifier of the writer instance that reported the virtual machine.
+    pub instance_id: VSS_ID,
+    /// The id of the virtual machine, for example
+    /// `"A1B2C3D4-0000-1111-2222-333344445555"`.
+    pub id: String,
+    /// The name of the virtual machine.
+    pub name: String,
+    /// How the writer will back up the virtual machine.
+    pub backup_method: BackupMethod,
+    /// Whether the writer needs to change the shadow copy after it was
+    /// created, see the [module documentation](self).
+    pub requires_auto_recovery: bool,
+    /// The configuration files and virtual disks of the virtual machine.
+    pub files: Vec<FileSet>,
+    component: WriterComponent,
+}
+impl VirtualMachine {
+    /// Identifies the virtual machine's component in calls to
+    /// [`IBackupComponents`] methods.
+    pub fn spec(&self) -> ComponentSpec<'_> {
+        self.component.spec()
+    }
+    /// The logical path of the component followed by its name, as used by
+    /// [`SelectionRule::component`](crate::session::SelectionRule::component).
+    pub fn component_path(&self) -> String {
+        self.component.component_path()
+    }
+    /// Check if `name_or_id` is the name or the id of the virtual machine.
+    /// Case is ignored.
+    pub fn matches(&self, name_or_id: &str) -> bool {
+        let id = name_or_id.trim_start_matches('{').trim_end_matches('}');
+        self.name.eq_ignore_ascii_case(name_or_id) || self.id.eq_ignore_ascii_case(id)
+    }
+    /// The virtual disks of the virtual machine.
+    pub fn virtual_disks(&self) -> impl Iterator<Item = &FileSet> + '_ {
+        self.files
+            .iter()
+            .filter(|file_set| is_virtual_disk(file_set.file_specification()))
+    }
+    /// Add the virtual machine to the Backup Components Document of a backup
+    /// that is being prepared.
+    #[doc(alias = "AddComponent")]
+    pub fn select_for_backup(&self, backup_comp: &IBackupComponents) -> Result<(), SessionError> {
+        self.component.select_for_backup(backup_comp)
+    }
+}
+impl fmt::Debug for VirtualMachine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VirtualMachine")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("backup_method", &self.backup_method)
+            .field("requires_auto_recovery", &self.requires_auto_recovery)
+            .field("files", &self.files)
+            .finish()
+    }
+}
+
+/// Split the caption of a virtual machine's component into the backup method
+/// and the name of the virtual machine.
+fn parse_caption(caption: &str) -> (BackupMethod, &str) {
+    const PREFIXES: &[(&str, BackupMethod)] = &[
+        ("Online\\", BackupMethod::Online),
+        (
+            "Backup Using Child Partition Snapshot\\",
+            BackupMethod::Online,
+        ),
+        ("Offline\\", BackupMethod::Offline),
+        ("Backup Using Saved State\\", BackupMethod::Offline),
+    ];
+    for (prefix, method) in PREFIXES {
+        if matches!(caption.get(..prefix.len()), Some(start) if start.eq_ignore_ascii_case(prefix))
+        {
+            return (*method, &caption[prefix.len()..]);
+        }
+    }
+    (BackupMethod::Unknown, caption)
+}
+
+/// Check if a component name is a virtual machine id. The writer also reports
+/// components that aren't virtual machines, such as its initial store.
+fn is_virtual_machine_id(name: &str) -> bool {
+    name.len() == 36
+        && name.char_indices().all(|(index, c)| match index {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Check if a file specification matches virtual disk files.
+fn is_virtual_disk(file_specification: &[u16]) -> bool {
+    let file_specification = String::from_utf16_lossy(file_specification).to_ascii_lowercase();
+    [".vhd", ".vhdx", ".avhdx"]
+        .iter()
+        .any(|extension| file_specification.ends_with(extension))
+}
+
+/// List the virtual machines on this Hyper-V host.
+///
+/// Writer metadata must have been gathered with
+/// [`IBackupComponents::gather_writer_metadata`] first. Returns an empty list
+/// if the Hyper-V writer isn't running.
+pub fn virtual_machines(
+    backup_comp: &IBackupComponents,
+) -> Result<Vec<VirtualMachine>, SessionError> {
+    let mut machines = Vec::new();
+    for_each_component(
+        backup_comp,
+        |identity| IsEqualGUID(&identity.writer, &WRITER_ID),
+        |_, component, info, wm_component| {
+            let id = String::from_utf16_lossy(bstr_as_u16_slice(info.component_name()));
+            if !is_virtual_machine_id(&id) {
+                return Ok(());
+            }
+            let caption = info
+                .caption()
+                .map(|caption| String::from_utf16_lossy(bstr_as_u16_slice(caption)))
+                .unwrap_or_default();
+            let (backup_method, name) = parse_caption(&caption);
+            machines.push(VirtualMachine {
+                instance_id: component.instance_id,
+                name: name.to_owned(),
+                backup_method,
+                requires_auto_recovery: info
+                    .component_flags()
+                    .flags()
+                    .contains(VssComponentFlags::BACKUP_RECOVERY),
+                files: ComponentFileSets::read(wm_component)?.into_vec(),
+                component,
+                id,
+            });
+            Ok(())
+        },
+    )?;
+    Ok(machines)
+}
+
+/// Create a [`SelectionConfig`] that only backs up `machines`, for use with
+/// [`SnapshotBuilder::create_component_backup`].
+pub fn selection<'a, I>(machines: I) -> SelectionConfig
+where
+    I: IntoIterator<Item = &'a VirtualMachine>,
+{
+    super::selection(
+        WRITER_NAME,
+        machines.into_iter().map(|machine| &machine.component),
+    )
+}
+
+/// Adjust a builder so that the Hyper-V writer can back up `machines`.
+///
+/// Shadow copies aren't made transportable if any of the virtual machines
+/// requires auto-recovery.
+pub fn configure<'a, I>(builder: SnapshotBuilder, machines: I) -> SnapshotBuilder
+where
+    I: IntoIterator<Item = &'a VirtualMachine>,
+{
+    if machines
+        .into_iter()
+        .any(|machine| machine.requires_auto_recovery)
+    {
+        builder.transportable(false)
+    } else {
+        builder
+    }
+}
+
+/// The virtual disks of a virtual machine that was backed up with
+/// [`SnapshotBuilder::create_component_backup`], with their paths inside the
+/// shadow copies.
+pub fn virtual_disks(component: &BackedUpComponent) -> impl Iterator<Item = &ComponentFiles> + '_ {
+    component
+        .files
+        .iter()
+        .filter(|files| is_virtual_disk(files.file_set.file_specification()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caption_and_id() {
+        assert_eq!(
+            parse_caption("Online\\Web Server"),
+            (BackupMethod::Online, "Web Server")
+        );
+        assert_eq!(
+            parse_caption("Backup Using Saved State\\db"),
+            (BackupMethod::Offline, "db")
+        );
+        assert_eq!(
+            parse_caption("Initial Store"),
+            (BackupMethod::Unknown, "Initial Store")
+        );
+        assert!(is_virtual_machine_id(
+            "A1B2C3D4-0000-1111-2222-333344445555"
+        ));
+        assert!(!is_virtual_machine_id(
+            "{A1B2C3D4-0000-1111-2222-333344445555}"
+        ));
+        assert!(!is_virtual_machine_id("Initial Store"));
+    }
+
+    #[test]
+    fn virtual_disk_extensions() {
+        let wide = |text: &str| text.encode_utf16().collect::<Vec<_>>();
+        assert!(is_virtual_disk(&wide("disk.VHDX")));
+        assert!(is_virtual_disk(&wide("*.avhdx")));
+        assert!(is_virtual_disk(&wide("old.vhd")));
+        assert!(!is_virtual_disk(&wide("config.vmcx")));
+    }
+}
@@ -0,0 +1,211 @@
+//! Back up and restore SQL Server databases through the `SqlServerWriter`.
+//!
+//! The SQL Server writer adds one component for each database. The logical
+//! path of a component is the name of the server, followed by a backslash
+//! and the instance name for named instances, and the component name is the
+//! name of the database. [`databases`] reads this mapping from the gathered
+//! writer metadata so that databases can be chosen by name.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use volume_shadow_copy::{
+//!     session::SnapshotBuilder, vsbackup::IBackupComponents, writers::sql, ComGuard,
+//! };
+//!
+//! // `backup_comp` must have gathered writer metadata.
+//! fn back_up_sales(
+//!     com: &ComGuard,
+//!     backup_comp: &IBackupComponents,
+//! ) -> Result<(), Box<dyn std::error::Error>> {
+//!     let databases = sql::databases(backup_comp)?;
+//!     let selection = sql::selection(
+//!         databases
+//!             .iter()
+//!             .filter(|database| database.matches(Some("SQLEXPRESS"), "Sales")),
+//!     );
+//!     let backup = SnapshotBuilder::new().create_component_backup(com, &selection)?;
+//!     // Copy the files listed by `backup.components()`...
+//!     backup.finish()?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # References
+//!
+//! [SQL Writer Service - SQL Server | Microsoft Docs](https://docs.microsoft.com/en-us/sql/database-engine/configure-windows/sql-writer-service)
+
+use std::fmt;
+
+use winapi::shared::guiddef::IsEqualGUID;
+
+use super::{for_each_component, ComponentFileSets, WriterComponent};
+use crate::{
+    bstr_as_u16_slice,
+    paths::FileSet,
+    session::{RestoreSession, SelectionConfig, SessionError},
+    vsbackup::{ComponentSpec, IBackupComponents},
+    vss::RollForward,
+    VSS_ID,
+};
+
+/// The writer class id of the SQL Server writer.
+pub const WRITER_ID: VSS_ID = super::SQL_SERVER.id;
+/// The name of the SQL Server writer.
+pub const WRITER_NAME: &str = super::SQL_SERVER.name;
+
+/// A database that the SQL Server writer reported in its metadata.
+#[derive(Clone)]
+pub struct SqlDatabase {
+    /// Identifier of the writer instance that reported the database.
+    pub instance_id: VSS_ID,
+    /// The name of the server.
+    pub server: String,
+    /// The name of the SQL Server instance, or `None` for the default
+    /// instance.
+    pub instance: Option<String>,
+    /// The name of the database.
+    pub database: String,
+    /// Whether the database can be selected for backup.
+    pub selectable: bool,
+    /// The data and log files of the database.
+    pub files: Vec<FileSet>,
+    component: WriterComponent,
+}
+impl SqlDatabase {
+    /// Identifies the database's component in calls to
+    /// [`IBackupComponents`] and [`RestoreSession`] methods.
+    pub fn spec(&self) -> ComponentSpec<'_> {
+        self.component.spec()
+    }
+    /// The logical path of the component followed by its name, as used by
+    /// [`SelectionRule::component`](crate::session::SelectionRule::component).
+    pub fn component_path(&self) -> String {
+        self.component.component_path()
+    }
+    /// Check if this is the database named `database` in the SQL Server
+    /// instance named `instance`, where `None` is the default instance. Case
+    /// is ignored since SQL Server names usually are case insensitive.
+    pub fn matches(&self, instance: Option<&str>, database: &str) -> bool {
+        let same_instance = match (&self.instance, instance) {
+            (None, None) => true,
+            (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+            _ => false,
+        };
+        same_instance && self.database.eq_ignore_ascii_case(database)
+    }
+    /// Add the database to the Backup Components Document of a backup that
+    /// is being prepared.
+    #[doc(alias = "AddComponent")]
+    pub fn select_for_backup(&self, backup_comp: &IBackupComponents) -> Result<(), SessionError> {
+        self.component.select_for_backup(backup_comp)
+    }
+    /// Select the database for restore and tell the writer how it should be
+    /// recovered afterwards.
+    pub fn select_for_restore(
+        &self,
+        session: &RestoreSession,
+        options: &SqlRestoreOptions<'_>,
+    ) -> Result<(), SessionError> {
+        let spec = self.spec();
+        session.select_component(&spec)?;
+        if options.no_recovery {
+            session.set_additional_restores(&spec, true)?;
+        }
+        if let Some(roll_forward) = options.roll_forward {
+            session.set_roll_forward(&spec, roll_forward)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SqlDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqlDatabase")
+            .field("server", &self.server)
+            .field("instance", &self.instance)
+            .field("database", &self.database)
+            .field("selectable", &self.selectable)
+            .field("files", &self.files)
+            .finish()
+    }
+}
+
+/// How a database that is selected with [`SqlDatabase::select_for_restore`]
+/// is recovered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SqlRestoreOptions<'a> {
+    /// Leave the database in the restoring state, like `RESTORE ... WITH
+    /// NORECOVERY`, so that log backups can be applied after the restore.
+    pub no_recovery: bool,
+    /// How the writer rolls the database forward. `None` leaves the choice to
+    /// the writer.
+    pub roll_forward: Option<RollForward<'a>>,
+}
+
+/// Split a logical path of the SQL Server writer into the server name and the
+/// instance name.
+fn parse_logical_path(logical_path: &str) -> (String, Option<String>) {
+    match logical_path.split_once('\\') {
+        Some((server, instance)) => (server.to_owned(), Some(instance.to_owned())),
+        None => (logical_path.to_owned(), None),
+    }
+}
+
+/// List the databases of every SQL Server instance on the machine.
+///
+/// Writer metadata must have been gathered with
+/// [`IBackupComponents::gather_writer_metadata`] first. Returns an empty list
+/// if the SQL Server writer isn't running.
+pub fn databases(backup_comp: &IBackupComponents) -> Result<Vec<SqlDatabase>, SessionError> {
+    let mut databases = Vec::new();
+    for_each_component(
+        backup_comp,
+        |identity| IsEqualGUID(&identity.writer, &WRITER_ID),
+        |_, component, info, wm_component| {
+            let (server, instance) = match &component.logical_path {
+                Some(logical_path) => parse_logical_path(&logical_path.to_string_lossy()),
+                None => (String::new(), None),
+            };
+            databases.push(SqlDatabase {
+                instance_id: component.instance_id,
+                server,
+                instance,
+                database: String::from_utf16_lossy(bstr_as_u16_slice(info.component_name())),
+                selectable: info.selectable(),
+                files: ComponentFileSets::read(wm_component)?.into_vec(),
+                component,
+            });
+            Ok(())
+        },
+    )?;
+    Ok(databases)
+}
+
+/// Create a [`SelectionConfig`] that only backs up `databases`, for use with
+/// [`SnapshotBuilder::create_component_backup`].
+///
+/// [`SnapshotBuilder::create_component_backup`]: crate::session::SnapshotBuilder::create_component_backup
+pub fn selection<'a, I>(databases: I) -> SelectionConfig
+where
+    I: IntoIterator<Item = &'a SqlDatabase>,
+{
+    super::selection(
+        WRITER_NAME,
+        databases.into_iter().map(|database| &database.component),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logical_path_names() {
+        assert_eq!(parse_logical_path("HOST"), ("HOST".to_owned(), None));
+        assert_eq!(
+            parse_logical_path("HOST\\SQLEXPRESS"),
+            ("HOST".to_owned(), Some("SQLEXPRESS".to_owned()))
+        );
+    }
+}